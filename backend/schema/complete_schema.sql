@@ -24,6 +24,33 @@ CREATE TABLE companies (
     deleted_at TIMESTAMP WITH TIME ZONE
 );
 
+-- =====================================================
+-- NIVEL 1B - SECTORS (zonas de reparto por empresa)
+-- =====================================================
+CREATE TABLE sectors (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    
+    -- Identificación
+    code VARCHAR(50) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    
+    -- Cobertura: códigos postales, comunas y polígono opcional
+    postal_codes TEXT[] NOT NULL DEFAULT '{}',
+    communes TEXT[] NOT NULL DEFAULT '{}',
+    boundary GEOGRAPHY(MULTIPOLYGON, 4326),
+    
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    deleted_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Código único entre los sectores vivos: tras la baja lógica se puede reutilizar
+CREATE UNIQUE INDEX unique_sector_code_per_company ON sectors(company_id, code) WHERE deleted_at IS NULL;
+
 -- =====================================================
 -- NIVEL 2A - USERS
 -- =====================================================
@@ -45,10 +72,12 @@ CREATE TABLE users (
     
     -- Específicos para drivers
     tournee_number VARCHAR(20),
+    sector_id UUID REFERENCES sectors(id) ON DELETE SET NULL,
     driver_license VARCHAR(50),
     hire_date DATE,
     device_token VARCHAR(255),
     last_location POINT,
+    last_location_at TIMESTAMP WITH TIME ZONE,
     shift_start_time TIME,
     shift_end_time TIME,
     
@@ -308,6 +337,7 @@ CREATE TABLE tournees (
     tournee_origin VARCHAR(50) DEFAULT 'manual',
     external_tournee_id VARCHAR(100),
     integration_id UUID REFERENCES api_integrations(id) ON DELETE SET NULL,
+    sector_id UUID REFERENCES sectors(id) ON DELETE SET NULL,
     
     -- Metadatos
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
@@ -315,7 +345,8 @@ CREATE TABLE tournees (
     deleted_at TIMESTAMP WITH TIME ZONE,
     
     -- Constraints
-    CONSTRAINT unique_tournee_per_driver_date UNIQUE (driver_id, tournee_date)
+    CONSTRAINT unique_tournee_per_driver_date UNIQUE (driver_id, tournee_date),
+    CONSTRAINT unique_external_tournee UNIQUE (integration_id, external_tournee_id, tournee_date)
 );
 
 -- =====================================================
@@ -364,6 +395,12 @@ CREATE TABLE packages (
     failure_notes TEXT,
     reschedule_date DATE,
     
+    -- Siguiente paso según la política de la empresa (reschedule, relay_point, return_to_sender)
+    failure_action VARCHAR(30),
+    relay_point_address TEXT,
+    max_attempts_reached_at TIMESTAMP WITH TIME ZONE,
+    carried_over_from UUID REFERENCES packages(id) ON DELETE SET NULL,
+    
     -- Evidencia de entrega
     delivery_photo TEXT,
     signature_required BOOLEAN DEFAULT FALSE,
@@ -372,7 +409,10 @@ CREATE TABLE packages (
     
     -- Ubicación y tiempo de entrega
     delivery_coordinates POINT,
+    -- Coordenadas fijadas por el driver: la validación no las sobrescribe
+    coordinates_confirmed_at TIMESTAMP WITH TIME ZONE,
     delivery_duration_minutes INTEGER,
+    delivery_sequence INTEGER,
     
     -- Notas del chofer
     driver_notes TEXT,
@@ -384,7 +424,8 @@ CREATE TABLE packages (
     deleted_at TIMESTAMP WITH TIME ZONE,
     
     -- Constraints
-    CONSTRAINT unique_tracking_per_tournee UNIQUE (tournee_id, tracking_number)
+    CONSTRAINT unique_tracking_per_tournee UNIQUE (tournee_id, tracking_number),
+    CONSTRAINT unique_external_package_per_tournee UNIQUE (tournee_id, external_package_id)
 );
 
 -- =====================================================
@@ -566,3 +607,243 @@ CREATE TABLE sync_log (
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
 
+
+-- =====================================================
+-- NIVEL 6A - GEOCODING_CACHE
+-- =====================================================
+CREATE TABLE geocoding_cache (
+    -- Dirección normalizada (mayúsculas, sin acentos ni puntuación)
+    normalized_address TEXT PRIMARY KEY,
+    original_address TEXT NOT NULL,
+    
+    -- Resultado del geocoding
+    latitude DECIMAL(10,8) NOT NULL,
+    longitude DECIMAL(11,8) NOT NULL,
+    formatted_address TEXT,
+    provider VARCHAR(50) NOT NULL,
+    confidence DOUBLE PRECISION,
+    
+    -- Uso y caducidad
+    hit_count INTEGER NOT NULL DEFAULT 0,
+    last_hit_at TIMESTAMP WITH TIME ZONE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- =====================================================
+-- NIVEL 6B - BAN_ADDRESSES (Base Adresse Nationale importada)
+-- =====================================================
+CREATE TABLE ban_addresses (
+    -- Identificador BAN (p. ej. 75118_5054_00016_bis)
+    id VARCHAR(50) PRIMARY KEY,
+    
+    -- Dirección
+    numero INTEGER NOT NULL,
+    rep VARCHAR(10),
+    nom_voie TEXT NOT NULL,
+    nom_voie_normalized TEXT NOT NULL,
+    code_postal VARCHAR(5) NOT NULL,
+    code_insee VARCHAR(5) NOT NULL,
+    nom_commune VARCHAR(255) NOT NULL,
+    
+    -- Posición (WGS84)
+    location GEOGRAPHY(POINT, 4326) NOT NULL,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- =====================================================
+-- NIVEL 6C - ADDRESS_CORRECTIONS (confirmadas por drivers)
+-- =====================================================
+CREATE TABLE address_corrections (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    
+    -- Dirección tal como llega de Colis Privé y su clave normalizada
+    raw_address TEXT NOT NULL,
+    address_key TEXT NOT NULL,
+    postal_code VARCHAR(5) NOT NULL,
+    
+    -- Posición confirmada
+    latitude DECIMAL(10,8) NOT NULL,
+    longitude DECIMAL(11,8) NOT NULL,
+    formatted_address TEXT,
+    
+    -- Confirmaciones
+    confirmation_count INTEGER NOT NULL DEFAULT 1,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    last_confirmed_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    last_confirmed_at TIMESTAMP WITH TIME ZONE,
+    
+    -- Revocación
+    revoked_at TIMESTAMP WITH TIME ZONE,
+    revoked_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT unique_address_correction_per_company UNIQUE (company_id, address_key, postal_code)
+);
+
+-- =====================================================
+-- NIVEL 6D - GEOCODING_USAGE (llamadas a proveedores por día)
+-- =====================================================
+CREATE TABLE geocoding_usage (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    -- NULL = llamadas sin empresa asociada
+    company_id UUID REFERENCES companies(id) ON DELETE CASCADE,
+    provider VARCHAR(50) NOT NULL,
+    usage_date DATE NOT NULL DEFAULT CURRENT_DATE,
+    requests INTEGER NOT NULL DEFAULT 0,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT unique_geocoding_usage_per_day UNIQUE NULLS NOT DISTINCT (company_id, provider, usage_date)
+);
+
+-- =====================================================
+-- NIVEL 6E - ADDRESS_VALIDATION_JOBS (validación en segundo plano)
+-- =====================================================
+CREATE TABLE address_validation_jobs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID REFERENCES companies(id) ON DELETE CASCADE,
+    
+    -- Una tarea por société, driver y fecha: los reintentos la reutilizan
+    job_key VARCHAR(255) NOT NULL UNIQUE,
+    matricule VARCHAR(100) NOT NULL,
+    tournee_date DATE NOT NULL,
+    -- Tournée persistida cuyos paquetes reciben las coordenadas validadas
+    tournee_id UUID REFERENCES tournees(id) ON DELETE SET NULL,
+    
+    -- Progreso: pending, running, completed, failed
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    total INTEGER NOT NULL DEFAULT 0,
+    processed INTEGER NOT NULL DEFAULT 0,
+    summary JSONB,
+    error TEXT,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE TABLE address_validation_job_items (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    job_id UUID NOT NULL REFERENCES address_validation_jobs(id) ON DELETE CASCADE,
+    package_id VARCHAR(100) NOT NULL,
+    position INTEGER NOT NULL,
+    address TEXT NOT NULL,
+    
+    -- Resultado del validador y resultado final (con plausibilidad de la tournée)
+    result JSONB,
+    final_result JSONB,
+    validated_at TIMESTAMP WITH TIME ZONE,
+    
+    -- Constraints
+    CONSTRAINT unique_validation_item_per_job UNIQUE (job_id, package_id)
+);
+
+-- =====================================================
+-- NIVEL 7 - HISTÓRICO DE ESTADOS (solo inserción)
+-- =====================================================
+CREATE TABLE tournee_status_history (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id),
+    tournee_id UUID NOT NULL REFERENCES tournees(id),
+    
+    -- Transición (from_status NULL en la creación)
+    from_status tournee_status,
+    to_status tournee_status NOT NULL,
+    changed_by UUID REFERENCES users(id),
+    notes TEXT,
+    
+    -- Metadatos (clock_timestamp: ordena varias transiciones de una misma transacción)
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT clock_timestamp()
+);
+
+CREATE TABLE package_status_history (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id),
+    package_id UUID NOT NULL REFERENCES packages(id),
+    
+    -- Transición (from_status NULL en la creación)
+    from_status delivery_status,
+    to_status delivery_status NOT NULL,
+    changed_by UUID REFERENCES users(id),
+    notes TEXT,
+    
+    -- Metadatos (clock_timestamp: ordena varias transiciones de una misma transacción)
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT clock_timestamp()
+);
+
+-- =====================================================
+-- NIVEL 8 - SINCRONIZACIÓN OFFLINE (app de drivers)
+-- =====================================================
+CREATE TABLE sync_operations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    
+    -- Operación tal como la generó el cliente
+    idempotency_key VARCHAR(100) NOT NULL,
+    operation_type VARCHAR(20) NOT NULL,
+    package_id UUID REFERENCES packages(id) ON DELETE SET NULL,
+    client_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    
+    -- Resultado devuelto al cliente (se repite tal cual en cada reenvío)
+    outcome VARCHAR(20) NOT NULL,
+    result JSONB NOT NULL,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT unique_sync_operation_per_user UNIQUE (user_id, idempotency_key)
+);
+
+-- Reloj por campo para las reglas last-writer-wins
+CREATE TABLE package_field_versions (
+    package_id UUID NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+    field VARCHAR(50) NOT NULL,
+    written_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    written_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    
+    PRIMARY KEY (package_id, field)
+);
+
+-- =====================================================
+-- NIVEL 9 - POLÍTICAS DE ENTREGA FALLIDA
+-- =====================================================
+CREATE TABLE failure_policies (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    failure_reason delivery_failure_reason NOT NULL,
+    
+    -- Siguiente paso y límite de intentos (al alcanzarlo se devuelve al remitente)
+    failure_action VARCHAR(30) NOT NULL CHECK (failure_action IN ('reschedule', 'relay_point', 'return_to_sender')),
+    max_attempts INTEGER NOT NULL CHECK (max_attempts >= 1),
+    
+    -- Punto relais (solo para relay_point)
+    relay_point_name VARCHAR(255),
+    relay_point_address TEXT,
+    
+    -- Metadatos
+    updated_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT unique_failure_policy_per_reason UNIQUE (company_id, failure_reason),
+    CONSTRAINT relay_point_requires_address CHECK (failure_action <> 'relay_point' OR relay_point_address IS NOT NULL)
+);
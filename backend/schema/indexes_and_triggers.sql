@@ -6,11 +6,17 @@
 CREATE INDEX idx_companies_subscription_status ON companies(subscription_status);
 CREATE INDEX idx_companies_deleted_at ON companies(deleted_at);
 
+-- Índices para sectors
+CREATE INDEX idx_sectors_company_active ON sectors(company_id) WHERE deleted_at IS NULL;
+CREATE INDEX idx_sectors_postal_codes ON sectors USING GIN(postal_codes);
+CREATE INDEX idx_sectors_boundary ON sectors USING GIST(boundary);
+
 -- Índices para users
 CREATE INDEX idx_users_company_id ON users(company_id);
 CREATE INDEX idx_users_user_type ON users(user_type);
 CREATE INDEX idx_users_user_status ON users(user_status);
 CREATE INDEX idx_users_tournee_number ON users(tournee_number);
+CREATE INDEX idx_users_sector_id ON users(sector_id);
 CREATE INDEX idx_users_deleted_at ON users(deleted_at);
 CREATE INDEX idx_users_company_type ON users(company_id, user_type);
 CREATE INDEX idx_users_device_token ON users(device_token);
@@ -55,6 +61,7 @@ CREATE INDEX idx_tournees_status ON tournees(tournee_status);
 CREATE INDEX idx_tournees_deleted_at ON tournees(deleted_at);
 CREATE INDEX idx_tournees_driver_date ON tournees(driver_id, tournee_date);
 CREATE INDEX idx_tournees_company_date ON tournees(company_id, tournee_date);
+CREATE INDEX idx_tournees_sector_id ON tournees(sector_id);
 CREATE INDEX idx_tournees_traffic_conditions ON tournees USING GIN(traffic_conditions);
 CREATE INDEX idx_tournees_weather_conditions ON tournees USING GIN(weather_conditions);
 
@@ -67,6 +74,7 @@ CREATE INDEX idx_packages_delivery_date ON packages(delivery_date);
 CREATE INDEX idx_packages_deleted_at ON packages(deleted_at);
 CREATE INDEX idx_packages_delivery_coordinates ON packages USING GIST(delivery_coordinates);
 CREATE INDEX idx_packages_company_status_date ON packages(company_id, delivery_status, delivery_date);
+CREATE INDEX idx_packages_tournee_sequence ON packages(tournee_id, delivery_sequence);
 
 -- Índices para driver_field_data
 CREATE INDEX idx_driver_field_data_company_id ON driver_field_data(company_id);
@@ -103,6 +111,36 @@ CREATE INDEX idx_sync_log_sync_type ON sync_log(sync_type);
 CREATE INDEX idx_sync_log_errors_count ON sync_log(errors_count);
 CREATE INDEX idx_sync_log_company_date ON sync_log(company_id, sync_date);
 
+-- Índices para geocoding_cache
+CREATE INDEX idx_geocoding_cache_expires_at ON geocoding_cache(expires_at);
+CREATE INDEX idx_geocoding_cache_provider ON geocoding_cache(provider);
+
+-- Índices para ban_addresses
+CREATE INDEX idx_ban_addresses_street ON ban_addresses(code_postal, nom_voie_normalized, numero);
+CREATE INDEX idx_ban_addresses_location ON ban_addresses USING GIST(location);
+
+-- Índices para address_corrections
+CREATE INDEX idx_address_corrections_company_active ON address_corrections(company_id) WHERE revoked_at IS NULL;
+
+-- Índices para geocoding_usage
+CREATE INDEX idx_geocoding_usage_provider_date ON geocoding_usage(provider, usage_date);
+
+-- Índices para address_validation_jobs
+CREATE INDEX idx_address_validation_jobs_company_date ON address_validation_jobs(company_id, tournee_date);
+CREATE INDEX idx_address_validation_job_items_job ON address_validation_job_items(job_id, position);
+
+-- Índices para el histórico de estados
+CREATE INDEX idx_tournee_status_history_tournee ON tournee_status_history(tournee_id, created_at);
+CREATE INDEX idx_package_status_history_package ON package_status_history(package_id, created_at);
+
+-- Índices para la sincronización offline (delta desde el cursor del cliente)
+CREATE INDEX idx_tournees_driver_updated ON tournees(driver_id, updated_at);
+CREATE INDEX idx_packages_tournee_updated ON packages(tournee_id, updated_at);
+
+-- Índices para los reintentos de entregas fallidas
+CREATE UNIQUE INDEX idx_packages_carried_over_from ON packages(carried_over_from) WHERE carried_over_from IS NOT NULL;
+CREATE INDEX idx_packages_max_attempts ON packages(company_id, max_attempts_reached_at) WHERE max_attempts_reached_at IS NOT NULL;
+
 -- =====================================================
 -- FUNCIONES Y TRIGGERS AUTOMÁTICOS
 -- =====================================================
@@ -152,6 +190,14 @@ BEGIN
 END;
 $$ language 'plpgsql';
 
+-- Función para impedir cambios en tablas de solo inserción
+CREATE OR REPLACE FUNCTION prevent_history_changes()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION '% es de solo inserción', TG_TABLE_NAME;
+END;
+$$ language 'plpgsql';
+
 -- =====================================================
 -- TRIGGERS
 -- =====================================================
@@ -166,6 +212,9 @@ CREATE TRIGGER update_users_updated_at BEFORE UPDATE ON users
 CREATE TRIGGER update_vehicles_updated_at BEFORE UPDATE ON vehicles
     FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 
+CREATE TRIGGER update_sectors_updated_at BEFORE UPDATE ON sectors
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
 CREATE TRIGGER update_api_integrations_updated_at BEFORE UPDATE ON api_integrations
     FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 
@@ -187,6 +236,9 @@ CREATE TRIGGER update_driver_field_data_updated_at BEFORE UPDATE ON driver_field
 CREATE TRIGGER update_performance_analytics_updated_at BEFORE UPDATE ON performance_analytics
     FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 
+CREATE TRIGGER update_failure_policies_updated_at BEFORE UPDATE ON failure_policies
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
 -- Trigger para calcular distancia de tournée
 CREATE TRIGGER calculate_tournee_distance_trigger
     BEFORE INSERT OR UPDATE ON tournees
@@ -197,3 +249,11 @@ CREATE TRIGGER update_document_status_trigger
     BEFORE INSERT OR UPDATE ON vehicle_documents
     FOR EACH ROW EXECUTE FUNCTION update_document_status();
 
+-- Triggers para que el histórico de estados sea de solo inserción
+CREATE TRIGGER tournee_status_history_append_only
+    BEFORE UPDATE OR DELETE ON tournee_status_history
+    FOR EACH ROW EXECUTE FUNCTION prevent_history_changes();
+
+CREATE TRIGGER package_status_history_append_only
+    BEFORE UPDATE OR DELETE ON package_status_history
+    FOR EACH ROW EXECUTE FUNCTION prevent_history_changes();
@@ -0,0 +1,34 @@
+#!/bin/bash
+
+# 📥 IMPORTAR LA BASE ADRESSE NATIONALE (BAN) PARA GEOCODING LOCAL
+# Descarga el extracto de un département y lo carga en la tabla ban_addresses
+# Uso: ./scripts/import_ban.sh 75 [directorio_de_descarga]
+
+set -e
+
+DEPARTEMENT="${1:-75}"
+DATA_DIR="${2:-./data/ban}"
+BAN_URL="https://adresse.data.gouv.fr/data/ban/adresses/latest/csv/adresses-${DEPARTEMENT}.csv.gz"
+CSV_FILE="$DATA_DIR/adresses-${DEPARTEMENT}.csv"
+
+echo "🗺️ Importando BAN del département $DEPARTEMENT..."
+
+mkdir -p "$DATA_DIR"
+
+echo "📡 Descargando $BAN_URL..."
+if ! curl -fsSL "$BAN_URL" -o "$CSV_FILE.gz"; then
+    echo "❌ Error descargando el extracto BAN"
+    exit 1
+fi
+
+echo "📦 Descomprimiendo..."
+gunzip -f "$CSV_FILE.gz"
+
+echo "💾 Cargando en PostgreSQL (DATABASE_URL)..."
+if [ -x ./target/release/delivery-optimizer ]; then
+    ./target/release/delivery-optimizer import-ban "$CSV_FILE"
+else
+    cargo run --release -- import-ban "$CSV_FILE"
+fi
+
+echo "✅ BAN importada: las direcciones del $DEPARTEMENT se geocodifican sin red"
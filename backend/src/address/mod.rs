@@ -0,0 +1,8 @@
+//! Direcciones francesas
+//!
+//! Utilidades puras (sin red ni base de datos) para descomponer y normalizar
+//! las direcciones que llegan de Colis Privé.
+
+pub mod parser;
+
+pub use parser::parse_address;
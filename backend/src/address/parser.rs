@@ -0,0 +1,582 @@
+//! Parser de direcciones francesas
+//!
+//! Descompone una línea de dirección tal como llega de Colis Privé en número,
+//! indicativo de repetición, tipo y nombre de vía, complemento (bâtiment,
+//! escalier, étage...), código postal, comuna y arrondissement. Es puro: sin
+//! red ni base de datos, así que se prueba con direcciones reales.
+
+use serde::{Deserialize, Serialize};
+
+/// Tipos de vía: forma completa y abreviaturas habituales
+const STREET_TYPES: &[(&str, &[&str])] = &[
+    ("RUE", &[]),
+    ("AVENUE", &["AV", "AVE"]),
+    ("BOULEVARD", &["BD", "BLD", "BLVD", "BOUL"]),
+    ("PLACE", &["PL"]),
+    ("IMPASSE", &["IMP"]),
+    ("ALLEE", &["ALL"]),
+    ("CHEMIN", &["CHE", "CHEM"]),
+    ("ROUTE", &["RTE"]),
+    ("PASSAGE", &["PASS", "PSGE"]),
+    ("SQUARE", &["SQ"]),
+    ("QUAI", &[]),
+    ("ESPLANADE", &["ESP"]),
+    ("COURS", &["CRS"]),
+    ("VILLA", &[]),
+    ("RESIDENCE", &["RES", "RESID"]),
+    ("LOTISSEMENT", &["LOT"]),
+    ("FAUBOURG", &["FG", "FBG"]),
+    ("SENTIER", &["SENT"]),
+    ("PROMENADE", &["PROM"]),
+    ("HAMEAU", &["HAM"]),
+    ("LIEU-DIT", &["LD"]),
+    ("CITE", &[]),
+    ("PARC", &[]),
+    ("ZONE", &[]),
+    ("VOIE", &[]),
+    ("RUELLE", &[]),
+    ("ROND-POINT", &["RPT"]),
+];
+
+/// Abreviaturas dentro del nombre de la vía
+const NAME_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("ST", "SAINT"),
+    ("STE", "SAINTE"),
+    ("GAL", "GENERAL"),
+    ("MAL", "MARECHAL"),
+    ("DR", "DOCTEUR"),
+    ("PDT", "PRESIDENT"),
+];
+
+/// Palabras que abren un complemento: forma completa y abreviaturas
+const COMPLEMENT_KEYWORDS: &[(&str, &[&str])] = &[
+    ("BATIMENT", &["BAT", "BATI", "BT"]),
+    ("ESCALIER", &["ESC"]),
+    ("ETAGE", &["ETG"]),
+    ("APPARTEMENT", &["APT", "APPT", "APP"]),
+    ("PORTE", &["PTE"]),
+    ("LOGEMENT", &["LGT", "LOG"]),
+];
+
+/// Indicativos de repetición (16 BIS, 94BIS, 16B...)
+const REPETITIONS: &[&str] = &["BIS", "TER", "QUATER", "A", "B", "C", "D"];
+
+/// Dirección descompuesta
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParsedAddress {
+    pub number: Option<u32>,
+    pub repetition: Option<String>,
+    /// Forma completa: `IMP` → `IMPASSE`
+    pub street_type: Option<String>,
+    pub street_name: Option<String>,
+    /// `BATIMENT B ESCALIER 2 ETAGE 3`
+    pub complement: Option<String>,
+    pub postal_code: Option<String>,
+    pub commune: Option<String>,
+    /// Paris, Lyon y Marseille
+    pub arrondissement: Option<u8>,
+    /// Correcciones aplicadas (números duplicados, texto descartado...)
+    pub warnings: Vec<String>,
+}
+
+impl ParsedAddress {
+    /// `RUE JEAN COTTIN`
+    pub fn street(&self) -> Option<String> {
+        match (&self.street_type, &self.street_name) {
+            (Some(street_type), Some(name)) => Some(format!("{} {}", street_type, name)),
+            (Some(street_type), None) => Some(street_type.clone()),
+            (None, Some(name)) => Some(name.clone()),
+            (None, None) => None,
+        }
+    }
+
+    /// `16 BIS RUE JEAN COTTIN`
+    pub fn street_line(&self) -> Option<String> {
+        let street = self.street()?;
+        Some(match (self.number, &self.repetition) {
+            (Some(number), Some(repetition)) => format!("{} {} {}", number, repetition, street),
+            (Some(number), None) => format!("{} {}", number, street),
+            _ => street,
+        })
+    }
+
+    /// `75018 PARIS`
+    pub fn locality(&self) -> Option<String> {
+        match (&self.postal_code, &self.commune) {
+            (Some(postal_code), Some(commune)) => Some(format!("{} {}", postal_code, commune)),
+            (Some(postal_code), None) => Some(postal_code.clone()),
+            (None, Some(commune)) => Some(commune.clone()),
+            (None, None) => None,
+        }
+    }
+
+    /// Línea para el geocoder, sin complemento: `16 BIS RUE JEAN COTTIN, 75018 PARIS`
+    pub fn to_query(&self) -> String {
+        match (self.street_line(), self.locality()) {
+            (Some(street), Some(locality)) => format!("{}, {}", street, locality),
+            (Some(street), None) => street,
+            (None, Some(locality)) => match self.number {
+                Some(number) => format!("{}, {}", number, locality),
+                None => locality,
+            },
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Hay número o código postal pero no calle (`75, 75018 PARIS`)
+    pub fn is_missing_street(&self) -> bool {
+        self.street().is_none() && (self.number.is_some() || self.postal_code.is_some())
+    }
+}
+
+/// Mayúsculas sin acentos
+pub fn fold_accents(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_uppercase) {
+        match c {
+            'À' | 'Â' | 'Ä' => folded.push('A'),
+            'Ç' => folded.push('C'),
+            'É' | 'È' | 'Ê' | 'Ë' => folded.push('E'),
+            'Î' | 'Ï' => folded.push('I'),
+            'Ô' | 'Ö' => folded.push('O'),
+            'Ù' | 'Û' | 'Ü' => folded.push('U'),
+            'Ÿ' => folded.push('Y'),
+            'Œ' => folded.push_str("OE"),
+            'Æ' => folded.push_str("AE"),
+            c => folded.push(c),
+        }
+    }
+    folded
+}
+
+/// Forma completa de un tipo de vía (`BD` → `BOULEVARD`); `None` si no lo es
+pub fn street_type(token: &str) -> Option<&'static str> {
+    STREET_TYPES
+        .iter()
+        .find(|(full, abbreviations)| *full == token || abbreviations.contains(&token))
+        .map(|(full, _)| *full)
+}
+
+/// Expandir una abreviatura de vía o de nombre (`IMP` → `IMPASSE`, `ST` → `SAINT`)
+pub fn expand_abbreviation(token: &str) -> Option<&'static str> {
+    street_type(token).or_else(|| {
+        NAME_ABBREVIATIONS
+            .iter()
+            .find(|(abbreviation, _)| *abbreviation == token)
+            .map(|(_, full)| *full)
+    })
+}
+
+fn complement_keyword(token: &str) -> Option<&'static str> {
+    COMPLEMENT_KEYWORDS
+        .iter()
+        .find(|(full, abbreviations)| *full == token || abbreviations.contains(&token))
+        .map(|(full, _)| *full)
+}
+
+/// Token de la dirección: texto en mayúsculas y clave sin acentos ni puntos
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    key: String,
+}
+
+impl Token {
+    fn is_comma(&self) -> bool {
+        self.key == ","
+    }
+}
+
+fn tokenize(raw: &str) -> Vec<Token> {
+    raw.replace([',', ';'], " , ")
+        .split_whitespace()
+        .filter_map(|word| {
+            let text = word
+                .trim_matches(|c: char| matches!(c, '.' | '(' | ')' | '"' | '\'' | '/'))
+                .to_uppercase();
+            (!text.is_empty()).then(|| Token { key: fold_accents(&text), text })
+        })
+        .collect()
+}
+
+/// `16`, `16B`, `94BIS`, `16-18` → número e indicativo
+fn parse_number(key: &str) -> Option<(u32, Option<String>)> {
+    let digits_end = key.find(|c: char| !c.is_ascii_digit()).unwrap_or(key.len());
+    if digits_end == 0 || digits_end > 4 {
+        return None;
+    }
+    let number = key[..digits_end].parse().ok()?;
+    let suffix = &key[digits_end..];
+
+    if suffix.is_empty() {
+        Some((number, None))
+    } else if REPETITIONS.contains(&suffix) {
+        Some((number, Some(suffix.to_string())))
+    } else if suffix.strip_prefix('-').is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit())) {
+        Some((number, None)) // rango "16-18": el primero
+    } else {
+        None
+    }
+}
+
+/// `18E`, `18EME`, `1ER` → 18, 1
+fn parse_ordinal(key: &str) -> Option<u8> {
+    let digits_end = key.find(|c: char| !c.is_ascii_digit())?;
+    let suffix = &key[digits_end..];
+    if digits_end == 0 || !matches!(suffix, "E" | "EME" | "ER" | "ERE") {
+        return None;
+    }
+    key[..digits_end].parse().ok()
+}
+
+/// Arrondissement implícito en el código postal
+pub fn arrondissement_from_postal_code(postal_code: &str) -> Option<u8> {
+    let code: u32 = postal_code.parse().ok()?;
+    match code {
+        75001..=75020 => Some((code - 75000) as u8),
+        75116 => Some(16),
+        69001..=69009 => Some((code - 69000) as u8),
+        13001..=13016 => Some((code - 13000) as u8),
+        _ => None,
+    }
+}
+
+fn join(tokens: &[Token]) -> Option<String> {
+    let words: Vec<&str> = tokens.iter().filter(|t| !t.is_comma()).map(|t| t.text.as_str()).collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// Descomponer una línea de dirección
+pub fn parse_address(raw: &str) -> ParsedAddress {
+    let mut parsed = ParsedAddress::default();
+    let mut tokens = tokenize(raw);
+
+    if tokens.last().is_some_and(|t| t.key == "FRANCE") {
+        tokens.pop();
+    }
+
+    extract_locality(&mut tokens, &mut parsed);
+    extract_arrondissement(&mut tokens, &mut parsed);
+    extract_complement(&mut tokens, &mut parsed);
+    extract_street(&tokens, &mut parsed);
+
+    if parsed.arrondissement.is_none() {
+        parsed.arrondissement = parsed.postal_code.as_deref().and_then(arrondissement_from_postal_code);
+    }
+
+    parsed
+}
+
+/// Código postal (el último) y comuna: lo que le sigue o, si no hay nada,
+/// el segmento sin números que le precede (`PARIS 75018`)
+fn extract_locality(tokens: &mut Vec<Token>, parsed: &mut ParsedAddress) {
+    let Some(index) = tokens
+        .iter()
+        .rposition(|t| t.key.len() == 5 && t.key.chars().all(|c| c.is_ascii_digit()))
+    else {
+        return;
+    };
+
+    let mut tail = tokens.drain(index..);
+    parsed.postal_code = tail.next().map(|t| t.key);
+    let mut commune: Vec<Token> = tail.filter(|t| !t.is_comma()).collect();
+
+    while tokens.last().is_some_and(Token::is_comma) {
+        tokens.pop();
+    }
+
+    if commune.is_empty() {
+        let segment_start = tokens.iter().rposition(Token::is_comma).map_or(0, |i| i + 1);
+        let segment = &tokens[segment_start..];
+        if segment_start > 0
+            && !segment.is_empty()
+            && segment.iter().all(|t| !t.key.chars().any(|c| c.is_ascii_digit()) && street_type(&t.key).is_none())
+        {
+            commune = tokens.drain(segment_start..).collect();
+            while tokens.last().is_some_and(Token::is_comma) {
+                tokens.pop();
+            }
+        }
+    }
+
+    // "PARIS 18" / "PARIS 18E"
+    if commune.len() > 1 {
+        let last = &commune[commune.len() - 1].key;
+        let arrondissement = parse_ordinal(last).or_else(|| last.parse().ok().filter(|n| (1..=20).contains(n)));
+        if arrondissement.is_some() {
+            parsed.arrondissement = arrondissement;
+            commune.pop();
+        }
+    }
+
+    parsed.commune = join(&commune);
+}
+
+/// `18EME ARRONDISSEMENT`, `18E ARR`: se guarda y se quita de la línea
+fn extract_arrondissement(tokens: &mut Vec<Token>, parsed: &mut ParsedAddress) {
+    let found = tokens.windows(2).position(|pair| {
+        parse_ordinal(&pair[0].key).is_some() && matches!(pair[1].key.as_str(), "ARRONDISSEMENT" | "ARR" | "ARRDT")
+    });
+
+    if let Some(index) = found {
+        let removed: Vec<Token> = tokens.drain(index..index + 2).collect();
+        parsed.arrondissement = parse_ordinal(&removed[0].key);
+        parsed.warnings.push(format!("Arrondissement quitado de la calle: {} {}", removed[0].text, removed[1].text));
+    }
+}
+
+/// `BAT B`, `ESC 2`, `3EME ETAGE`, `APPT 12`, `RDC`: fuera de la línea de calle
+fn extract_complement(tokens: &mut Vec<Token>, parsed: &mut ParsedAddress) {
+    let mut parts = Vec::new();
+    let mut kept = Vec::with_capacity(tokens.len());
+    let mut iter = std::mem::take(tokens).into_iter().peekable();
+
+    while let Some(token) = iter.next() {
+        if token.key == "RDC" {
+            parts.push("REZ-DE-CHAUSSEE".to_string());
+            continue;
+        }
+
+        // "3EME ETAGE"
+        if let Some(floor) = parse_ordinal(&token.key) {
+            if iter.peek().is_some_and(|next| complement_keyword(&next.key) == Some("ETAGE")) {
+                iter.next();
+                parts.push(format!("ETAGE {}", floor));
+                continue;
+            }
+        }
+
+        if let Some(keyword) = complement_keyword(&token.key) {
+            let value = iter
+                .next_if(|next| !next.is_comma() && next.key.len() <= 4 && street_type(&next.key).is_none())
+                .map(|next| parse_ordinal(&next.key).map_or(next.text, |n| n.to_string()));
+            parts.push(match value {
+                Some(value) => format!("{} {}", keyword, value),
+                None => keyword.to_string(),
+            });
+            continue;
+        }
+
+        kept.push(token);
+    }
+
+    *tokens = kept;
+    if !parts.is_empty() {
+        parsed.complement = Some(parts.join(" "));
+    }
+}
+
+/// Número, tipo y nombre de vía. Lo que precede al número que no es un número
+/// (nombre del destinatario) se descarta; los segmentos sobrantes van al complemento.
+fn extract_street(tokens: &[Token], parsed: &mut ParsedAddress) {
+    let mut segments = tokens.split(Token::is_comma).filter(|segment| !segment.is_empty());
+    let Some(mut segment) = segments.next() else {
+        return;
+    };
+
+    // "75, RUE X": el número solo en su segmento
+    let mut numbers: Vec<(u32, Option<String>)> = Vec::new();
+    if let [token] = segment {
+        if let Some(number) = parse_number(&token.key) {
+            numbers.push(number);
+            segment = segments.next().unwrap_or_default();
+        }
+    }
+
+    let split_at = segment
+        .iter()
+        .position(|t| street_type(&t.key).is_some())
+        .or_else(|| segment.iter().position(|t| parse_number(&t.key).is_none()))
+        .unwrap_or(segment.len());
+    let (before, mut street) = segment.split_at(split_at);
+
+    // Antes de la vía: números ("6 7", "16 BIS") y texto a descartar ("MARTIN")
+    let mut discarded = Vec::new();
+    for (i, token) in before.iter().enumerate() {
+        if let Some(number) = parse_number(&token.key) {
+            numbers.push(number);
+        } else if i > 0 && REPETITIONS.contains(&token.key.as_str()) && parse_number(&before[i - 1].key).is_some() {
+            if let Some(last) = numbers.last_mut() {
+                last.1.get_or_insert_with(|| token.key.clone());
+            }
+        } else {
+            discarded.push(token.text.as_str());
+        }
+    }
+    if !discarded.is_empty() {
+        parsed.warnings.push(format!("Texto antes de la calle descartado: {}", discarded.join(" ")));
+    }
+
+    if let Some(first) = street.first() {
+        if let Some(full) = street_type(&first.key) {
+            parsed.street_type = Some(full.to_string());
+            street = &street[1..];
+        }
+    }
+
+    // "RUE JEAN COTTIN 3": número al final
+    if numbers.is_empty() && street.len() > 1 {
+        if let Some(number) = street.last().and_then(|t| parse_number(&t.key)) {
+            numbers.push(number);
+            street = &street[..street.len() - 1];
+            parsed.warnings.push("Número al final de la calle movido delante".to_string());
+        }
+    }
+
+    // "35 35" → 35; "6 7" → 7
+    if let Some((number, repetition)) = numbers.last().cloned() {
+        let mut distinct: Vec<u32> = numbers.iter().map(|n| n.0).collect();
+        distinct.dedup();
+        if distinct.len() > 1 {
+            let listed: Vec<String> = distinct.iter().map(u32::to_string).collect();
+            parsed.warnings.push(format!("Números separados ({}), se toma el último: {}", listed.join(" "), number));
+        } else if numbers.len() > 1 {
+            parsed.warnings.push(format!("Número duplicado corregido: {}", number));
+        }
+        parsed.number = Some(number);
+        parsed.repetition = repetition;
+    }
+
+    let name: Vec<String> = street
+        .iter()
+        .map(|t| {
+            NAME_ABBREVIATIONS
+                .iter()
+                .find(|(abbreviation, _)| *abbreviation == t.key)
+                .map_or_else(|| t.text.clone(), |(_, full)| full.to_string())
+        })
+        .collect();
+    if !name.is_empty() {
+        parsed.street_name = Some(name.join(" "));
+    }
+
+    // Segmentos sobrantes ("LES JARDINS") al complemento
+    let rest: Vec<String> = segments.filter_map(join).collect();
+    if !rest.is_empty() {
+        parsed.complement = Some(match parsed.complement.take() {
+            Some(complement) => format!("{} {}", rest.join(" "), complement),
+            None => rest.join(" "),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (entrada, número, repetición, vía, complemento, código postal, comuna, arrondissement)
+    type Case = (
+        &'static str,
+        Option<u32>,
+        Option<&'static str>,
+        Option<&'static str>,
+        Option<&'static str>,
+        Option<&'static str>,
+        Option<&'static str>,
+        Option<u8>,
+    );
+
+    #[test]
+    fn test_parse_colis_prive_samples() {
+        let cases: &[Case] = &[
+            ("16 RUE JEAN COTTIN, 75018 PARIS", Some(16), None, Some("RUE JEAN COTTIN"), None, Some("75018"), Some("PARIS"), Some(18)),
+            ("16 RUE JEAN COTTIN 75018 PARIS", Some(16), None, Some("RUE JEAN COTTIN"), None, Some("75018"), Some("PARIS"), Some(18)),
+            ("94BIS RUE RIQUET, 75018 PARIS", Some(94), Some("BIS"), Some("RUE RIQUET"), None, Some("75018"), Some("PARIS"), Some(18)),
+            ("16 bis rue Jean Cottin 75018 Paris", Some(16), Some("BIS"), Some("RUE JEAN COTTIN"), None, Some("75018"), Some("PARIS"), Some(18)),
+            ("16B RUE JEAN COTTIN 75018 PARIS", Some(16), Some("B"), Some("RUE JEAN COTTIN"), None, Some("75018"), Some("PARIS"), Some(18)),
+            ("35 35 RUE MARC SEGUIN", Some(35), None, Some("RUE MARC SEGUIN"), None, None, None, None),
+            ("6 7 IMP. DU CURE", Some(7), None, Some("IMPASSE DU CURE"), None, None, None, None),
+            ("3 BD DE L HOPITAL, 75013 PARIS", Some(3), None, Some("BOULEVARD DE L HOPITAL"), None, Some("75013"), Some("PARIS"), Some(13)),
+            ("12 AV EMILE ZOLA 75015 PARIS", Some(12), None, Some("AVENUE EMILE ZOLA"), None, Some("75015"), Some("PARIS"), Some(15)),
+            ("1 RUE ST ELOI", Some(1), None, Some("RUE SAINT ELOI"), None, None, None, None),
+            ("16 RUE JEAN COTTIN 18EME ARRONDISSEMENT", Some(16), None, Some("RUE JEAN COTTIN"), None, None, None, Some(18)),
+            ("Rue Jean Cottin 3", Some(3), None, Some("RUE JEAN COTTIN"), None, None, None, None),
+            ("MARTIN 15 Rue de la Paix, 75001 Paris", Some(15), None, Some("RUE DE LA PAIX"), None, Some("75001"), Some("PARIS"), Some(1)),
+            ("MARTIN Rue de la République, 75001 Paris", None, None, Some("RUE DE LA RÉPUBLIQUE"), None, Some("75001"), Some("PARIS"), Some(1)),
+            (
+                "30 RUE MARX DORMOY BAT B ESC 2 3EME ETAGE, 75018 PARIS",
+                Some(30), None, Some("RUE MARX DORMOY"), Some("BATIMENT B ESCALIER 2 ETAGE 3"), Some("75018"), Some("PARIS"), Some(18),
+            ),
+            (
+                "12 RUE DES ROSIERS, APPT 12, 93400 SAINT-OUEN",
+                Some(12), None, Some("RUE DES ROSIERS"), Some("APPARTEMENT 12"), Some("93400"), Some("SAINT-OUEN"), None,
+            ),
+            (
+                "8 ALL. DES TILLEULS, RESIDENCE LES JARDINS, 69003 LYON",
+                Some(8), None, Some("ALLEE DES TILLEULS"), Some("RESIDENCE LES JARDINS"), Some("69003"), Some("LYON"), Some(3),
+            ),
+            ("3 RUE ORDENER, PARIS 75018", Some(3), None, Some("RUE ORDENER"), None, Some("75018"), Some("PARIS"), Some(18)),
+            ("3 RUE ORDENER 75018 PARIS 18", Some(3), None, Some("RUE ORDENER"), None, Some("75018"), Some("PARIS"), Some(18)),
+            ("16 RUE JEAN COTTIN, 75018 PARIS, FRANCE", Some(16), None, Some("RUE JEAN COTTIN"), None, Some("75018"), Some("PARIS"), Some(18)),
+            ("75, 75018 PARIS", Some(75), None, None, None, Some("75018"), Some("PARIS"), Some(18)),
+            ("LE BOURG, 23200 AUBUSSON", None, None, Some("LE BOURG"), None, Some("23200"), Some("AUBUSSON"), None),
+        ];
+
+        for &(input, number, repetition, street, complement, postal_code, commune, arrondissement) in cases {
+            let parsed = parse_address(input);
+            assert_eq!(parsed.number, number, "número: {}", input);
+            assert_eq!(parsed.repetition.as_deref(), repetition, "repetición: {}", input);
+            assert_eq!(parsed.street().as_deref(), street, "vía: {}", input);
+            assert_eq!(parsed.complement.as_deref(), complement, "complemento: {}", input);
+            assert_eq!(parsed.postal_code.as_deref(), postal_code, "código postal: {}", input);
+            assert_eq!(parsed.commune.as_deref(), commune, "comuna: {}", input);
+            assert_eq!(parsed.arrondissement, arrondissement, "arrondissement: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_to_query() {
+        let cases = [
+            ("16 bis rue Jean Cottin 75018 Paris", "16 BIS RUE JEAN COTTIN, 75018 PARIS"),
+            ("MARTIN 15 Rue de la Paix, 75001 Paris", "15 RUE DE LA PAIX, 75001 PARIS"),
+            ("30 RUE MARX DORMOY BAT B, 75018 PARIS", "30 RUE MARX DORMOY, 75018 PARIS"),
+            ("6 7 IMP. DU CURE", "7 IMPASSE DU CURE"),
+            ("75, 75018 PARIS", "75, 75018 PARIS"),
+            ("", ""),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(parse_address(input).to_query(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_warnings() {
+        let cases = [
+            ("35 35 RUE MARC SEGUIN", "Número duplicado corregido: 35"),
+            ("6 7 IMP. DU CURE", "Números separados (6 7), se toma el último: 7"),
+            ("MARTIN 15 Rue de la Paix", "Texto antes de la calle descartado: MARTIN"),
+            ("Rue Jean Cottin 3", "Número al final de la calle movido delante"),
+        ];
+
+        for (input, warning) in cases {
+            assert!(parse_address(input).warnings.iter().any(|w| w == warning), "{}", input);
+        }
+        assert!(parse_address("16 RUE JEAN COTTIN, 75018 PARIS").warnings.is_empty());
+    }
+
+    #[test]
+    fn test_is_missing_street() {
+        assert!(parse_address("75, 75018 PARIS").is_missing_street());
+        assert!(!parse_address("16 RUE JEAN COTTIN, 75018 PARIS").is_missing_street());
+        assert!(!parse_address("").is_missing_street());
+    }
+
+    #[test]
+    fn test_expand_abbreviation() {
+        assert_eq!(expand_abbreviation("IMP"), Some("IMPASSE"));
+        assert_eq!(expand_abbreviation("BD"), Some("BOULEVARD"));
+        assert_eq!(expand_abbreviation("AV"), Some("AVENUE"));
+        assert_eq!(expand_abbreviation("ST"), Some("SAINT"));
+        assert_eq!(expand_abbreviation("COTTIN"), None);
+    }
+
+    #[test]
+    fn test_arrondissement_from_postal_code() {
+        assert_eq!(arrondissement_from_postal_code("75018"), Some(18));
+        assert_eq!(arrondissement_from_postal_code("75116"), Some(16));
+        assert_eq!(arrondissement_from_postal_code("69003"), Some(3));
+        assert_eq!(arrondissement_from_postal_code("13016"), Some(16));
+        assert_eq!(arrondissement_from_postal_code("93400"), None);
+    }
+}
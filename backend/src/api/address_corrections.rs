@@ -0,0 +1,148 @@
+//! Correcciones de direcciones por los drivers
+//!
+//! Un driver confirma la posición real de una dirección (opcionalmente de un
+//! paquete concreto); la validación de direcciones la usa en las siguientes
+//! tournées. Los admins pueden listarlas y revocarlas.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::services::address_corrections::{AddressCorrection, ConfirmedAddress, CorrectionKey};
+use crate::state::AppState;
+use crate::utils::errors::{AppError, AppResult};
+use crate::utils::jwt::{require_admin, require_user, JwtClaims, JwtConfig};
+
+/// Request para confirmar la posición de una dirección
+#[derive(Debug, Deserialize)]
+pub struct ConfirmCorrectionRequest {
+    /// Dirección de Colis Privé; si se omite se usa la del paquete
+    pub address: Option<String>,
+    /// Paquete cuya posición se corrige
+    pub package_id: Option<Uuid>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub formatted_address: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListCorrectionsQuery {
+    #[serde(default)]
+    pub include_revoked: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevokeCorrectionResponse {
+    pub id: Uuid,
+    pub revoked: bool,
+}
+
+fn company_and_user(claims: &JwtClaims) -> AppResult<(Uuid, Option<Uuid>)> {
+    let company_id = Uuid::parse_str(&claims.company_id)
+        .map_err(|_| AppError::Unauthorized("company_id inválido en el token".to_string()))?;
+    Ok((company_id, Uuid::parse_str(&claims.sub).ok()))
+}
+
+/// POST /api/address-corrections - Confirmar la posición de una dirección
+pub async fn confirm_correction(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ConfirmCorrectionRequest>,
+) -> AppResult<Json<AddressCorrection>> {
+    let claims = require_user(&headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    let (company_id, driver_id) = company_and_user(&claims)?;
+
+    if !(-90.0..=90.0).contains(&request.latitude) || !(-180.0..=180.0).contains(&request.longitude) {
+        return Err(AppError::BadRequest("Coordenadas fuera de rango".to_string()));
+    }
+
+    let mut address = request.address.filter(|a| !a.trim().is_empty());
+    let mut tx = state.pool.begin().await?;
+
+    // Tomar la dirección del paquete si no se indicó otra
+    if let Some(package_id) = request.package_id {
+        let package_address: Option<String> = sqlx::query_scalar(
+            r#"
+            SELECT delivery_address FROM packages
+            WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+            FOR UPDATE
+            "#,
+        )
+        .bind(package_id)
+        .bind(company_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let package_address = package_address
+            .ok_or_else(|| AppError::NotFound(format!("Paquete {} no encontrado", package_id)))?;
+        address.get_or_insert(package_address);
+    }
+
+    let address = address.ok_or_else(|| AppError::BadRequest("address o package_id es obligatorio".to_string()))?;
+    // Validar la dirección antes de escribir nada
+    let key = CorrectionKey::from_address(&address)
+        .ok_or_else(|| AppError::BadRequest(format!("Dirección sin código postal: '{}'", address)))?;
+
+    // Corregir también el paquete, en la misma transacción que la corrección
+    if let Some(package_id) = request.package_id {
+        sqlx::query(
+            r#"
+            UPDATE packages
+            SET delivery_coordinates = point($1::float8, $2::float8), coordinates_confirmed_at = NOW(), updated_at = NOW()
+            WHERE id = $3
+            "#,
+        )
+        .bind(request.longitude)
+        .bind(request.latitude)
+        .bind(package_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let correction = state.address_corrections
+        .confirm(&mut tx, company_id, driver_id, &ConfirmedAddress {
+            key,
+            address: &address,
+            latitude: request.latitude,
+            longitude: request.longitude,
+            formatted_address: request.formatted_address.as_deref(),
+        })
+        .await?;
+    tx.commit().await?;
+
+    log::info!("📍 Corrección de '{}' confirmada ({} veces)", address, correction.confirmation_count);
+    Ok(Json(correction))
+}
+
+/// GET /api/admin/address-corrections - Correcciones de la empresa
+pub async fn list_corrections(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ListCorrectionsQuery>,
+) -> AppResult<Json<Vec<AddressCorrection>>> {
+    let claims = require_admin(&headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    let (company_id, _) = company_and_user(&claims)?;
+
+    Ok(Json(state.address_corrections.list(company_id, query.include_revoked).await?))
+}
+
+/// DELETE /api/admin/address-corrections/:id - Revocar una corrección
+pub async fn revoke_correction(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<RevokeCorrectionResponse>> {
+    let claims = require_admin(&headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    let (company_id, admin_id) = company_and_user(&claims)?;
+
+    if !state.address_corrections.revoke(company_id, id, admin_id).await? {
+        return Err(AppError::NotFound(format!("Corrección {} no encontrada o ya revocada", id)));
+    }
+
+    log::info!("🗑️ Corrección {} revocada", id);
+    Ok(Json(RevokeCorrectionResponse { id, revoked: true }))
+}
@@ -6,102 +6,90 @@ use axum::{
     extract::{Query, State},
     Json,
 };
-use sqlx::PgPool;
-use validator::Validate;
+use chrono::NaiveDate;
+use uuid::Uuid;
 
 use crate::{
-    models::analytics::{
-        PerformanceAnalytics, DashboardSummary, AnalyticsResponse,
-        CreateAnalyticsRequest, AnalyticsFilters,
-    },
-    utils::errors::{AppError, AppResult},
+    models::analytics::{DashboardSummary, AnalyticsResponse, AnalyticsFilters},
+    utils::errors::AppResult,
     middleware::auth::AuthenticatedUser,
+    state::FleetState,
 };
 
 /// Obtener resumen del dashboard
 pub async fn get_dashboard_summary(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
 ) -> AppResult<Json<DashboardSummary>> {
     let today = chrono::Utc::now().date_naive();
 
-    // Obtener resumen de tournées
-    let tournees_summary = sqlx::query!(
+    // Resumen de tournées, paquetes y rendimiento del día
+    let (total_tournees, completed_tournees, active_tournees) = sqlx::query_as::<_, (i64, i64, i64)>(
         r#"
-        SELECT 
-            COUNT(*) as total_tournees,
-            COUNT(CASE WHEN tournee_status = 'completed' THEN 1 END) as completed_tournees,
-            COUNT(CASE WHEN tournee_status = 'in_progress' THEN 1 END) as active_tournees
-        FROM tournees 
-        WHERE company_id = $1 
+        SELECT
+            COUNT(*),
+            COUNT(*) FILTER (WHERE tournee_status = 'completed'),
+            COUNT(*) FILTER (WHERE tournee_status = 'in_progress')
+        FROM tournees
+        WHERE company_id = $1
         AND tournee_date = $2
         AND deleted_at IS NULL
         "#,
-        user.company_id,
-        today
     )
+    .bind(user.company_id)
+    .bind(today)
     .fetch_one(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
-    // Obtener resumen de paquetes
-    let packages_summary = sqlx::query!(
+    let (total_packages, delivered_packages, failed_packages) = sqlx::query_as::<_, (i64, i64, i64)>(
         r#"
-        SELECT 
-            COUNT(*) as total_packages,
-            COUNT(CASE WHEN delivery_status = 'delivered' THEN 1 END) as delivered_packages,
-            COUNT(CASE WHEN delivery_status = 'failed' THEN 1 END) as failed_packages
+        SELECT
+            COUNT(*),
+            COUNT(*) FILTER (WHERE p.delivery_status = 'delivered'),
+            COUNT(*) FILTER (WHERE p.delivery_status = 'failed')
         FROM packages p
         JOIN tournees t ON p.tournee_id = t.id
-        WHERE p.company_id = $1 
+        WHERE p.company_id = $1
         AND t.tournee_date = $2
         AND p.deleted_at IS NULL
+        AND t.deleted_at IS NULL
         "#,
-        user.company_id,
-        today
     )
+    .bind(user.company_id)
+    .bind(today)
     .fetch_one(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
-    // Obtener métricas de rendimiento
-    let performance_metrics = sqlx::query!(
+    let (avg_delivery_time, avg_route_efficiency, total_distance) = sqlx::query_as::<_, (f64, f64, f64)>(
         r#"
-        SELECT 
-            AVG(actual_duration_minutes) as avg_delivery_time,
-            AVG(route_optimization_score) as avg_route_efficiency,
-            SUM(total_distance) as total_distance
-        FROM tournees 
-        WHERE company_id = $1 
+        SELECT
+            COALESCE(AVG(actual_duration_minutes), 0)::float8,
+            COALESCE(AVG(route_optimization_score), 0)::float8,
+            COALESCE(SUM(total_distance), 0)::float8
+        FROM tournees
+        WHERE company_id = $1
         AND tournee_date = $2
         AND tournee_status = 'completed'
         AND deleted_at IS NULL
         "#,
-        user.company_id,
-        today
     )
+    .bind(user.company_id)
+    .bind(today)
     .fetch_one(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     let summary = DashboardSummary {
         company_id: user.company_id,
         date: today,
-        total_tournees: tournees_summary.total_tournees.unwrap_or(0) as i32,
-        completed_tournees: tournees_summary.completed_tournees.unwrap_or(0) as i32,
-        active_tournees: tournees_summary.active_tournees.unwrap_or(0) as i32,
-        total_packages: packages_summary.total_packages.unwrap_or(0) as i32,
-        delivered_packages: packages_summary.delivered_packages.unwrap_or(0) as i32,
-        failed_packages: packages_summary.failed_packages.unwrap_or(0) as i32,
-        average_delivery_time_minutes: performance_metrics.avg_delivery_time
-            .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-            .unwrap_or(0.0),
-        average_route_efficiency: performance_metrics.avg_route_efficiency
-            .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-            .unwrap_or(0.0),
-        total_distance_km: performance_metrics.total_distance
-            .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-            .unwrap_or(0.0),
+        total_tournees: total_tournees as i32,
+        completed_tournees: completed_tournees as i32,
+        active_tournees: active_tournees as i32,
+        total_packages: total_packages as i32,
+        delivered_packages: delivered_packages as i32,
+        failed_packages: failed_packages as i32,
+        average_delivery_time_minutes: avg_delivery_time,
+        average_route_efficiency: avg_route_efficiency,
+        total_distance_km: total_distance,
         total_revenue: None,
         total_costs: None,
         profit_margin: None,
@@ -112,111 +100,108 @@ pub async fn get_dashboard_summary(
     Ok(Json(summary))
 }
 
-/// Obtener métricas de rendimiento por tournée
-pub async fn get_performance_by_tournee(
-    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
-    Query(filters): Query<AnalyticsFilters>,
-) -> AppResult<Json<Vec<AnalyticsResponse>>> {
-    let date_from = filters.date_from.unwrap_or_else(|| {
-        chrono::Utc::now().date_naive() - chrono::Duration::days(30)
-    });
-    let date_to = filters.date_to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+/// Métricas comunes a todas las vistas de rendimiento, agregadas por grupo de tournées
+const PERFORMANCE_METRICS: &str = r#"
+    SUM(COALESCE(t.actual_duration_minutes, 0))::int4 AS total_time_minutes,
+    SUM(COALESCE(t.actual_duration_minutes, 0))::int4 AS driving_time_minutes,
+    0::int4 AS waiting_time_minutes,
+    SUM(COALESCE(t.total_distance, 0))::float8 AS total_distance_km,
+    AVG(COALESCE(t.route_optimization_score, 0))::float8 AS route_efficiency,
+    COALESCE(SUM(p.delivered), 0)::int4 AS packages_delivered,
+    COALESCE(SUM(p.failed), 0)::int4 AS packages_failed,
+    (CASE
+        WHEN COALESCE(SUM(p.total), 0) > 0 THEN SUM(p.delivered)::float8 / SUM(p.total)::float8 * 100
+        ELSE 0
+    END)::float8 AS delivery_success_rate,
+    SUM(COALESCE(t.fuel_consumed, 0))::float8 AS fuel_consumed_liters,
+    (CASE
+        WHEN SUM(t.fuel_consumed) > 0 AND SUM(t.total_distance) > 0 THEN SUM(t.total_distance) / SUM(t.fuel_consumed)
+        ELSE 0
+    END)::float8 AS fuel_efficiency_km_l,
+    SUM(COALESCE(t.fuel_cost, 0)) AS total_cost,
+    CASE
+        WHEN COALESCE(SUM(p.total), 0) > 0 THEN SUM(COALESCE(t.fuel_cost, 0)) / SUM(p.total)
+        ELSE 0
+    END AS cost_per_package,
+    CASE
+        WHEN SUM(t.total_distance) > 0 THEN SUM(COALESCE(t.fuel_cost, 0)) / SUM(t.total_distance)
+        ELSE 0
+    END AS cost_per_km,
+    NULL::float8 AS customer_rating,
+    0::int4 AS complaints_count,
+    COALESCE(MIN(t.created_at), NOW()) AS created_at,
+    COALESCE(MAX(t.updated_at), NOW()) AS updated_at
+"#;
+
+/// Rendimiento de las tournées completadas de la empresa, agrupado según `keys`/`group_by`
+///
+/// Los paquetes se cuentan por tournée antes de agregar, para no multiplicar
+/// distancias y costes por el número de paquetes.
+async fn fetch_performance(
+    state: &FleetState,
+    company_id: Uuid,
+    filters: &AnalyticsFilters,
+    keys: &str,
+    group_by: &str,
+    order_by: &str,
+) -> AppResult<Vec<AnalyticsResponse>> {
+    let today = chrono::Utc::now().date_naive();
+    let date_from: NaiveDate = filters.date_from.unwrap_or(today - chrono::Duration::days(30));
+    let date_to: NaiveDate = filters.date_to.unwrap_or(today);
 
-    let rows = sqlx::query!(
+    let rows = sqlx::query_as::<_, AnalyticsResponse>(&format!(
         r#"
-        SELECT 
-            t.id,
-            t.company_id,
-            t.id as tournee_id,
-            t.driver_id,
-            t.vehicle_id,
-            COALESCE(t.actual_duration_minutes, 0) as total_time_minutes,
-            COALESCE(t.actual_duration_minutes, 0) as driving_time_minutes,
-            0 as waiting_time_minutes,
-            COALESCE(t.total_distance, 0) as total_distance_km,
-            COALESCE(t.route_optimization_score, 0) as route_efficiency,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered') as packages_delivered,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'failed') as packages_failed,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    (COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered')::float / COUNT(p.id)::float) * 100
-                ELSE 0 
-            END as delivery_success_rate,
-            COALESCE(t.fuel_consumed, 0) as fuel_consumed_liters,
-            CASE 
-                WHEN t.fuel_consumed > 0 AND t.total_distance > 0 THEN 
-                    t.total_distance / t.fuel_consumed
-                ELSE 0 
-            END as fuel_efficiency_km_l,
-            COALESCE(t.fuel_cost, 0) as total_cost,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    COALESCE(t.fuel_cost, 0) / COUNT(p.id)
-                ELSE 0 
-            END as cost_per_package,
-            CASE 
-                WHEN t.total_distance > 0 THEN 
-                    COALESCE(t.fuel_cost, 0) / t.total_distance
-                ELSE 0 
-            END as cost_per_km,
-            NULL::float as customer_rating,
-            0 as complaints_count,
-            t.tournee_date as date,
-            t.created_at,
-            t.updated_at
+        SELECT {}, {}
         FROM tournees t
-        LEFT JOIN packages p ON t.id = p.tournee_id AND p.deleted_at IS NULL
+        LEFT JOIN (
+            SELECT
+                tournee_id,
+                COUNT(*) FILTER (WHERE delivery_status = 'delivered') AS delivered,
+                COUNT(*) FILTER (WHERE delivery_status = 'failed') AS failed,
+                COUNT(*) AS total
+            FROM packages
+            WHERE company_id = $1 AND deleted_at IS NULL
+            GROUP BY tournee_id
+        ) p ON p.tournee_id = t.id
         WHERE t.company_id = $1
         AND t.tournee_date BETWEEN $2 AND $3
         AND t.tournee_status = 'completed'
         AND t.deleted_at IS NULL
-        GROUP BY t.id, t.company_id, t.driver_id, t.vehicle_id, t.tournee_date,
-                 t.actual_duration_minutes, t.total_distance, t.route_optimization_score,
-                 t.fuel_consumed, t.fuel_cost, t.created_at, t.updated_at
-        ORDER BY t.tournee_date DESC, t.created_at DESC
+        AND ($4::uuid IS NULL OR t.id = $4)
+        AND ($5::uuid IS NULL OR t.driver_id = $5)
+        AND ($6::uuid IS NULL OR t.vehicle_id = $6)
+        GROUP BY {}
+        ORDER BY {}
         "#,
-        user.company_id,
-        date_from,
-        date_to
-    )
+        keys, PERFORMANCE_METRICS, group_by, order_by
+    ))
+    .bind(company_id)
+    .bind(date_from)
+    .bind(date_to)
+    .bind(filters.tournee_id)
+    .bind(filters.driver_id)
+    .bind(filters.vehicle_id)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
+
+    Ok(rows)
+}
 
-    let analytics: Vec<AnalyticsResponse> = rows
-        .into_iter()
-        .map(|row| AnalyticsResponse {
-            id: row.id,
-            company_id: row.company_id,
-            tournee_id: Some(row.tournee_id),
-            driver_id: Some(row.driver_id),
-            vehicle_id: Some(row.vehicle_id),
-            total_time_minutes: row.total_time_minutes.unwrap_or(0) as i32,
-            driving_time_minutes: row.driving_time_minutes.unwrap_or(0) as i32,
-            waiting_time_minutes: row.waiting_time_minutes.expect("waiting_time_minutes should not be null"),
-            total_distance_km: row.total_distance_km
-                .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            route_efficiency: row.route_efficiency
-                .map(|e| e.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            packages_delivered: row.packages_delivered.unwrap_or(0) as i32,
-            packages_failed: row.packages_failed.unwrap_or(0) as i32,
-            delivery_success_rate: row.delivery_success_rate.unwrap_or(0.0),
-            fuel_consumed_liters: row.fuel_consumed_liters
-                .map(|f| f.to_string().parse::<f64>().unwrap_or(0.0)),
-            fuel_efficiency_km_l: row.fuel_efficiency_km_l.map(|d| d.to_string().parse::<f64>().unwrap_or(0.0)),
-            total_cost: row.total_cost,
-            cost_per_package: row.cost_per_package,
-            cost_per_km: row.cost_per_km,
-            customer_rating: row.customer_rating,
-            complaints_count: row.complaints_count.expect("complaints_count should not be null"),
-            date: row.date,
-            created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-            updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-        })
-        .collect();
+/// Obtener métricas de rendimiento por tournée
+pub async fn get_performance_by_tournee(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Query(filters): Query<AnalyticsFilters>,
+) -> AppResult<Json<Vec<AnalyticsResponse>>> {
+    let analytics = fetch_performance(
+        &state,
+        user.company_id,
+        &filters,
+        "t.id, t.company_id, t.id AS tournee_id, t.driver_id, t.vehicle_id, t.tournee_date AS date",
+        "t.id",
+        "t.tournee_date DESC, t.created_at DESC",
+    )
+    .await?;
 
     Ok(Json(analytics))
 }
@@ -224,106 +209,18 @@ pub async fn get_performance_by_tournee(
 /// Obtener métricas agregadas por conductor
 pub async fn get_driver_performance(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Query(filters): Query<AnalyticsFilters>,
 ) -> AppResult<Json<Vec<AnalyticsResponse>>> {
-    let date_from = filters.date_from.unwrap_or_else(|| {
-        chrono::Utc::now().date_naive() - chrono::Duration::days(30)
-    });
-    let date_to = filters.date_to.unwrap_or_else(|| chrono::Utc::now().date_naive());
-
-    let rows = sqlx::query!(
-        r#"
-        SELECT 
-            t.driver_id as id,
-            t.company_id,
-            NULL::uuid as tournee_id,
-            t.driver_id,
-            NULL::uuid as vehicle_id,
-            SUM(COALESCE(t.actual_duration_minutes, 0)) as total_time_minutes,
-            SUM(COALESCE(t.actual_duration_minutes, 0)) as driving_time_minutes,
-            0 as waiting_time_minutes,
-            SUM(COALESCE(t.total_distance, 0)) as total_distance_km,
-            AVG(COALESCE(t.route_optimization_score, 0)) as route_efficiency,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered') as packages_delivered,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'failed') as packages_failed,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    (COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered')::float / COUNT(p.id)::float) * 100
-                ELSE 0 
-            END as delivery_success_rate,
-            SUM(COALESCE(t.fuel_consumed, 0)) as fuel_consumed_liters,
-            CASE 
-                WHEN SUM(t.fuel_consumed) > 0 AND SUM(t.total_distance) > 0 THEN 
-                    SUM(t.total_distance) / SUM(t.fuel_consumed)
-                ELSE 0 
-            END as fuel_efficiency_km_l,
-            SUM(COALESCE(t.fuel_cost, 0)) as total_cost,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    SUM(COALESCE(t.fuel_cost, 0)) / COUNT(p.id)
-                ELSE 0 
-            END as cost_per_package,
-            CASE 
-                WHEN SUM(t.total_distance) > 0 THEN 
-                    SUM(COALESCE(t.fuel_cost, 0)) / SUM(t.total_distance)
-                ELSE 0 
-            END as cost_per_km,
-            NULL::float as customer_rating,
-            0 as complaints_count,
-            $3::date as date,
-            MIN(t.created_at) as created_at,
-            MAX(t.updated_at) as updated_at
-        FROM tournees t
-        LEFT JOIN packages p ON t.id = p.tournee_id AND p.deleted_at IS NULL
-        WHERE t.company_id = $1
-        AND t.tournee_date BETWEEN $2 AND $3
-        AND t.tournee_status = 'completed'
-        AND t.deleted_at IS NULL
-        GROUP BY t.driver_id, t.company_id
-        ORDER BY packages_delivered DESC
-        "#,
+    let analytics = fetch_performance(
+        &state,
         user.company_id,
-        date_from,
-        date_to
+        &filters,
+        "t.driver_id AS id, t.company_id, NULL::uuid AS tournee_id, t.driver_id, NULL::uuid AS vehicle_id, MAX(t.tournee_date) AS date",
+        "t.driver_id, t.company_id",
+        "packages_delivered DESC",
     )
-    .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let analytics: Vec<AnalyticsResponse> = rows
-        .into_iter()
-        .map(|row| AnalyticsResponse {
-            id: row.id,
-            company_id: row.company_id,
-            tournee_id: row.tournee_id,
-            driver_id: Some(row.driver_id),
-            vehicle_id: row.vehicle_id,
-            total_time_minutes: row.total_time_minutes.unwrap_or(0) as i32,
-            driving_time_minutes: row.driving_time_minutes.unwrap_or(0) as i32,
-            waiting_time_minutes: row.waiting_time_minutes.expect("waiting_time_minutes should not be null"),
-            total_distance_km: row.total_distance_km
-                .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            route_efficiency: row.route_efficiency
-                .map(|e| e.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            packages_delivered: row.packages_delivered.unwrap_or(0) as i32,
-            packages_failed: row.packages_failed.unwrap_or(0) as i32,
-            delivery_success_rate: row.delivery_success_rate.unwrap_or(0.0),
-            fuel_consumed_liters: row.fuel_consumed_liters
-                .map(|f| f.to_string().parse::<f64>().unwrap_or(0.0)),
-            fuel_efficiency_km_l: row.fuel_efficiency_km_l.map(|d| d.to_string().parse::<f64>().unwrap_or(0.0)),
-            total_cost: row.total_cost,
-            cost_per_package: row.cost_per_package,
-            cost_per_km: row.cost_per_km,
-            customer_rating: row.customer_rating,
-            complaints_count: row.complaints_count.expect("complaints_count should not be null"),
-            date: row.date.expect("date should not be null"),
-            created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-            updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-        })
-        .collect();
+    .await?;
 
     Ok(Json(analytics))
 }
@@ -331,106 +228,18 @@ pub async fn get_driver_performance(
 /// Obtener métricas agregadas por vehículo
 pub async fn get_vehicle_performance(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Query(filters): Query<AnalyticsFilters>,
 ) -> AppResult<Json<Vec<AnalyticsResponse>>> {
-    let date_from = filters.date_from.unwrap_or_else(|| {
-        chrono::Utc::now().date_naive() - chrono::Duration::days(30)
-    });
-    let date_to = filters.date_to.unwrap_or_else(|| chrono::Utc::now().date_naive());
-
-    let rows = sqlx::query!(
-        r#"
-        SELECT 
-            t.vehicle_id as id,
-            t.company_id,
-            NULL::uuid as tournee_id,
-            NULL::uuid as driver_id,
-            t.vehicle_id,
-            SUM(COALESCE(t.actual_duration_minutes, 0)) as total_time_minutes,
-            SUM(COALESCE(t.actual_duration_minutes, 0)) as driving_time_minutes,
-            0 as waiting_time_minutes,
-            SUM(COALESCE(t.total_distance, 0)) as total_distance_km,
-            AVG(COALESCE(t.route_optimization_score, 0)) as route_efficiency,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered') as packages_delivered,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'failed') as packages_failed,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    (COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered')::float / COUNT(p.id)::float) * 100
-                ELSE 0 
-            END as delivery_success_rate,
-            SUM(COALESCE(t.fuel_consumed, 0)) as fuel_consumed_liters,
-            CASE 
-                WHEN SUM(t.fuel_consumed) > 0 AND SUM(t.total_distance) > 0 THEN 
-                    SUM(t.total_distance) / SUM(t.fuel_consumed)
-                ELSE 0 
-            END as fuel_efficiency_km_l,
-            SUM(COALESCE(t.fuel_cost, 0)) as total_cost,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    SUM(COALESCE(t.fuel_cost, 0)) / COUNT(p.id)
-                ELSE 0 
-            END as cost_per_package,
-            CASE 
-                WHEN SUM(t.total_distance) > 0 THEN 
-                    SUM(COALESCE(t.fuel_cost, 0)) / SUM(t.total_distance)
-                ELSE 0 
-            END as cost_per_km,
-            NULL::float as customer_rating,
-            0 as complaints_count,
-            $3::date as date,
-            MIN(t.created_at) as created_at,
-            MAX(t.updated_at) as updated_at
-        FROM tournees t
-        LEFT JOIN packages p ON t.id = p.tournee_id AND p.deleted_at IS NULL
-        WHERE t.company_id = $1
-        AND t.tournee_date BETWEEN $2 AND $3
-        AND t.tournee_status = 'completed'
-        AND t.deleted_at IS NULL
-        GROUP BY t.vehicle_id, t.company_id
-        ORDER BY packages_delivered DESC
-        "#,
+    let analytics = fetch_performance(
+        &state,
         user.company_id,
-        date_from,
-        date_to
+        &filters,
+        "t.vehicle_id AS id, t.company_id, NULL::uuid AS tournee_id, NULL::uuid AS driver_id, t.vehicle_id, MAX(t.tournee_date) AS date",
+        "t.vehicle_id, t.company_id",
+        "packages_delivered DESC",
     )
-    .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let analytics: Vec<AnalyticsResponse> = rows
-        .into_iter()
-        .map(|row| AnalyticsResponse {
-            id: row.id,
-            company_id: row.company_id,
-            tournee_id: row.tournee_id,
-            driver_id: row.driver_id,
-            vehicle_id: Some(row.vehicle_id),
-            total_time_minutes: row.total_time_minutes.unwrap_or(0) as i32,
-            driving_time_minutes: row.driving_time_minutes.unwrap_or(0) as i32,
-            waiting_time_minutes: row.waiting_time_minutes.expect("waiting_time_minutes should not be null"),
-            total_distance_km: row.total_distance_km
-                .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            route_efficiency: row.route_efficiency
-                .map(|e| e.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            packages_delivered: row.packages_delivered.unwrap_or(0) as i32,
-            packages_failed: row.packages_failed.unwrap_or(0) as i32,
-            delivery_success_rate: row.delivery_success_rate.unwrap_or(0.0),
-            fuel_consumed_liters: row.fuel_consumed_liters
-                .map(|f| f.to_string().parse::<f64>().unwrap_or(0.0)),
-            fuel_efficiency_km_l: row.fuel_efficiency_km_l.map(|d| d.to_string().parse::<f64>().unwrap_or(0.0)),
-            total_cost: row.total_cost,
-            cost_per_package: row.cost_per_package,
-            cost_per_km: row.cost_per_km,
-            customer_rating: row.customer_rating,
-            complaints_count: row.complaints_count.expect("complaints_count should not be null"),
-            date: row.date.expect("date should not be null"),
-            created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-            updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-        })
-        .collect();
+    .await?;
 
     Ok(Json(analytics))
-}
\ No newline at end of file
+}
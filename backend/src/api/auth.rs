@@ -9,17 +9,17 @@ use axum::{
 };
 use bcrypt::{hash, verify, DEFAULT_COST};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
-    config::EnvironmentConfig,
-    models::user::{User, UserType, UserStatus, CreateUserRequest, UserResponse},
+    api::users::{map_user_write_error, USER_COLUMNS},
+    models::auth::RegisterRequest,
+    models::user::{User, UserStatus, UserResponse},
     utils::errors::{AppError, AppResult},
     utils::jwt::{generate_token, JwtConfig},
     middleware::auth::AuthenticatedUser,
-    state::AppState,
+    state::FleetState,
 };
 
 /// Request de login
@@ -30,6 +30,9 @@ pub struct LoginRequest {
     
     #[validate(length(min = 6, max = 100))]
     pub password: String,
+
+    /// Empresa del usuario (el username solo es único dentro de cada empresa)
+    pub company_id: Option<Uuid>,
 }
 
 /// Response de login exitoso
@@ -84,24 +87,9 @@ pub struct RefreshTokenResponse {
     pub expires_in: u64,
 }
 
-/// Request de registro
-#[derive(Debug, Deserialize, Validate)]
-pub struct RegisterRequest {
-    #[validate(length(min = 3, max = 50))]
-    pub username: String,
-    
-    #[validate(email)]
-    pub email: String,
-    
-    #[validate(length(min = 6, max = 100))]
-    pub password: String,
-    
-    pub user_type: UserType,
-}
-
 /// Handler de login
 pub async fn login(
-    State(app_state): State<AppState>,
+    State(app_state): State<FleetState>,
     Json(login_data): Json<LoginRequest>,
 ) -> AppResult<Json<LoginResponseFlexible>> {
     let pool = &app_state.pool;
@@ -110,57 +98,40 @@ pub async fn login(
     login_data.validate()
         .map_err(AppError::Validation)?;
 
-    // Buscar usuario por username
-    let row = sqlx::query!(
+    // Buscar usuarios con ese username (uno por empresa como máximo)
+    let candidates = sqlx::query_as::<_, User>(&format!(
         r#"
-        SELECT 
-            id, company_id, user_type as "user_type: String", user_status as "user_status: String", username, 
-            password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE username = $1 
+        SELECT {}
+        FROM users
+        WHERE username = $1
+        AND ($2::uuid IS NULL OR company_id = $2)
         AND deleted_at IS NULL
         "#,
-        login_data.username
-    )
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
-    .ok_or_else(|| AppError::Unauthorized("Credenciales inválidas".to_string()))?;
-
-    let user = User {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: match row.user_type.as_str() {
-            "admin" => UserType::Admin,
-            "driver" => UserType::Driver,
-            _ => return Err(AppError::Database(sqlx::Error::Decode("Invalid user_type".into()))),
-        },
-        user_status: match row.user_status.as_str() {
-            "active" => UserStatus::Active,
-            "inactive" => UserStatus::Inactive,
-            "suspended" => UserStatus::Suspended,
-            _ => return Err(AppError::Database(sqlx::Error::Decode("Invalid user_status".into()))),
-        },
-        username: row.username,
-        password_hash: row.password_hash,
-        created_at: row.created_at.expect("created_at should not be null"),
-        updated_at: row.updated_at.expect("updated_at should not be null"),
-        deleted_at: row.deleted_at,
-    };
+        USER_COLUMNS
+    ))
+    .bind(&login_data.username)
+    .bind(login_data.company_id)
+    .fetch_all(pool)
+    .await?;
+
+    // Verificar password contra cada candidato
+    let mut authenticated = None;
+    for candidate in candidates {
+        let password_valid = verify(&login_data.password, &candidate.password_hash)
+            .map_err(|e| AppError::Hash(format!("Error verificando password: {}", e)))?;
+        if password_valid {
+            authenticated = Some(candidate);
+            break;
+        }
+    }
+    let user = authenticated
+        .ok_or_else(|| AppError::Unauthorized("Credenciales inválidas".to_string()))?;
 
     // Verificar que el usuario esté activo
     if user.user_status != UserStatus::Active {
         return Err(AppError::Unauthorized("Usuario inactivo o suspendido".to_string()));
     }
 
-    // Verificar password
-    let password_valid = verify(&login_data.password, &user.password_hash)
-        .map_err(|e| AppError::Hash(format!("Error verificando password: {}", e)))?;
-
-    if !password_valid {
-        return Err(AppError::Unauthorized("Credenciales inválidas".to_string()));
-    }
-
     // Generar JWT token
     let jwt_config = JwtConfig::from(config);
     let access_token = generate_token(user.id, user.company_id, user.user_type.clone(), &jwt_config)?;
@@ -190,9 +161,9 @@ pub async fn login(
     Ok(Json(response))
 }
 
-/// Handler de registro
+/// Handler de registro: alta de una empresa con su primer admin
 pub async fn register(
-    State(app_state): State<AppState>,
+    State(app_state): State<FleetState>,
     Json(register_data): Json<RegisterRequest>,
 ) -> AppResult<Json<LoginResponseFlexible>> {
     let pool = &app_state.pool;
@@ -201,74 +172,42 @@ pub async fn register(
     register_data.validate()
         .map_err(AppError::Validation)?;
 
-    // Verificar que el username no exista
-    let existing_user = sqlx::query!(
-        "SELECT id FROM users WHERE username = $1 AND deleted_at IS NULL",
-        register_data.username
-    )
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    if existing_user.is_some() {
-        return Err(AppError::Conflict("Username ya existe".to_string()));
-    }
-
-    // Verificar que el email no exista
-    let existing_email = sqlx::query!(
-        "SELECT id FROM users WHERE email = $1 AND deleted_at IS NULL",
-        register_data.email
-    )
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    if existing_email.is_some() {
-        return Err(AppError::Conflict("Email ya existe".to_string()));
-    }
-
     // Hash del password
-    let password_hash = hash(&register_data.password, DEFAULT_COST)
+    let password_hash = hash(&register_data.admin_password, DEFAULT_COST)
         .map_err(|e| AppError::Hash(format!("Error hasheando password: {}", e)))?;
 
-    // Crear usuario (asumiendo que se crea en una empresa por defecto)
-    // En producción, esto debería ser manejado por un admin o proceso de onboarding
-    let company_id = Uuid::new_v4(); // Placeholder - en producción esto vendría del contexto
+    // Empresa y admin en la misma transacción
+    let mut tx = pool.begin().await?;
+
+    let company_id = sqlx::query_scalar::<_, Uuid>(
+        "INSERT INTO companies (name, address) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(&register_data.company_name)
+    .bind(&register_data.company_address)
+    .fetch_one(&mut *tx)
+    .await?;
 
-    let row = sqlx::query!(
+    let new_user = sqlx::query_as::<_, User>(&format!(
         r#"
         INSERT INTO users (
-            company_id, user_type, user_status, username, 
-            password_hash, created_at, updated_at
-        ) VALUES ($1, ($2::text)::user_type, ($3::text)::user_status, $4, $5, NOW(), NOW())
-        RETURNING 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", username, 
-            password_hash, created_at, updated_at, deleted_at
+            company_id, user_type, user_status, username,
+            password_hash, full_name, email, phone, created_at, updated_at
+        ) VALUES ($1, 'admin', 'active', $2, $3, $4, $5, $6, NOW(), NOW())
+        RETURNING {}
         "#,
-        company_id,
-        match register_data.user_type {
-            UserType::Admin => "admin",
-            UserType::Driver => "driver",
-        },
-        "active",
-        register_data.username,
-        password_hash
-    )
-    .fetch_one(pool)
+        USER_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(&register_data.admin_username)
+    .bind(&password_hash)
+    .bind(&register_data.admin_full_name)
+    .bind(&register_data.admin_email)
+    .bind(&register_data.admin_phone)
+    .fetch_one(&mut *tx)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let new_user = User {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: row.user_type,
-        user_status: row.user_status,
-        username: row.username,
-        password_hash: row.password_hash,
-        created_at: row.created_at.expect("created_at should not be null"),
-        updated_at: row.updated_at.expect("updated_at should not be null"),
-        deleted_at: row.deleted_at,
-    };
+    .map_err(map_user_write_error)?;
+
+    tx.commit().await?;
 
     // Generar JWT token
     let jwt_config = JwtConfig::from(config);
@@ -290,7 +229,7 @@ pub async fn register(
             message: "Usuario registrado exitosamente".to_string(),
         }),
         credentials_used: Some(CredentialsUsed {
-            username: register_data.username.clone(),
+            username: register_data.admin_username.clone(),
             timestamp: chrono::Utc::now().to_rfc3339(),
         }),
         timestamp: chrono::Utc::now().to_rfc3339(),
@@ -302,36 +241,23 @@ pub async fn register(
 /// Handler para obtener información del usuario autenticado
 pub async fn me(
     Extension(user): Extension<AuthenticatedUser>,
-    State(app_state): State<AppState>,
+    State(app_state): State<FleetState>,
 ) -> AppResult<Json<UserResponse>> {
-    let pool = &app_state.pool;
-    // Buscar usuario completo
-    let row = sqlx::query!(
+    let user_data = sqlx::query_as::<_, User>(&format!(
         r#"
-        SELECT 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", username, 
-            password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE id = $1 
+        SELECT {}
+        FROM users
+        WHERE id = $1
+        AND company_id = $2
         AND deleted_at IS NULL
         "#,
-        user.user_id
-    )
-    .fetch_one(pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let user_data = User {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: row.user_type,
-        user_status: row.user_status,
-        username: row.username,
-        password_hash: row.password_hash,
-        created_at: row.created_at.expect("created_at should not be null"),
-        updated_at: row.updated_at.expect("updated_at should not be null"),
-        deleted_at: row.deleted_at,
-    };
+        USER_COLUMNS
+    ))
+    .bind(user.user_id)
+    .bind(user.company_id)
+    .fetch_optional(&app_state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Usuario no encontrado".to_string()))?;
 
     let user_response = UserResponse::from(user_data);
     Ok(Json(user_response))
@@ -339,7 +265,7 @@ pub async fn me(
 
 /// Handler de refresh token
 pub async fn refresh_token(
-    State(_app_state): State<AppState>,
+    State(_app_state): State<FleetState>,
     Json(refresh_data): Json<RefreshTokenRequest>,
 ) -> AppResult<Json<RefreshTokenResponse>> {
     // Validar datos de entrada
@@ -359,15 +285,3 @@ pub async fn logout() -> StatusCode {
     // Por ahora, solo retornamos OK
     StatusCode::OK
 }
-
-use axum::Router;
-
-/// Crear el router de autenticación
-pub fn create_auth_router() -> Router<crate::state::AppState> {
-    Router::new()
-        .route("/login", axum::routing::post(login))
-        .route("/register", axum::routing::post(register))
-        .route("/me", axum::routing::get(me))
-        .route("/refresh", axum::routing::post(refresh_token))
-        .route("/logout", axum::routing::post(logout))
-}
@@ -5,14 +5,18 @@
 
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     Json,
 };
 use serde_json::json;
 use log;
+use uuid::Uuid;
 use crate::{
     state::AppState,
-    services::colis_prive_service::{ColisPriveAuthRequest, GetTourneeRequest, GetPackagesRequest, ColisPriveAuthResponse},
+    services::colis_prive_service::{ColisPriveAuthRequest, GetTourneeRequest, GetPackagesRequest, ColisPriveAuthResponse, InvalidateTourneeRequest},
+    services::credential_vault::VaultError,
+    client::ColisPriveClientError,
+    utils::jwt::{require_user, JwtConfig},
 };
 
 /// POST /api/colis-prive/auth - Autenticar con Colis Privé
@@ -25,7 +29,7 @@ pub async fn authenticate_colis_prive(
     let societe = credentials.societe.clone();
     
     // 🔧 IMPLEMENTACIÓN REAL: Autenticación directa con Colis Privé
-    match authenticate_colis_prive_simple(&credentials).await {
+    match authenticate_colis_prive_simple(&state, &credentials).await {
         Ok(auth_response) => {
             if auth_response.success {
                 // 🆕 ALMACENAR EL TOKEN EN EL ESTADO DE LA APLICACIÓN
@@ -91,6 +95,7 @@ pub async fn authenticate_colis_prive(
 
 /// 🔧 FUNCIÓN AUXILIAR: Autenticación simple sin device_info
 async fn authenticate_colis_prive_simple(
+    state: &AppState,
     credentials: &ColisPriveAuthRequest
 ) -> Result<ColisPriveAuthResponse, anyhow::Error> {
     log::info!("🔐 Autenticando con Colis Privé (modo real)");
@@ -100,100 +105,16 @@ async fn authenticate_colis_prive_simple(
         anyhow::bail!("Credenciales incompletas");
     }
     
-    // 🔧 IMPLEMENTACIÓN REAL: Autenticación directa con Colis Privé
-    let auth_url = "https://wsauthentificationexterne.colisprive.com/api/auth/login/Membership";
-    let login_field = format!("{}_{}", credentials.societe, credentials.username);
-    let auth_payload = json!({
-        "login": login_field,
-        "password": credentials.password,
-        "societe": credentials.societe,
-        "commun": {
-            "dureeTokenInHour": 24
-        }
-    });
-    
-    log::info!("📤 Enviando autenticación a: {}", auth_url);
-    log::info!("🔑 Login field: {}", login_field);
-    
-    // 🆕 USAR CURL DIRECTAMENTE PARA AUTENTICACIÓN
-    let auth_payload_str = serde_json::to_string(&auth_payload).map_err(|e| {
-        log::error!("❌ Error serializando payload de autenticación: {}", e);
-        anyhow::anyhow!("Error serializando payload: {}", e)
-    })?;
-    
-    let curl_output = std::process::Command::new("curl")
-        .arg("-X")
-        .arg("POST")
-        .arg(auth_url)
-        .arg("-H")
-        .arg("Content-Type: application/json")
-        .arg("-d")
-        .arg(&auth_payload_str)
-        .arg("--max-time")
-        .arg("30")
-        .arg("--silent")
-        .arg("--show-error")
-        .output()
-        .map_err(|e| {
-            log::error!("❌ Error ejecutando curl para autenticación: {}", e);
-            anyhow::anyhow!("Error ejecutando curl: {}", e)
-        })?;
-
-    if !curl_output.status.success() {
-        let error_msg = String::from_utf8_lossy(&curl_output.stderr);
-        log::error!("❌ Curl falló en autenticación: {}", error_msg);
-        anyhow::bail!("Curl falló: {}", error_msg);
-    }
-
-    let response_body = String::from_utf8_lossy(&curl_output.stdout);
-    log::info!("📥 Respuesta de autenticación curl: {}", response_body);
-    
-        // 🆕 PARSEAR RESPUESTA DE AUTENTICACIÓN CURL
-    let auth_text = response_body.to_string();
-    
-    log::info!("📥 Respuesta de autenticación recibida: {}", &auth_text[..auth_text.len().min(200)]);
-    
-    // Parsear la respuesta de Colis Privé
-    let auth_data: serde_json::Value = serde_json::from_str(&auth_text).map_err(|e| {
-        log::error!("❌ Error parseando respuesta de autenticación: {}", e);
-        anyhow::anyhow!("Error parseando respuesta: {}", e)
-    })?;
-    
-    // 🔍 DEBUG: Imprimir todos los campos de la respuesta
-    log::info!("🔍 Campos disponibles en la respuesta:");
-    if let Some(obj) = auth_data.as_object() {
-        for (key, value) in obj {
-            log::info!("  - {}: {}", key, value);
-        }
-    }
-    
-    // 🔍 BUSCAR EL TOKEN EN DIFERENTES CAMPOS POSIBLES (incluyendo campos anidados)
-    let sso_hopps = auth_data.get("SsoHopps")
-        .or_else(|| auth_data.get("ssoHopps"))
-        .or_else(|| auth_data.get("token"))
-        .or_else(|| auth_data.get("Token"))
-        .or_else(|| auth_data.get("access_token"))
-        .or_else(|| auth_data.get("accessToken"))
-        .or_else(|| auth_data.get("tokens").and_then(|t| t.get("SsoHopps")))
-        .or_else(|| auth_data.get("shortToken").and_then(|t| t.get("SsoHopps")))
-        .or_else(|| auth_data.get("habilitationAD")
-            .and_then(|h| h.get("SsoHopps"))
-            .and_then(|s| s.as_array())
-            .and_then(|arr| arr.get(0))
-            .and_then(|item| item.get("valeur")))
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| {
-            log::error!("❌ Token no encontrado en ningún campo. Campos disponibles: {:?}", 
-                auth_data.as_object().map(|obj| obj.keys().collect::<Vec<_>>()));
-            anyhow::anyhow!("Token no encontrado en la respuesta")
-        })?;
+    let sso_hopps = state.colis_prive
+        .login(&credentials.societe, &credentials.username, &credentials.password)
+        .await?;
     
     log::info!("✅ Token SsoHopps obtenido exitosamente");
     
     let auth_response = ColisPriveAuthResponse {
         success: true,
         message: "Autenticación exitosa con Colis Privé".to_string(),
-        token: Some(sso_hopps.to_string()),
+        token: Some(sso_hopps),
         matricule: Some(credentials.username.clone()),
     };
     
@@ -213,18 +134,21 @@ pub async fn test_packages_endpoint() -> Result<Json<serde_json::Value>, StatusC
 /// POST /api/colis-prive/packages - Obtener paquetes desde Colis Privé (IMPLEMENTACIÓN REAL)
 pub async fn get_packages(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(request): Json<GetPackagesRequest>,
 ) -> Result<Json<crate::services::GetPackagesResponse>, StatusCode> {
     use tracing::info;
-    use crate::services::{GetPackagesResponse, PackageData};
+    use crate::client::parse_tournee;
+    use crate::models::colis_prive_web_models::WebTourneeData;
+    use std::collections::HashMap;
+    use crate::services::{map_tournee_packages, GetPackagesResponse};
+    use crate::services::validation_jobs::{apply_to_package, JobStatus, ValidationRequest};
 
     log::info!("🔥 FUNCIÓN GET_PACKAGES INICIADA");
     info!("🚀 ENDPOINT GET_PACKAGES LLAMADO - matricule: {}", request.matricule);
     info!("📦 Obteniendo paquetes para matricule: {}", request.matricule);
 
-    // Construir el matricule completo (societe + username)
-    let societe = "PCP0010699".to_string();
-    let matricule_completo = format!("{}_{}", societe, request.matricule);
+    let (company_id, societe) = authorize_driver(&state, &headers, &request.matricule, request.societe.as_deref()).await?;
     
     // Construir la fecha (hoy si no se especifica)
     let date = request.date.unwrap_or_else(|| {
@@ -234,13 +158,10 @@ pub async fn get_packages(
     // Llamar al endpoint real de Colis Privé
 
     // 🆕 OBTENER EL TOKEN DINÁMICAMENTE DEL ESTADO DE LA APLICACIÓN
-    // request.matricule es el username, no el matricule completo
+    // request.matricule es el username, no el matricule completo.
+    // Solo se devuelven tokens vigentes: uno expirado pasa por la autenticación automática
     let sso_hopps = match state.get_auth_token(&request.matricule, &societe).await {
         Some(auth_token) => {
-            if auth_token.is_expired() {
-                log::warn!("⚠️ Token expirado para {}:{}, necesitamos re-autenticar", societe, request.matricule);
-                return Err(StatusCode::UNAUTHORIZED);
-            }
             log::info!("✅ Usando token almacenado para {}:{}", societe, request.matricule);
             auth_token.token
         }
@@ -261,92 +182,30 @@ pub async fn get_packages(
         }
     };
 
-    // 🆕 USAR CURL DIRECTAMENTE
-    let payload = serde_json::json!({
-        "Matricule": matricule_completo,
-        "DateDebut": date
-    });
-
-    let payload_str = serde_json::to_string(&payload).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let tournee_url = "https://wstournee-v2.colisprive.com/WS-TourneeColis/api/getTourneeByMatriculeDistributeurDateDebut_POST";
-    
-    log::info!("📤 Llamando a: {}", tournee_url);
-    log::info!("📦 Payload: {}", payload_str);
-    
-    let curl_output = std::process::Command::new("curl")
-        .arg("-X").arg("POST")
-        .arg(tournee_url)
-        .arg("-H").arg("Content-Type: application/json")
-        .arg("-H").arg(format!("SsoHopps: {}", sso_hopps))
-        .arg("-d").arg(&payload_str)
-        .arg("--max-time").arg("30")
-        .arg("--silent").arg("--show-error")
-        .output()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if !curl_output.status.success() {
-        log::error!("❌ Curl falló - stderr: {}", String::from_utf8_lossy(&curl_output.stderr));
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    let response_str = String::from_utf8_lossy(&curl_output.stdout);
-    log::info!("📥 Respuesta recibida: {} bytes", response_str.len());
-
-    // Parsear la respuesta JSON de Colis Privé
-    let tournee_data: serde_json::Value = serde_json::from_str(&response_str)
+    let tournee_data: WebTourneeData = fetch_tournee_reauth(&state, &sso_hopps, &societe, &request.matricule, &date)
+        .await
+        .and_then(|body| parse_tournee(&body))
         .map_err(|e| {
-            log::error!("❌ Error parseando respuesta JSON: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            log::error!("❌ Error obteniendo tournée de Colis Privé: {}", e);
+            e.status_code()
         })?;
 
     // Extraer paquetes de LstLieuArticle
-    let packages = if let Some(lst_lieu_article) = tournee_data.get("LstLieuArticle") {
-        if let Some(packages_array) = lst_lieu_article.as_array() {
-            packages_array
-                .iter()
-                .filter_map(|package| {
-                    // Solo procesar paquetes de tipo COLIS
-                    if package.get("metier")?.as_str() == Some("COLIS") {
-                        Some(PackageData {
-                            id: package.get("idArticle")?.as_str()?.to_string(),
-                            tracking_number: package.get("refExterneArticle")?.as_str()?.to_string(),
-                            recipient_name: package.get("nomDestinataire")?.as_str()?.to_string(),
-                            address: format!(
-                                "{}, {} {}",
-                                package.get("LibelleVoieOrigineDestinataire")?.as_str()?,
-                                package.get("codePostalOrigineDestinataire")?.as_str()?,
-                                package.get("LibelleLocaliteOrigineDestinataire")?.as_str()?
-                            ),
-                            status: package.get("codeStatutArticle")?.as_str()?.to_string(),
-                            instructions: package.get("PreferenceLivraison")?.as_str()?.to_string(),
-                            phone: package.get("telephoneMobileDestinataire")?.as_str()?.to_string(),
-                            priority: package.get("priorite")?.as_u64()?.to_string(),
-                            latitude: None,
-                            longitude: None,
-                            formatted_address: None,
-                            validation_method: None,
-                            validation_confidence: None,
-                            validation_warnings: None,
-                        })
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>()
-        } else {
-            vec![]
-        }
-    } else {
-        vec![]
-    };
-
-    log::info!("📦 Paquetes extraídos: {} paquetes", packages.len());
+    let mapping = map_tournee_packages(&tournee_data);
+    let packages = mapping.packages;
+    let unmapped_packages = mapping.unmapped;
+
+    log::info!("📦 Paquetes extraídos: {} paquetes ({} no mapeados, {} no COLIS)",
+        packages.len(), unmapped_packages.len(), mapping.skipped_non_colis);
+    for unmapped in &unmapped_packages {
+        log::warn!("⚠️ Artículo #{} ({:?}) no mapeado: {}", unmapped.index, unmapped.id_article, unmapped.reason);
+    }
 
     // Si no hay paquetes, verificar si es una tournée completada
-    if packages.is_empty() {
-        if let Some(infos_tournee) = tournee_data.get("InfosTournee") {
-            let code_tournee = infos_tournee.get("codeTourneeDistribution")
-                .and_then(|v| v.as_str())
+    if packages.is_empty() && unmapped_packages.is_empty() {
+        if let Some(infos_tournee) = &tournee_data.infos_tournee {
+            let code_tournee = infos_tournee.code_tournee_distribution
+                .as_deref()
                 .unwrap_or("Desconocida");
             return Ok(Json(GetPackagesResponse {
                 success: true,
@@ -354,104 +213,217 @@ pub async fn get_packages(
                 packages: None,
                 error: None,
                 address_validation: None,
+                unmapped_packages: None,
+                validation_job: None,
             }));
         }
     }
 
-    // 🆕 VALIDACIÓN INTELIGENTE DE DIRECCIONES
-    log::info!("🔍 Iniciando validación inteligente de direcciones para {} paquetes", packages.len());
-    
-    let mut validated_packages = Vec::new();
-    let mut validation_summary = crate::services::AddressValidationSummary {
-        total_packages: packages.len(),
-        auto_validated: 0,
-        cleaned_auto: 0,
-        completed_auto: 0,
-        partial_found: 0,
-        requires_manual: 0,
-        warnings: Vec::new(),
+    // 🆕 VALIDACIÓN DE DIRECCIONES EN SEGUNDO PLANO
+    log::info!("🔍 Preparando validación de direcciones para {} paquetes", packages.len());
+
+    // Crear el validador de direcciones (cadena de geocoders configurada)
+    // con el consumo de geocoding contado para la empresa del usuario
+    let mut address_validator = crate::services::AddressValidator::new(state.geocoder_for(Some(company_id)))
+        .with_corrections(state.address_corrections.clone(), company_id);
+
+    // Sector de la tournée del día o, si no tiene, el del driver
+    match state.sectors.for_driver(company_id, &request.matricule, &date).await {
+        Ok(Some(sector)) => {
+            log::info!("🏢 Sector {} ({}) para {}", sector.code, sector.postal_codes.join(", "), request.matricule);
+            address_validator = address_validator.with_sector(sector);
+        }
+        Ok(None) => log::warn!("⚠️ {} no tiene sector asignado", request.matricule),
+        Err(e) => log::warn!("⚠️ Error obteniendo el sector de {}: {}", request.matricule, e),
+    }
+
+    // 💾 Persistir la tournée antes de validar: la tarea completa sus coordenadas
+    // (un fallo no bloquea la respuesta a Android)
+    let code_tournee = tournee_data.infos_tournee.and_then(|i| i.code_tournee_distribution);
+    let tournee_id = persist_tournee(&state, &societe, &request.matricule, &date, code_tournee, &packages).await;
+
+    // 🧵 Tarea de validación: un reintento reutiliza los resultados ya guardados
+    let job = match chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+        Ok(tournee_date) => {
+            let validation_request = ValidationRequest {
+                company_id,
+                tournee_id,
+                societe: &societe,
+                matricule: &request.matricule,
+                date: tournee_date,
+                packages: &packages,
+            };
+            state.validation_jobs
+                .start(validation_request, address_validator)
+                .await
+                .map_err(|e| log::error!("❌ Error creando la tarea de validación: {}", e))
+                .ok()
+        }
+        Err(e) => {
+            log::warn!("⚠️ Fecha '{}' inválida, direcciones sin validar: {}", date, e);
+            None
+        }
     };
 
-    // Crear el validador de direcciones
-    if let Some(mapbox_token) = &state.config.mapbox_token {
-        let geocoding_service = crate::services::GeocodingService::new(mapbox_token.clone());
-        let address_validator = crate::services::AddressValidator::new(geocoding_service);
-        
-        // Validar cada paquete
-        for mut package in packages {
-            match address_validator.validate_address(&package.address, &request.matricule).await {
-                Ok(validated) => {
-                    // Actualizar el paquete con la información de validación
-                    package.latitude = validated.latitude;
-                    package.longitude = validated.longitude;
-                    package.formatted_address = validated.formatted_address;
-                    package.validation_method = Some(format!("{:?}", validated.validation_method));
-                    package.validation_confidence = Some(format!("{:?}", validated.confidence));
-                    package.validation_warnings = Some(validated.warnings.clone());
-                    
-                    // Actualizar estadísticas
-                    match validated.validation_method {
-                        crate::services::ValidationMethod::Original => validation_summary.auto_validated += 1,
-                        crate::services::ValidationMethod::Cleaned => validation_summary.cleaned_auto += 1,
-                        crate::services::ValidationMethod::CompletedWithSector => validation_summary.completed_auto += 1,
-                        crate::services::ValidationMethod::PartialSearch => validation_summary.partial_found += 1,
-                        crate::services::ValidationMethod::ManualRequired => validation_summary.requires_manual += 1,
-                    }
-                    
-                    // Agregar warnings al resumen
-                    validation_summary.warnings.extend(validated.warnings);
-                    
-                    validated_packages.push(package);
-                }
-                Err(e) => {
-                    log::error!("❌ Error validando dirección '{}': {}", package.address, e);
-                    validation_summary.requires_manual += 1;
-                    package.validation_method = Some("ManualRequired".to_string());
-                    package.validation_confidence = Some("None".to_string());
-                    package.validation_warnings = Some(vec![format!("Error de validación: {}", e)]);
-                    validated_packages.push(package);
-                }
+    let mut packages = packages;
+    if let Some(job) = &job {
+        let results: HashMap<&str, &crate::services::ValidatedAddress> = job.items
+            .iter()
+            .filter_map(|item| item.result.as_ref().map(|result| (item.package_id.as_str(), result)))
+            .collect();
+        for package in &mut packages {
+            if let Some(result) = results.get(package.id.as_str()) {
+                apply_to_package(package, result);
             }
         }
-        
-        log::info!("✅ Validación completada: {} auto-validados, {} limpiados, {} completados, {} parciales, {} manuales", 
-            validation_summary.auto_validated, 
-            validation_summary.cleaned_auto, 
-            validation_summary.completed_auto, 
-            validation_summary.partial_found, 
-            validation_summary.requires_manual
-        );
-    } else {
-        log::warn!("⚠️ MAPBOX_TOKEN no configurado, saltando validación de direcciones");
-        validation_summary.requires_manual = packages.len();
-        validated_packages = packages;
     }
 
+    let message = match &job {
+        Some(job) if job.status == JobStatus::Completed => {
+            format!("Paquetes obtenidos y validados exitosamente - {} paquetes", packages.len())
+        }
+        Some(job) => format!(
+            "Paquetes obtenidos - {} paquetes, validando {} direcciones en segundo plano",
+            packages.len(), job.total - job.processed
+        ),
+        None => format!("Paquetes obtenidos sin validar direcciones - {} paquetes", packages.len()),
+    };
+    log::info!("✅ {}", message);
+
     Ok(Json(GetPackagesResponse {
         success: true,
-        message: format!("Paquetes obtenidos y validados exitosamente - {} paquetes", validated_packages.len()),
-        packages: Some(validated_packages),
+        message,
+        packages: Some(packages),
         error: None,
-        address_validation: Some(validation_summary),
+        address_validation: job.as_ref().and_then(|job| job.summary.clone()),
+        unmapped_packages: Some(unmapped_packages),
+        validation_job: job.as_ref().map(|job| job.info()),
     }))
 }
 
+/// Obtener la tournée pasando por el cache (stale-while-revalidate)
+async fn fetch_tournee_cached(
+    state: &AppState,
+    sso_hopps: &str,
+    societe: &str,
+    username: &str,
+    date: &str,
+) -> Result<String, ColisPriveClientError> {
+    let client = state.colis_prive.clone();
+    let (sso_hopps, owned_societe, owned_username, owned_date) =
+        (sso_hopps.to_string(), societe.to_string(), username.to_string(), date.to_string());
+
+    state.tournee_cache
+        .get_or_fetch(societe, username, date, move || async move {
+            client.get_tournee_raw(&sso_hopps, &owned_societe, &owned_username, &owned_date).await
+        })
+        .await
+}
+
+/// Obtener la tournée; si Colis Privé rechaza el token (`AuthExpired`) se
+/// descarta, se re-autentica una vez y se reintenta
+async fn fetch_tournee_reauth(
+    state: &AppState,
+    sso_hopps: &str,
+    societe: &str,
+    username: &str,
+    date: &str,
+) -> Result<String, ColisPriveClientError> {
+    match fetch_tournee_cached(state, sso_hopps, societe, username, date).await {
+        Err(ColisPriveClientError::AuthExpired(status)) => {
+            log::warn!("🔑 Colis Privé rechazó el token de {}:{} (HTTP {}), re-autenticando", societe, username, status);
+            let token = auto_auth(state, username, societe, Some(sso_hopps))
+                .await
+                .map_err(|_| ColisPriveClientError::AuthExpired(status))?;
+            fetch_tournee_cached(state, &token, societe, username, date).await
+        }
+        other => other,
+    }
+}
+
+/// POST /api/colis-prive/tournee/invalidate - Invalidar la tournée cacheada tras marcar una entrega
+pub async fn invalidate_tournee_cache(
+    State(state): State<AppState>,
+    Json(request): Json<InvalidateTourneeRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let societe = resolve_societe(&state, &request.matricule, request.societe.as_deref()).await?;
+    let date = request.date.unwrap_or_else(|| {
+        chrono::Utc::now().format("%Y-%m-%d").to_string()
+    });
+
+    state.tournee_cache
+        .invalidate(&societe, &request.matricule, &date)
+        .await
+        .map_err(|e| {
+            log::error!("❌ Error invalidando tournée cacheada: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })?;
+
+    Ok(Json(json!({
+        "success": true,
+        "matricule": request.matricule,
+        "date": date
+    })))
+}
+
+/// Importar la tournée en `tournees`/`packages` y registrar la ejecución en `sync_log`;
+/// devuelve la tournée persistida
+async fn persist_tournee(
+    state: &AppState,
+    societe: &str,
+    matricule: &str,
+    date: &str,
+    code_tournee: Option<String>,
+    packages: &[crate::services::PackageData],
+) -> Option<Uuid> {
+    use crate::services::{import_tournee, resolve_import_context, ImportedTournee};
+
+    let tournee_date = match chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(d) => d,
+        Err(e) => {
+            log::warn!("⚠️ Fecha '{}' inválida, tournée no persistida: {}", date, e);
+            return None;
+        }
+    };
+
+    let ctx = match resolve_import_context(&state.pool, societe, matricule).await {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            log::warn!("⚠️ Tournée de {}:{} no persistida: {}", societe, matricule, e);
+            return None;
+        }
+    };
+
+    let tournee = ImportedTournee {
+        external_tournee_id: code_tournee.clone().unwrap_or_else(|| format!("{}_{}", societe, matricule)),
+        tournee_number: code_tournee,
+        date: tournee_date,
+        packages,
+    };
+
+    match import_tournee(&state.pool, &ctx, &tournee).await {
+        Ok(report) => Some(report.tournee_id),
+        Err(e) => {
+            log::error!("❌ Error importando tournée de {}:{}: {}", societe, matricule, e);
+            None
+        }
+    }
+}
+
 /// POST /api/colis-prive/tournee - Obtener tournée (IMPLEMENTACIÓN COMPLETA)
 pub async fn get_tournee_data(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(request): Json<GetTourneeRequest>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     log::info!("🔄 Obteniendo tournée para: {}", request.matricule);
+    authorize_driver(&state, &headers, &request.username, Some(&request.societe)).await?;
     
     // 🆕 PASO 1: OBTENER TOKEN DEL ESTADO COMPARTIDO (AUTENTICACIÓN DINÁMICA)
     log::info!("🔍 Buscando token para username: '{}', societe: '{}'", request.username, request.societe);
     
     let sso_hopps = match state.get_auth_token(&request.username, &request.societe).await {
         Some(auth_token) => {
-            if auth_token.is_expired() {
-                log::warn!("⚠️ Token expirado para {}:{}, necesitamos re-autenticar", request.societe, request.username);
-                return Err(StatusCode::UNAUTHORIZED);
-            }
             log::info!("✅ Usando token almacenado para {}:{}", request.societe, request.username);
             auth_token.token
         }
@@ -473,85 +445,16 @@ pub async fn get_tournee_data(
     };
 
     // 🆕 PASO 2: Hacer petición REAL a Colis Privé para obtener tournée
-    let tournee_url = "https://wstournee-v2.colisprive.com/WS-TourneeColis/api/getTourneeByMatriculeDistributeurDateDebut_POST";
-
-    // 🆕 PAYLOAD YA DEFINIDO ARRIBA
-
-    log::info!("📤 Enviando petición tournée a: {}", tournee_url);
-    
-    // 🔍 LOGGING DETALLADO DE HEADERS Y TOKEN
-    log::info!("🔑 TOKEN USADO: {}", sso_hopps);
-    log::info!("📋 HEADERS SIMPLIFICADOS (como curl):");
-    log::info!("   Content-Type: application/json");
-    log::info!("   SsoHopps: {}", sso_hopps);
-    log::info!("   User-Agent: curl/7.68.0");
-
-        // 🆕 USAR CURL DIRECTAMENTE EN LUGAR DE REQWEST
-    let matricule_completo = format!("{}_{}", request.societe, request.username);
     let date = request.date.clone().unwrap_or_else(|| "2025-09-01".to_string());
-    
-    let tournee_payload = json!({
-        "Matricule": matricule_completo,
-        "DateDebut": date
-    });
-    
-    let payload_str = serde_json::to_string(&tournee_payload).map_err(|e| {
-        log::error!("❌ Error serializando payload: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    
-    let curl_output = std::process::Command::new("curl")
-        .arg("-X")
-        .arg("POST")
-        .arg(tournee_url)
-        .arg("-H")
-        .arg("Content-Type: application/json")
-        .arg("-H")
-        .arg(format!("SsoHopps: {}", sso_hopps))
-        .arg("-d")
-        .arg(&payload_str)
-        .arg("--max-time")
-        .arg("30")
-        .arg("--silent")
-        .arg("--show-error")
-        .output()
+
+    // 🔧 PASO 3: El cliente decodifica base64 si es necesario
+    let decoded_data = fetch_tournee_reauth(&state, &sso_hopps, &request.societe, &request.username, &date)
+        .await
         .map_err(|e| {
-            log::error!("❌ Error ejecutando curl: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            log::error!("❌ Error obteniendo tournée de Colis Privé: {}", e);
+            e.status_code()
         })?;
 
-    if !curl_output.status.success() {
-        let error_msg = String::from_utf8_lossy(&curl_output.stderr);
-        log::error!("❌ Curl falló: {}", error_msg);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-    }
-
-    let response_body = String::from_utf8_lossy(&curl_output.stdout);
-    log::info!("📥 Respuesta de curl: {}", response_body);
-
-    // 🆕 PARSEAR RESPUESTA DE CURL
-    let tournee_text = response_body.to_string();
-
-    log::info!("📥 Respuesta tournée recibida: {} bytes", tournee_text.len());
-
-    // 🔧 PASO 3: Decodificar base64 si es necesario
-    let decoded_data = if tournee_text.starts_with('"') && tournee_text.ends_with('"') {
-        let base64_content = &tournee_text[1..tournee_text.len()-1];
-        match base64::Engine::decode(&base64::engine::general_purpose::STANDARD, base64_content) {
-            Ok(decoded) => {
-                log::info!("✅ Datos decodificados de base64: {} bytes", decoded.len());
-                String::from_utf8(decoded).unwrap_or(tournee_text)
-            },
-            Err(_) => {
-                log::info!("ℹ️ No se pudo decodificar base64, usando texto original");
-                tournee_text
-            }
-        }
-    } else {
-        log::info!("ℹ️ Respuesta no es base64, usando texto original");
-        tournee_text
-    };
-
     // 🔧 PASO 4: Respuesta final con datos reales de Colis Privé
     let response = json!({
         "success": true,
@@ -560,7 +463,7 @@ pub async fn get_tournee_data(
         "metadata": {
             "matricule": request.matricule,
             "societe": request.societe,
-            "date": request.date.clone().unwrap_or_else(|| "2025-08-28".to_string()),
+            "date": date,
             "api_type": "web",
             "token_used": true,
             "headers_sent": true,
@@ -621,52 +524,122 @@ pub async fn health_check_colis_prive() -> Result<Json<serde_json::Value>, Statu
 // FUNCIONES DE AUTENTICACIÓN AUTOMÁTICA
 // ====================================================================
 
-/// 🆕 FUNCIÓN DE AUTENTICACIÓN AUTOMÁTICA
-/// Intenta autenticar automáticamente cuando no hay token disponible
+/// Empresa del usuario autenticado y société del driver. Se comprueba que
+/// ambos son de esa empresa antes de consultar la bóveda o importar nada.
+async fn authorize_driver(
+    state: &AppState,
+    headers: &HeaderMap,
+    username: &str,
+    requested: Option<&str>,
+) -> Result<(Uuid, String), StatusCode> {
+    let claims = require_user(headers, &JwtConfig::from(&state.config), &state.pool)
+        .await
+        .map_err(|e| {
+            log::warn!("⚠️ Request sin usuario válido para {}: {}", username, e);
+            StatusCode::UNAUTHORIZED
+        })?;
+    let company_id = Uuid::parse_str(&claims.company_id).map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+    match state.credential_vault.find_company_societe(company_id, username, requested).await {
+        Ok(Some(societe)) => Ok((company_id, societe)),
+        Ok(None) => {
+            log::warn!("⚠️ {} (société {:?}) no pertenece a la empresa {}", username, requested, company_id);
+            Err(StatusCode::FORBIDDEN)
+        }
+        Err(VaultError::AmbiguousDriver(_)) => {
+            log::warn!("⚠️ {} está en varias sociétés de la empresa, la request debe indicar la société", username);
+            Err(StatusCode::CONFLICT)
+        }
+        Err(e) => {
+            log::error!("❌ Error consultando la bóveda de credenciales: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Société del driver: la indicada en la request, la de la bóveda o la de un
+/// login previo vía /auth
+async fn resolve_societe(state: &AppState, username: &str, requested: Option<&str>) -> Result<String, StatusCode> {
+    if let Some(societe) = requested {
+        return Ok(societe.to_string());
+    }
+
+    match state.credential_vault.find_societe_for_driver(username).await {
+        Ok(Some(societe)) => return Ok(societe),
+        Ok(None) => {}
+        Err(VaultError::AmbiguousDriver(_)) => {
+            log::warn!("⚠️ {} tiene credenciales en varias sociétés, la request debe indicar la société", username);
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(e) => log::error!("❌ Error consultando la bóveda de credenciales: {}", e),
+    }
+
+    state.find_societe_for_username(username).await.ok_or_else(|| {
+        log::warn!("⚠️ No se pudo determinar la société de {}", username);
+        StatusCode::UNAUTHORIZED
+    })
+}
+
+/// Autenticación automática sin token previo
 async fn attempt_auto_auth(
     state: &AppState,
     username: &str,
     societe: &str,
+) -> Result<String, anyhow::Error> {
+    auto_auth(state, username, societe, None).await
+}
+
+/// 🆕 FUNCIÓN DE AUTENTICACIÓN AUTOMÁTICA
+/// Intenta autenticar automáticamente cuando no hay token disponible; con
+/// `rejected` se descarta antes ese token (Colis Privé lo ha rechazado aunque
+/// no había expirado)
+async fn auto_auth(
+    state: &AppState,
+    username: &str,
+    societe: &str,
+    rejected: Option<&str>,
 ) -> Result<String, anyhow::Error> {
     use crate::services::colis_prive_service::ColisPriveAuthRequest;
-    
+    use crate::state::AuthToken;
+
     log::info!("🔄 Intentando autenticación automática para {}:{}", societe, username);
-    
-    // 🔑 CREDENCIALES PREDEFINIDAS PARA AUTENTICACIÓN AUTOMÁTICA
-    // En producción, esto debería venir de variables de entorno o configuración segura
-    let credentials = ColisPriveAuthRequest {
-        username: username.to_string(),
-        password: "INTI7518".to_string(), // ⚠️ En producción, usar configuración segura
-        societe: societe.to_string(),
-    };
-    
-    // Intentar autenticación
-    match authenticate_colis_prive_simple(&credentials).await {
-        Ok(auth_response) => {
-            if auth_response.success {
-                if let Some(token) = auth_response.token {
-                    // Almacenar el token en el estado
-                    state.store_auth_token(
-                        username.to_string(),
-                        societe.to_string(),
-                        token.clone(),
-                        24
-                    ).await;
-                    
-                    log::info!("✅ Autenticación automática exitosa para {}:{}", societe, username);
-                    Ok(token)
-                } else {
-                    anyhow::bail!("Token no recibido en la respuesta de autenticación")
-                }
-            } else {
-                anyhow::bail!("Autenticación automática falló: {}", auth_response.message)
-            }
-        }
-        Err(e) => {
-            log::error!("❌ Error en autenticación automática: {}", e);
-            Err(e)
+
+    let login = || async {
+        // 🔑 CREDENCIALES DE LA BÓVEDA (cifradas por empresa y driver)
+        let stored = state.credential_vault
+            .get_by_societe(societe, username)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("No hay credenciales almacenadas para {}:{}", societe, username))?;
+
+        let credentials = ColisPriveAuthRequest {
+            username: stored.username,
+            password: stored.password,
+            societe: stored.societe,
+        };
+
+        let auth_response = authenticate_colis_prive_simple(state, &credentials).await?;
+        if !auth_response.success {
+            anyhow::bail!("Autenticación automática falló: {}", auth_response.message);
         }
+
+        let token = auth_response.token
+            .ok_or_else(|| anyhow::anyhow!("Token no recibido en la respuesta de autenticación"))?;
+
+        Ok(AuthToken::new(token, username.to_string(), societe.to_string(), 24))
+    };
+
+    // Single-flight: si otra request ya está re-autenticando a este driver, reutiliza su token
+    let auth_token = match rejected {
+        Some(rejected) => state.auth_tokens.replace_rejected(username, societe, rejected, login).await,
+        None => state.auth_tokens.get_or_login(username, societe, login).await,
     }
+    .map_err(|e| {
+        log::error!("❌ Error en autenticación automática: {}", e);
+        e
+    })?;
+
+    log::info!("✅ Autenticación automática exitosa para {}:{}", societe, username);
+    Ok(auth_token.token)
 }
 
 // ====================================================================
@@ -1,5 +1,5 @@
 //! Handlers de Companies
-//! 
+//!
 //! Este módulo maneja las operaciones CRUD para empresas.
 
 use axum::{
@@ -7,51 +7,47 @@ use axum::{
     http::StatusCode,
     Json,
 };
-use sqlx::PgPool;
 use uuid::Uuid;
-use validator::Validate;
 
 use crate::{
     models::company::{Company, CompanyResponse, CreateCompanyRequest},
     utils::errors::{AppError, AppResult},
     middleware::auth::AuthenticatedUser,
+    state::FleetState,
 };
 
+/// Columnas de `companies` (timestamps nulos en el schema, no en el modelo)
+const COMPANY_COLUMNS: &str = r#"
+    id, name, address, subscription_plan, subscription_status,
+    max_drivers, max_vehicles,
+    COALESCE(created_at, NOW()) AS created_at,
+    COALESCE(updated_at, NOW()) AS updated_at,
+    deleted_at
+"#;
+
+/// Empresa no eliminada por ID
+async fn find_company(state: &FleetState, id: Uuid) -> AppResult<Option<Company>> {
+    let company = sqlx::query_as::<_, Company>(&format!(
+        "SELECT {} FROM companies WHERE id = $1 AND deleted_at IS NULL",
+        COMPANY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    Ok(company)
+}
+
 /// Handler para listar empresas (solo la empresa del usuario autenticado)
 pub async fn get_companies(
     Extension(user): Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
 ) -> AppResult<Json<Vec<CompanyResponse>>> {
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, name, address, subscription_plan, subscription_status, 
-            max_drivers, max_vehicles, created_at, updated_at, deleted_at
-        FROM companies 
-        WHERE id = $1 
-        AND deleted_at IS NULL
-        "#,
-        user.company_id
-    )
-    .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let companies = if let Some(row) = row {
-        vec![CompanyResponse {
-            id: row.id,
-            name: row.name,
-            address: row.address,
-            subscription_plan: row.subscription_plan,
-            subscription_status: row.subscription_status,
-            max_drivers: row.max_drivers,
-            max_vehicles: row.max_vehicles,
-            created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-            updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-        }]
-    } else {
-        vec![]
-    };
+    let companies = find_company(&state, user.company_id)
+        .await?
+        .into_iter()
+        .map(CompanyResponse::from)
+        .collect();
 
     Ok(Json(companies))
 }
@@ -59,7 +55,7 @@ pub async fn get_companies(
 /// Handler para crear empresa (solo admins)
 pub async fn create_company(
     Extension(_user): Extension<AuthenticatedUser>,
-    State(_state): State<crate::state::AppState>,
+    State(_state): State<FleetState>,
     Json(_company_data): Json<CreateCompanyRequest>,
 ) -> AppResult<Json<CompanyResponse>> {
     // Por ahora retorna Not Implemented
@@ -69,7 +65,7 @@ pub async fn create_company(
 /// Handler para obtener empresa por ID
 pub async fn get_company(
     Extension(user): Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<Json<CompanyResponse>> {
     // Solo puede ver su propia empresa
@@ -77,41 +73,17 @@ pub async fn get_company(
         return Err(AppError::Forbidden("No tienes acceso a esta empresa".to_string()));
     }
 
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, name, address, subscription_plan, subscription_status, 
-            max_drivers, max_vehicles, created_at, updated_at, deleted_at
-        FROM companies 
-        WHERE id = $1 
-        AND deleted_at IS NULL
-        "#,
-        id
-    )
-    .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
-    .ok_or_else(|| AppError::NotFound("Empresa no encontrada".to_string()))?;
-
-    let company_response = CompanyResponse {
-        id: row.id,
-        name: row.name,
-        address: row.address,
-        subscription_plan: row.subscription_plan,
-        subscription_status: row.subscription_status,
-        max_drivers: row.max_drivers,
-        max_vehicles: row.max_vehicles,
-        created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-        updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-    };
+    let company = find_company(&state, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Empresa no encontrada".to_string()))?;
 
-    Ok(Json(company_response))
+    Ok(Json(CompanyResponse::from(company)))
 }
 
 /// Handler para actualizar empresa (versión simplificada)
 pub async fn update_company(
     Extension(_user): Extension<AuthenticatedUser>,
-    State(_state): State<crate::state::AppState>,
+    State(_state): State<FleetState>,
     Path(_id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
     // Por ahora retorna Not Implemented
@@ -121,7 +93,7 @@ pub async fn update_company(
 /// Handler para eliminar empresa (soft delete)
 pub async fn delete_company(
     Extension(_user): Extension<AuthenticatedUser>,
-    State(_state): State<crate::state::AppState>,
+    State(_state): State<FleetState>,
     Path(_id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
     // Por ahora retorna Not Implemented - eliminar empresa es operación muy delicada
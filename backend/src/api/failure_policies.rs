@@ -0,0 +1,95 @@
+//! Handlers de políticas de entrega fallida
+//!
+//! Cada empresa configura, por motivo de fallo, el siguiente paso y el número
+//! máximo de intentos; sin configuración se aplica la política por defecto.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use validator::Validate;
+
+use crate::{
+    lifecycle::failure_policy::{FailureAction, FailurePolicy},
+    middleware::auth::AuthenticatedUser,
+    models::failure_policy::{
+        FailurePolicyResponse, MaxAttemptsEntry, MaxAttemptsFilters, UpsertFailurePolicyRequest,
+    },
+    models::package::DeliveryFailureReason,
+    services::failure_policies,
+    state::FleetState,
+    utils::errors::{AppError, AppResult},
+};
+
+/// Políticas efectivas de la empresa para todos los motivos de fallo
+pub async fn get_failure_policies(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+) -> AppResult<Json<Vec<FailurePolicyResponse>>> {
+    let configured = failure_policies::configured_policies(&state.pool, user.company_id).await?;
+
+    let policies = DeliveryFailureReason::ALL
+        .into_iter()
+        .map(|reason| match configured.iter().find(|(r, _)| *r == reason) {
+            Some((_, policy)) => FailurePolicyResponse::new(reason, policy.clone(), false),
+            None => FailurePolicyResponse::new(reason, FailurePolicy::default_for(reason), true),
+        })
+        .collect();
+
+    Ok(Json(policies))
+}
+
+/// Configurar la política de un motivo de fallo
+pub async fn update_failure_policy(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(reason): Path<DeliveryFailureReason>,
+    Json(policy_data): Json<UpsertFailurePolicyRequest>,
+) -> AppResult<Json<FailurePolicyResponse>> {
+    policy_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let action = FailureAction::parse(&policy_data.failure_action).ok_or_else(|| {
+        AppError::BadRequest(format!(
+            "Acción desconocida: '{}' (admitidas: reschedule, relay_point, return_to_sender)",
+            policy_data.failure_action
+        ))
+    })?;
+    if action == FailureAction::RelayPoint && policy_data.relay_point_address.is_none() {
+        return Err(AppError::BadRequest("relay_point requiere relay_point_address".to_string()));
+    }
+
+    failure_policies::upsert_policy(&state.pool, user.company_id, reason, action, &policy_data, user.user_id).await?;
+
+    let policy = FailurePolicy {
+        action,
+        max_attempts: policy_data.max_attempts,
+        relay_point_name: policy_data.relay_point_name,
+        relay_point_address: policy_data.relay_point_address,
+    };
+    Ok(Json(FailurePolicyResponse::new(reason, policy, false)))
+}
+
+/// Volver a la política por defecto de un motivo de fallo
+pub async fn delete_failure_policy(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(reason): Path<DeliveryFailureReason>,
+) -> AppResult<StatusCode> {
+    if !failure_policies::delete_policy(&state.pool, user.company_id, reason).await? {
+        return Err(AppError::NotFound("La empresa no tiene política para este motivo".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Informe de paquetes devueltos al remitente por agotar los intentos
+pub async fn get_max_attempts_report(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Query(filters): Query<MaxAttemptsFilters>,
+) -> AppResult<Json<Vec<MaxAttemptsEntry>>> {
+    let report = failure_policies::max_attempts_report(&state.pool, user.company_id, &filters).await?;
+    Ok(Json(report))
+}
@@ -1,194 +1,156 @@
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::HeaderMap,
     response::Json,
-    routing::post,
-    Router,
 };
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::services::geocoding_service::{GeocodingRequest, GeocodingResponse, GeocodingService};
+use crate::services::geocoder::ReverseCandidate;
+use crate::services::geocoding_cache::GeocodingCacheStats;
+use crate::services::geocoding_usage::GeocodingUsageReport;
+use crate::services::route_optimizer::GeoPoint;
 use crate::state::AppState;
+use crate::utils::errors::{AppError, AppResult};
+use crate::utils::jwt::{require_admin, require_user, JwtConfig};
 
-#[derive(Debug, Deserialize)]
-pub struct GeocodingApiRequest {
-    pub address: String,
-}
+/// Candidatos por defecto y máximo del geocoding inverso
+const DEFAULT_REVERSE_LIMIT: usize = 5;
+const MAX_REVERSE_LIMIT: usize = 20;
 
+/// Request de geocoding inverso (posición GPS capturada por el driver)
 #[derive(Debug, Deserialize)]
-pub struct BatchGeocodingApiRequest {
-    pub addresses: Vec<String>,
+pub struct ReverseGeocodingRequest {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub limit: Option<usize>,
+    /// Paquete al que asignar la posición (p. ej. dirección en ManualRequired)
+    pub package_id: Option<Uuid>,
 }
 
 #[derive(Debug, Serialize)]
-pub struct GeocodingApiResponse {
-    pub success: bool,
-    pub latitude: Option<f64>,
-    pub longitude: Option<f64>,
-    pub formatted_address: Option<String>,
-    pub message: Option<String>,
-    pub error: Option<String>,
+pub struct ReverseGeocodingResponse {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Direcciones más cercanas, de la más cercana a la más lejana
+    pub candidates: Vec<ReverseCandidate>,
+    /// Paquete actualizado con la posición, si se pidió
+    pub package_id: Option<Uuid>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct BatchGeocodingApiResponse {
-    pub success: bool,
-    pub results: Vec<GeocodingApiResponse>,
-    pub message: Option<String>,
-    pub error: Option<String>,
+/// Request para invalidar una dirección cacheada
+#[derive(Debug, Deserialize)]
+pub struct InvalidateGeocodingRequest {
+    pub address: String,
 }
 
-pub fn create_geocoding_router() -> Router<AppState> {
-    Router::new()
-        .route("/geocoding", post(geocode_address))
-        .route("/geocoding/batch", post(batch_geocode_addresses))
+#[derive(Debug, Serialize)]
+pub struct InvalidateGeocodingResponse {
+    pub address: String,
+    pub removed: bool,
 }
 
-/// Endpoint para geocodificar una sola dirección
-pub async fn geocode_address(
+/// POST /api/geocoding/reverse - Direcciones cercanas a una posición y, opcionalmente,
+/// asignar esa posición a un paquete
+pub async fn reverse_geocode(
     State(state): State<AppState>,
-    Json(request): Json<GeocodingApiRequest>,
-) -> Result<Json<GeocodingApiResponse>, StatusCode> {
-    log::info!("🗺️ Geocoding request received: {}", request.address);
-
-    // Validar que la dirección no esté vacía
-    if request.address.trim().is_empty() {
-        log::warn!("⚠️ Empty address provided");
-        return Ok(Json(GeocodingApiResponse {
-            success: false,
-            latitude: None,
-            longitude: None,
-            formatted_address: None,
-            message: None,
-            error: Some("Address cannot be empty".to_string()),
-        }));
+    headers: HeaderMap,
+    Json(request): Json<ReverseGeocodingRequest>,
+) -> AppResult<Json<ReverseGeocodingResponse>> {
+    let claims = require_user(&headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    let company_id = Uuid::parse_str(&claims.company_id)
+        .map_err(|_| AppError::Unauthorized("company_id inválido en el token".to_string()))?;
+
+    if !(-90.0..=90.0).contains(&request.latitude) || !(-180.0..=180.0).contains(&request.longitude) {
+        return Err(AppError::BadRequest("Coordenadas fuera de rango".to_string()));
     }
 
-    // Obtener el token de Mapbox del estado
-    let mapbox_token = match &state.config.mapbox_token {
-        Some(token) => token.clone(),
-        None => {
-            log::error!("❌ Mapbox token not configured");
-            return Ok(Json(GeocodingApiResponse {
-                success: false,
-                latitude: None,
-                longitude: None,
-                formatted_address: None,
-                message: None,
-                error: Some("Mapbox token not configured".to_string()),
-            }));
-        }
-    };
-
-    // Crear el servicio de geocoding
-    let geocoding_service = GeocodingService::new(mapbox_token);
-
-    // Realizar la geocodificación
-    match geocoding_service.geocode_address(&request.address).await {
-        Ok(response) => {
-            log::info!("✅ Geocoding successful for: {}", request.address);
-            Ok(Json(GeocodingApiResponse {
-                success: response.success,
-                latitude: response.latitude,
-                longitude: response.longitude,
-                formatted_address: response.formatted_address,
-                message: response.message,
-                error: response.error,
-            }))
-        }
-        Err(e) => {
-            log::error!("❌ Geocoding error for {}: {}", request.address, e);
-            Ok(Json(GeocodingApiResponse {
-                success: false,
-                latitude: None,
-                longitude: None,
-                formatted_address: None,
-                message: None,
-                error: Some(format!("Geocoding failed: {}", e)),
-            }))
+    let position = GeoPoint { latitude: request.latitude, longitude: request.longitude };
+    let limit = request.limit.unwrap_or(DEFAULT_REVERSE_LIMIT).clamp(1, MAX_REVERSE_LIMIT);
+
+    log::info!("📍 Reverse geocoding ({}, {})", position.latitude, position.longitude);
+    let candidates = state.geocoder_for(Some(company_id))
+        .reverse(position, limit)
+        .await
+        .map_err(|e| AppError::Internal(format!("Error en geocoding inverso: {}", e)))?;
+
+    if let Some(package_id) = request.package_id {
+        let updated = sqlx::query(
+            r#"
+            UPDATE packages
+            SET delivery_coordinates = point($1::float8, $2::float8), coordinates_confirmed_at = NOW(), updated_at = NOW()
+            WHERE id = $3 AND company_id = $4 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(position.longitude)
+        .bind(position.latitude)
+        .bind(package_id)
+        .bind(company_id)
+        .execute(&state.pool)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Paquete {} no encontrado", package_id)));
         }
+        log::info!("✅ Coordenadas del paquete {} corregidas por el driver", package_id);
     }
+
+    Ok(Json(ReverseGeocodingResponse {
+        latitude: position.latitude,
+        longitude: position.longitude,
+        candidates,
+        package_id: request.package_id,
+    }))
 }
 
-/// Endpoint para geocodificar múltiples direcciones en lote
-pub async fn batch_geocode_addresses(
+/// GET /api/geocoding/usage - Consumo de geocoding de la empresa y estado de los presupuestos
+pub async fn geocoding_usage(
     State(state): State<AppState>,
-    Json(request): Json<BatchGeocodingApiRequest>,
-) -> Result<Json<BatchGeocodingApiResponse>, StatusCode> {
-    log::info!("🗺️ Batch geocoding request received: {} addresses", request.addresses.len());
-
-    // Validar que haya direcciones
-    if request.addresses.is_empty() {
-        log::warn!("⚠️ No addresses provided for batch geocoding");
-        return Ok(Json(BatchGeocodingApiResponse {
-            success: false,
-            results: vec![],
-            message: None,
-            error: Some("No addresses provided".to_string()),
-        }));
-    }
+    headers: HeaderMap,
+) -> AppResult<Json<GeocodingUsageReport>> {
+    let claims = require_user(&headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    let company_id = Uuid::parse_str(&claims.company_id)
+        .map_err(|_| AppError::Unauthorized("company_id inválido en el token".to_string()))?;
+
+    let report = state.geocoding_usage
+        .report(company_id)
+        .await
+        .map_err(|e| AppError::Internal(format!("Error obteniendo el consumo de geocoding: {}", e)))?;
+
+    Ok(Json(report))
+}
 
-    // Validar límite de direcciones (máximo 50 según la documentación)
-    if request.addresses.len() > 50 {
-        log::warn!("⚠️ Too many addresses for batch geocoding: {}", request.addresses.len());
-        return Ok(Json(BatchGeocodingApiResponse {
-            success: false,
-            results: vec![],
-            message: None,
-            error: Some("Maximum 50 addresses allowed per batch".to_string()),
-        }));
-    }
+/// GET /api/admin/geocoding/cache/stats - Aciertos y fallos del cache de geocoding
+pub async fn geocoding_cache_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> AppResult<Json<GeocodingCacheStats>> {
+    require_admin(&headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    Ok(Json(state.geocoding_cache.stats().await))
+}
 
-    // Obtener el token de Mapbox del estado
-    let mapbox_token = match &state.config.mapbox_token {
-        Some(token) => token.clone(),
-        None => {
-            log::error!("❌ Mapbox token not configured");
-            return Ok(Json(BatchGeocodingApiResponse {
-                success: false,
-                results: vec![],
-                message: None,
-                error: Some("Mapbox token not configured".to_string()),
-            }));
-        }
-    };
+/// DELETE /api/admin/geocoding/cache - Invalidar una dirección (p. ej. coordenadas erróneas)
+pub async fn invalidate_geocoding_cache(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<InvalidateGeocodingRequest>,
+) -> AppResult<Json<InvalidateGeocodingResponse>> {
+    require_admin(&headers, &JwtConfig::from(&state.config), &state.pool).await?;
 
-    // Crear el servicio de geocoding
-    let geocoding_service = GeocodingService::new(mapbox_token);
-
-    // Realizar la geocodificación en lote
-    match geocoding_service.batch_geocode(request.addresses).await {
-        Ok(responses) => {
-            let api_responses: Vec<GeocodingApiResponse> = responses
-                .into_iter()
-                .map(|response| GeocodingApiResponse {
-                    success: response.success,
-                    latitude: response.latitude,
-                    longitude: response.longitude,
-                    formatted_address: response.formatted_address,
-                    message: response.message,
-                    error: response.error,
-                })
-                .collect();
-
-            log::info!("✅ Batch geocoding completed: {} results", api_responses.len());
-            Ok(Json(BatchGeocodingApiResponse {
-                success: true,
-                results: api_responses,
-                message: Some("Batch geocoding completed".to_string()),
-                error: None,
-            }))
-        }
-        Err(e) => {
-            log::error!("❌ Batch geocoding error: {}", e);
-            Ok(Json(BatchGeocodingApiResponse {
-                success: false,
-                results: vec![],
-                message: None,
-                error: Some(format!("Batch geocoding failed: {}", e)),
-            }))
-        }
+    if request.address.trim().is_empty() {
+        return Err(AppError::BadRequest("address es obligatorio".to_string()));
     }
+
+    let removed = state.geocoding_cache
+        .invalidate(&request.address)
+        .await
+        .map_err(|e| AppError::Internal(format!("Error invalidando geocoding: {}", e)))?;
+
+    Ok(Json(InvalidateGeocodingResponse {
+        address: request.address,
+        removed,
+    }))
 }
 
 #[cfg(test)]
@@ -0,0 +1,112 @@
+//! Administración de integraciones con transportistas
+//!
+//! Endpoints de admin para rotar y probar las credenciales de Colis Privé
+//! guardadas en la bóveda, sin devolverlas nunca en claro.
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::services::DriverCredentialInfo;
+use crate::state::AppState;
+use crate::utils::errors::{AppError, AppResult};
+use crate::utils::jwt::{require_admin, JwtConfig};
+
+/// Request para rotar las credenciales de un driver
+#[derive(Debug, Deserialize)]
+pub struct RotateCredentialsRequest {
+    pub societe: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// Request para probar las credenciales de un driver
+#[derive(Debug, Deserialize)]
+pub struct TestCredentialsRequest {
+    pub username: String,
+}
+
+/// Resultado de probar credenciales contra Colis Privé
+#[derive(Debug, Serialize)]
+pub struct TestCredentialsResponse {
+    pub success: bool,
+    pub username: String,
+    pub message: String,
+}
+
+/// Empresa del admin autenticado
+async fn admin_company_id(state: &AppState, headers: &HeaderMap) -> AppResult<Uuid> {
+    let claims = require_admin(headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    Uuid::parse_str(&claims.company_id)
+        .map_err(|_| AppError::Unauthorized("company_id inválido en el token".to_string()))
+}
+
+/// GET /api/admin/integrations/colis-prive/credentials - Drivers con credenciales (sin secretos)
+pub async fn list_credentials(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> AppResult<Json<Vec<DriverCredentialInfo>>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+    Ok(Json(state.credential_vault.list_drivers(company_id).await?))
+}
+
+/// PUT /api/admin/integrations/colis-prive/credentials - Rotar credenciales de un driver
+pub async fn rotate_credentials(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<RotateCredentialsRequest>,
+) -> AppResult<Json<DriverCredentialInfo>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+
+    if request.societe.trim().is_empty() || request.username.trim().is_empty() || request.password.is_empty() {
+        return Err(AppError::BadRequest("societe, username y password son obligatorios".to_string()));
+    }
+
+    let info = state.credential_vault
+        .store(company_id, request.societe.trim(), request.username.trim(), &request.password)
+        .await?;
+
+    // El token cacheado se obtuvo con la contraseña anterior
+    state.auth_tokens.invalidate(request.username.trim(), request.societe.trim()).await;
+
+    Ok(Json(info))
+}
+
+/// POST /api/admin/integrations/colis-prive/credentials/test - Probar login con las credenciales guardadas
+pub async fn test_credentials(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<TestCredentialsRequest>,
+) -> AppResult<Json<TestCredentialsResponse>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+
+    let credentials = state.credential_vault
+        .get_by_company(company_id, &request.username)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Sin credenciales para {}", request.username)))?;
+
+    let response = match state.colis_prive
+        .login(&credentials.societe, &credentials.username, &credentials.password)
+        .await
+    {
+        Ok(_) => TestCredentialsResponse {
+            success: true,
+            username: credentials.username,
+            message: "Credenciales válidas".to_string(),
+        },
+        Err(e) => {
+            log::warn!("⚠️ Prueba de credenciales fallida para {}:{}: {}", credentials.societe, credentials.username, e);
+            TestCredentialsResponse {
+                success: false,
+                username: credentials.username,
+                message: e.to_string(),
+            }
+        }
+    };
+
+    Ok(Json(response))
+}
@@ -3,11 +3,30 @@
 //! Este módulo contiene todos los handlers HTTP para la API Web de Colis Privé,
 //! organizados por entidad del negocio.
 
+pub mod address_corrections;
+pub mod analytics;
+pub mod auth;
 pub mod colis_prive;
 pub mod colis_prive_router;
+pub mod companies;
+pub mod failure_policies;
 pub mod geocoding;
+pub mod integrations;
+pub mod packages;
+pub mod route_optimization;
+pub mod routers;
+pub mod sectors;
+pub mod sync;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod tournees;
+pub mod uploads;
+pub mod users;
+pub mod validation_jobs;
+pub mod vehicles;
 
 pub use colis_prive_router::*;
+pub use routers::create_v1_router;
 
 use axum::Router;
 use crate::state::AppState;
@@ -16,5 +35,4 @@ use crate::state::AppState;
 pub fn create_api_router() -> Router<AppState> {
     Router::new()
         .nest("/colis-prive", create_colis_prive_router())
-        .nest("/api", geocoding::create_geocoding_router())
 }
@@ -1,5 +1,5 @@
 //! Handlers de Packages
-//! 
+//!
 //! Este módulo maneja las operaciones CRUD para paquetes.
 
 use axum::{
@@ -7,181 +7,139 @@ use axum::{
     http::StatusCode,
     Json,
 };
-use chrono::Utc;
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
+    lifecycle::check_package_transition,
+    lifecycle::failure_policy::{decide, NextStep},
     models::package::{
-        Package, DeliveryStatus, DeliveryFailureReason, PackageResponse, PackageListResponse,
-        CreatePackageRequest, UpdatePackageRequest, PackageFilters,
+        Package, PackageResponse, PackageListResponse, DeliveryStatus, DeliveryFailureReason,
+        CreatePackageRequest, PackageFilters,
         MarkDeliveredRequest, MarkFailedRequest,
     },
+    models::tournee::{StatusChangeRequest, StatusHistoryEntry, TourneeStatus},
+    services::offline_sync::{self, SyncField},
+    services::failure_policies,
+    services::{proof_of_delivery::package_prefix, status_history},
+    api::uploads::check_uploaded,
     utils::errors::{AppError, AppResult},
+    utils::validation::{parse_filter, validate_date, validate_datetime, validate_uuid},
     middleware::auth::AuthenticatedUser,
+    state::FleetState,
 };
 
+/// Columnas de `packages` que mapea `Package` (las coordenadas POINT no se leen)
+pub(crate) const PACKAGE_COLUMNS: &str = r#"
+    id, company_id, tournee_id, tracking_number, external_tracking_number,
+    package_origin, external_package_id, integration_id, package_type,
+    package_weight, package_dimensions, delivery_status,
+    delivery_date, delivery_time, COALESCE(delivery_attempts, 0) AS delivery_attempts,
+    recipient_name, recipient_phone, delivery_address, delivery_instructions,
+    failure_reason, failure_notes, reschedule_date,
+    failure_action, relay_point_address, carried_over_from,
+    delivery_photo, COALESCE(signature_required, FALSE) AS signature_required,
+    signature_image, signature_photo,
+    delivery_duration_minutes, driver_notes, package_condition,
+    created_at, updated_at, deleted_at
+"#;
+
+/// Bloquear el paquete (FOR UPDATE) y su tournée en modo compartido, para que
+/// la tournée no se complete mientras cambia el estado del paquete.
+/// Devuelve el estado del paquete, el de la tournée y si se exige firma.
+pub(crate) async fn lock_package(
+    conn: &mut PgConnection,
+    id: Uuid,
+    company_id: Uuid,
+) -> AppResult<(DeliveryStatus, TourneeStatus, bool)> {
+    sqlx::query_as::<_, (DeliveryStatus, TourneeStatus, bool)>(
+        r#"
+        SELECT p.delivery_status, t.tournee_status, COALESCE(p.signature_required, FALSE)
+        FROM packages p
+        JOIN tournees t ON t.id = p.tournee_id
+        WHERE p.id = $1 AND p.company_id = $2 AND p.deleted_at IS NULL
+        FOR UPDATE OF p
+        FOR SHARE OF t
+        "#,
+    )
+    .bind(id)
+    .bind(company_id)
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Paquete no encontrado".to_string()))
+}
+
 /// Obtener todos los paquetes con filtros
 pub async fn get_packages(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Query(filters): Query<PackageFilters>,
 ) -> AppResult<Json<Vec<PackageListResponse>>> {
     let limit = filters.limit.unwrap_or(50).min(100);
     let offset = filters.offset.unwrap_or(0);
+    let tournee_id = parse_filter(filters.tournee_id.as_ref(), "tournee_id", validate_uuid)?;
+    let date_from = parse_filter(filters.delivery_date_from.as_ref(), "delivery_date_from", validate_date)?;
+    let date_to = parse_filter(filters.delivery_date_to.as_ref(), "delivery_date_to", validate_date)?;
+    let created_after = parse_filter(filters.created_after.as_ref(), "created_after", validate_datetime)?;
+    let created_before = parse_filter(filters.created_before.as_ref(), "created_before", validate_datetime)?;
 
-    let rows = sqlx::query!(
+    let packages = sqlx::query_as::<_, Package>(&format!(
         r#"
-        SELECT 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, delivery_status as "delivery_status: String",
-            delivery_date, delivery_attempts, recipient_name, delivery_address,
-            failure_reason as "failure_reason: String", created_at, updated_at, deleted_at
-        FROM packages 
-        WHERE company_id = $1 
+        SELECT {}
+        FROM packages
+        WHERE company_id = $1
         AND deleted_at IS NULL
+        AND ($4::text IS NULL OR delivery_status::text = $4)
+        AND ($5::uuid IS NULL OR tournee_id = $5)
+        AND ($6::text IS NULL OR tracking_number = $6)
+        AND ($7::text IS NULL OR external_tracking_number = $7)
+        AND ($8::text IS NULL OR package_origin = $8)
+        AND ($9::date IS NULL OR delivery_date >= $9)
+        AND ($10::date IS NULL OR delivery_date <= $10)
+        AND ($11::text IS NULL OR failure_reason::text = $11)
+        AND ($12::timestamptz IS NULL OR created_at > $12)
+        AND ($13::timestamptz IS NULL OR created_at < $13)
         ORDER BY created_at DESC
         LIMIT $2 OFFSET $3
         "#,
-        user.company_id,
-        limit,
-        offset
-    )
+        PACKAGE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(limit)
+    .bind(offset)
+    .bind(&filters.delivery_status)
+    .bind(tournee_id)
+    .bind(&filters.tracking_number)
+    .bind(&filters.external_tracking_number)
+    .bind(&filters.package_origin)
+    .bind(date_from)
+    .bind(date_to)
+    .bind(&filters.failure_reason)
+    .bind(created_after)
+    .bind(created_before)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let packages: Vec<PackageListResponse> = rows
-        .into_iter()
-        .map(|row| {
-            let package = Package {
-                id: row.id,
-                company_id: row.company_id,
-                tournee_id: row.tournee_id,
-                tracking_number: row.tracking_number,
-                external_tracking_number: row.external_tracking_number,
-                package_origin: row.package_origin,
-                external_package_id: row.external_package_id,
-                integration_id: None,
-                package_type: None,
-                package_weight: None,
-                package_dimensions: None,
-                delivery_status: match row.delivery_status.as_str() {
-                    "pending" => DeliveryStatus::Pending,
-                    "intransit" => DeliveryStatus::InTransit,
-                    "outfordelivery" => DeliveryStatus::OutForDelivery,
-                    "delivered" => DeliveryStatus::Delivered,
-                    "failed" => DeliveryStatus::Failed,
-                    "returned" => DeliveryStatus::Returned,
-                    "cancelled" => DeliveryStatus::Cancelled,
-                    _ => DeliveryStatus::Pending,
-                },
-                delivery_date: row.delivery_date,
-                delivery_time: None,
-                delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-                recipient_name: row.recipient_name,
-                recipient_phone: None,
-                delivery_address: row.delivery_address,
-                delivery_instructions: None,
-                failure_reason: row.failure_reason.map(|r| match r.as_str() {
-                    "recipientnothome" => DeliveryFailureReason::RecipientNotHome,
-                    "wrongaddress" => DeliveryFailureReason::WrongAddress,
-                    "packagedamaged" => DeliveryFailureReason::PackageDamaged,
-                    "refuseddelivery" => DeliveryFailureReason::RefusedDelivery,
-                    "securityrestriction" => DeliveryFailureReason::SecurityRestriction,
-                    "weatherconditions" => DeliveryFailureReason::WeatherConditions,
-                    "vehiclebreakdown" => DeliveryFailureReason::VehicleBreakdown,
-                    "driveremergency" => DeliveryFailureReason::DriverEmergency,
-                    _ => DeliveryFailureReason::RecipientNotHome,
-                }),
-                failure_notes: None,
-                reschedule_date: None,
-                delivery_photo: None,
-                signature_required: false,
-                signature_image: None,
-                signature_photo: None,
-                delivery_coordinates: None,
-                delivery_duration_minutes: None,
-                driver_notes: None,
-                package_condition: None,
-                created_at: row.created_at,
-                updated_at: row.updated_at,
-                deleted_at: row.deleted_at,
-            };
-            PackageListResponse::from(package)
-        })
-        .collect();
-
-    Ok(Json(packages))
+    .await?;
+
+    Ok(Json(packages.into_iter().map(PackageListResponse::from).collect()))
 }
 
 /// Obtener un paquete por ID
 pub async fn get_package(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<Json<PackageResponse>> {
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, integration_id, package_type,
-            package_weight, package_dimensions, delivery_status as "delivery_status: crate::models::package::DeliveryStatus",
-            delivery_date, delivery_time, delivery_attempts, recipient_name,
-            recipient_phone, delivery_address, delivery_instructions,
-            failure_reason as "failure_reason: crate::models::package::DeliveryFailureReason", failure_notes, reschedule_date,
-            delivery_photo, signature_required, signature_image, signature_photo,
-            delivery_duration_minutes, driver_notes, package_condition,
-            created_at, updated_at, deleted_at
-        FROM packages 
-        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
-        "#,
-        id,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Paquete no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let package = Package {
-        id: row.id,
-        company_id: row.company_id,
-        tournee_id: row.tournee_id,
-        tracking_number: row.tracking_number,
-        external_tracking_number: row.external_tracking_number,
-        package_origin: row.package_origin,
-        external_package_id: row.external_package_id,
-        integration_id: row.integration_id,
-        package_type: row.package_type,
-        package_weight: row.package_weight,
-        package_dimensions: row.package_dimensions,
-        delivery_status: row.delivery_status,
-        delivery_date: row.delivery_date,
-        delivery_time: row.delivery_time,
-        delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-        recipient_name: row.recipient_name,
-        recipient_phone: row.recipient_phone,
-        delivery_address: row.delivery_address,
-        delivery_instructions: row.delivery_instructions,
-        failure_reason: row.failure_reason,
-        failure_notes: row.failure_notes,
-        reschedule_date: row.reschedule_date,
-        delivery_photo: row.delivery_photo,
-        signature_required: row.signature_required.expect("signature_required should not be null"),
-        signature_image: row.signature_image,
-        signature_photo: row.signature_photo,
-        delivery_coordinates: None,
-        delivery_duration_minutes: row.delivery_duration_minutes,
-        driver_notes: row.driver_notes,
-        package_condition: row.package_condition,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    let package = sqlx::query_as::<_, Package>(&format!(
+        "SELECT {} FROM packages WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        PACKAGE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Paquete no encontrado".to_string()))?;
 
     Ok(Json(PackageResponse::from(package)))
 }
@@ -189,7 +147,7 @@ pub async fn get_package(
 /// Crear un nuevo paquete
 pub async fn create_package(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Json(package_data): Json<CreatePackageRequest>,
 ) -> AppResult<Json<PackageResponse>> {
     // Validar datos de entrada
@@ -199,7 +157,22 @@ pub async fn create_package(
     let tournee_id = Uuid::parse_str(&package_data.tournee_id)
         .map_err(|_| AppError::BadRequest("ID de tournée inválido".to_string()))?;
 
-    let row = sqlx::query!(
+    // La tournée tiene que ser de la misma empresa
+    let tournee_exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM tournees WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(tournee_id)
+    .bind(user.company_id)
+    .fetch_one(&state.pool)
+    .await?;
+
+    if !tournee_exists {
+        return Err(AppError::BadRequest("Tournée no encontrada en la empresa".to_string()));
+    }
+
+    let mut tx = state.pool.begin().await?;
+
+    let package = sqlx::query_as::<_, Package>(&format!(
         r#"
         INSERT INTO packages (
             company_id, tournee_id, tracking_number, external_tracking_number,
@@ -208,75 +181,41 @@ pub async fn create_package(
             recipient_name, recipient_phone, delivery_address, delivery_instructions,
             signature_required, created_at, updated_at
         ) VALUES (
-            $1, $2, $3, $4, $5, $6, $7, $8, $9, ($10::text)::delivery_status, $11, $12, $13, $14, $15, $16, NOW(), NOW()
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, 'pending', 0, $10, $11, $12, $13, $14, NOW(), NOW()
         )
-        RETURNING 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, integration_id, package_type,
-            package_weight, package_dimensions, delivery_status as "delivery_status: crate::models::package::DeliveryStatus",
-            delivery_date, delivery_time, delivery_attempts, recipient_name,
-            recipient_phone, delivery_address, delivery_instructions,
-            failure_reason as "failure_reason: crate::models::package::DeliveryFailureReason", failure_notes, reschedule_date,
-            delivery_photo, signature_required, signature_image, signature_photo,
-            delivery_duration_minutes, driver_notes, package_condition,
-            created_at, updated_at, deleted_at
+        RETURNING {}
         "#,
-        user.company_id,
-        tournee_id,
-        package_data.tracking_number,
-        package_data.external_tracking_number,
-        package_data.package_origin.unwrap_or_else(|| "manual".to_string()),
-        package_data.external_package_id,
-        package_data.package_type,
-        package_data.package_weight,
-        package_data.package_dimensions,
-        "pending",
-        0, // delivery_attempts = 0
-        package_data.recipient_name,
-        package_data.recipient_phone,
-        package_data.delivery_address,
-        package_data.delivery_instructions,
-        package_data.signature_required.unwrap_or(false)
-    )
-    .fetch_one(&state.pool)
+        PACKAGE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(tournee_id)
+    .bind(&package_data.tracking_number)
+    .bind(&package_data.external_tracking_number)
+    .bind(package_data.package_origin.clone().unwrap_or_else(|| "manual".to_string()))
+    .bind(&package_data.external_package_id)
+    .bind(&package_data.package_type)
+    .bind(package_data.package_weight)
+    .bind(&package_data.package_dimensions)
+    .bind(&package_data.recipient_name)
+    .bind(&package_data.recipient_phone)
+    .bind(&package_data.delivery_address)
+    .bind(&package_data.delivery_instructions)
+    .bind(package_data.signature_required.unwrap_or(false))
+    .fetch_one(&mut *tx)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let package = Package {
-        id: row.id,
-        company_id: row.company_id,
-        tournee_id: row.tournee_id,
-        tracking_number: row.tracking_number,
-        external_tracking_number: row.external_tracking_number,
-        package_origin: row.package_origin,
-        external_package_id: row.external_package_id,
-        integration_id: row.integration_id,
-        package_type: row.package_type,
-        package_weight: row.package_weight,
-        package_dimensions: row.package_dimensions,
-        delivery_status: row.delivery_status,
-        delivery_date: row.delivery_date,
-        delivery_time: row.delivery_time,
-        delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-        recipient_name: row.recipient_name,
-        recipient_phone: row.recipient_phone,
-        delivery_address: row.delivery_address,
-        delivery_instructions: row.delivery_instructions,
-        failure_reason: row.failure_reason,
-        failure_notes: row.failure_notes,
-        reschedule_date: row.reschedule_date,
-        delivery_photo: row.delivery_photo,
-        signature_required: row.signature_required.expect("signature_required should not be null"),
-        signature_image: row.signature_image,
-        signature_photo: row.signature_photo,
-        delivery_coordinates: None,
-        delivery_duration_minutes: row.delivery_duration_minutes,
-        driver_notes: row.driver_notes,
-        package_condition: row.package_condition,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    .map_err(|e| match e.as_database_error().and_then(|d| d.code()).as_deref() {
+        Some("23505") => AppError::Conflict(format!(
+            "El paquete {} ya existe en la tournée",
+            package_data.tracking_number
+        )),
+        _ => AppError::Database(e),
+    })?;
+
+    status_history::record_package_status(
+        &mut tx, user.company_id, package.id, None, &package.delivery_status, Some(user.user_id), None,
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(Json(PackageResponse::from(package)))
 }
@@ -284,7 +223,7 @@ pub async fn create_package(
 /// Marcar paquete como entregado
 pub async fn mark_delivered(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
     Json(delivery_data): Json<MarkDeliveredRequest>,
 ) -> AppResult<Json<PackageResponse>> {
@@ -292,82 +231,24 @@ pub async fn mark_delivered(
     delivery_data.validate()
         .map_err(AppError::Validation)?;
 
-    let row = sqlx::query!(
-        r#"
-        UPDATE packages SET
-            delivery_status = 'delivered',
-            delivery_date = CURRENT_DATE,
-            delivery_time = CURRENT_TIME,
-            delivery_photo = $2,
-            signature_image = $3,
-            signature_photo = $4,
-            delivery_duration_minutes = $5,
-            driver_notes = $6,
-            package_condition = $7,
-            updated_at = NOW()
-        WHERE id = $1 AND company_id = $8
-        RETURNING 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, integration_id, package_type,
-            package_weight, package_dimensions, delivery_status as "delivery_status: crate::models::package::DeliveryStatus",
-            delivery_date, delivery_time, delivery_attempts, recipient_name,
-            recipient_phone, delivery_address, delivery_instructions,
-            failure_reason as "failure_reason: crate::models::package::DeliveryFailureReason", failure_notes, reschedule_date,
-            delivery_photo, signature_required, signature_image, signature_photo,
-            delivery_duration_minutes, driver_notes, package_condition,
-            created_at, updated_at, deleted_at
-        "#,
-        id,
-        delivery_data.delivery_photo,
-        delivery_data.signature_image,
-        delivery_data.signature_photo,
-        delivery_data.delivery_duration_minutes,
-        delivery_data.driver_notes,
-        delivery_data.package_condition,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Paquete no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
+    // Las fotos y firmas se suben antes con POST /packages/:id/uploads
+    let uploads = [
+        &delivery_data.delivery_photo,
+        &delivery_data.signature_image,
+        &delivery_data.signature_photo,
+    ];
+    check_uploaded(&state, &package_prefix(user.company_id, id), uploads.into_iter().flatten()).await?;
 
-    let package = Package {
-        id: row.id,
-        company_id: row.company_id,
-        tournee_id: row.tournee_id,
-        tracking_number: row.tracking_number,
-        external_tracking_number: row.external_tracking_number,
-        package_origin: row.package_origin,
-        external_package_id: row.external_package_id,
-        integration_id: row.integration_id,
-        package_type: row.package_type,
-        package_weight: row.package_weight,
-        package_dimensions: row.package_dimensions,
-        delivery_status: row.delivery_status,
-        delivery_date: row.delivery_date,
-        delivery_time: row.delivery_time,
-        delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-        recipient_name: row.recipient_name,
-        recipient_phone: row.recipient_phone,
-        delivery_address: row.delivery_address,
-        delivery_instructions: row.delivery_instructions,
-        failure_reason: None,
-        failure_notes: row.failure_notes,
-        reschedule_date: row.reschedule_date,
-        delivery_photo: row.delivery_photo,
-        signature_required: row.signature_required.expect("signature_required should not be null"),
-        signature_image: row.signature_image,
-        signature_photo: row.signature_photo,
-        delivery_coordinates: None,
-        delivery_duration_minutes: row.delivery_duration_minutes,
-        driver_notes: row.driver_notes,
-        package_condition: row.package_condition,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    let mut tx = state.pool.begin().await?;
+    let (from, tournee_status, signature_required) = lock_package(&mut tx, id, user.company_id).await?;
+    check_package_transition(&from, &DeliveryStatus::Delivered, &tournee_status)?;
+
+    check_signature(signature_required, &delivery_data)?;
+
+    let package = apply_delivered(&mut tx, &user, id, &from, &delivery_data, Utc::now()).await?;
+    tx.commit().await?;
+
+    invalidate_driver_tournee(&state, package.id).await;
 
     Ok(Json(PackageResponse::from(package)))
 }
@@ -375,7 +256,7 @@ pub async fn mark_delivered(
 /// Marcar paquete como fallido
 pub async fn mark_failed(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
     Json(failure_data): Json<MarkFailedRequest>,
 ) -> AppResult<Json<PackageResponse>> {
@@ -383,121 +264,371 @@ pub async fn mark_failed(
     failure_data.validate()
         .map_err(AppError::Validation)?;
 
-    let failure_reason_enum = match failure_data.failure_reason.as_str() {
-        "recipient_not_home" => "recipient_not_home",
-        "wrong_address" => "wrong_address",
-        "package_damaged" => "package_damaged",
-        "refused_delivery" => "refused_delivery",
-        "security_restriction" => "security_restriction",
-        "weather_conditions" => "weather_conditions",
-        "vehicle_breakdown" => "vehicle_breakdown",
-        "driver_emergency" => "driver_emergency",
-        _ => "recipient_not_home",
-    };
+    let mut tx = state.pool.begin().await?;
+    let (from, tournee_status, _) = lock_package(&mut tx, id, user.company_id).await?;
+    check_package_transition(&from, &DeliveryStatus::Failed, &tournee_status)?;
 
-    let reschedule_date = if let Some(date_str) = &failure_data.reschedule_date {
+    let package = apply_failed(&mut tx, &user, id, &from, &failure_data, Utc::now()).await?;
+    tx.commit().await?;
+
+    invalidate_driver_tournee(&state, package.id).await;
+
+    Ok(Json(PackageResponse::from(package)))
+}
+
+/// Rechazar la entrega sin firma de un paquete que la exige
+pub(crate) fn check_signature(signature_required: bool, delivery_data: &MarkDeliveredRequest) -> AppResult<()> {
+    if signature_required && delivery_data.signature_image.is_none() && delivery_data.signature_photo.is_none() {
+        return Err(AppError::BadRequest("El paquete requiere la firma del destinatario".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Entregar un paquete ya bloqueado y con la transición validada.
+/// `delivered_at` es la hora real de la entrega (la del dispositivo si llega por
+/// la sincronización offline); las notas y el estado del paquete solo se
+/// sobrescriben si vienen en la petición.
+pub(crate) async fn apply_delivered(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    from: &DeliveryStatus,
+    delivery_data: &MarkDeliveredRequest,
+    delivered_at: DateTime<Utc>,
+) -> AppResult<Package> {
+    let package = sqlx::query_as::<_, Package>(&format!(
+        r#"
+        UPDATE packages SET
+            delivery_status = 'delivered',
+            delivery_date = ($8::timestamptz)::date,
+            delivery_time = ($8::timestamptz)::time,
+            delivery_photo = $2,
+            signature_image = $3,
+            signature_photo = $4,
+            delivery_duration_minutes = $5,
+            driver_notes = COALESCE($6, driver_notes),
+            package_condition = COALESCE($7, package_condition),
+            failure_reason = NULL,
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        PACKAGE_COLUMNS
+    ))
+    .bind(id)
+    .bind(&delivery_data.delivery_photo)
+    .bind(&delivery_data.signature_image)
+    .bind(&delivery_data.signature_photo)
+    .bind(delivery_data.delivery_duration_minutes)
+    .bind(&delivery_data.driver_notes)
+    .bind(&delivery_data.package_condition)
+    .bind(delivered_at)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let written: Vec<SyncField> = [
+        (SyncField::DriverNotes, delivery_data.driver_notes.is_some()),
+        (SyncField::PackageCondition, delivery_data.package_condition.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(field, written)| written.then_some(field))
+    .collect();
+    offline_sync::record_field_versions(conn, id, &written, delivered_at, user.user_id).await?;
+
+    status_history::record_package_status(
+        conn, user.company_id, id, Some(from), &package.delivery_status, Some(user.user_id),
+        delivery_data.driver_notes.as_deref(),
+    )
+    .await?;
+
+    Ok(package)
+}
+
+/// Marcar como fallido un paquete ya bloqueado y con la transición validada,
+/// y aplicar la política de la empresa para el motivo: reprogramar, desviar a
+/// un punto relais o devolver al remitente.
+pub(crate) async fn apply_failed(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    from: &DeliveryStatus,
+    failure_data: &MarkFailedRequest,
+    failed_at: DateTime<Utc>,
+) -> AppResult<Package> {
+    let reason = DeliveryFailureReason::parse(&failure_data.failure_reason).ok_or_else(|| {
+        AppError::BadRequest(format!("Motivo de fallo desconocido: {}", failure_data.failure_reason))
+    })?;
+    let requested_date = if let Some(date_str) = &failure_data.reschedule_date {
         chrono::NaiveDate::parse_from_str(date_str, "%Y-%m-%d").ok()
     } else {
         None
     };
 
-    let row = sqlx::query!(
+    let (attempts, tournee_date) = failure_policies::failure_context(conn, id).await?;
+    let policy = failure_policies::effective_policy(conn, user.company_id, reason).await?;
+    let step = decide(&policy, attempts + 1, tournee_date, requested_date);
+
+    let (reschedule_date, relay_point_address, max_attempts_reached) = match &step {
+        NextStep::Reschedule { date } => (Some(*date), None, false),
+        NextStep::RelayPoint { date, address } => (Some(*date), Some(address.clone()), false),
+        NextStep::ReturnToSender { max_attempts_reached } => (None, None, *max_attempts_reached),
+    };
+
+    let mut package = sqlx::query_as::<_, Package>(&format!(
         r#"
         UPDATE packages SET
             delivery_status = 'failed',
-            failure_reason = ($2::text)::delivery_failure_reason,
+            failure_reason = $2,
             failure_notes = $3,
             reschedule_date = $4,
-            driver_notes = $5,
-            delivery_attempts = delivery_attempts + 1,
+            driver_notes = COALESCE($5, driver_notes),
+            delivery_attempts = COALESCE(delivery_attempts, 0) + 1,
+            failure_action = $6,
+            relay_point_address = $7,
+            max_attempts_reached_at = $8,
             updated_at = NOW()
-        WHERE id = $1 AND company_id = $6
-        RETURNING 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, integration_id, package_type,
-            package_weight, package_dimensions, delivery_status as "delivery_status: crate::models::package::DeliveryStatus",
-            delivery_date, delivery_time, delivery_attempts, recipient_name,
-            recipient_phone, delivery_address, delivery_instructions,
-            failure_reason as "failure_reason: crate::models::package::DeliveryFailureReason", failure_notes, reschedule_date,
-            delivery_photo, signature_required, signature_image, signature_photo,
-            delivery_duration_minutes, driver_notes, package_condition,
-            created_at, updated_at, deleted_at
+        WHERE id = $1
+        RETURNING {}
         "#,
-        id,
-        failure_reason_enum,
-        failure_data.failure_notes,
-        reschedule_date,
-        failure_data.driver_notes,
-        user.company_id
+        PACKAGE_COLUMNS
+    ))
+    .bind(id)
+    .bind(reason)
+    .bind(&failure_data.failure_notes)
+    .bind(reschedule_date)
+    .bind(&failure_data.driver_notes)
+    .bind(step.action().as_str())
+    .bind(relay_point_address)
+    .bind(max_attempts_reached.then_some(failed_at))
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if failure_data.driver_notes.is_some() {
+        offline_sync::record_field_versions(conn, id, &[SyncField::DriverNotes], failed_at, user.user_id).await?;
+    }
+
+    status_history::record_package_status(
+        conn, user.company_id, id, Some(from), &package.delivery_status, Some(user.user_id),
+        Some(&failure_data.failure_reason),
+    )
+    .await?;
+
+    // Devolución inmediata (failed -> returned siempre está permitido)
+    if let NextStep::ReturnToSender { max_attempts_reached } = step {
+        package = sqlx::query_as::<_, Package>(&format!(
+            "UPDATE packages SET delivery_status = 'returned', updated_at = NOW() WHERE id = $1 RETURNING {}",
+            PACKAGE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let notes = if max_attempts_reached {
+            format!("Devolución al remitente: {} intento(s) fallidos", package.delivery_attempts)
+        } else {
+            format!("Devolución al remitente por política ({})", reason.as_str())
+        };
+        status_history::record_package_status(
+            conn, user.company_id, id, Some(&DeliveryStatus::Failed), &package.delivery_status, Some(user.user_id),
+            Some(&notes),
+        )
+        .await?;
+    }
+
+    Ok(package)
+}
+
+/// Marcar paquete como en reparto (el conductor va hacia la dirección)
+pub async fn mark_out_for_delivery(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(id): Path<Uuid>,
+    request: Option<Json<StatusChangeRequest>>,
+) -> AppResult<Json<PackageResponse>> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let (from, tournee_status, _) = lock_package(&mut tx, id, user.company_id).await?;
+    check_package_transition(&from, &DeliveryStatus::OutForDelivery, &tournee_status)?;
+
+    let package = sqlx::query_as::<_, Package>(&format!(
+        "UPDATE packages SET delivery_status = 'out_for_delivery', updated_at = NOW() WHERE id = $1 RETURNING {}",
+        PACKAGE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    status_history::record_package_status(
+        &mut tx, user.company_id, id, Some(&from), &package.delivery_status, Some(user.user_id),
+        request.notes.as_deref(),
     )
+    .await?;
+    tx.commit().await?;
+
+    invalidate_driver_tournee(&state, package.id).await;
+
+    Ok(Json(PackageResponse::from(package)))
+}
+
+/// Obtener el histórico de estados de un paquete
+pub async fn get_package_history(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<StatusHistoryEntry>>> {
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM packages WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(id)
+    .bind(user.company_id)
     .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Paquete no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
+    .await?;
+
+    if !exists {
+        return Err(AppError::NotFound("Paquete no encontrado".to_string()));
+    }
 
-    let package = Package {
-        id: row.id,
-        company_id: row.company_id,
-        tournee_id: row.tournee_id,
-        tracking_number: row.tracking_number,
-        external_tracking_number: row.external_tracking_number,
-        package_origin: row.package_origin,
-        external_package_id: row.external_package_id,
-        integration_id: row.integration_id,
-        package_type: row.package_type,
-        package_weight: row.package_weight,
-        package_dimensions: row.package_dimensions,
-        delivery_status: row.delivery_status,
-        delivery_date: row.delivery_date,
-        delivery_time: row.delivery_time,
-        delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-        recipient_name: row.recipient_name,
-        recipient_phone: row.recipient_phone,
-        delivery_address: row.delivery_address,
-        delivery_instructions: row.delivery_instructions,
-        failure_reason: row.failure_reason,
-        failure_notes: row.failure_notes,
-        reschedule_date: row.reschedule_date,
-        delivery_photo: row.delivery_photo,
-        signature_required: row.signature_required.expect("signature_required should not be null"),
-        signature_image: row.signature_image,
-        signature_photo: row.signature_photo,
-        delivery_coordinates: None,
-        delivery_duration_minutes: row.delivery_duration_minutes,
-        driver_notes: row.driver_notes,
-        package_condition: row.package_condition,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
+    let history = status_history::package_history(&state.pool, user.company_id, id).await?;
+    Ok(Json(history))
+}
+
+/// Invalidar la tournée cacheada del driver del paquete (la lista de Android debe reflejar el cambio)
+pub(crate) async fn invalidate_driver_tournee(state: &FleetState, package_id: Uuid) {
+    let Some(tournee_cache) = &state.tournee_cache else {
+        return;
     };
 
-    Ok(Json(PackageResponse::from(package)))
+    match cached_tournee_key(&state.pool, package_id).await {
+        Ok(Some((societe, username, date))) => {
+            if let Err(e) = tournee_cache.invalidate(&societe, &username, &date).await {
+                log::warn!("⚠️ No se pudo invalidar la tournée cacheada de {}: {}", username, e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("⚠️ Error buscando la tournée del paquete {}: {}", package_id, e),
+    }
+}
+
+/// Clave `(société, matricule, fecha)` con la que `/api/colis-prive` cachea la
+/// tournée del paquete. `None` si la tournée no viene de Colis Privé.
+async fn cached_tournee_key(
+    pool: &sqlx::PgPool,
+    package_id: Uuid,
+) -> Result<Option<(String, String, String)>, sqlx::Error> {
+    let row = sqlx::query_as::<_, (Option<String>, String, chrono::NaiveDate)>(
+        r#"
+        SELECT ai.api_credentials->>'societe', u.username, t.tournee_date
+        FROM packages p
+        JOIN tournees t ON t.id = p.tournee_id
+        JOIN users u ON u.id = t.driver_id
+        JOIN api_integrations ai ON ai.id = t.integration_id
+        WHERE p.id = $1
+        "#,
+    )
+    .bind(package_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|(societe, username, date)| {
+        societe.map(|societe| (societe, username, date.format("%Y-%m-%d").to_string()))
+    }))
 }
 
 /// Eliminar un paquete (soft delete)
 pub async fn delete_package(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    let result = sqlx::query!(
+    let result = sqlx::query(
         r#"
-        UPDATE packages 
+        UPDATE packages
         SET deleted_at = NOW(), updated_at = NOW()
         WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
         "#,
-        id,
-        user.company_id
     )
+    .bind(id)
+    .bind(user.company_id)
     .execute(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     if result.rows_affected() == 0 {
         return Err(AppError::NotFound("Paquete no encontrado".to_string()));
     }
 
     Ok(StatusCode::NO_CONTENT)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::api::test_support::{post_ok, register, TestDb};
+    use crate::services::{import_tournee, resolve_import_context, ImportedTournee};
+
+    #[tokio::test]
+    async fn test_cached_tournee_key_of_imported_package_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (admin, company_id) = register(&app, "Mu").await;
+
+        post_ok(&app, "/users", &admin, json!({
+            "username": "A187518",
+            "password": "driverpass",
+            "full_name": "Hugo Petit",
+            "email": "hugo@mu.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "TU-678-VW", "brand": "Renault", "model": "Kangoo", "fuel_type": "electric",
+        }))
+        .await;
+        sqlx::query(
+            "INSERT INTO api_integrations (company_id, provider_name, api_credentials) VALUES ($1, 'colis_prive', '{\"societe\": \"PCP0010699\"}')",
+        )
+        .bind(Uuid::parse_str(&company_id).unwrap())
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        let ctx = resolve_import_context(&db.pool, "PCP0010699", "A187518").await.unwrap();
+        let tournee = ImportedTournee {
+            external_tournee_id: "T-A187518".to_string(),
+            tournee_number: None,
+            date: chrono::Utc::now().date_naive(),
+            packages: &[],
+        };
+        let report = import_tournee(&db.pool, &ctx, &tournee).await.unwrap();
+        let package_id = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO packages (company_id, tournee_id, tracking_number, delivery_address) VALUES ($1, $2, 'CP-1001', '8 rue Oberkampf, 75011 Paris') RETURNING id",
+        )
+        .bind(ctx.company_id)
+        .bind(report.tournee_id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+        // Al marcar una entrega se invalida la misma clave con la que se cacheó la tournée
+        let key = cached_tournee_key(&db.pool, package_id).await.unwrap();
+        let date = tournee.date.format("%Y-%m-%d").to_string();
+        assert_eq!(key, Some(("PCP0010699".to_string(), "A187518".to_string(), date)));
+
+        // Un paquete de una tournée manual no tiene tournée cacheada
+        sqlx::query("UPDATE tournees SET integration_id = NULL WHERE id = $1")
+            .bind(report.tournee_id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(cached_tournee_key(&db.pool, package_id).await.unwrap(), None);
+
+        db.drop().await;
+    }
+}
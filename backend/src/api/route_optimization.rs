@@ -0,0 +1,484 @@
+//! Optimización de la ruta de una tournée
+//!
+//! Reordena los paquetes persistidos de una tournée y guarda el orden
+//! (`packages.delivery_sequence`) y la puntuación (`tournees.route_optimization_score`).
+//! `/schedule` secuencia además con ventanas horarias y devuelve las ETAs;
+//! `/zones` agrupa en zonas park-and-walk. Las distancias salen del
+//! `DistanceMatrixService` configurado (OSRM, Mapbox o haversine); `/matrix`
+//! devuelve esa matriz.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::colis_prive_v3_models::DeliveryWindow;
+use crate::models::tournee::TourneeStatus;
+use crate::services::distance_matrix::MatrixMetric;
+use crate::services::route_optimizer::{DistanceMetric, GeoPoint, OptimizedRoute, RouteOptimizer};
+use crate::services::time_window_scheduler::{
+    ScheduleConfig, ScheduleStop, TimeWindow, TimeWindowSchedule, TimeWindowScheduler, WindowKind,
+};
+use crate::services::zone_clustering::{ClusteringConfig, ZoneClusterer, ZonedRoute};
+use crate::state::AppState;
+use crate::utils::errors::{AppError, AppResult};
+use crate::utils::jwt::{require_user, JwtConfig};
+
+/// Request de optimización: salida del depósito y destino opcional
+#[derive(Debug, Deserialize)]
+pub struct OptimizeTourneeRequest {
+    pub depot: GeoPoint,
+    pub end: Option<GeoPoint>,
+}
+
+/// Ruta optimizada de una tournée
+#[derive(Debug, Serialize)]
+pub struct OptimizeTourneeResponse {
+    pub tournee_id: Uuid,
+    #[serde(flatten)]
+    pub route: OptimizedRoute,
+}
+
+/// Ventana horaria y prioridad de un paquete
+#[derive(Debug, Deserialize)]
+pub struct PackageConstraint {
+    pub package_id: Uuid,
+    pub delivery_window: Option<DeliveryWindow>,
+    /// COLIS RENDEZ-VOUS: la ventana es dura
+    #[serde(default)]
+    pub rendez_vous: bool,
+    #[serde(default)]
+    pub priority: u8,
+}
+
+/// Request de secuenciación con ventanas horarias
+#[derive(Debug, Deserialize)]
+pub struct ScheduleTourneeRequest {
+    pub depot: GeoPoint,
+    pub end: Option<GeoPoint>,
+    /// Hora de salida; por defecto la de `ScheduleConfig`
+    pub start_time: Option<NaiveTime>,
+    #[serde(default)]
+    pub constraints: Vec<PackageConstraint>,
+}
+
+/// Secuencia con ETAs de una tournée
+#[derive(Debug, Serialize)]
+pub struct ScheduleTourneeResponse {
+    pub tournee_id: Uuid,
+    #[serde(flatten)]
+    pub schedule: TimeWindowSchedule,
+    /// Paquetes sin coordenadas, que quedan al final
+    pub unrouted: Vec<String>,
+}
+
+/// Request de agrupación por zonas
+#[derive(Debug, Deserialize)]
+pub struct ZoneTourneeRequest {
+    pub depot: GeoPoint,
+    pub end: Option<GeoPoint>,
+    /// Radio a pie entre paquetes de una zona; por defecto el de `ClusteringConfig`
+    pub walking_radius_m: Option<f64>,
+}
+
+/// Ruta por zonas de una tournée
+#[derive(Debug, Serialize)]
+pub struct ZoneTourneeResponse {
+    pub tournee_id: Uuid,
+    #[serde(flatten)]
+    pub route: ZonedRoute,
+}
+
+/// Depósito opcional para la matriz
+#[derive(Debug, Deserialize)]
+pub struct MatrixQuery {
+    pub depot_latitude: Option<f64>,
+    pub depot_longitude: Option<f64>,
+}
+
+/// Matriz de distancias de una tournée; `point_ids[i]` corresponde a la fila `i`
+#[derive(Debug, Serialize)]
+pub struct TourneeMatrixResponse {
+    pub tournee_id: Uuid,
+    pub provider: String,
+    pub point_ids: Vec<String>,
+    pub points: Vec<GeoPoint>,
+    pub distances_km: Vec<Vec<f64>>,
+    pub durations_minutes: Vec<Vec<f64>>,
+    pub unrouted: Vec<String>,
+}
+
+/// Paquete de la tournée con sus coordenadas (si está geocodificado)
+#[derive(Debug, sqlx::FromRow)]
+struct PackageLocation {
+    id: Uuid,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    signature_required: Option<bool>,
+}
+
+/// Comprobar que la tournée existe y pertenece a la empresa del usuario; devuelve su estado
+async fn authorize_tournee(state: &AppState, headers: &HeaderMap, tournee_id: Uuid) -> AppResult<TourneeStatus> {
+    let claims = require_user(headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    let company_id = Uuid::parse_str(&claims.company_id)
+        .map_err(|_| AppError::Unauthorized("company_id inválido en el token".to_string()))?;
+
+    let status: Option<TourneeStatus> = sqlx::query_scalar(
+        "SELECT tournee_status FROM tournees WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+    )
+    .bind(tournee_id)
+    .bind(company_id)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    status.ok_or_else(|| AppError::NotFound(format!("Tournée {} no encontrada", tournee_id)))
+}
+
+/// Solo se reordenan tournées planificadas o en curso
+fn ensure_resequenceable(tournee_id: Uuid, status: &TourneeStatus) -> AppResult<()> {
+    match status {
+        TourneeStatus::Pending | TourneeStatus::InProgress => Ok(()),
+        other => Err(AppError::Conflict(format!(
+            "La tournée {} está {} y no se puede reordenar",
+            tournee_id,
+            other.as_str()
+        ))),
+    }
+}
+
+/// Rechazar coordenadas fuera de rango antes de pedir la matriz
+fn validate_point(name: &str, point: &GeoPoint) -> AppResult<()> {
+    if !(-90.0..=90.0).contains(&point.latitude) || !(-180.0..=180.0).contains(&point.longitude) {
+        return Err(AppError::BadRequest(format!("Coordenadas de {} fuera de rango", name)));
+    }
+    Ok(())
+}
+
+/// Depósito y destino opcional de una petición de ruta
+fn validate_route_points(depot: &GeoPoint, end: Option<&GeoPoint>) -> AppResult<()> {
+    validate_point("depot", depot)?;
+    if let Some(end) = end {
+        validate_point("end", end)?;
+    }
+    Ok(())
+}
+
+/// Paquetes en el orden actual: el de Colis Privé o el de la última optimización
+async fn load_packages(pool: &PgPool, tournee_id: Uuid) -> AppResult<Vec<PackageLocation>> {
+    let packages = sqlx::query_as(
+        r#"
+        SELECT id, delivery_coordinates[1] AS latitude, delivery_coordinates[0] AS longitude,
+               signature_required
+        FROM packages
+        WHERE tournee_id = $1 AND deleted_at IS NULL
+        ORDER BY delivery_sequence NULLS LAST, created_at, tracking_number
+        "#,
+    )
+    .bind(tournee_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(packages)
+}
+
+impl PackageLocation {
+    fn location(&self) -> Option<GeoPoint> {
+        Some(GeoPoint { latitude: self.latitude?, longitude: self.longitude? })
+    }
+}
+
+/// Distancias por carretera entre depósito, paquetes geocodificados y destino
+async fn road_metric(
+    state: &AppState,
+    depot: GeoPoint,
+    end: Option<GeoPoint>,
+    packages: &[PackageLocation],
+) -> Arc<dyn DistanceMetric> {
+    let mut points = vec![depot];
+    points.extend(packages.iter().filter_map(PackageLocation::location));
+    points.extend(end);
+
+    let matrix = state.distance_matrix.matrix(&points).await;
+    Arc::new(MatrixMetric::new(&points, &matrix))
+}
+
+/// `(id, latitud, longitud)` de los paquetes, en su orden actual
+fn locations(packages: &[PackageLocation]) -> Vec<(String, Option<f64>, Option<f64>)> {
+    packages.iter().map(|p| (p.id.to_string(), p.latitude, p.longitude)).collect()
+}
+
+/// Ejecutar un solver fuera del runtime async: las búsquedas locales son CPU
+/// y con rutas largas bloquearían un worker de Tokio
+async fn solve<T, F>(solver: F) -> AppResult<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(solver)
+        .await
+        .map_err(|e| AppError::Internal(format!("Error en el optimizador de rutas: {}", e)))
+}
+
+/// Guardar `delivery_sequence` según el orden dado
+async fn store_sequence<'a>(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    tournee_id: Uuid,
+    ids: impl Iterator<Item = &'a String>,
+) -> AppResult<()> {
+    let ordered_ids = ids
+        .map(|id| {
+            Uuid::parse_str(id)
+                .map_err(|_| AppError::Internal(format!("Id de paquete inválido en la secuencia: {}", id)))
+        })
+        .collect::<AppResult<Vec<Uuid>>>()?;
+
+    sqlx::query(
+        r#"
+        UPDATE packages p
+        SET delivery_sequence = s.position
+        FROM UNNEST($1::uuid[]) WITH ORDINALITY AS s(id, position)
+        WHERE p.id = s.id AND p.tournee_id = $2
+        "#,
+    )
+    .bind(&ordered_ids)
+    .bind(tournee_id)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// POST /api/tournees/:id/optimize - Optimizar el orden de entrega
+pub async fn optimize_tournee(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(tournee_id): Path<Uuid>,
+    Json(request): Json<OptimizeTourneeRequest>,
+) -> AppResult<Json<OptimizeTourneeResponse>> {
+    let status = authorize_tournee(&state, &headers, tournee_id).await?;
+    ensure_resequenceable(tournee_id, &status)?;
+    validate_route_points(&request.depot, request.end.as_ref())?;
+    let packages = load_packages(&state.pool, tournee_id).await?;
+
+    let metric = road_metric(&state, request.depot, request.end, &packages).await;
+    let located = locations(&packages);
+    let route = solve(move || RouteOptimizer::new(metric).optimize_located(request.depot, request.end, located)).await?;
+
+    log::info!(
+        "🧭 Tournée {} optimizada: {} paradas, {:.1} km (antes {:.1} km), score {:.2}, {} sin coordenadas",
+        tournee_id,
+        route.sequence.len(),
+        route.total_distance_km,
+        route.original_distance_km,
+        route.score,
+        route.unrouted.len()
+    );
+
+    // Los paquetes sin coordenadas quedan al final, en su orden actual
+    let mut tx = state.pool.begin().await?;
+    store_sequence(&mut tx, tournee_id, route.sequence.iter().chain(&route.unrouted)).await?;
+
+    sqlx::query("UPDATE tournees SET route_optimization_score = $2 WHERE id = $1")
+        .bind(tournee_id)
+        .bind(route.score)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(OptimizeTourneeResponse { tournee_id, route }))
+}
+
+/// POST /api/tournees/:id/schedule - Secuenciar con ventanas horarias y calcular ETAs
+pub async fn schedule_tournee(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(tournee_id): Path<Uuid>,
+    Json(request): Json<ScheduleTourneeRequest>,
+) -> AppResult<Json<ScheduleTourneeResponse>> {
+    let status = authorize_tournee(&state, &headers, tournee_id).await?;
+    ensure_resequenceable(tournee_id, &status)?;
+    validate_route_points(&request.depot, request.end.as_ref())?;
+    let packages = load_packages(&state.pool, tournee_id).await?;
+
+    let mut stops = Vec::with_capacity(packages.len());
+    let mut unrouted = Vec::new();
+    for package in &packages {
+        let (Some(latitude), Some(longitude)) = (package.latitude, package.longitude) else {
+            unrouted.push(package.id.to_string());
+            continue;
+        };
+
+        let constraint = request.constraints.iter().find(|c| c.package_id == package.id);
+        let window = match constraint.and_then(|c| c.delivery_window.as_ref().map(|w| (w, c.rendez_vous))) {
+            Some((window, rendez_vous)) => {
+                let kind = if rendez_vous { WindowKind::Hard } else { WindowKind::Soft };
+                Some(TimeWindow::from_delivery_window(window, kind).ok_or_else(|| {
+                    AppError::BadRequest(format!("Ventana horaria inválida para el paquete {}", package.id))
+                })?)
+            }
+            None => None,
+        };
+
+        stops.push(ScheduleStop {
+            id: package.id.to_string(),
+            location: GeoPoint { latitude, longitude },
+            window,
+            priority: constraint.map_or(0, |c| c.priority),
+            signature_required: package.signature_required.unwrap_or(false),
+        });
+    }
+
+    let mut config = ScheduleConfig::default();
+    if let Some(start_time) = request.start_time {
+        config.start_time = start_time;
+    }
+    let metric = road_metric(&state, request.depot, request.end, &packages).await;
+    let schedule = solve(move || {
+        TimeWindowScheduler::new(metric, config).schedule(request.depot, request.end, &stops)
+    })
+    .await?;
+
+    log::info!(
+        "🕐 Tournée {} secuenciada: {} paradas, fin {}, {} fuera de ventana, {} sin coordenadas",
+        tournee_id,
+        schedule.stops.len(),
+        schedule.finish_time.format("%H:%M"),
+        schedule.late.len(),
+        unrouted.len()
+    );
+
+    let ordered_ids: Vec<String> = schedule.stops.iter().map(|stop| stop.id.clone()).collect();
+    let mut tx = state.pool.begin().await?;
+    store_sequence(&mut tx, tournee_id, ordered_ids.iter().chain(&unrouted)).await?;
+    tx.commit().await?;
+
+    Ok(Json(ScheduleTourneeResponse { tournee_id, schedule, unrouted }))
+}
+
+/// POST /api/tournees/:id/zones - Agrupar en zonas con tramos a pie
+pub async fn zone_tournee(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(tournee_id): Path<Uuid>,
+    Json(request): Json<ZoneTourneeRequest>,
+) -> AppResult<Json<ZoneTourneeResponse>> {
+    let status = authorize_tournee(&state, &headers, tournee_id).await?;
+    ensure_resequenceable(tournee_id, &status)?;
+    validate_route_points(&request.depot, request.end.as_ref())?;
+
+    let mut config = ClusteringConfig::default();
+    if let Some(radius) = request.walking_radius_m {
+        if !(radius > 0.0 && radius <= 1000.0) {
+            return Err(AppError::BadRequest("walking_radius_m debe estar entre 0 y 1000".to_string()));
+        }
+        config.walking_radius_m = radius;
+        config.max_walk_from_parking_m = config.max_walk_from_parking_m.max(2.0 * radius);
+    }
+
+    let packages = load_packages(&state.pool, tournee_id).await?;
+    let metric = road_metric(&state, request.depot, request.end, &packages).await;
+    let located = locations(&packages);
+    let route = solve(move || ZoneClusterer::new(metric, config).plan(request.depot, request.end, located)).await?;
+
+    log::info!(
+        "🅿️ Tournée {} agrupada: {} zonas, {:.1} km en coche, {:.1} km a pie, {:.0} min, {} sin coordenadas",
+        tournee_id,
+        route.zones.len(),
+        route.driving_distance_km,
+        route.walking_distance_km,
+        route.total_minutes,
+        route.unrouted.len()
+    );
+
+    let ordered_ids = route.zones.iter().flat_map(|zone| &zone.package_ids).chain(&route.unrouted);
+    let mut tx = state.pool.begin().await?;
+    store_sequence(&mut tx, tournee_id, ordered_ids).await?;
+    tx.commit().await?;
+
+    Ok(Json(ZoneTourneeResponse { tournee_id, route }))
+}
+
+/// GET /api/tournees/:id/matrix - Matriz de distancias de los paquetes geocodificados
+pub async fn tournee_matrix(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(tournee_id): Path<Uuid>,
+    Query(query): Query<MatrixQuery>,
+) -> AppResult<Json<TourneeMatrixResponse>> {
+    authorize_tournee(&state, &headers, tournee_id).await?;
+    let packages = load_packages(&state.pool, tournee_id).await?;
+
+    let mut point_ids = Vec::with_capacity(packages.len() + 1);
+    let mut points = Vec::with_capacity(packages.len() + 1);
+    if let (Some(latitude), Some(longitude)) = (query.depot_latitude, query.depot_longitude) {
+        let depot = GeoPoint { latitude, longitude };
+        validate_point("depot", &depot)?;
+        point_ids.push("depot".to_string());
+        points.push(depot);
+    }
+
+    let mut unrouted = Vec::new();
+    for package in &packages {
+        match package.location() {
+            Some(location) => {
+                point_ids.push(package.id.to_string());
+                points.push(location);
+            }
+            None => unrouted.push(package.id.to_string()),
+        }
+    }
+
+    let matrix = state.distance_matrix.matrix(&points).await;
+
+    Ok(Json(TourneeMatrixResponse {
+        tournee_id,
+        provider: matrix.provider,
+        point_ids,
+        points,
+        distances_km: matrix.distances_km,
+        durations_minutes: matrix.durations_minutes,
+        unrouted,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(latitude: f64, longitude: f64) -> GeoPoint {
+        GeoPoint { latitude, longitude }
+    }
+
+    #[test]
+    fn test_only_open_tournees_are_resequenced() {
+        let id = Uuid::new_v4();
+        assert!(ensure_resequenceable(id, &TourneeStatus::Pending).is_ok());
+        assert!(ensure_resequenceable(id, &TourneeStatus::InProgress).is_ok());
+
+        for status in [TourneeStatus::Completed, TourneeStatus::Cancelled, TourneeStatus::Paused] {
+            assert!(matches!(ensure_resequenceable(id, &status), Err(AppError::Conflict(_))));
+        }
+    }
+
+    #[test]
+    fn test_route_points_out_of_range() {
+        assert!(validate_route_points(&point(48.85, 2.34), None).is_ok());
+        assert!(validate_route_points(&point(-90.0, 180.0), Some(&point(90.0, -180.0))).is_ok());
+
+        assert!(matches!(validate_route_points(&point(91.0, 2.34), None), Err(AppError::BadRequest(_))));
+        assert!(matches!(validate_route_points(&point(48.85, -180.5), None), Err(AppError::BadRequest(_))));
+        assert!(matches!(
+            validate_route_points(&point(48.85, 2.34), Some(&point(48.85, 200.0))),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(validate_route_points(&point(f64::NAN, 2.34), None), Err(AppError::BadRequest(_))));
+    }
+}
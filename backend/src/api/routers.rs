@@ -1,32 +1,1082 @@
-use axum::Router;
-use crate::state::AppState;
+//! Routers de la API de flota (`/api/v1`)
+//!
+//! Todas las rutas salvo login y registro pasan por `auth_middleware`, que
+//! inyecta el `AuthenticatedUser`; las altas, cambios y bajas son solo de admin.
+
+use axum::{
+    extract::DefaultBodyLimit,
+    handler::Handler,
+    middleware::{from_fn, from_fn_with_state},
+    routing::{get, post, put},
+    Router,
+};
+
+use crate::api::{analytics, auth, companies, failure_policies, packages, sync, tournees, uploads, users, vehicles};
+use crate::middleware::auth::{admin_only_middleware, auth_middleware};
+use crate::services::proof_of_delivery::UploadConfig;
+use crate::state::FleetState;
+
+/// Límite del cuerpo de las subidas multipart (por defecto axum corta en 2 MB)
+fn upload_body_limit() -> DefaultBodyLimit {
+    DefaultBodyLimit::max(UploadConfig::default().max_request_bytes())
+}
 
 /// Crear el router de companies
-pub fn create_companies_router() -> Router<AppState> {
+pub fn create_companies_router() -> Router<FleetState> {
+    let admin = from_fn(admin_only_middleware);
     Router::new()
+        .route("/", get(companies::get_companies).post(companies::create_company.layer(admin.clone())))
+        .route(
+            "/:id",
+            get(companies::get_company)
+                .put(companies::update_company.layer(admin.clone()))
+                .delete(companies::delete_company.layer(admin)),
+        )
 }
 
 /// Crear el router de users
-pub fn create_users_router() -> Router<AppState> {
+pub fn create_users_router() -> Router<FleetState> {
+    let admin = from_fn(admin_only_middleware);
     Router::new()
+        .route("/", get(users::get_users).post(users::create_user.layer(admin.clone())))
+        .route(
+            "/:id",
+            get(users::get_user)
+                .put(users::update_user.layer(admin.clone()))
+                .delete(users::delete_user.layer(admin)),
+        )
 }
 
 /// Crear el router de vehicles
-pub fn create_vehicles_router() -> Router<AppState> {
+pub fn create_vehicles_router() -> Router<FleetState> {
+    let admin = from_fn(admin_only_middleware);
     Router::new()
+        .route("/", get(vehicles::get_vehicles).post(vehicles::create_vehicle.layer(admin.clone())))
+        .route(
+            "/:id",
+            get(vehicles::get_vehicle)
+                .put(vehicles::update_vehicle.layer(admin.clone()))
+                .delete(vehicles::delete_vehicle.layer(admin)),
+        )
 }
 
 /// Crear el router de tournees
-pub fn create_tournees_router() -> Router<AppState> {
+pub fn create_tournees_router() -> Router<FleetState> {
+    let admin = from_fn(admin_only_middleware);
     Router::new()
+        .route("/", get(tournees::get_tournees).post(tournees::create_tournee.layer(admin.clone())))
+        .route("/:id", get(tournees::get_tournee).delete(tournees::delete_tournee.layer(admin.clone())))
+        .route("/:id/start", post(tournees::start_tournee))
+        .route("/:id/pause", post(tournees::pause_tournee))
+        .route("/:id/resume", post(tournees::resume_tournee))
+        .route("/:id/end", post(tournees::end_tournee))
+        .route("/:id/cancel", post(tournees::cancel_tournee.layer(admin)))
+        .route("/:id/history", get(tournees::get_tournee_history))
+        .route("/:id/uploads", post(uploads::upload_tournee_files.layer(upload_body_limit())))
 }
 
 /// Crear el router de packages
-pub fn create_packages_router() -> Router<AppState> {
+pub fn create_packages_router() -> Router<FleetState> {
+    let admin = from_fn(admin_only_middleware);
     Router::new()
+        .route("/", get(packages::get_packages).post(packages::create_package.layer(admin.clone())))
+        .route("/:id", get(packages::get_package).delete(packages::delete_package.layer(admin)))
+        .route("/:id/out-for-delivery", post(packages::mark_out_for_delivery))
+        .route("/:id/delivered", post(packages::mark_delivered))
+        .route("/:id/failed", post(packages::mark_failed))
+        .route("/:id/history", get(packages::get_package_history))
+        .route("/:id/uploads", post(uploads::upload_package_files.layer(upload_body_limit())))
 }
 
 /// Crear el router de analytics
-pub fn create_analytics_router() -> Router<AppState> {
+pub fn create_analytics_router() -> Router<FleetState> {
+    Router::new()
+        .route("/dashboard", get(analytics::get_dashboard_summary))
+        .route("/tournees", get(analytics::get_performance_by_tournee))
+        .route("/drivers", get(analytics::get_driver_performance))
+        .route("/vehicles", get(analytics::get_vehicle_performance))
+        .route("/max-attempts", get(failure_policies::get_max_attempts_report))
+}
+
+/// Crear el router de políticas de entrega fallida
+pub fn create_failure_policies_router() -> Router<FleetState> {
+    let admin = from_fn(admin_only_middleware);
     Router::new()
+        .route("/", get(failure_policies::get_failure_policies))
+        .route(
+            "/:reason",
+            put(failure_policies::update_failure_policy.layer(admin.clone()))
+                .delete(failure_policies::delete_failure_policy.layer(admin)),
+        )
+}
+
+/// Crear el router de auth (`/me` y `/logout` requieren token)
+pub fn create_auth_router(state: FleetState) -> Router<FleetState> {
+    let protected = Router::new()
+        .route("/me", get(auth::me))
+        .route("/logout", post(auth::logout))
+        .route_layer(from_fn_with_state(state, auth_middleware));
+
+    Router::new()
+        .route("/login", post(auth::login))
+        .route("/register", post(auth::register))
+        .route("/refresh", post(auth::refresh_token))
+        .merge(protected)
+}
+
+/// Crear el router completo de `/api/v1`
+pub fn create_v1_router(state: FleetState) -> Router {
+    let protected = Router::new()
+        .nest("/companies", create_companies_router())
+        .nest("/users", create_users_router())
+        .nest("/vehicles", create_vehicles_router())
+        .nest("/tournees", create_tournees_router())
+        .nest("/packages", create_packages_router())
+        .nest("/analytics", create_analytics_router())
+        .nest("/failure-policies", create_failure_policies_router())
+        .route("/files/*key", get(uploads::get_file))
+        .route("/sync", post(sync::sync))
+        .route_layer(from_fn_with_state(state.clone(), auth_middleware));
+
+    Router::new()
+        .nest("/auth", create_auth_router(state.clone()))
+        .merge(protected)
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+    };
+    use serde_json::{json, Value};
+    use tower::Service;
+    use uuid::Uuid;
+
+    use crate::api::test_support::{post_ok, register, send, TestDb};
+
+    #[tokio::test]
+    async fn test_v1_routes_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+
+        // Auth: registro, login y rutas protegidas sin token
+        let (admin, company_id) = register(&app, "Alpha").await;
+        let (other_admin, other_company_id) = register(&app, "Beta").await;
+
+        let (status, _) = send(&app, Method::GET, "/vehicles", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&app, Method::GET, "/auth/me", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // Mismo username en dos empresas: sin company_id entra con la contraseña que coincide
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "admin", "password": "password123", "company_id": company_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "admin", "password": "wrong-password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // Companies: solo la propia
+        let (status, companies) = send(&app, Method::GET, "/companies", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(companies.as_array().unwrap().len(), 1);
+        let (status, _) = send(&app, Method::GET, &format!("/companies/{}", company_id), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(&app, Method::GET, &format!("/companies/{}", other_company_id), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&app, Method::PUT, &format!("/companies/{}", company_id), Some(&admin), Some(json!({}))).await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+
+        // Users: alta de un conductor y login con su empresa
+        let driver_body = json!({
+            "username": "driver1",
+            "password": "driverpass",
+            "full_name": "Jean Dupont",
+            "email": "driver1@alpha.fr",
+            "user_type": "driver",
+        });
+        let (status, driver) = send(&app, Method::POST, "/users", Some(&admin), Some(driver_body.clone())).await;
+        assert_eq!(status, StatusCode::OK, "{}", driver);
+        let driver_id = driver["id"].as_str().unwrap().to_string();
+        let (status, _) = send(&app, Method::POST, "/users", Some(&admin), Some(driver_body)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, login) = send(
+            &app,
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "driver1", "password": "driverpass", "company_id": company_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let driver_token = login["token"].as_str().unwrap().to_string();
+
+        let (status, users) = send(&app, Method::GET, "/users", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(users.as_array().unwrap().len(), 2);
+        let (status, _) = send(&app, Method::GET, &format!("/users/{}", driver_id), Some(&other_admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Vehicles: CRUD, escrituras solo de admin y aislamiento entre empresas
+        let vehicle_body = json!({
+            "license_plate": "AB-123-CD",
+            "brand": "Renault",
+            "model": "Master",
+            "fuel_type": "diesel",
+        });
+        let (status, _) = send(&app, Method::POST, "/vehicles", Some(&driver_token), Some(vehicle_body.clone())).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, vehicle) = send(&app, Method::POST, "/vehicles", Some(&admin), Some(vehicle_body.clone())).await;
+        assert_eq!(status, StatusCode::OK, "{}", vehicle);
+        let vehicle_id = vehicle["id"].as_str().unwrap().to_string();
+        let (status, _) = send(&app, Method::POST, "/vehicles", Some(&admin), Some(vehicle_body.clone())).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        // La misma matrícula en otra empresa es válida
+        let (status, _) = send(&app, Method::POST, "/vehicles", Some(&other_admin), Some(vehicle_body)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let vehicle_uri = format!("/vehicles/{}", vehicle_id);
+        let (status, updated) = send(&app, Method::PUT, &vehicle_uri, Some(&admin), Some(json!({ "color": "Blanc" }))).await;
+        assert_eq!(status, StatusCode::OK, "{}", updated);
+        assert_eq!(updated["color"], "Blanc");
+        let (status, vehicles) = send(&app, Method::GET, "/vehicles?fuel_type=diesel", Some(&driver_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(vehicles.as_array().unwrap().len(), 1);
+        let (status, _) = send(&app, Method::GET, &vehicle_uri, Some(&other_admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&app, Method::DELETE, &vehicle_uri, Some(&other_admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Tournées: el conductor y el vehículo tienen que ser de la empresa
+        let tournee_body = json!({
+            "driver_id": driver_id,
+            "vehicle_id": vehicle_id,
+            "tournee_number": "T-001",
+            "start_mileage": 1000,
+        });
+        let (status, _) = send(&app, Method::POST, "/tournees", Some(&other_admin), Some(tournee_body.clone())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, tournee) = send(&app, Method::POST, "/tournees", Some(&admin), Some(tournee_body)).await;
+        assert_eq!(status, StatusCode::OK, "{}", tournee);
+        let tournee_id = tournee["id"].as_str().unwrap().to_string();
+
+        let (status, tournees) = send(&app, Method::GET, &format!("/tournees?driver_id={}", driver_id), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tournees.as_array().unwrap().len(), 1);
+
+        // Todos los filtros de la lista se aplican; los mal formados dan 400
+        let date = tournee["tournee_date"].as_str().unwrap();
+        for (query, expected) in [
+            (format!("tournee_date_from={}&tournee_date_to={}", date, date), 1),
+            ("tournee_date_from=2999-01-01".to_string(), 0),
+            ("tournee_date_to=2000-01-01".to_string(), 0),
+            ("tournee_origin=manual".to_string(), 1),
+            ("tournee_origin=colis_prive".to_string(), 0),
+            ("created_after=2000-01-01T00:00:00Z".to_string(), 1),
+            ("created_before=2000-01-01T00:00:00Z".to_string(), 0),
+        ] {
+            let (status, tournees) = send(&app, Method::GET, &format!("/tournees?{}", query), Some(&admin), None).await;
+            assert_eq!(status, StatusCode::OK, "{}", query);
+            assert_eq!(tournees.as_array().unwrap().len(), expected, "{}", query);
+        }
+        for query in ["tournee_date_from=01/01/2025", "created_after=hier"] {
+            let (status, _) = send(&app, Method::GET, &format!("/tournees?{}", query), Some(&admin), None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+        }
+        let (status, _) = send(&app, Method::GET, &format!("/tournees/{}", tournee_id), Some(&other_admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Packages
+        let (status, package) = send(
+            &app,
+            Method::POST,
+            "/packages",
+            Some(&admin),
+            Some(json!({
+                "tournee_id": tournee_id,
+                "tracking_number": "PKG-0001",
+                "delivery_address": "5 avenue Foch, 75116 Paris",
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", package);
+        let delivered_id = package["id"].as_str().unwrap().to_string();
+        let (status, package) = send(
+            &app,
+            Method::POST,
+            "/packages",
+            Some(&admin),
+            Some(json!({
+                "tournee_id": tournee_id,
+                "tracking_number": "PKG-0002",
+                "delivery_address": "8 rue Lepic, 75018 Paris",
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", package);
+        let failed_id = package["id"].as_str().unwrap().to_string();
+
+        let (status, packages) = send(&app, Method::GET, &format!("/packages?tournee_id={}", tournee_id), Some(&driver_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(packages.as_array().unwrap().len(), 2);
+        for (query, expected) in [
+            ("package_origin=manual", 2),
+            ("package_origin=colis_prive", 0),
+            ("external_tracking_number=PKG-0001", 0),
+            ("delivery_date_from=2000-01-01", 0),
+            ("created_after=2000-01-01T00:00:00Z", 2),
+            ("created_before=2000-01-01T00:00:00Z", 0),
+        ] {
+            let (status, packages) = send(&app, Method::GET, &format!("/packages?{}", query), Some(&admin), None).await;
+            assert_eq!(status, StatusCode::OK, "{}", query);
+            assert_eq!(packages.as_array().unwrap().len(), expected, "{}", query);
+        }
+        let (status, _) = send(&app, Method::GET, "/packages?delivery_date_to=demain", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(&app, Method::GET, &format!("/packages/{}", delivered_id), Some(&other_admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Ciclo de la tournée llevado por el conductor
+        let tournee_uri = format!("/tournees/{}", tournee_id);
+        let (status, _) = send(&app, Method::POST, &format!("{}/end", tournee_uri), Some(&driver_token), Some(json!({ "end_mileage": 1080 }))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = send(&app, Method::POST, &format!("{}/start", tournee_uri), Some(&driver_token), Some(json!({ "start_mileage": 1000 }))).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(&app, Method::POST, &format!("/packages/{}/delivered", delivered_id), Some(&driver_token), Some(json!({}))).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &format!("/packages/{}/failed", failed_id),
+            Some(&driver_token),
+            Some(json!({ "failure_reason": "not_a_reason" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, failed) = send(
+            &app,
+            Method::POST,
+            &format!("/packages/{}/failed", failed_id),
+            Some(&driver_token),
+            Some(json!({ "failure_reason": "recipient_not_home" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", failed);
+        assert_eq!(failed["delivery_attempts"], 1);
+        let (status, packages) = send(&app, Method::GET, "/packages?failure_reason=recipient_not_home", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(packages.as_array().unwrap().len(), 1);
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &format!("/packages/{}/failed", failed_id),
+            Some(&other_admin),
+            Some(json!({ "failure_reason": "recipient_not_home" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, ended) = send(&app, Method::POST, &format!("{}/end", tournee_uri), Some(&driver_token), Some(json!({ "end_mileage": 1080 }))).await;
+        assert_eq!(status, StatusCode::OK, "{}", ended);
+
+        // Analytics de la empresa (la otra empresa no ve nada)
+        let (status, dashboard) = send(&app, Method::GET, "/analytics/dashboard", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK, "{}", dashboard);
+        assert_eq!(dashboard["total_packages"], 2);
+        assert_eq!(dashboard["delivered_packages"], 1);
+        assert_eq!(dashboard["completed_tournees"], 1);
+        let (status, dashboard) = send(&app, Method::GET, "/analytics/dashboard", Some(&other_admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(dashboard["total_packages"], 0);
+
+        for (uri, expected) in [("/analytics/tournees", 1), ("/analytics/drivers", 1), ("/analytics/vehicles", 1)] {
+            let (status, rows) = send(&app, Method::GET, uri, Some(&admin), None).await;
+            assert_eq!(status, StatusCode::OK, "{} {}", uri, rows);
+            assert_eq!(rows.as_array().unwrap().len(), expected, "{}", uri);
+            assert_eq!(rows[0]["packages_delivered"], 1, "{}", uri);
+            assert_eq!(rows[0]["total_distance_km"], 80.0, "{}", uri);
+        }
+        let (status, rows) = send(&app, Method::GET, "/analytics/drivers", Some(&other_admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(rows.as_array().unwrap().is_empty());
+
+        // Bajas (soft delete)
+        let (status, _) = send(&app, Method::DELETE, &format!("/packages/{}", delivered_id), Some(&driver_token), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&app, Method::DELETE, &format!("/packages/{}", delivered_id), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, Method::GET, &format!("/packages/{}", delivered_id), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&app, Method::DELETE, &tournee_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, Method::DELETE, &vehicle_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, _) = send(&app, Method::DELETE, &format!("/users/{}", driver_id), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        // El token del conductor borrado deja de valer
+        let (status, _) = send(&app, Method::GET, "/vehicles", Some(&driver_token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&app, Method::POST, "/auth/logout", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn test_status_transitions_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (admin, company_id) = register(&app, "Gamma").await;
+
+        let driver = post_ok(&app, "/users", &admin, json!({
+            "username": "driver1",
+            "password": "driverpass",
+            "full_name": "Jean Dupont",
+            "email": "driver1@gamma.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        let (_, login) = send(
+            &app,
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "driver1", "password": "driverpass", "company_id": company_id })),
+        )
+        .await;
+        let driver_token = login["token"].as_str().unwrap().to_string();
+        let vehicle = post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "EF-456-GH", "brand": "Renault", "model": "Kangoo", "fuel_type": "diesel",
+        }))
+        .await;
+        let tournee = post_ok(&app, "/tournees", &admin, json!({
+            "driver_id": driver["id"], "vehicle_id": vehicle["id"], "start_mileage": 500,
+        }))
+        .await;
+        let tournee_uri = format!("/tournees/{}", tournee["id"].as_str().unwrap());
+        let package = post_ok(&app, "/packages", &admin, json!({
+            "tournee_id": tournee["id"], "tracking_number": "PKG-1001", "delivery_address": "3 rue Cler, 75007 Paris",
+        }))
+        .await;
+        let package_uri = format!("/packages/{}", package["id"].as_str().unwrap());
+
+        // Sin tournée en curso no se reparte
+        let (status, _) = send(&app, Method::POST, &format!("{}/out-for-delivery", package_uri), Some(&driver_token), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = send(&app, Method::POST, &format!("{}/pause", tournee_uri), Some(&driver_token), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        post_ok(&app, &format!("{}/start", tournee_uri), &driver_token, json!({ "start_mileage": 500 })).await;
+        let paused = post_ok(&app, &format!("{}/pause", tournee_uri), &driver_token, json!({ "notes": "Déjeuner" })).await;
+        assert_eq!(paused["tournee_status"], "paused");
+        let (status, _) = send(&app, Method::POST, &format!("{}/delivered", package_uri), Some(&driver_token), Some(json!({}))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = send(&app, Method::POST, &format!("{}/end", tournee_uri), Some(&driver_token), Some(json!({ "end_mileage": 540 }))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        post_ok(&app, &format!("{}/resume", tournee_uri), &driver_token, json!({})).await;
+
+        // No se completa con paquetes en reparto
+        post_ok(&app, &format!("{}/out-for-delivery", package_uri), &driver_token, json!({})).await;
+        let (status, body) = send(&app, Method::POST, &format!("{}/end", tournee_uri), Some(&driver_token), Some(json!({ "end_mileage": 540 }))).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+
+        post_ok(&app, &format!("{}/failed", package_uri), &driver_token, json!({ "failure_reason": "recipient_not_home" })).await;
+        post_ok(&app, &format!("{}/out-for-delivery", package_uri), &driver_token, json!({})).await;
+        post_ok(&app, &format!("{}/delivered", package_uri), &driver_token, json!({})).await;
+        let (status, _) = send(&app, Method::POST, &format!("{}/failed", package_uri), Some(&driver_token), Some(json!({ "failure_reason": "recipient_not_home" }))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let ended = post_ok(&app, &format!("{}/end", tournee_uri), &driver_token, json!({ "end_mileage": 540 })).await;
+        assert_eq!(ended["tournee_status"], "completed");
+
+        // Una tournée completada no se reinicia ni se cancela
+        let (status, _) = send(&app, Method::POST, &format!("{}/start", tournee_uri), Some(&driver_token), Some(json!({ "start_mileage": 540 }))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = send(&app, Method::POST, &format!("{}/cancel", tournee_uri), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        // Histórico completo y en orden
+        let (status, history) = send(&app, Method::GET, &format!("{}/history", tournee_uri), Some(&driver_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let steps: Vec<(Value, Value)> = history
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|h| (h["from_status"].clone(), h["to_status"].clone()))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (Value::Null, json!("pending")),
+                (json!("pending"), json!("in_progress")),
+                (json!("in_progress"), json!("paused")),
+                (json!("paused"), json!("in_progress")),
+                (json!("in_progress"), json!("completed")),
+            ]
+        );
+        assert_eq!(history[2]["notes"], "Déjeuner");
+
+        let (status, history) = send(&app, Method::GET, &format!("{}/history", package_uri), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let to: Vec<&str> = history.as_array().unwrap().iter().map(|h| h["to_status"].as_str().unwrap()).collect();
+        assert_eq!(to, ["pending", "out_for_delivery", "failed", "out_for_delivery", "delivered"]);
+
+        // El histórico es de solo inserción
+        assert!(sqlx::query("UPDATE tournee_status_history SET notes = 'x'").execute(&db.pool).await.is_err());
+        assert!(sqlx::query("DELETE FROM package_status_history").execute(&db.pool).await.is_err());
+
+        // Cancelación (solo admin) de una tournée pendiente de otro conductor
+        let driver2 = post_ok(&app, "/users", &admin, json!({
+            "username": "driver2",
+            "password": "driverpass",
+            "full_name": "Marie Curie",
+            "email": "driver2@gamma.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        let second = post_ok(&app, "/tournees", &admin, json!({
+            "driver_id": driver2["id"], "vehicle_id": vehicle["id"],
+        }))
+        .await;
+        let second_uri = format!("/tournees/{}", second["id"].as_str().unwrap());
+        let (status, _) = send(&app, Method::POST, &format!("{}/cancel", second_uri), Some(&driver_token), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let cancelled = post_ok(&app, &format!("{}/cancel", second_uri), &admin, json!({ "notes": "Véhicule en panne" })).await;
+        assert_eq!(cancelled["tournee_status"], "cancelled");
+        let (status, _) = send(&app, Method::POST, &format!("{}/start", second_uri), Some(&admin), Some(json!({ "start_mileage": 540 }))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        db.drop().await;
+    }
+
+    /// POST multipart con partes `(campo, content-type, bytes)`
+    async fn send_multipart(app: &Router, uri: &str, token: &str, parts: &[(&str, &str, Vec<u8>)]) -> (StatusCode, Value) {
+        let boundary = "----test-boundary-7MA4YWxkTrZu0gW";
+        let mut body = Vec::new();
+        for (field, content_type, data) in parts {
+            body.extend_from_slice(
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}.bin\"\r\nContent-Type: {}\r\n\r\n",
+                    boundary, field, field, content_type
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Content-Type", format!("multipart/form-data; boundary={}", boundary))
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.clone().call(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    fn test_image(format: image::ImageOutputFormat) -> Vec<u8> {
+        let image = image::RgbImage::from_pixel(640, 480, image::Rgb([30, 120, 200]));
+        let mut output = Vec::new();
+        image::DynamicImage::ImageRgb8(image)
+            .write_to(&mut std::io::Cursor::new(&mut output), format)
+            .unwrap();
+        output
+    }
+
+    #[tokio::test]
+    async fn test_proof_of_delivery_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (admin, company_id) = register(&app, "Delta").await;
+        let (other_admin, _) = register(&app, "Epsilon").await;
+
+        let driver = post_ok(&app, "/users", &admin, json!({
+            "username": "driver1",
+            "password": "driverpass",
+            "full_name": "Jean Dupont",
+            "email": "driver1@delta.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        let (_, login) = send(
+            &app,
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "driver1", "password": "driverpass", "company_id": company_id })),
+        )
+        .await;
+        let driver_token = login["token"].as_str().unwrap().to_string();
+        let vehicle = post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "IJ-789-KL", "brand": "Peugeot", "model": "Partner", "fuel_type": "electric",
+        }))
+        .await;
+        let tournee = post_ok(&app, "/tournees", &admin, json!({
+            "driver_id": driver["id"], "vehicle_id": vehicle["id"], "start_mileage": 100,
+        }))
+        .await;
+        let tournee_uri = format!("/tournees/{}", tournee["id"].as_str().unwrap());
+        let package = post_ok(&app, "/packages", &admin, json!({
+            "tournee_id": tournee["id"],
+            "tracking_number": "PKG-2001",
+            "delivery_address": "10 rue Oberkampf, 75011 Paris",
+            "signature_required": true,
+        }))
+        .await;
+        let package_uri = format!("/packages/{}", package["id"].as_str().unwrap());
+
+        // Fotos de inspección antes de iniciar
+        let (status, uploaded) = send_multipart(
+            &app,
+            &format!("{}/uploads", tournee_uri),
+            &driver_token,
+            &[("pre_inspection_photos", "image/jpeg", test_image(image::ImageOutputFormat::Jpeg(85)))],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", uploaded);
+        let inspection_key = uploaded["files"][0]["key"].clone();
+        post_ok(&app, &format!("{}/start", tournee_uri), &driver_token, json!({
+            "start_mileage": 100, "pre_inspection_photos": [inspection_key],
+        }))
+        .await;
+
+        // Tipo, contenido y campo validados
+        let uploads_uri = format!("{}/uploads", package_uri);
+        let (status, _) = send_multipart(&app, &uploads_uri, &driver_token, &[("delivery_photo", "text/plain", b"hello".to_vec())]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let png = test_image(image::ImageOutputFormat::Png);
+        let (status, _) = send_multipart(&app, &uploads_uri, &driver_token, &[("delivery_photo", "image/jpeg", png.clone())]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send_multipart(&app, &uploads_uri, &driver_token, &[("avatar", "image/png", png.clone())]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send_multipart(&app, &uploads_uri, &other_admin, &[("delivery_photo", "image/png", png.clone())]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, uploaded) = send_multipart(
+            &app,
+            &uploads_uri,
+            &driver_token,
+            &[
+                ("delivery_photo", "image/jpeg", test_image(image::ImageOutputFormat::Jpeg(85))),
+                ("signature_image", "image/png", png.clone()),
+            ],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", uploaded);
+        let files = uploaded["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1]["field"], "signature_image");
+        assert_eq!(files[1]["content_type"], "image/png");
+        let photo_key = files[0]["key"].as_str().unwrap().to_string();
+        let signature_key = files[1]["key"].as_str().unwrap().to_string();
+        let thumbnail_key = files[1]["thumbnail_key"].as_str().unwrap().to_string();
+
+        // Descarga de la miniatura, solo para la empresa
+        let request = Request::builder()
+            .uri(format!("/files/{}", thumbnail_key))
+            .header("Authorization", format!("Bearer {}", driver_token))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "image/png");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(image::load_from_memory(&bytes).unwrap().width(), 256);
+        let (status, _) = send(&app, Method::GET, &format!("/files/{}", thumbnail_key), Some(&other_admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Firma obligatoria y claves de otro recurso rechazadas
+        let delivered_uri = format!("{}/delivered", package_uri);
+        let (status, body) = send(&app, Method::POST, &delivered_uri, Some(&driver_token), Some(json!({ "delivery_photo": photo_key }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &delivered_uri,
+            Some(&driver_token),
+            Some(json!({ "signature_image": inspection_key })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &delivered_uri,
+            Some(&driver_token),
+            Some(json!({ "signature_image": thumbnail_key })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let delivered = post_ok(&app, &delivered_uri, &driver_token, json!({
+            "delivery_photo": photo_key, "signature_image": signature_key,
+        }))
+        .await;
+        assert_eq!(delivered["delivery_status"], "delivered");
+        assert_eq!(delivered["signature_image"], signature_key);
+
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &format!("{}/end", tournee_uri),
+            Some(&driver_token),
+            Some(json!({ "end_mileage": 150, "post_inspection_photos": [photo_key] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn test_offline_sync_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (admin, company_id) = register(&app, "Delta").await;
+
+        let driver = post_ok(&app, "/users", &admin, json!({
+            "username": "driver1",
+            "password": "driverpass",
+            "full_name": "Paul Martin",
+            "email": "driver1@delta.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        let (_, login) = send(
+            &app,
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "driver1", "password": "driverpass", "company_id": company_id })),
+        )
+        .await;
+        let driver_token = login["token"].as_str().unwrap().to_string();
+        let vehicle = post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "KL-012-MN", "brand": "Citroën", "model": "Berlingo", "fuel_type": "electric",
+        }))
+        .await;
+        let tournee = post_ok(&app, "/tournees", &admin, json!({
+            "driver_id": driver["id"], "vehicle_id": vehicle["id"],
+        }))
+        .await;
+        let tournee_uri = format!("/tournees/{}", tournee["id"].as_str().unwrap());
+        let mut package_ids = Vec::new();
+        for tracking in ["PKG-3001", "PKG-3002", "PKG-3003"] {
+            let package = post_ok(&app, "/packages", &admin, json!({
+                "tournee_id": tournee["id"], "tracking_number": tracking, "delivery_address": "8 rue Oberkampf, 75011 Paris",
+            }))
+            .await;
+            package_ids.push(package["id"].as_str().unwrap().to_string());
+        }
+        post_ok(&app, &format!("{}/start", tournee_uri), &driver_token, json!({ "start_mileage": 300 })).await;
+
+        // Primera sincronización: tournée y paquetes del driver
+        let first = post_ok(&app, "/sync", &driver_token, json!({})).await;
+        assert_eq!(first["changes"]["tournees"].as_array().unwrap().len(), 1);
+        assert_eq!(first["changes"]["packages"].as_array().unwrap().len(), 3);
+        let cursor = first["cursor"].clone();
+        let (_, admin_sync) = send(&app, Method::POST, "/sync", Some(&admin), Some(json!({}))).await;
+        assert!(admin_sync["changes"]["packages"].as_array().unwrap().is_empty());
+
+        // Entrega online del segundo paquete, después de las operaciones offline
+        post_ok(&app, &format!("/packages/{}/delivered", package_ids[1]), &driver_token, json!({
+            "driver_notes": "Remis en main propre",
+        }))
+        .await;
+
+        let ago = |minutes: i64| (chrono::Utc::now() - chrono::Duration::minutes(minutes)).to_rfc3339();
+        let batch = json!({
+            "cursor": cursor,
+            "operations": [
+                { "idempotency_key": "op-1", "client_timestamp": ago(20), "type": "deliver",
+                  "package_id": package_ids[0], "driver_notes": "Laissé au gardien" },
+                { "idempotency_key": "op-2", "client_timestamp": ago(15), "type": "fail",
+                  "package_id": package_ids[1], "failure_reason": "recipient_not_home" },
+                { "idempotency_key": "op-3", "client_timestamp": ago(10), "type": "note",
+                  "package_id": package_ids[1], "driver_notes": "Sonnette en panne" },
+                { "idempotency_key": "op-4", "client_timestamp": ago(5), "type": "note",
+                  "package_id": package_ids[2], "driver_notes": "Code 4521B" },
+                { "idempotency_key": "op-5", "client_timestamp": ago(8), "type": "note",
+                  "package_id": package_ids[2], "driver_notes": "Code 1234A" },
+                { "idempotency_key": "op-6", "client_timestamp": ago(1), "type": "location",
+                  "latitude": 48.8656, "longitude": 2.3780 },
+                { "idempotency_key": "op-7", "client_timestamp": ago(1), "type": "deliver",
+                  "package_id": Uuid::new_v4() },
+                { "idempotency_key": "op-8", "client_timestamp": ago(1), "type": "fail",
+                  "package_id": package_ids[2], "failure_reason": "abducted_by_aliens" },
+            ],
+        });
+
+        let synced = post_ok(&app, "/sync", &driver_token, batch.clone()).await;
+        let results = synced["results"].as_array().unwrap();
+        let outcomes: Vec<&str> = results.iter().map(|r| r["outcome"].as_str().unwrap()).collect();
+        assert_eq!(outcomes, ["applied", "conflict", "conflict", "applied", "conflict", "applied", "rejected", "rejected"]);
+        assert_eq!(results[1]["fields"][0]["field"], "delivery_status");
+        assert_eq!(results[1]["fields"][0]["resolution"], "server_wins");
+        assert_eq!(results[2]["fields"][0]["resolution"], "superseded");
+        assert_eq!(results[4]["fields"][0]["resolution"], "superseded");
+        assert!(results.iter().all(|r| r["replayed"] == false));
+
+        // Delta desde el cursor anterior: los tres paquetes con los valores del servidor
+        let packages = synced["changes"]["packages"].as_array().unwrap();
+        let find = |id: &str| packages.iter().find(|p| p["id"] == id).unwrap().clone();
+        assert_eq!(find(&package_ids[0])["delivery_status"], "delivered");
+        assert_eq!(find(&package_ids[0])["driver_notes"], "Laissé au gardien");
+        assert_eq!(find(&package_ids[1])["delivery_status"], "delivered");
+        assert_eq!(find(&package_ids[1])["driver_notes"], "Remis en main propre");
+        assert_eq!(find(&package_ids[2])["delivery_status"], "pending");
+        assert_eq!(find(&package_ids[2])["driver_notes"], "Code 4521B");
+
+        let located_at = sqlx::query_scalar::<_, Option<chrono::DateTime<chrono::Utc>>>(
+            "SELECT last_location_at FROM users WHERE id = $1",
+        )
+        .bind(Uuid::parse_str(driver["id"].as_str().unwrap()).unwrap())
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert!(located_at.is_some());
+
+        // Reenviar el mismo lote no cambia nada
+        let count_history = || {
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM package_status_history").fetch_one(&db.pool)
+        };
+        let history_before = count_history().await.unwrap();
+        let replay = post_ok(&app, "/sync", &driver_token, batch).await;
+        let replayed = replay["results"].as_array().unwrap();
+        assert!(replayed.iter().all(|r| r["replayed"] == true));
+        let replayed_outcomes: Vec<&str> = replayed.iter().map(|r| r["outcome"].as_str().unwrap()).collect();
+        assert_eq!(replayed_outcomes, outcomes);
+        assert_eq!(count_history().await.unwrap(), history_before);
+        let stored = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM sync_operations")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, 8);
+
+        // Bajas en el delta y cursor al día sin cambios
+        let (status, _) = send(&app, Method::DELETE, &format!("/packages/{}", package_ids[2]), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let after_delete = post_ok(&app, "/sync", &driver_token, json!({ "cursor": synced["cursor"] })).await;
+        assert_eq!(after_delete["changes"]["deleted_package_ids"], json!([package_ids[2]]));
+
+        let future = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+        let empty = post_ok(&app, "/sync", &driver_token, json!({ "cursor": future })).await;
+        assert!(empty["changes"]["packages"].as_array().unwrap().is_empty());
+        assert!(empty["changes"]["tournees"].as_array().unwrap().is_empty());
+
+        // Claves inválidas rechazan el lote entero
+        let (status, _) = send(&app, Method::POST, "/sync", Some(&driver_token), Some(json!({
+            "operations": [{ "idempotency_key": "", "client_timestamp": ago(0), "type": "location", "latitude": 0.0, "longitude": 0.0 }],
+        })))
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn test_failure_policies_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (admin, company_id) = register(&app, "Epsilon").await;
+
+        let driver = post_ok(&app, "/users", &admin, json!({
+            "username": "driver1",
+            "password": "driverpass",
+            "full_name": "Luc Bernard",
+            "email": "driver1@epsilon.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        let (_, login) = send(
+            &app,
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "driver1", "password": "driverpass", "company_id": company_id })),
+        )
+        .await;
+        let driver_token = login["token"].as_str().unwrap().to_string();
+        let vehicle = post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "PQ-345-RS", "brand": "Peugeot", "model": "Partner", "fuel_type": "diesel",
+        }))
+        .await;
+
+        // Políticas: por defecto, configuración (solo admin) y validación
+        let (status, policies) = send(&app, Method::GET, "/failure-policies", Some(&driver_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(policies.as_array().unwrap().len(), 8);
+        assert!(policies.as_array().unwrap().iter().all(|p| p["is_default"] == true));
+
+        let relay = json!({
+            "failure_action": "relay_point", "max_attempts": 2,
+            "relay_point_name": "Tabac du Marché", "relay_point_address": "4 place du Marché, 75011 Paris",
+        });
+        let (status, _) = send(&app, Method::PUT, "/failure-policies/security_restriction", Some(&driver_token), Some(relay.clone())).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&app, Method::PUT, "/failure-policies/security_restriction", Some(&admin), Some(json!({
+            "failure_action": "relay_point", "max_attempts": 2,
+        })))
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(&app, Method::PUT, "/failure-policies/alien_abduction", Some(&admin), Some(relay.clone())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(&app, Method::PUT, "/failure-policies/security_restriction", Some(&admin), Some(relay)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, configured) = send(&app, Method::PUT, "/failure-policies/recipient_not_home", Some(&admin), Some(json!({
+            "failure_action": "reschedule", "max_attempts": 2,
+        })))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(configured["is_default"], false);
+
+        // Tournée de hace dos días (la API solo crea tournées del día)
+        let first = post_ok(&app, "/tournees", &admin, json!({
+            "driver_id": driver["id"], "vehicle_id": vehicle["id"],
+        }))
+        .await;
+        let first_id = Uuid::parse_str(first["id"].as_str().unwrap()).unwrap();
+        sqlx::query("UPDATE tournees SET tournee_date = CURRENT_DATE - 2 WHERE id = $1")
+            .bind(first_id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let first_uri = format!("/tournees/{}", first_id);
+        let mut ids = Vec::new();
+        for tracking in ["PKG-4001", "PKG-4002", "PKG-4003", "PKG-4004"] {
+            let package = post_ok(&app, "/packages", &admin, json!({
+                "tournee_id": first["id"], "tracking_number": tracking, "delivery_address": "21 rue de Charonne, 75011 Paris",
+            }))
+            .await;
+            ids.push(package["id"].as_str().unwrap().to_string());
+        }
+        post_ok(&app, &format!("{}/start", first_uri), &driver_token, json!({ "start_mileage": 800 })).await;
+
+        let fail = |id: &str, reason: &str| {
+            let uri = format!("/packages/{}/failed", id);
+            let body = json!({ "failure_reason": reason });
+            let (app, token) = (app.clone(), driver_token.clone());
+            async move { post_ok(&app, &uri, &token, body).await }
+        };
+
+        // Primer fallo: reprogramado; segundo fallo: intentos agotados
+        let rescheduled = fail(&ids[0], "recipient_not_home").await;
+        assert_eq!(rescheduled["delivery_status"], "failed");
+        assert_eq!(rescheduled["failure_action"], "reschedule");
+        assert!(rescheduled["reschedule_date"].is_string());
+        post_ok(&app, &format!("/packages/{}/out-for-delivery", ids[0]), &driver_token, json!({})).await;
+        let exhausted = fail(&ids[0], "recipient_not_home").await;
+        assert_eq!(exhausted["delivery_status"], "returned");
+        assert_eq!(exhausted["delivery_attempts"], 2);
+
+        let relayed = fail(&ids[1], "security_restriction").await;
+        assert_eq!(relayed["failure_action"], "relay_point");
+        assert_eq!(relayed["relay_point_address"], "4 place du Marché, 75011 Paris");
+
+        let refused = fail(&ids[2], "refused_delivery").await;
+        assert_eq!(refused["delivery_status"], "returned");
+        assert_eq!(refused["failure_action"], "return_to_sender");
+
+        let retried = fail(&ids[3], "recipient_not_home").await;
+        assert_eq!(retried["failure_action"], "reschedule");
+
+        let (status, history) = send(&app, Method::GET, &format!("/packages/{}/history", ids[2]), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let to: Vec<&str> = history.as_array().unwrap().iter().map(|h| h["to_status"].as_str().unwrap()).collect();
+        assert_eq!(to, ["pending", "failed", "returned"]);
+
+        // Sin tournée siguiente al terminar: se cargan al crearla
+        post_ok(&app, &format!("{}/end", first_uri), &driver_token, json!({ "end_mileage": 850 })).await;
+        let second = post_ok(&app, "/tournees", &admin, json!({
+            "driver_id": driver["id"], "vehicle_id": vehicle["id"],
+        }))
+        .await;
+        let (status, carried) = send(
+            &app,
+            Method::GET,
+            &format!("/packages?tournee_id={}", second["id"].as_str().unwrap()),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let mut tracking: Vec<&str> = carried.as_array().unwrap().iter().map(|p| p["tracking_number"].as_str().unwrap()).collect();
+        tracking.sort();
+        assert_eq!(tracking, ["PKG-4002", "PKG-4004"]);
+
+        let copies = sqlx::query_as::<_, (String, String, i32, Option<Uuid>)>(
+            "SELECT tracking_number, delivery_address, delivery_attempts, carried_over_from FROM packages WHERE tournee_id = $1 ORDER BY tracking_number",
+        )
+        .bind(Uuid::parse_str(second["id"].as_str().unwrap()).unwrap())
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(copies[0].1, "4 place du Marché, 75011 Paris");
+        assert_eq!(copies[0].3, Some(Uuid::parse_str(&ids[1]).unwrap()));
+        assert_eq!(copies[1].1, "21 rue de Charonne, 75011 Paris");
+        assert_eq!(copies[1].2, 1);
+
+        // Volver a ejecutar la reprogramación no duplica
+        let mut conn = db.pool.acquire().await.unwrap();
+        let again = crate::services::failure_policies::carry_over_failed(
+            &mut conn,
+            Uuid::parse_str(&company_id).unwrap(),
+            Uuid::parse_str(driver["id"].as_str().unwrap()).unwrap(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(again.is_empty());
+        drop(conn);
+
+        // Informe de intentos agotados
+        let (status, report) = send(&app, Method::GET, "/analytics/max-attempts", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let report = report.as_array().unwrap();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0]["tracking_number"], "PKG-4001");
+        assert_eq!(report[0]["delivery_attempts"], 2);
+        assert_eq!(report[0]["failure_reason"], "recipient_not_home");
+
+        let (status, _) = send(&app, Method::DELETE, "/failure-policies/recipient_not_home", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, Method::DELETE, "/failure-policies/recipient_not_home", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        db.drop().await;
+    }
 }
@@ -0,0 +1,149 @@
+//! Administración de sectores
+//!
+//! CRUD de los sectores de la empresa y asignación de drivers y tournées.
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::services::sectors::{Sector, SectorInput};
+use crate::state::AppState;
+use crate::utils::errors::{AppError, AppResult};
+use crate::utils::jwt::{require_admin, JwtConfig};
+
+/// Request de asignación; `sector_id: null` quita el sector
+#[derive(Debug, Deserialize)]
+pub struct AssignSectorRequest {
+    pub sector_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AssignSectorResponse {
+    pub id: Uuid,
+    pub sector_id: Option<Uuid>,
+}
+
+async fn admin_company_id(state: &AppState, headers: &HeaderMap) -> AppResult<Uuid> {
+    let claims = require_admin(headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    Uuid::parse_str(&claims.company_id)
+        .map_err(|_| AppError::Unauthorized("company_id inválido en el token".to_string()))
+}
+
+/// Código de sector repetido → 409
+fn map_write_error(error: sqlx::Error, code: &str) -> AppError {
+    match error.as_database_error().and_then(|e| e.code()).as_deref() {
+        Some("23505") => AppError::Conflict(format!("Ya existe un sector con código '{}'", code)),
+        _ => AppError::Database(error),
+    }
+}
+
+/// GET /api/admin/sectors - Sectores de la empresa
+pub async fn list_sectors(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> AppResult<Json<Vec<Sector>>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+    Ok(Json(state.sectors.list(company_id).await?))
+}
+
+/// GET /api/admin/sectors/:id - Detalle de un sector
+pub async fn get_sector(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Sector>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+    state.sectors
+        .get(company_id, id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Sector {} no encontrado", id)))
+}
+
+/// POST /api/admin/sectors - Crear un sector
+pub async fn create_sector(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(input): Json<SectorInput>,
+) -> AppResult<Json<Sector>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+    input.validate().map_err(AppError::BadRequest)?;
+
+    let sector = state.sectors
+        .create(company_id, &input)
+        .await
+        .map_err(|e| map_write_error(e, &input.code))?;
+
+    log::info!("🏢 Sector {} creado ({})", sector.code, sector.postal_codes.join(", "));
+    Ok(Json(sector))
+}
+
+/// PUT /api/admin/sectors/:id - Modificar un sector
+pub async fn update_sector(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(input): Json<SectorInput>,
+) -> AppResult<Json<Sector>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+    input.validate().map_err(AppError::BadRequest)?;
+
+    state.sectors
+        .update(company_id, id, &input)
+        .await
+        .map_err(|e| map_write_error(e, &input.code))?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Sector {} no encontrado", id)))
+}
+
+/// DELETE /api/admin/sectors/:id - Eliminar un sector
+pub async fn delete_sector(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<serde_json::Value>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+
+    if !state.sectors.delete(company_id, id).await? {
+        return Err(AppError::NotFound(format!("Sector {} no encontrado", id)));
+    }
+
+    log::info!("🗑️ Sector {} eliminado", id);
+    Ok(Json(serde_json::json!({ "id": id, "deleted": true })))
+}
+
+/// PUT /api/admin/drivers/:id/sector - Asignar sector a un driver
+pub async fn assign_driver_sector(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(driver_id): Path<Uuid>,
+    Json(request): Json<AssignSectorRequest>,
+) -> AppResult<Json<AssignSectorResponse>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+
+    if !state.sectors.assign_driver(company_id, driver_id, request.sector_id).await? {
+        return Err(AppError::NotFound("Driver o sector no encontrado".to_string()));
+    }
+
+    Ok(Json(AssignSectorResponse { id: driver_id, sector_id: request.sector_id }))
+}
+
+/// PUT /api/admin/tournees/:id/sector - Asignar sector a una tournée
+pub async fn assign_tournee_sector(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(tournee_id): Path<Uuid>,
+    Json(request): Json<AssignSectorRequest>,
+) -> AppResult<Json<AssignSectorResponse>> {
+    let company_id = admin_company_id(&state, &headers).await?;
+
+    if !state.sectors.assign_tournee(company_id, tournee_id, request.sector_id).await? {
+        return Err(AppError::NotFound("Tournée o sector no encontrado".to_string()));
+    }
+
+    Ok(Json(AssignSectorResponse { id: tournee_id, sector_id: request.sector_id }))
+}
@@ -0,0 +1,327 @@
+//! Handler de sincronización offline
+//!
+//! `POST /api/v1/sync`: aplica en orden las operaciones acumuladas por la app
+//! del driver (cada una en su transacción, con su clave de idempotencia) y
+//! devuelve los cambios del servidor desde el cursor del cliente.
+
+use std::collections::HashSet;
+
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::packages::{apply_delivered, apply_failed, check_signature, lock_package, invalidate_driver_tournee, PACKAGE_COLUMNS},
+    api::tournees::TOURNEE_COLUMNS,
+    api::uploads::check_uploaded,
+    lifecycle::check_package_transition,
+    middleware::auth::AuthenticatedUser,
+    models::package::{DeliveryStatus, MarkDeliveredRequest, Package, PackageResponse},
+    models::sync::{
+        FieldResult, SyncAction, SyncChanges, SyncOperation, SyncOperationResult, SyncOutcome,
+        SyncRequest, SyncResponse,
+    },
+    models::tournee::{Tournee, TourneeResponse},
+    services::offline_sync::{self, SyncField, INITIAL_SYNC_DAYS, MAX_OPERATIONS},
+    services::proof_of_delivery::package_prefix,
+    state::FleetState,
+    utils::errors::{AppError, AppResult},
+};
+
+/// Sincronizar: aplicar las operaciones offline y devolver el delta del servidor
+pub async fn sync(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Json(request): Json<SyncRequest>,
+) -> AppResult<Json<SyncResponse>> {
+    if request.operations.len() > MAX_OPERATIONS {
+        return Err(AppError::BadRequest(format!(
+            "Como máximo {} operaciones por sincronización",
+            MAX_OPERATIONS
+        )));
+    }
+    for operation in &request.operations {
+        operation.validate()
+            .map_err(AppError::Validation)?;
+    }
+
+    let now = Utc::now();
+    let mut results = Vec::with_capacity(request.operations.len());
+    let mut touched = HashSet::new();
+
+    for operation in &request.operations {
+        let result = apply_operation(&state, &user, operation, now).await?;
+
+        if !result.replayed && result.outcome != SyncOutcome::Rejected {
+            touched.extend(operation.action.package_id());
+        }
+        results.push(result);
+    }
+
+    for package_id in touched {
+        invalidate_driver_tournee(&state, package_id).await;
+    }
+
+    let (changes, cursor) = changes_since(&state.pool, &user, request.cursor).await?;
+
+    Ok(Json(SyncResponse { results, changes, cursor }))
+}
+
+/// Aplicar una operación y guardar su resultado en la misma transacción.
+/// Las claves ya procesadas devuelven el resultado guardado sin tocar nada.
+async fn apply_operation(
+    state: &FleetState,
+    user: &AuthenticatedUser,
+    operation: &SyncOperation,
+    now: DateTime<Utc>,
+) -> AppResult<SyncOperationResult> {
+    let key = &operation.idempotency_key;
+
+    let mut conn = state.pool.acquire().await?;
+    if let Some(stored) = offline_sync::find_result(&mut conn, user.user_id, key).await? {
+        return Ok(stored);
+    }
+    drop(conn);
+
+    let at = offline_sync::effective_timestamp(operation.client_timestamp, now);
+
+    // Las fotos se comprueban fuera de la transacción (no bloquear el paquete durante la E/S)
+    let uploaded = match &operation.action {
+        SyncAction::Deliver { package_id, delivery } => {
+            let uploads = [&delivery.delivery_photo, &delivery.signature_image, &delivery.signature_photo];
+            check_uploaded(state, &package_prefix(user.company_id, *package_id), uploads.into_iter().flatten()).await
+        }
+        _ => Ok(()),
+    };
+
+    let mut tx = state.pool.begin().await?;
+    let applied = match uploaded {
+        Ok(()) => apply_action(&mut tx, user, &operation.action, at).await,
+        Err(e) => Err(e),
+    };
+
+    let result = match applied {
+        Ok(fields) => SyncOperationResult::resolved(key, fields),
+        Err(e) if is_rejection(&e) => {
+            // Deshacer lo que se haya escrito y guardar solo el rechazo
+            tx.rollback().await?;
+            tx = state.pool.begin().await?;
+            SyncOperationResult::rejected(key, e.to_string())
+        }
+        Err(e) => return Err(e),
+    };
+
+    if !offline_sync::store_result(&mut tx, user.company_id, user.user_id, operation, &result).await? {
+        // Otra petición con la misma clave se ha adelantado: su resultado es el bueno
+        tx.rollback().await?;
+        let mut conn = state.pool.acquire().await?;
+        return offline_sync::find_result(&mut conn, user.user_id, key)
+            .await?
+            .ok_or_else(|| AppError::Internal(format!("Resultado de la operación {} no encontrado", key)));
+    }
+    tx.commit().await?;
+
+    Ok(result)
+}
+
+/// Errores que rechazan la operación (se guardan); el resto aborta la sincronización
+fn is_rejection(error: &AppError) -> bool {
+    matches!(
+        error,
+        AppError::BadRequest(_) | AppError::NotFound(_) | AppError::Validation(_) | AppError::Forbidden(_)
+    )
+}
+
+/// Aplicar la acción campo a campo con sus reglas de conflicto
+async fn apply_action(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    action: &SyncAction,
+    at: DateTime<Utc>,
+) -> AppResult<Vec<FieldResult>> {
+    match action {
+        SyncAction::Deliver { package_id, delivery } => {
+            delivery.validate()
+                .map_err(AppError::Validation)?;
+
+            let (from, tournee_status, signature_required) = lock_package(conn, *package_id, user.company_id).await?;
+            let mut fields = Vec::new();
+
+            match check_package_transition(&from, &DeliveryStatus::Delivered, &tournee_status) {
+                Ok(()) => {
+                    check_signature(signature_required, delivery)?;
+
+                    // Notas y estado del paquete van aparte, con last-writer-wins
+                    let status_only = MarkDeliveredRequest {
+                        driver_notes: None,
+                        package_condition: None,
+                        ..delivery.clone()
+                    };
+                    apply_delivered(conn, user, *package_id, &from, &status_only, at).await?;
+                    fields.push(FieldResult::applied(SyncField::DeliveryStatus));
+                }
+                Err(e) => fields.push(FieldResult::server_wins(SyncField::DeliveryStatus, e.to_string())),
+            }
+
+            let written = [
+                (SyncField::DriverNotes, &delivery.driver_notes),
+                (SyncField::PackageCondition, &delivery.package_condition),
+            ];
+            for (field, value) in written {
+                if let Some(value) = value {
+                    fields.push(write_field(conn, user, *package_id, field, value, at).await?);
+                }
+            }
+
+            Ok(fields)
+        }
+        SyncAction::Fail { package_id, failure } => {
+            failure.validate()
+                .map_err(AppError::Validation)?;
+
+            let (from, tournee_status, _) = lock_package(conn, *package_id, user.company_id).await?;
+            let mut fields = Vec::new();
+
+            match check_package_transition(&from, &DeliveryStatus::Failed, &tournee_status) {
+                Ok(()) => {
+                    let mut status_only = failure.clone();
+                    status_only.driver_notes = None;
+                    apply_failed(conn, user, *package_id, &from, &status_only, at).await?;
+                    fields.push(FieldResult::applied(SyncField::DeliveryStatus));
+                }
+                Err(e) => fields.push(FieldResult::server_wins(SyncField::DeliveryStatus, e.to_string())),
+            }
+
+            if let Some(notes) = &failure.driver_notes {
+                fields.push(write_field(conn, user, *package_id, SyncField::DriverNotes, notes, at).await?);
+            }
+
+            Ok(fields)
+        }
+        SyncAction::Note { package_id, driver_notes } => {
+            if driver_notes.chars().count() > 1000 {
+                return Err(AppError::BadRequest("Las notas no pueden superar 1000 caracteres".to_string()));
+            }
+
+            lock_package(conn, *package_id, user.company_id).await?;
+            let field = write_field(conn, user, *package_id, SyncField::DriverNotes, driver_notes, at).await?;
+
+            Ok(vec![field])
+        }
+        SyncAction::Location { latitude, longitude } => {
+            if !(-90.0..=90.0).contains(latitude) || !(-180.0..=180.0).contains(longitude) {
+                return Err(AppError::BadRequest(format!("Posición inválida: {}, {}", latitude, longitude)));
+            }
+
+            let field = if offline_sync::update_driver_location(conn, user.user_id, *latitude, *longitude, at).await? {
+                FieldResult::applied(SyncField::DriverLocation)
+            } else {
+                FieldResult::superseded(SyncField::DriverLocation)
+            };
+
+            Ok(vec![field])
+        }
+    }
+}
+
+/// Escribir un campo last-writer-wins del paquete
+async fn write_field(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    package_id: Uuid,
+    field: SyncField,
+    value: &str,
+    at: DateTime<Utc>,
+) -> AppResult<FieldResult> {
+    let written = offline_sync::write_package_field(conn, package_id, field, value, at, user.user_id).await?;
+
+    Ok(if written {
+        FieldResult::applied(field)
+    } else {
+        FieldResult::superseded(field)
+    })
+}
+
+/// Tournées y paquetes del driver modificados desde el cursor (o los de los
+/// últimos días en la primera sincronización) y el nuevo cursor
+async fn changes_since(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    cursor: Option<DateTime<Utc>>,
+) -> AppResult<(SyncChanges, DateTime<Utc>)> {
+    let since = cursor.map(offline_sync::delta_since);
+
+    // Una sola instantánea para tournées, paquetes y el cursor
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+
+    let next_cursor = sqlx::query_scalar::<_, DateTime<Utc>>("SELECT NOW()")
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let tournees = sqlx::query_as::<_, Tournee>(&format!(
+        r#"
+        SELECT {}
+        FROM tournees
+        WHERE company_id = $1 AND driver_id = $2
+        AND (
+            ($3::timestamptz IS NULL AND deleted_at IS NULL AND tournee_date >= CURRENT_DATE - $4)
+            OR updated_at > $3
+        )
+        ORDER BY tournee_date, created_at
+        "#,
+        TOURNEE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(user.user_id)
+    .bind(since)
+    .bind(INITIAL_SYNC_DAYS)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let packages = sqlx::query_as::<_, Package>(&format!(
+        r#"
+        SELECT {}
+        FROM packages
+        WHERE company_id = $1
+        AND tournee_id IN (
+            SELECT id FROM tournees
+            WHERE company_id = $1 AND driver_id = $2
+            AND ($3::timestamptz IS NOT NULL OR (deleted_at IS NULL AND tournee_date >= CURRENT_DATE - $4))
+        )
+        AND (($3::timestamptz IS NULL AND deleted_at IS NULL) OR updated_at > $3)
+        ORDER BY tournee_id, created_at
+        "#,
+        PACKAGE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(user.user_id)
+    .bind(since)
+    .bind(INITIAL_SYNC_DAYS)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let mut changes = SyncChanges::default();
+    for tournee in tournees {
+        if tournee.deleted_at.is_some() {
+            changes.deleted_tournee_ids.push(tournee.id);
+        } else {
+            changes.tournees.push(TourneeResponse::from(tournee));
+        }
+    }
+    for package in packages {
+        if package.deleted_at.is_some() {
+            changes.deleted_package_ids.push(package.id);
+        } else {
+            changes.packages.push(PackageResponse::from(package));
+        }
+    }
+
+    Ok((changes, next_cursor))
+}
@@ -1,5 +1,5 @@
 //! Handlers de Tournees
-//! 
+//!
 //! Este módulo maneja las operaciones CRUD para tournées.
 
 use axum::{
@@ -7,173 +7,117 @@ use axum::{
     http::StatusCode,
     Json,
 };
-use chrono::Utc;
-use sqlx::PgPool;
+use sqlx::PgConnection;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
+    lifecycle::check_tournee_transition,
     models::tournee::{
-        Tournee, TourneeStatus, TourneeResponse, TourneeListResponse,
-        CreateTourneeRequest, UpdateTourneeRequest, TourneeFilters,
+        Tournee, TourneeResponse, TourneeListResponse, TourneeStatus,
+        CreateTourneeRequest, TourneeFilters,
         StartTourneeRequest, EndTourneeRequest,
+        StatusChangeRequest, StatusHistoryEntry,
     },
+    services::{failure_policies, proof_of_delivery::tournee_prefix, status_history},
+    api::uploads::check_uploaded,
     utils::errors::{AppError, AppResult},
+    utils::validation::{parse_filter, validate_date, validate_datetime, validate_uuid},
     middleware::auth::AuthenticatedUser,
+    state::FleetState,
 };
 
+/// Columnas de `tournees` que mapea `Tournee`
+pub(crate) const TOURNEE_COLUMNS: &str = r#"
+    id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
+    start_location, end_location, tournee_status,
+    start_time, end_time, start_mileage, end_mileage, total_distance,
+    fuel_consumed, fuel_cost, pre_inspection_notes, post_inspection_notes,
+    pre_inspection_photos, post_inspection_photos,
+    route_optimization_score, estimated_duration_minutes, actual_duration_minutes,
+    route_coordinates, traffic_conditions, weather_conditions,
+    tournee_origin, external_tournee_id, integration_id,
+    created_at, updated_at, deleted_at
+"#;
+
+/// Bloquear la tournée (FOR UPDATE) hasta el final de la transacción y devolver su estado
+async fn lock_tournee(conn: &mut PgConnection, id: Uuid, company_id: Uuid) -> AppResult<TourneeStatus> {
+    sqlx::query_scalar::<_, TourneeStatus>(
+        "SELECT tournee_status FROM tournees WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL FOR UPDATE",
+    )
+    .bind(id)
+    .bind(company_id)
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Tournée no encontrada".to_string()))
+}
+
 /// Obtener todas las tournées con filtros
 pub async fn get_tournees(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Query(filters): Query<TourneeFilters>,
 ) -> AppResult<Json<Vec<TourneeListResponse>>> {
     let limit = filters.limit.unwrap_or(50).min(100);
     let offset = filters.offset.unwrap_or(0);
+    let driver_id = parse_filter(filters.driver_id.as_ref(), "driver_id", validate_uuid)?;
+    let vehicle_id = parse_filter(filters.vehicle_id.as_ref(), "vehicle_id", validate_uuid)?;
+    let date_from = parse_filter(filters.tournee_date_from.as_ref(), "tournee_date_from", validate_date)?;
+    let date_to = parse_filter(filters.tournee_date_to.as_ref(), "tournee_date_to", validate_date)?;
+    let created_after = parse_filter(filters.created_after.as_ref(), "created_after", validate_datetime)?;
+    let created_before = parse_filter(filters.created_before.as_ref(), "created_before", validate_datetime)?;
 
-    let rows = sqlx::query!(
+    let tournees = sqlx::query_as::<_, Tournee>(&format!(
         r#"
-        SELECT 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: String",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, tournee_origin, external_tournee_id,
-            created_at, updated_at, deleted_at
-        FROM tournees 
-        WHERE company_id = $1 
+        SELECT {}
+        FROM tournees
+        WHERE company_id = $1
         AND deleted_at IS NULL
+        AND ($4::text IS NULL OR tournee_status::text = $4)
+        AND ($5::uuid IS NULL OR driver_id = $5)
+        AND ($6::uuid IS NULL OR vehicle_id = $6)
+        AND ($7::date IS NULL OR tournee_date >= $7)
+        AND ($8::date IS NULL OR tournee_date <= $8)
+        AND ($9::text IS NULL OR tournee_origin = $9)
+        AND ($10::timestamptz IS NULL OR created_at > $10)
+        AND ($11::timestamptz IS NULL OR created_at < $11)
         ORDER BY created_at DESC
         LIMIT $2 OFFSET $3
         "#,
-        user.company_id,
-        limit,
-        offset
-    )
+        TOURNEE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(limit)
+    .bind(offset)
+    .bind(&filters.tournee_status)
+    .bind(driver_id)
+    .bind(vehicle_id)
+    .bind(date_from)
+    .bind(date_to)
+    .bind(&filters.tournee_origin)
+    .bind(created_after)
+    .bind(created_before)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let tournees: Vec<TourneeListResponse> = rows
-        .into_iter()
-        .map(|row| {
-            let tournee = Tournee {
-                id: row.id,
-                company_id: row.company_id,
-                driver_id: row.driver_id,
-                vehicle_id: row.vehicle_id,
-                tournee_date: row.tournee_date,
-                tournee_number: row.tournee_number,
-                start_location: row.start_location,
-                end_location: row.end_location,
-                tournee_status: match row.tournee_status.as_str() {
-                    "pending" => TourneeStatus::Pending,
-                    "in_progress" => TourneeStatus::InProgress,
-                    "completed" => TourneeStatus::Completed,
-                    "cancelled" => TourneeStatus::Cancelled,
-                    "paused" => TourneeStatus::Paused,
-                    _ => TourneeStatus::Pending,
-                },
-                start_time: row.start_time,
-                end_time: row.end_time,
-                start_mileage: row.start_mileage,
-                end_mileage: row.end_mileage,
-                total_distance: row.total_distance,
-                fuel_consumed: row.fuel_consumed,
-                fuel_cost: row.fuel_cost,
-                pre_inspection_notes: None,
-                post_inspection_notes: None,
-                pre_inspection_photos: None,
-                post_inspection_photos: None,
-                route_optimization_score: None,
-                estimated_duration_minutes: None,
-                actual_duration_minutes: None,
-                route_coordinates: None,
-                traffic_conditions: None,
-                weather_conditions: None,
-                tournee_origin: row.tournee_origin,
-                external_tournee_id: row.external_tournee_id,
-                integration_id: None,
-                created_at: row.created_at,
-                updated_at: row.updated_at,
-                deleted_at: row.deleted_at,
-            };
-            TourneeListResponse::from(tournee)
-        })
-        .collect();
-
-    Ok(Json(tournees))
+    .await?;
+
+    Ok(Json(tournees.into_iter().map(TourneeListResponse::from).collect()))
 }
 
 /// Obtener una tournée por ID
 pub async fn get_tournee(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<Json<TourneeResponse>> {
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: String",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, pre_inspection_notes, post_inspection_notes,
-            route_optimization_score, estimated_duration_minutes, actual_duration_minutes,
-            tournee_origin, external_tournee_id, integration_id,
-            created_at, updated_at, deleted_at
-        FROM tournees 
-        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
-        "#,
-        id,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Tournée no encontrada".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let tournee = Tournee {
-        id: row.id,
-        company_id: row.company_id,
-        driver_id: row.driver_id,
-        vehicle_id: row.vehicle_id,
-        tournee_date: row.tournee_date,
-        tournee_number: row.tournee_number,
-        start_location: row.start_location,
-        end_location: row.end_location,
-        tournee_status: match row.tournee_status.as_str() {
-            "pending" => TourneeStatus::Pending,
-            "in_progress" => TourneeStatus::InProgress,
-            "completed" => TourneeStatus::Completed,
-            "cancelled" => TourneeStatus::Cancelled,
-            "paused" => TourneeStatus::Paused,
-            _ => TourneeStatus::Pending,
-        },
-        start_time: row.start_time,
-        end_time: row.end_time,
-        start_mileage: row.start_mileage,
-        end_mileage: row.end_mileage,
-        total_distance: row.total_distance,
-        fuel_consumed: row.fuel_consumed,
-        fuel_cost: row.fuel_cost,
-        pre_inspection_notes: row.pre_inspection_notes,
-        post_inspection_notes: row.post_inspection_notes,
-        pre_inspection_photos: None,
-        post_inspection_photos: None,
-        route_optimization_score: row.route_optimization_score,
-        estimated_duration_minutes: row.estimated_duration_minutes,
-        actual_duration_minutes: row.actual_duration_minutes,
-        route_coordinates: None,
-        traffic_conditions: None,
-        weather_conditions: None,
-        tournee_origin: row.tournee_origin,
-        external_tournee_id: row.external_tournee_id,
-        integration_id: row.integration_id,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    let tournee = sqlx::query_as::<_, Tournee>(&format!(
+        "SELECT {} FROM tournees WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        TOURNEE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Tournée no encontrada".to_string()))?;
 
     Ok(Json(TourneeResponse::from(tournee)))
 }
@@ -181,7 +125,7 @@ pub async fn get_tournee(
 /// Crear una nueva tournée
 pub async fn create_tournee(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Json(tournee_data): Json<CreateTourneeRequest>,
 ) -> AppResult<Json<TourneeResponse>> {
     // Validar datos de entrada
@@ -193,7 +137,30 @@ pub async fn create_tournee(
     let vehicle_id = Uuid::parse_str(&tournee_data.vehicle_id)
         .map_err(|_| AppError::BadRequest("ID de vehículo inválido".to_string()))?;
 
-    let row = sqlx::query!(
+    // El conductor y el vehículo tienen que ser de la misma empresa
+    let (driver_ok, vehicle_ok) = sqlx::query_as::<_, (bool, bool)>(
+        r#"
+        SELECT
+            EXISTS(SELECT 1 FROM users WHERE id = $1 AND company_id = $3 AND user_type = 'driver' AND deleted_at IS NULL),
+            EXISTS(SELECT 1 FROM vehicles WHERE id = $2 AND company_id = $3 AND deleted_at IS NULL)
+        "#,
+    )
+    .bind(driver_id)
+    .bind(vehicle_id)
+    .bind(user.company_id)
+    .fetch_one(&state.pool)
+    .await?;
+
+    if !driver_ok {
+        return Err(AppError::BadRequest("Conductor no encontrado en la empresa".to_string()));
+    }
+    if !vehicle_ok {
+        return Err(AppError::BadRequest("Vehículo no encontrado en la empresa".to_string()));
+    }
+
+    let mut tx = state.pool.begin().await?;
+
+    let tournee = sqlx::query_as::<_, Tournee>(&format!(
         r#"
         INSERT INTO tournees (
             company_id, driver_id, vehicle_id, tournee_date, tournee_number,
@@ -201,67 +168,36 @@ pub async fn create_tournee(
             estimated_duration_minutes, tournee_origin, external_tournee_id,
             created_at, updated_at
         ) VALUES (
-            $1, $2, $3, CURRENT_DATE, $4, $5, $6, ($7::text)::tournee_status, $8, $9, $10, $11, NOW(), NOW()
+            $1, $2, $3, CURRENT_DATE, $4, $5, $6, 'pending', $7, $8, $9, $10, NOW(), NOW()
         )
-        RETURNING 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: crate::models::tournee::TourneeStatus",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, pre_inspection_notes, post_inspection_notes,
-            route_optimization_score, estimated_duration_minutes, actual_duration_minutes,
-            tournee_origin, external_tournee_id, integration_id,
-            created_at, updated_at, deleted_at
+        RETURNING {}
         "#,
-        user.company_id,
-        driver_id,
-        vehicle_id,
-        tournee_data.tournee_number,
-        tournee_data.start_location,
-        tournee_data.end_location,
-        "pending",
-        tournee_data.start_mileage,
-        tournee_data.estimated_duration_minutes,
-        tournee_data.tournee_origin.unwrap_or_else(|| "manual".to_string()),
-        tournee_data.external_tournee_id
-    )
-    .fetch_one(&state.pool)
+        TOURNEE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(driver_id)
+    .bind(vehicle_id)
+    .bind(&tournee_data.tournee_number)
+    .bind(&tournee_data.start_location)
+    .bind(&tournee_data.end_location)
+    .bind(tournee_data.start_mileage)
+    .bind(tournee_data.estimated_duration_minutes)
+    .bind(tournee_data.tournee_origin.unwrap_or_else(|| "manual".to_string()))
+    .bind(&tournee_data.external_tournee_id)
+    .fetch_one(&mut *tx)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let tournee = Tournee {
-        id: row.id,
-        company_id: row.company_id,
-        driver_id: row.driver_id,
-        vehicle_id: row.vehicle_id,
-        tournee_date: row.tournee_date,
-        tournee_number: row.tournee_number,
-        start_location: row.start_location,
-        end_location: row.end_location,
-        tournee_status: row.tournee_status,
-        start_time: row.start_time,
-        end_time: row.end_time,
-        start_mileage: row.start_mileage,
-        end_mileage: row.end_mileage,
-        total_distance: row.total_distance,
-        fuel_consumed: row.fuel_consumed,
-        fuel_cost: row.fuel_cost,
-        pre_inspection_notes: row.pre_inspection_notes,
-        post_inspection_notes: row.post_inspection_notes,
-        pre_inspection_photos: None,
-        post_inspection_photos: None,
-        route_optimization_score: row.route_optimization_score,
-        estimated_duration_minutes: row.estimated_duration_minutes,
-        actual_duration_minutes: row.actual_duration_minutes,
-        route_coordinates: None,
-        traffic_conditions: None,
-        weather_conditions: None,
-        tournee_origin: row.tournee_origin,
-        external_tournee_id: row.external_tournee_id,
-        integration_id: row.integration_id,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    .map_err(|e| match e.as_database_error().and_then(|d| d.code()).as_deref() {
+        Some("23505") => AppError::Conflict("El conductor ya tiene una tournée hoy".to_string()),
+        _ => AppError::Database(e),
+    })?;
+
+    status_history::record_tournee_status(
+        &mut tx, user.company_id, tournee.id, None, &tournee.tournee_status, user.user_id, None,
+    )
+    .await?;
+    // Paquetes fallidos de tournées anteriores del driver pendientes de reprogramar
+    failure_policies::carry_over_failed(&mut tx, user.company_id, tournee.driver_id, Some(user.user_id)).await?;
+    tx.commit().await?;
 
     Ok(Json(TourneeResponse::from(tournee)))
 }
@@ -269,78 +205,45 @@ pub async fn create_tournee(
 /// Iniciar una tournée
 pub async fn start_tournee(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
     Json(start_data): Json<StartTourneeRequest>,
 ) -> AppResult<Json<TourneeResponse>> {
     // Validar datos de entrada
     start_data.validate()
         .map_err(AppError::Validation)?;
+    check_uploaded(&state, &tournee_prefix(user.company_id, id), start_data.pre_inspection_photos.iter().flatten()).await?;
+
+    let mut tx = state.pool.begin().await?;
+    let from = lock_tournee(&mut tx, id, user.company_id).await?;
+    check_tournee_transition(&from, &TourneeStatus::InProgress, 0)?;
 
-    let row = sqlx::query!(
+    let tournee = sqlx::query_as::<_, Tournee>(&format!(
         r#"
         UPDATE tournees SET
             tournee_status = 'in_progress',
             start_time = NOW(),
             start_mileage = $2,
             pre_inspection_notes = $3,
+            pre_inspection_photos = COALESCE($4, pre_inspection_photos),
             updated_at = NOW()
-        WHERE id = $1 AND company_id = $4 AND tournee_status = 'pending'
-        RETURNING 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: String",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, pre_inspection_notes, post_inspection_notes,
-            route_optimization_score, estimated_duration_minutes, actual_duration_minutes,
-            tournee_origin, external_tournee_id, integration_id,
-            created_at, updated_at, deleted_at
+        WHERE id = $1
+        RETURNING {}
         "#,
-        id,
-        start_data.start_mileage,
-        start_data.pre_inspection_notes,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Tournée no encontrada o ya iniciada".to_string()),
-        _ => AppError::Database(e),
-    })?;
+        TOURNEE_COLUMNS
+    ))
+    .bind(id)
+    .bind(start_data.start_mileage)
+    .bind(&start_data.pre_inspection_notes)
+    .bind(&start_data.pre_inspection_photos)
+    .fetch_one(&mut *tx)
+    .await?;
 
-    let tournee = Tournee {
-        id: row.id,
-        company_id: row.company_id,
-        driver_id: row.driver_id,
-        vehicle_id: row.vehicle_id,
-        tournee_date: row.tournee_date,
-        tournee_number: row.tournee_number,
-        start_location: row.start_location,
-        end_location: row.end_location,
-        tournee_status: TourneeStatus::InProgress,
-        start_time: row.start_time,
-        end_time: row.end_time,
-        start_mileage: row.start_mileage,
-        end_mileage: row.end_mileage,
-        total_distance: row.total_distance,
-        fuel_consumed: row.fuel_consumed,
-        fuel_cost: row.fuel_cost,
-        pre_inspection_notes: row.pre_inspection_notes,
-        post_inspection_notes: row.post_inspection_notes,
-        pre_inspection_photos: None,
-        post_inspection_photos: None,
-        route_optimization_score: row.route_optimization_score,
-        estimated_duration_minutes: row.estimated_duration_minutes,
-        actual_duration_minutes: row.actual_duration_minutes,
-        route_coordinates: None,
-        traffic_conditions: None,
-        weather_conditions: None,
-        tournee_origin: row.tournee_origin,
-        external_tournee_id: row.external_tournee_id,
-        integration_id: row.integration_id,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    status_history::record_tournee_status(
+        &mut tx, user.company_id, id, Some(&from), &tournee.tournee_status, user.user_id, None,
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(Json(TourneeResponse::from(tournee)))
 }
@@ -348,126 +251,185 @@ pub async fn start_tournee(
 /// Finalizar una tournée
 pub async fn end_tournee(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
     Json(end_data): Json<EndTourneeRequest>,
 ) -> AppResult<Json<TourneeResponse>> {
     // Validar datos de entrada
     end_data.validate()
         .map_err(AppError::Validation)?;
+    check_uploaded(&state, &tournee_prefix(user.company_id, id), end_data.post_inspection_photos.iter().flatten()).await?;
 
-    // Calcular total_distance y actual_duration_minutes
-    let total_distance = if let Some(start_mileage) = sqlx::query_scalar!(
-        "SELECT start_mileage FROM tournees WHERE id = $1",
-        id
+    let mut tx = state.pool.begin().await?;
+    let from = lock_tournee(&mut tx, id, user.company_id).await?;
+
+    let out_for_delivery = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM packages
+        WHERE tournee_id = $1 AND delivery_status = 'out_for_delivery' AND deleted_at IS NULL
+        "#,
     )
-    .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
-    .flatten()
-    {
-        Some(end_data.end_mileage - start_mileage)
-    } else {
-        None
-    };
-
-    let row = sqlx::query!(
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await?;
+    check_tournee_transition(&from, &TourneeStatus::Completed, out_for_delivery)?;
+
+    let paused_minutes = status_history::paused_minutes(&mut tx, id).await?;
+
+    // total_distance y actual_duration_minutes se calculan a partir del inicio,
+    // descontando el tiempo en pausa
+    let tournee = sqlx::query_as::<_, Tournee>(&format!(
         r#"
         UPDATE tournees SET
             tournee_status = 'completed',
             end_time = NOW(),
             end_mileage = $2,
-            total_distance = $3,
-            fuel_consumed = $4,
-            fuel_cost = $5,
-            post_inspection_notes = $6,
-            actual_duration_minutes = EXTRACT(EPOCH FROM (NOW() - start_time)) / 60,
+            total_distance = $2 - start_mileage,
+            fuel_consumed = $3,
+            fuel_cost = $4,
+            post_inspection_notes = $5,
+            post_inspection_photos = COALESCE($6, post_inspection_photos),
+            actual_duration_minutes = GREATEST(EXTRACT(EPOCH FROM (NOW() - start_time)) / 60 - $7, 0),
             updated_at = NOW()
-        WHERE id = $1 AND company_id = $7 AND tournee_status = 'in_progress'
-        RETURNING 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: String",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, pre_inspection_notes, post_inspection_notes,
-            route_optimization_score, estimated_duration_minutes, actual_duration_minutes,
-            tournee_origin, external_tournee_id, integration_id,
-            created_at, updated_at, deleted_at
+        WHERE id = $1
+        RETURNING {}
         "#,
-        id,
-        end_data.end_mileage,
-        total_distance,
-        end_data.fuel_consumed,
-        end_data.fuel_cost,
-        end_data.post_inspection_notes,
-        user.company_id
+        TOURNEE_COLUMNS
+    ))
+    .bind(id)
+    .bind(end_data.end_mileage)
+    .bind(end_data.fuel_consumed)
+    .bind(end_data.fuel_cost)
+    .bind(&end_data.post_inspection_notes)
+    .bind(&end_data.post_inspection_photos)
+    .bind(paused_minutes)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    status_history::record_tournee_status(
+        &mut tx, user.company_id, id, Some(&from), &tournee.tournee_status, user.user_id, None,
     )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Tournée no encontrada o no está en progreso".to_string()),
-        _ => AppError::Database(e),
-    })?;
+    .await?;
+    // Los fallidos del día pasan a la siguiente tournée del driver si ya existe
+    failure_policies::carry_over_failed(&mut tx, user.company_id, tournee.driver_id, Some(user.user_id)).await?;
+    tx.commit().await?;
+
+    Ok(Json(TourneeResponse::from(tournee)))
+}
+
+/// Cambio de estado sin más datos que unas notas (pausa, reanudación, cancelación)
+async fn change_tournee_status(
+    state: &FleetState,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    to: TourneeStatus,
+    request: StatusChangeRequest,
+) -> AppResult<Json<TourneeResponse>> {
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let from = lock_tournee(&mut tx, id, user.company_id).await?;
+    check_tournee_transition(&from, &to, 0)?;
 
-    let tournee = Tournee {
-        id: row.id,
-        company_id: row.company_id,
-        driver_id: row.driver_id,
-        vehicle_id: row.vehicle_id,
-        tournee_date: row.tournee_date,
-        tournee_number: row.tournee_number,
-        start_location: row.start_location,
-        end_location: row.end_location,
-        tournee_status: TourneeStatus::Completed,
-        start_time: row.start_time,
-        end_time: row.end_time,
-        start_mileage: row.start_mileage,
-        end_mileage: row.end_mileage,
-        total_distance: row.total_distance,
-        fuel_consumed: row.fuel_consumed,
-        fuel_cost: row.fuel_cost,
-        pre_inspection_notes: row.pre_inspection_notes,
-        post_inspection_notes: row.post_inspection_notes,
-        pre_inspection_photos: None,
-        post_inspection_photos: None,
-        route_optimization_score: row.route_optimization_score,
-        estimated_duration_minutes: row.estimated_duration_minutes,
-        actual_duration_minutes: row.actual_duration_minutes,
-        route_coordinates: None,
-        traffic_conditions: None,
-        weather_conditions: None,
-        tournee_origin: row.tournee_origin,
-        external_tournee_id: row.external_tournee_id,
-        integration_id: row.integration_id,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    let tournee = sqlx::query_as::<_, Tournee>(&format!(
+        "UPDATE tournees SET tournee_status = $2, updated_at = NOW() WHERE id = $1 RETURNING {}",
+        TOURNEE_COLUMNS
+    ))
+    .bind(id)
+    .bind(to.clone())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    status_history::record_tournee_status(
+        &mut tx, user.company_id, id, Some(&from), &to, user.user_id, request.notes.as_deref(),
+    )
+    .await?;
+    // Los fallidos de una tournée cancelada también pasan a la siguiente
+    if to == TourneeStatus::Cancelled {
+        failure_policies::carry_over_failed(&mut tx, user.company_id, tournee.driver_id, Some(user.user_id)).await?;
+    }
+    tx.commit().await?;
 
     Ok(Json(TourneeResponse::from(tournee)))
 }
 
+/// Pausar una tournée en curso
+pub async fn pause_tournee(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(id): Path<Uuid>,
+    request: Option<Json<StatusChangeRequest>>,
+) -> AppResult<Json<TourneeResponse>> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    change_tournee_status(&state, &user, id, TourneeStatus::Paused, request).await
+}
+
+/// Reanudar una tournée pausada
+pub async fn resume_tournee(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(id): Path<Uuid>,
+    request: Option<Json<StatusChangeRequest>>,
+) -> AppResult<Json<TourneeResponse>> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    change_tournee_status(&state, &user, id, TourneeStatus::InProgress, request).await
+}
+
+/// Cancelar una tournée que no ha terminado
+pub async fn cancel_tournee(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(id): Path<Uuid>,
+    request: Option<Json<StatusChangeRequest>>,
+) -> AppResult<Json<TourneeResponse>> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    change_tournee_status(&state, &user, id, TourneeStatus::Cancelled, request).await
+}
+
+/// Obtener el histórico de estados de una tournée
+pub async fn get_tournee_history(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<StatusHistoryEntry>>> {
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM tournees WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(id)
+    .bind(user.company_id)
+    .fetch_one(&state.pool)
+    .await?;
+
+    if !exists {
+        return Err(AppError::NotFound("Tournée no encontrada".to_string()));
+    }
+
+    let history = status_history::tournee_history(&state.pool, user.company_id, id).await?;
+    Ok(Json(history))
+}
+
 /// Eliminar una tournée (soft delete)
 pub async fn delete_tournee(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    let result = sqlx::query!(
+    let result = sqlx::query(
         r#"
-        UPDATE tournees 
+        UPDATE tournees
         SET deleted_at = NOW(), updated_at = NOW()
         WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
         "#,
-        id,
-        user.company_id
     )
+    .bind(id)
+    .bind(user.company_id)
     .execute(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     if result.rows_affected() == 0 {
         return Err(AppError::NotFound("Tournée no encontrada".to_string()));
     }
 
     Ok(StatusCode::NO_CONTENT)
-}
\ No newline at end of file
+}
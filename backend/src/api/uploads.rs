@@ -0,0 +1,193 @@
+//! Handlers de subida y descarga de ficheros
+//!
+//! Fotos de entrega y firmas de un paquete, fotos de inspección de una
+//! tournée. Las claves devueltas se envían después en `MarkDeliveredRequest`,
+//! `StartTourneeRequest` o `EndTourneeRequest`.
+
+use axum::{
+    extract::{Multipart, Path, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::AuthenticatedUser,
+    services::blob_store::is_valid_key,
+    services::proof_of_delivery::{
+        is_upload_key, package_prefix, store_image, tournee_prefix, ImageKind, StoredImage, UploadError,
+    },
+    state::FleetState,
+    utils::errors::{AppError, AppResult},
+};
+
+/// Campos multipart admitidos para un paquete
+const PACKAGE_FIELDS: &[&str] = &["delivery_photo", "signature_image", "signature_photo"];
+
+/// Campos multipart admitidos para una tournée
+const TOURNEE_FIELDS: &[&str] = &["pre_inspection_photos", "post_inspection_photos"];
+
+/// Fichero guardado y campo del formulario del que viene
+#[derive(Debug, Serialize)]
+pub struct UploadedFile {
+    pub field: String,
+    #[serde(flatten)]
+    pub image: StoredImage,
+}
+
+/// Response de una subida
+#[derive(Debug, Serialize)]
+pub struct UploadResponse {
+    pub files: Vec<UploadedFile>,
+}
+
+fn multipart_error(error: axum::extract::multipart::MultipartError) -> AppError {
+    AppError::BadRequest(format!("Multipart inválido: {}", error))
+}
+
+/// Guardar cada parte del formulario bajo `prefix` (solo los campos admitidos)
+async fn store_parts(
+    state: &FleetState,
+    mut multipart: Multipart,
+    prefix: &str,
+    allowed: &[&str],
+) -> AppResult<Vec<UploadedFile>> {
+    let mut files = Vec::new();
+
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        let name = field.name().unwrap_or_default().to_string();
+        if !allowed.contains(&name.as_str()) {
+            return Err(AppError::BadRequest(format!(
+                "Campo desconocido: '{}' (admitidos: {})",
+                name,
+                allowed.join(", ")
+            )));
+        }
+        if files.len() >= state.uploads.max_files {
+            return Err(AppError::BadRequest(format!(
+                "Como máximo {} ficheros por petición",
+                state.uploads.max_files
+            )));
+        }
+
+        let content_type = field.content_type().map(str::to_string);
+        let mut data = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            if data.len() + chunk.len() > state.uploads.max_file_bytes {
+                return Err(UploadError::TooLarge { max: state.uploads.max_file_bytes }.into());
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        let image = store_image(state.blob_store.as_ref(), prefix, data, content_type.as_deref(), &state.uploads).await?;
+        files.push(UploadedFile { field: name, image });
+    }
+
+    if files.is_empty() {
+        return Err(AppError::BadRequest("La petición no contiene ficheros".to_string()));
+    }
+
+    Ok(files)
+}
+
+/// Subir fotos de entrega y firmas de un paquete
+pub async fn upload_package_files(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(id): Path<Uuid>,
+    multipart: Multipart,
+) -> AppResult<Json<UploadResponse>> {
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM packages WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(id)
+    .bind(user.company_id)
+    .fetch_one(&state.pool)
+    .await?;
+
+    if !exists {
+        return Err(AppError::NotFound("Paquete no encontrado".to_string()));
+    }
+
+    let files = store_parts(&state, multipart, &package_prefix(user.company_id, id), PACKAGE_FIELDS).await?;
+    Ok(Json(UploadResponse { files }))
+}
+
+/// Subir fotos de inspección de una tournée
+pub async fn upload_tournee_files(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(id): Path<Uuid>,
+    multipart: Multipart,
+) -> AppResult<Json<UploadResponse>> {
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM tournees WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(id)
+    .bind(user.company_id)
+    .fetch_one(&state.pool)
+    .await?;
+
+    if !exists {
+        return Err(AppError::NotFound("Tournée no encontrada".to_string()));
+    }
+
+    let files = store_parts(&state, multipart, &tournee_prefix(user.company_id, id), TOURNEE_FIELDS).await?;
+    Ok(Json(UploadResponse { files }))
+}
+
+/// Comprobar que cada clave es una imagen subida bajo `prefix` y que existe
+pub async fn check_uploaded<'a>(
+    state: &FleetState,
+    prefix: &str,
+    keys: impl IntoIterator<Item = &'a String>,
+) -> AppResult<()> {
+    for key in keys {
+        let exists = is_upload_key(key, prefix)
+            && state
+                .blob_store
+                .exists(key)
+                .await
+                .map_err(|e| AppError::Internal(format!("Error comprobando el fichero: {}", e)))?;
+
+        if !exists {
+            return Err(AppError::BadRequest(format!("Fichero no subido para este recurso: {}", key)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Descargar un fichero (original o miniatura) de la empresa
+pub async fn get_file(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<FleetState>,
+    Path(key): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let not_found = || AppError::NotFound("Fichero no encontrado".to_string());
+
+    if !is_valid_key(&key) || !key.starts_with(&format!("companies/{}/", user.company_id)) {
+        return Err(not_found());
+    }
+
+    let data = state
+        .blob_store
+        .get(&key)
+        .await
+        .map_err(|e| AppError::Internal(format!("Error leyendo el fichero: {}", e)))?
+        .ok_or_else(not_found)?;
+
+    let content_type = ImageKind::from_key(&key)
+        .map(|kind| kind.content_type())
+        .unwrap_or("application/octet-stream");
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, "private, max-age=86400"),
+        ],
+        data,
+    ))
+}
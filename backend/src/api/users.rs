@@ -1,5 +1,5 @@
 //! Handlers de usuarios
-//! 
+//!
 //! Este módulo maneja las operaciones CRUD para usuarios.
 
 use axum::{
@@ -8,70 +8,59 @@ use axum::{
     Json,
 };
 use bcrypt::{hash, DEFAULT_COST};
-use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
-    models::user::{User, UserResponse, CreateUserRequest, UserType, UserStatus},
+    models::user::{User, UserResponse, CreateUserRequest, UserType},
     utils::errors::{AppError, AppResult},
     middleware::auth::AuthenticatedUser,
+    state::FleetState,
 };
 
+/// Columnas de `users` que mapea `User` (timestamps nulos en el schema, no en el modelo)
+pub(crate) const USER_COLUMNS: &str = r#"
+    id, company_id, user_type, user_status, username, password_hash, full_name, email,
+    COALESCE(created_at, NOW()) AS created_at,
+    COALESCE(updated_at, NOW()) AS updated_at,
+    deleted_at
+"#;
+
+/// Username o email repetido en la empresa → 409
+pub(crate) fn map_user_write_error(error: sqlx::Error) -> AppError {
+    match error.as_database_error().and_then(|e| e.code()).as_deref() {
+        Some("23505") => AppError::Conflict("Username o email ya existe en la empresa".to_string()),
+        _ => AppError::Database(error),
+    }
+}
+
 /// Handler para listar usuarios
 pub async fn get_users(
     Extension(user): Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
 ) -> AppResult<Json<Vec<UserResponse>>> {
-    let rows = sqlx::query!(
+    let users = sqlx::query_as::<_, User>(&format!(
         r#"
-        SELECT 
-            id, company_id, user_type as "user_type: String", user_status as "user_status: String", 
-            username, password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE company_id = $1 
+        SELECT {}
+        FROM users
+        WHERE company_id = $1
         AND deleted_at IS NULL
         ORDER BY created_at DESC
         LIMIT 50
         "#,
-        user.company_id
-    )
+        USER_COLUMNS
+    ))
+    .bind(user.company_id)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let users: Vec<UserResponse> = rows
-        .into_iter()
-        .map(|row| {
-            UserResponse {
-                id: row.id,
-                company_id: row.company_id,
-                user_type: match row.user_type.as_str() {
-                    "admin" => UserType::Admin,
-                    "driver" => UserType::Driver,
-                    _ => UserType::Driver,
-                },
-                user_status: match row.user_status.as_str() {
-                    "active" => UserStatus::Active,
-                    "inactive" => UserStatus::Inactive,
-                    "suspended" => UserStatus::Suspended,
-                    _ => UserStatus::Active,
-                },
-                username: row.username,
-                email: None,
-                created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-                updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-            }
-        })
-        .collect();
-
-    Ok(Json(users))
+    .await?;
+
+    Ok(Json(users.into_iter().map(UserResponse::from).collect()))
 }
 
 /// Handler para crear usuario
 pub async fn create_user(
     Extension(user): Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Json(user_data): Json<CreateUserRequest>,
 ) -> AppResult<Json<UserResponse>> {
     // Validar datos de entrada
@@ -82,86 +71,59 @@ pub async fn create_user(
     let password_hash = hash(&user_data.password, DEFAULT_COST)
         .map_err(|e| AppError::Hash(format!("Error hasheando password: {}", e)))?;
 
-    // Crear usuario
-    let row = sqlx::query!(
+    // Crear usuario en la empresa del admin
+    let new_user = sqlx::query_as::<_, User>(&format!(
         r#"
         INSERT INTO users (
-            company_id, user_type, user_status, username, 
-            password_hash, created_at, updated_at
-        ) VALUES ($1, ($2::text)::user_type, ($3::text)::user_status, $4, $5, NOW(), NOW())
-        RETURNING 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", 
-            username, password_hash, created_at, updated_at, deleted_at
+            company_id, user_type, user_status, username,
+            password_hash, full_name, email, created_at, updated_at
+        ) VALUES ($1, $2, 'active', $3, $4, $5, $6, NOW(), NOW())
+        RETURNING {}
         "#,
-        user.company_id,
-        match user_data.user_type {
-            UserType::Admin => "admin",
-            UserType::Driver => "driver",
-        },
-        "active",
-        user_data.username,
-        password_hash
-    )
+        USER_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(&user_data.user_type)
+    .bind(&user_data.username)
+    .bind(&password_hash)
+    .bind(&user_data.full_name)
+    .bind(&user_data.email)
     .fetch_one(&state.pool)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let new_user_response = UserResponse {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: row.user_type,
-        user_status: row.user_status,
-        username: row.username,
-        email: None,
-        created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-        updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-    };
-
-    Ok(Json(new_user_response))
+    .map_err(map_user_write_error)?;
+
+    Ok(Json(UserResponse::from(new_user)))
 }
 
 /// Handler para obtener usuario por ID
 pub async fn get_user(
     Extension(user): Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(user_id): Path<Uuid>,
 ) -> AppResult<Json<UserResponse>> {
-    let row = sqlx::query!(
+    let found = sqlx::query_as::<_, User>(&format!(
         r#"
-        SELECT 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", 
-            username, password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE id = $1 
+        SELECT {}
+        FROM users
+        WHERE id = $1
         AND company_id = $2
         AND deleted_at IS NULL
         "#,
-        user_id,
-        user.company_id
-    )
+        USER_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(user.company_id)
     .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
+    .await?
     .ok_or_else(|| AppError::NotFound("Usuario no encontrado".to_string()))?;
 
-    let user_response = UserResponse {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: row.user_type,
-        user_status: row.user_status,
-        username: row.username,
-        email: None,
-        created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-        updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-    };
-
-    Ok(Json(user_response))
+    Ok(Json(UserResponse::from(found)))
 }
 
 /// Handler para actualizar usuario (versión simplificada)
 pub async fn update_user(
     Extension(_user): Extension<AuthenticatedUser>,
-    State(_state): State<crate::state::AppState>,
+    State(_state): State<FleetState>,
     Path(_user_id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
     // Por ahora retorna Not Implemented
@@ -171,21 +133,24 @@ pub async fn update_user(
 /// Handler para eliminar usuario (soft delete)
 pub async fn delete_user(
     Extension(user): Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(user_id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    let result = sqlx::query!(
+    if user_id == user.user_id {
+        return Err(AppError::BadRequest("No puedes eliminar tu propio usuario".to_string()));
+    }
+
+    let result = sqlx::query(
         r#"
-        UPDATE users 
+        UPDATE users
         SET deleted_at = NOW(), updated_at = NOW()
         WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
         "#,
-        user_id,
-        user.company_id
     )
+    .bind(user_id)
+    .bind(user.company_id)
     .execute(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     if result.rows_affected() == 0 {
         return Err(AppError::NotFound("Usuario no encontrado".to_string()));
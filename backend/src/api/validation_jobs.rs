@@ -0,0 +1,95 @@
+//! Progreso de la validación de direcciones en segundo plano
+//!
+//! El id de la tarea llega en `validation_job` de `POST /api/colis-prive/packages`.
+//! Solo los usuarios de la empresa de la tarea pueden consultarla (JWT).
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use futures::{stream, Stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::services::validation_jobs::{ValidationEvent, ValidationJobSnapshot};
+use crate::state::AppState;
+use crate::utils::errors::{AppError, AppResult};
+use crate::utils::jwt::{require_user, JwtConfig};
+
+/// Comprobar que la tarea es de la empresa del usuario; las de otras empresas
+/// se responden como inexistentes
+async fn authorize_job(state: &AppState, headers: &HeaderMap, id: Uuid) -> AppResult<()> {
+    let claims = require_user(headers, &JwtConfig::from(&state.config), &state.pool).await?;
+    let company_id = Uuid::parse_str(&claims.company_id)
+        .map_err(|_| AppError::Unauthorized("company_id inválido en el token".to_string()))?;
+
+    if !state.validation_jobs.belongs_to(id, company_id).await? {
+        return Err(AppError::NotFound(format!("Tarea de validación {} no encontrada", id)));
+    }
+    Ok(())
+}
+
+/// GET /api/address-validation/jobs/:id - Estado y resultados de una tarea
+pub async fn get_validation_job(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<ValidationJobSnapshot>> {
+    authorize_job(&state, &headers, id).await?;
+
+    state.validation_jobs
+        .snapshot(id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Tarea de validación {} no encontrada", id)))
+}
+
+/// GET /api/address-validation/jobs/:id/events - Paquetes validados (SSE)
+///
+/// Primero los resultados ya guardados y después cada paquete según se valida;
+/// el flujo termina con `completed` o `failed`.
+pub async fn validation_job_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> AppResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    authorize_job(&state, &headers, id).await?;
+
+    // Suscribirse antes de leer el estado para no perder eventos intermedios
+    let receiver = state.validation_jobs.subscribe(id);
+    let snapshot = state.validation_jobs
+        .snapshot(id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Tarea de validación {} no encontrada", id)))?;
+
+    let replay = snapshot.events();
+    let finished = replay.last().is_some_and(|event| event.is_terminal());
+    let receiver = if finished { None } else { receiver };
+
+    let live = stream::unfold(receiver, |receiver| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let next = if event.is_terminal() { None } else { Some(receiver) };
+                    return Some((event, next));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("⚠️ Suscriptor SSE retrasado, {} eventos perdidos", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let events = stream::iter(replay).chain(live).map(|event: ValidationEvent| {
+        let sse = Event::default().event(event.name());
+        Ok(sse.json_data(&event).unwrap_or_else(|_| Event::default().event(event.name())))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -1,5 +1,5 @@
 //! Handlers de Vehicles
-//! 
+//!
 //! Este módulo maneja las operaciones CRUD para vehículos.
 
 use axum::{
@@ -7,144 +7,85 @@ use axum::{
     http::StatusCode,
     Json,
 };
-use serde_json::json;
-use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     models::vehicle::{
-        Vehicle, VehicleStatus, VehicleResponse, VehicleListResponse,
+        Vehicle, VehicleResponse, VehicleListResponse,
         CreateVehicleRequest, UpdateVehicleRequest, VehicleFilters,
     },
     utils::errors::{AppError, AppResult},
     middleware::auth::AuthenticatedUser,
+    state::FleetState,
 };
 
+/// Columnas de `vehicles` que mapea `Vehicle`
+const VEHICLE_COLUMNS: &str = r#"
+    id, company_id, license_plate, brand, model, year, color,
+    vehicle_status, current_mileage, fuel_type,
+    fuel_capacity, weekly_fuel_allocation, total_damage_cost,
+    damage_incidents_count, vin, engine_size, transmission,
+    created_at, updated_at, deleted_at
+"#;
+
+/// Matrícula repetida en la empresa → 409, estado desconocido → 400
+fn map_write_error(error: sqlx::Error) -> AppError {
+    match error.as_database_error().and_then(|e| e.code()).as_deref() {
+        Some("23505") => AppError::Conflict("Ya existe un vehículo con esa matrícula".to_string()),
+        Some("22P02") => AppError::BadRequest("Estado de vehículo inválido".to_string()),
+        _ => AppError::Database(error),
+    }
+}
+
 /// Obtener todos los vehículos con filtros
 pub async fn get_vehicles(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Query(filters): Query<VehicleFilters>,
 ) -> AppResult<Json<Vec<VehicleListResponse>>> {
     let limit = filters.limit.unwrap_or(50).min(100);
     let offset = filters.offset.unwrap_or(0);
 
-    let rows = sqlx::query!(
+    let vehicles = sqlx::query_as::<_, Vehicle>(&format!(
         r#"
-        SELECT 
-            id, company_id, license_plate, brand, model, year, color,
-            vehicle_status as "vehicle_status: String", current_mileage, fuel_type,
-            fuel_capacity, weekly_fuel_allocation, total_damage_cost, 
-            damage_incidents_count, vin, engine_size, transmission,
-            created_at, updated_at, deleted_at
-        FROM vehicles 
-        WHERE company_id = $1 
+        SELECT {}
+        FROM vehicles
+        WHERE company_id = $1
         AND deleted_at IS NULL
+        AND ($4::text IS NULL OR vehicle_status::text = $4)
+        AND ($5::text IS NULL OR fuel_type = $5)
         ORDER BY created_at DESC
         LIMIT $2 OFFSET $3
         "#,
-        user.company_id,
-        limit,
-        offset
-    )
+        VEHICLE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(limit)
+    .bind(offset)
+    .bind(&filters.vehicle_status)
+    .bind(&filters.fuel_type)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let vehicles: Vec<VehicleListResponse> = rows
-        .into_iter()
-        .map(|row| {
-            let vehicle = Vehicle {
-                id: row.id,
-                company_id: row.company_id,
-                license_plate: row.license_plate,
-                brand: row.brand,
-                model: row.model,
-                year: row.year,
-                color: row.color,
-                vehicle_status: match row.vehicle_status.as_str() {
-                    "active" => VehicleStatus::Active,
-                    "maintenance" => VehicleStatus::Maintenance,
-                    "out_of_service" => VehicleStatus::OutOfService,
-                    "retired" => VehicleStatus::Retired,
-                    _ => VehicleStatus::Active,
-                },
-                current_mileage: row.current_mileage,
-                fuel_type: row.fuel_type,
-                fuel_capacity: row.fuel_capacity,
-                weekly_fuel_allocation: row.weekly_fuel_allocation,
-                total_damage_cost: row.total_damage_cost,
-                damage_incidents_count: row.damage_incidents_count,
-                vin: row.vin,
-                engine_size: row.engine_size,
-                transmission: row.transmission,
-                created_at: row.created_at,
-                updated_at: row.updated_at,
-                deleted_at: row.deleted_at,
-            };
-            VehicleListResponse::from(vehicle)
-        })
-        .collect();
+    .await?;
 
-    Ok(Json(vehicles))
+    Ok(Json(vehicles.into_iter().map(VehicleListResponse::from).collect()))
 }
 
 /// Obtener un vehículo por ID
 pub async fn get_vehicle(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<Json<VehicleResponse>> {
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, company_id, license_plate, brand, model, year, color,
-            vehicle_status as "vehicle_status: String", current_mileage, fuel_type,
-            fuel_capacity, weekly_fuel_allocation, total_damage_cost, 
-            damage_incidents_count, vin, engine_size, transmission,
-            created_at, updated_at, deleted_at
-        FROM vehicles 
-        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
-        "#,
-        id,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Vehículo no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let vehicle = Vehicle {
-        id: row.id,
-        company_id: row.company_id,
-        license_plate: row.license_plate,
-        brand: row.brand,
-        model: row.model,
-        year: row.year,
-        color: row.color,
-        vehicle_status: match row.vehicle_status.as_str() {
-            "active" => VehicleStatus::Active,
-            "maintenance" => VehicleStatus::Maintenance,
-            "out_of_service" => VehicleStatus::OutOfService,
-            "retired" => VehicleStatus::Retired,
-            _ => VehicleStatus::Active,
-        },
-        current_mileage: row.current_mileage,
-        fuel_type: row.fuel_type,
-        fuel_capacity: row.fuel_capacity,
-        weekly_fuel_allocation: row.weekly_fuel_allocation,
-        total_damage_cost: row.total_damage_cost,
-        damage_incidents_count: row.damage_incidents_count,
-        vin: row.vin,
-        engine_size: row.engine_size,
-        transmission: row.transmission,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    let vehicle = sqlx::query_as::<_, Vehicle>(&format!(
+        "SELECT {} FROM vehicles WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        VEHICLE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Vehículo no encontrado".to_string()))?;
 
     Ok(Json(VehicleResponse::from(vehicle)))
 }
@@ -152,14 +93,14 @@ pub async fn get_vehicle(
 /// Crear un nuevo vehículo
 pub async fn create_vehicle(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Json(vehicle_data): Json<CreateVehicleRequest>,
 ) -> AppResult<Json<VehicleResponse>> {
     // Validar datos de entrada
     vehicle_data.validate()
         .map_err(AppError::Validation)?;
 
-    let row = sqlx::query!(
+    let vehicle = sqlx::query_as::<_, Vehicle>(&format!(
         r#"
         INSERT INTO vehicles (
             company_id, license_plate, brand, model, year, color,
@@ -167,58 +108,27 @@ pub async fn create_vehicle(
             weekly_fuel_allocation, total_damage_cost, damage_incidents_count,
             vin, engine_size, transmission, created_at, updated_at
         ) VALUES (
-            $1, $2, $3, $4, $5, $6, ($7::text)::vehicle_status, $8, $9, $10, $11, $12, $13, $14, $15, $16, NOW(), NOW()
+            $1, $2, $3, $4, $5, $6, 'active', 0, $7, $8, $9, 0, 0, $10, $11, $12, NOW(), NOW()
         )
-        RETURNING 
-            id, company_id, license_plate, brand, model, year, color,
-            vehicle_status as "vehicle_status: crate::models::vehicle::VehicleStatus", current_mileage, fuel_type,
-            fuel_capacity, weekly_fuel_allocation, total_damage_cost, 
-            damage_incidents_count, vin, engine_size, transmission,
-            created_at, updated_at, deleted_at
+        RETURNING {}
         "#,
-        user.company_id,
-        vehicle_data.license_plate,
-        vehicle_data.brand,
-        vehicle_data.model,
-        vehicle_data.year,
-        vehicle_data.color,
-        "active",
-        rust_decimal::Decimal::new(0, 0), // current_mileage = 0
-        vehicle_data.fuel_type,
-        vehicle_data.fuel_capacity,
-        vehicle_data.weekly_fuel_allocation,
-        rust_decimal::Decimal::new(0, 0), // total_damage_cost = 0
-        0, // damage_incidents_count = 0
-        vehicle_data.vin,
-        vehicle_data.engine_size,
-        vehicle_data.transmission
-    )
+        VEHICLE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(&vehicle_data.license_plate)
+    .bind(&vehicle_data.brand)
+    .bind(&vehicle_data.model)
+    .bind(vehicle_data.year)
+    .bind(&vehicle_data.color)
+    .bind(&vehicle_data.fuel_type)
+    .bind(vehicle_data.fuel_capacity)
+    .bind(vehicle_data.weekly_fuel_allocation)
+    .bind(&vehicle_data.vin)
+    .bind(&vehicle_data.engine_size)
+    .bind(&vehicle_data.transmission)
     .fetch_one(&state.pool)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let vehicle = Vehicle {
-        id: row.id,
-        company_id: row.company_id,
-        license_plate: row.license_plate,
-        brand: row.brand,
-        model: row.model,
-        year: row.year,
-        color: row.color,
-        vehicle_status: row.vehicle_status,
-        current_mileage: row.current_mileage,
-        fuel_type: row.fuel_type,
-        fuel_capacity: row.fuel_capacity,
-        weekly_fuel_allocation: row.weekly_fuel_allocation,
-        total_damage_cost: row.total_damage_cost,
-        damage_incidents_count: row.damage_incidents_count,
-        vin: row.vin,
-        engine_size: row.engine_size,
-        transmission: row.transmission,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    .map_err(map_write_error)?;
 
     Ok(Json(VehicleResponse::from(vehicle)))
 }
@@ -226,7 +136,7 @@ pub async fn create_vehicle(
 /// Actualizar un vehículo existente
 pub async fn update_vehicle(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
     Json(vehicle_data): Json<UpdateVehicleRequest>,
 ) -> AppResult<Json<VehicleResponse>> {
@@ -234,20 +144,7 @@ pub async fn update_vehicle(
     vehicle_data.validate()
         .map_err(AppError::Validation)?;
 
-    // Verificar que el vehículo existe y pertenece a la empresa
-    let _existing = sqlx::query!(
-        "SELECT id FROM vehicles WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
-        id,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Vehículo no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let row = sqlx::query!(
+    let vehicle = sqlx::query_as::<_, Vehicle>(&format!(
         r#"
         UPDATE vehicles SET
             license_plate = COALESCE($2, license_plate),
@@ -264,56 +161,30 @@ pub async fn update_vehicle(
             engine_size = COALESCE($13, engine_size),
             transmission = COALESCE($14, transmission),
             updated_at = NOW()
-        WHERE id = $1 AND company_id = $15
-        RETURNING 
-            id, company_id, license_plate, brand, model, year, color,
-            vehicle_status as "vehicle_status: crate::models::vehicle::VehicleStatus", current_mileage, fuel_type,
-            fuel_capacity, weekly_fuel_allocation, total_damage_cost, 
-            damage_incidents_count, vin, engine_size, transmission,
-            created_at, updated_at, deleted_at
+        WHERE id = $1 AND company_id = $15 AND deleted_at IS NULL
+        RETURNING {}
         "#,
-        id,
-        vehicle_data.license_plate,
-        vehicle_data.brand,
-        vehicle_data.model,
-        vehicle_data.year,
-        vehicle_data.color,
-        vehicle_data.vehicle_status,
-        vehicle_data.current_mileage,
-        vehicle_data.fuel_type,
-        vehicle_data.fuel_capacity,
-        vehicle_data.weekly_fuel_allocation,
-        vehicle_data.vin,
-        vehicle_data.engine_size,
-        vehicle_data.transmission,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
+        VEHICLE_COLUMNS
+    ))
+    .bind(id)
+    .bind(&vehicle_data.license_plate)
+    .bind(&vehicle_data.brand)
+    .bind(&vehicle_data.model)
+    .bind(vehicle_data.year)
+    .bind(&vehicle_data.color)
+    .bind(&vehicle_data.vehicle_status)
+    .bind(vehicle_data.current_mileage)
+    .bind(&vehicle_data.fuel_type)
+    .bind(vehicle_data.fuel_capacity)
+    .bind(vehicle_data.weekly_fuel_allocation)
+    .bind(&vehicle_data.vin)
+    .bind(&vehicle_data.engine_size)
+    .bind(&vehicle_data.transmission)
+    .bind(user.company_id)
+    .fetch_optional(&state.pool)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let vehicle = Vehicle {
-        id: row.id,
-        company_id: row.company_id,
-        license_plate: row.license_plate,
-        brand: row.brand,
-        model: row.model,
-        year: row.year,
-        color: row.color,
-        vehicle_status: row.vehicle_status,
-        current_mileage: row.current_mileage,
-        fuel_type: row.fuel_type,
-        fuel_capacity: row.fuel_capacity,
-        weekly_fuel_allocation: row.weekly_fuel_allocation,
-        total_damage_cost: row.total_damage_cost,
-        damage_incidents_count: row.damage_incidents_count,
-        vin: row.vin,
-        engine_size: row.engine_size,
-        transmission: row.transmission,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    .map_err(map_write_error)?
+    .ok_or_else(|| AppError::NotFound("Vehículo no encontrado".to_string()))?;
 
     Ok(Json(VehicleResponse::from(vehicle)))
 }
@@ -321,25 +192,24 @@ pub async fn update_vehicle(
 /// Eliminar un vehículo (soft delete)
 pub async fn delete_vehicle(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
-    State(state): State<crate::state::AppState>,
+    State(state): State<FleetState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    let result = sqlx::query!(
+    let result = sqlx::query(
         r#"
-        UPDATE vehicles 
+        UPDATE vehicles
         SET deleted_at = NOW(), updated_at = NOW()
         WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
         "#,
-        id,
-        user.company_id
     )
+    .bind(id)
+    .bind(user.company_id)
     .execute(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     if result.rows_affected() == 0 {
         return Err(AppError::NotFound("Vehículo no encontrado".to_string()));
     }
 
     Ok(StatusCode::NO_CONTENT)
-}
\ No newline at end of file
+}
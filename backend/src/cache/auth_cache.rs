@@ -4,6 +4,12 @@ use tracing::{debug, info, warn};
 
 use super::{CacheOperations, RedisClient};
 
+/// Duración por defecto del lock de login entre instancias (segundos)
+const DEFAULT_LOGIN_LOCK_TTL: u64 = 30;
+
+/// Margen sobre la duración del login (bóveda, guardado del token...)
+const LOGIN_LOCK_MARGIN: u64 = 5;
+
 /// Datos de autenticación cacheados
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachedAuthData {
@@ -18,12 +24,20 @@ pub struct CachedAuthData {
 #[derive(Clone)]
 pub struct AuthCache {
     redis: RedisClient,
+    login_lock_ttl: u64,
 }
 
 impl AuthCache {
     /// Crear nuevo cache de autenticación
     pub fn new(redis: RedisClient) -> Self {
-        Self { redis }
+        Self { redis, login_lock_ttl: DEFAULT_LOGIN_LOCK_TTL }
+    }
+
+    /// Dimensionar el lock de login para un login que tarda como mucho `max_login`
+    /// (timeout × intentos del cliente), para que no expire mientras sigue en curso
+    pub fn with_max_login_duration(mut self, max_login: std::time::Duration) -> Self {
+        self.login_lock_ttl = max_login.as_secs_f64().ceil() as u64 + LOGIN_LOCK_MARGIN;
+        self
     }
     
     /// Obtener datos de autenticación del cache
@@ -43,8 +57,8 @@ impl AuthCache {
                     updated_data.request_count += 1;
                     updated_data.last_used = now;
                     
-                    // Actualizar en cache
-                    self.redis.set(&key, &updated_data, 1800).await?;
+                    // Actualizar en cache conservando la expiración del token
+                    self.redis.set(&key, &updated_data, cached_data.expires_at - now).await?;
                     
                     Ok(Some(updated_data))
                 } else {
@@ -96,6 +110,35 @@ impl AuthCache {
         Ok(())
     }
     
+    /// Tomar el lock de login del driver (una sola instancia re-autentica a la vez).
+    /// Devuelve el identificador del dueño, necesario para liberarlo.
+    pub async fn try_lock_login(&self, username: &str, societe: &str) -> Result<Option<String>> {
+        let key = self.redis.login_lock_key(username, societe);
+        let owner = uuid::Uuid::new_v4().to_string();
+
+        Ok(self.redis.set_nx(&key, &owner, self.login_lock_ttl).await?.then_some(owner))
+    }
+
+    /// Liberar el lock de login del driver si sigue siendo nuestro
+    /// (si expiró, otra instancia puede tenerlo ya)
+    pub async fn release_login_lock(&self, username: &str, societe: &str, owner: &str) -> Result<()> {
+        let key = self.redis.login_lock_key(username, societe);
+        if !self.redis.delete_if_equals(&key, owner).await? {
+            warn!("⚠️ El lock de login de {}:{} expiró antes de liberarlo", username, societe);
+        }
+        Ok(())
+    }
+
+    /// Buscar la société de un token cacheado del usuario
+    pub async fn find_societe(&self, username: &str) -> Result<Option<String>> {
+        let prefix = self.redis.auth_key(username, "");
+        let keys = self.redis.scan_match(&format!("{}*", prefix)).await?;
+
+        Ok(keys
+            .into_iter()
+            .find_map(|key| key.strip_prefix(&prefix).map(|societe| societe.to_string())))
+    }
+
     /// Obtener estadísticas de uso del cache de auth
     pub async fn get_auth_stats(&self, username: &str, societe: &str) -> Result<Option<AuthStats>> {
         let key = self.redis.auth_key(username, societe);
@@ -3,10 +3,12 @@
 pub mod redis_client;
 pub mod auth_cache;
 pub mod tournee_cache;
+pub mod token_store;
 
 pub use redis_client::RedisClient;
 pub use auth_cache::AuthCache;
 pub use tournee_cache::TourneeCache;
+pub use token_store::TokenStore;
 
 use anyhow::Result;
 use serde::{de::DeserializeOwned, Serialize};
@@ -28,6 +30,23 @@ pub trait CacheOperations {
     
     /// Obtener TTL de una clave
     async fn ttl(&self, key: &str) -> Result<Option<u64>>;
+
+    /// Obtener varios valores (por defecto, clave a clave)
+    async fn get_many<T: DeserializeOwned + Send>(&self, keys: &[String]) -> Result<Vec<Option<T>>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.get(key).await?);
+        }
+        Ok(values)
+    }
+
+    /// Guardar varios valores con el mismo TTL (por defecto, clave a clave)
+    async fn set_many<T: Serialize + Send + Sync>(&self, entries: &[(String, T)], ttl: u64) -> Result<()> {
+        for (key, value) in entries {
+            self.set(key, value, ttl).await?;
+        }
+        Ok(())
+    }
 }
 
 /// Configuración del cache
@@ -37,17 +56,69 @@ pub struct CacheConfig {
     pub default_ttl: u64,
     pub auth_cache_ttl: u64,
     pub tournee_cache_ttl: u64,
+    pub tournee_stale_ttl: u64,
     pub max_connections: u32,
 }
 
 impl Default for CacheConfig {
     fn default() -> Self {
         Self {
-            redis_url: "redis://localhost:6379".to_string(),
+            redis_url: std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string()),
             default_ttl: 3600,        // 1 hora por defecto
             auth_cache_ttl: 1800,     // 30 minutos para auth
             tournee_cache_ttl: 900,   // 15 minutos para tournée
+            tournee_stale_ttl: 21600, // 6 horas sirviendo datos vencidos mientras se refresca
             max_connections: 10,
         }
     }
 }
+
+/// Almacén en memoria para probar sin Redis
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    data: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, String>>>,
+}
+
+#[cfg(test)]
+impl MemoryStore {
+    /// Número de claves guardadas
+    pub fn len(&self) -> usize {
+        self.data.lock().unwrap().len()
+    }
+
+    /// Modificar el valor guardado en `key` (p. ej. para envejecer una entrada)
+    pub fn update<T: Serialize + DeserializeOwned>(&self, key: &str, change: impl FnOnce(&mut T)) {
+        let mut data = self.data.lock().unwrap();
+        let mut value: T = serde_json::from_str(&data[key]).unwrap();
+        change(&mut value);
+        data.insert(key.to_string(), serde_json::to_string(&value).unwrap());
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl CacheOperations for MemoryStore {
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let value = self.data.lock().unwrap().get(key).cloned();
+        Ok(value.map(|v| serde_json::from_str(&v)).transpose()?)
+    }
+
+    async fn set<T: Serialize + Send + Sync>(&self, key: &str, value: &T, _ttl: u64) -> Result<()> {
+        self.data.lock().unwrap().insert(key.to_string(), serde_json::to_string(value)?);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.data.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.data.lock().unwrap().contains_key(key))
+    }
+
+    async fn ttl(&self, _key: &str) -> Result<Option<u64>> {
+        Ok(None)
+    }
+}
@@ -5,6 +5,9 @@ use tracing::{debug, error, info, warn};
 
 use super::{CacheConfig, CacheOperations};
 
+/// Claves por comando MGET / pipeline
+const MULTI_KEY_BATCH: usize = 500;
+
 /// Cliente Redis con connection pooling y operaciones async
 #[derive(Clone)]
 pub struct RedisClient {
@@ -29,9 +32,14 @@ impl RedisClient {
         Ok(Self { manager, config })
     }
     
+    /// Configuración con la que se creó el cliente
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
     /// Generar clave de cache con prefijo
     fn make_key(&self, prefix: &str, identifier: &str) -> String {
-        format!("delivery_optimizer:{}:{}", prefix, identifier)
+        make_key(prefix, identifier)
     }
     
     /// Generar clave de auth cache
@@ -41,15 +49,43 @@ impl RedisClient {
     
     /// Generar clave de tournée cache
     pub fn tournee_key(&self, societe: &str, matricule: &str, date: &str) -> String {
-        self.make_key("tournee", &format!("{}:{}:{}", societe, matricule, date))
+        tournee_key(societe, matricule, date)
     }
     
+    /// Generar clave del lock de login
+    pub fn login_lock_key(&self, username: &str, societe: &str) -> String {
+        self.make_key("auth_lock", &format!("{}:{}", username, societe))
+    }
+
     /// Generar clave de rate limiting
     pub fn rate_limit_key(&self, identifier: &str) -> String {
         self.make_key("rate_limit", identifier)
     }
 }
 
+/// Clave de cache con el prefijo de la aplicación
+fn make_key(prefix: &str, identifier: &str) -> String {
+    format!("delivery_optimizer:{}:{}", prefix, identifier)
+}
+
+/// Clave de la tournée cacheada de un driver en una fecha
+pub fn tournee_key(societe: &str, matricule: &str, date: &str) -> String {
+    make_key("tournee", &format!("{}:{}:{}", societe, matricule, date))
+}
+
+/// Clave de la distancia entre dos coordenadas `(lat, lon)` para un proveedor
+pub fn matrix_pair_key(provider: &str, from: (f64, f64), to: (f64, f64)) -> String {
+    make_key(
+        "matrix",
+        &format!("{}:{:.5},{:.5}:{:.5},{:.5}", provider, from.0, from.1, to.0, to.1),
+    )
+}
+
+/// Clave del geocoding de una dirección ya normalizada
+pub fn geocode_key(normalized_address: &str) -> String {
+    make_key("geocode", normalized_address)
+}
+
 #[async_trait::async_trait]
 impl CacheOperations for RedisClient {
     async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
@@ -142,9 +178,95 @@ impl CacheOperations for RedisClient {
             }
         }
     }
+
+    async fn get_many<T: DeserializeOwned + Send>(&self, keys: &[String]) -> Result<Vec<Option<T>>> {
+        let mut conn = self.manager.clone();
+        let mut values = Vec::with_capacity(keys.len());
+
+        for chunk in keys.chunks(MULTI_KEY_BATCH) {
+            let raw: Vec<Option<String>> = redis::cmd("MGET").arg(chunk).query_async(&mut conn).await?;
+            for value in raw {
+                values.push(value.map(|v| serde_json::from_str(&v)).transpose()?);
+            }
+        }
+
+        debug!("📥 Cache MGET de {} claves", keys.len());
+        Ok(values)
+    }
+
+    async fn set_many<T: Serialize + Send + Sync>(&self, entries: &[(String, T)], ttl: u64) -> Result<()> {
+        let mut conn = self.manager.clone();
+
+        for chunk in entries.chunks(MULTI_KEY_BATCH) {
+            let mut pipe = redis::pipe();
+            for (key, value) in chunk {
+                pipe.set_ex(key, serde_json::to_string(value)?, ttl).ignore();
+            }
+            let _: () = pipe.query_async(&mut conn).await?;
+        }
+
+        debug!("💾 Cache SET de {} claves (TTL: {}s)", entries.len(), ttl);
+        Ok(())
+    }
 }
 
 impl RedisClient {
+    /// `SET key value NX EX ttl`: devuelve `true` si la clave no existía
+    pub async fn set_nx(&self, key: &str, value: &str, ttl: u64) -> Result<bool> {
+        let mut conn = self.manager.clone();
+
+        let result: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(result.is_some())
+    }
+
+    /// Borrar la clave solo si aún contiene `value` (atómico, vía script Lua).
+    /// Devuelve `true` si se borró.
+    pub async fn delete_if_equals(&self, key: &str, value: &str) -> Result<bool> {
+        let mut conn = self.manager.clone();
+
+        let deleted: i32 = redis::Script::new(
+            r"if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end",
+        )
+        .key(key)
+        .arg(value)
+        .invoke_async(&mut conn)
+        .await?;
+
+        Ok(deleted == 1)
+    }
+
+    /// Listar claves que coinciden con un patrón (SCAN, sin bloquear Redis)
+    pub async fn scan_match(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut conn = self.manager.clone();
+        let mut cursor: u64 = 0;
+        let mut keys = Vec::new();
+
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(100)
+                .query_async(&mut conn)
+                .await?;
+
+            keys.extend(batch);
+            if next == 0 {
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
+
     /// Obtener estadísticas del cache
     pub async fn get_stats(&self) -> Result<CacheStats> {
         let mut conn = self.manager.clone();
@@ -0,0 +1,472 @@
+//! Almacén de tokens de Colis Privé
+//!
+//! L1 en memoria delante de `AuthCache` (Redis), para que los tokens sobrevivan
+//! a reinicios y se compartan entre instancias. Las re-autenticaciones de un
+//! mismo driver se serializan (single-flight) dentro de la instancia y, con
+//! Redis, también entre instancias mediante un lock `SET NX`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, warn};
+
+use super::AuthCache;
+use crate::state::AuthToken;
+
+/// Tiempo máximo esperando a que otra instancia termine su login
+const REMOTE_LOGIN_WAIT: Duration = Duration::from_secs(10);
+
+/// Intervalo de consulta mientras otra instancia hace login
+const REMOTE_LOGIN_POLL: Duration = Duration::from_millis(250);
+
+/// Vida de una entrada L1 antes de volver a consultar Redis (invalidaciones remotas)
+const L1_TTL: Duration = Duration::from_secs(30);
+
+/// Entrada del L1
+#[derive(Clone)]
+struct L1Entry {
+    token: AuthToken,
+    cached_at: tokio::time::Instant,
+}
+
+impl L1Entry {
+    fn new(token: AuthToken) -> Self {
+        Self {
+            token,
+            cached_at: tokio::time::Instant::now(),
+        }
+    }
+}
+
+/// Logins en curso por driver
+type Inflight = StdMutex<HashMap<String, Arc<Mutex<()>>>>;
+
+/// Participación de una request en el login de un driver: la última en
+/// terminar (bien, mal o cancelada) retira la entrada de `inflight`
+struct Flight<'a> {
+    inflight: &'a Inflight,
+    key: String,
+    lock: Arc<Mutex<()>>,
+}
+
+impl<'a> Flight<'a> {
+    fn join(inflight: &'a Inflight, key: String) -> Self {
+        let lock = inflight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(())))
+            .clone();
+        Self { inflight, key, lock }
+    }
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        let mut inflight = self.inflight.lock().unwrap_or_else(|e| e.into_inner());
+        // Con el mapa bloqueado nadie más puede clonar el lock: 2 = el mapa y esta request
+        let last = Arc::strong_count(&self.lock) == 2
+            && inflight.get(&self.key).is_some_and(|lock| Arc::ptr_eq(lock, &self.lock));
+        if last {
+            inflight.remove(&self.key);
+        }
+    }
+}
+
+/// Almacén de tokens con L1 en memoria y L2 opcional en Redis
+#[derive(Clone)]
+pub struct TokenStore {
+    l1: Arc<RwLock<HashMap<String, L1Entry>>>,
+    l2: Option<AuthCache>,
+    inflight: Arc<Inflight>,
+}
+
+impl TokenStore {
+    /// Crear el almacén; sin `AuthCache` solo se usa el L1
+    pub fn new(l2: Option<AuthCache>) -> Self {
+        Self {
+            l1: Arc::new(RwLock::new(HashMap::new())),
+            l2,
+            inflight: Arc::new(StdMutex::new(HashMap::new())),
+        }
+    }
+
+    fn key(username: &str, societe: &str) -> String {
+        format!("{}:{}", societe, username)
+    }
+
+    /// Obtener un token vigente (L1, luego Redis)
+    pub async fn get(&self, username: &str, societe: &str) -> Option<AuthToken> {
+        let key = Self::key(username, societe);
+
+        if let Some(entry) = self.l1.read().await.get(&key) {
+            let fresh = self.l2.is_none() || entry.cached_at.elapsed() < L1_TTL;
+            if fresh && !entry.token.is_expired() {
+                debug!("🔑 Token L1 HIT para {}", key);
+                return Some(entry.token.clone());
+            }
+        }
+
+        let l2 = self.l2.as_ref()?;
+        match l2.get_auth(username, societe).await {
+            Ok(Some(cached)) => {
+                let token = AuthToken {
+                    token: cached.token,
+                    expires_at: chrono::DateTime::from_timestamp(cached.expires_at as i64, 0)?,
+                    username: username.to_string(),
+                    societe: societe.to_string(),
+                };
+                if token.is_expired() {
+                    return None;
+                }
+                debug!("🔑 Token Redis HIT para {}", key);
+                self.l1.write().await.insert(key, L1Entry::new(token.clone()));
+                Some(token)
+            }
+            Ok(None) => {
+                self.l1.write().await.remove(&key);
+                None
+            }
+            Err(e) => {
+                warn!("⚠️ Error leyendo token de Redis para {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    /// Guardar un token en L1 y Redis
+    pub async fn store(&self, token: AuthToken) {
+        let key = Self::key(&token.username, &token.societe);
+
+        if let Some(l2) = &self.l2 {
+            let ttl = (token.expires_at - chrono::Utc::now()).num_seconds().max(1) as u64;
+            let matricule = format!("{}_{}", token.societe, token.username);
+            if let Err(e) = l2.set_auth(&token.username, &token.societe, &token.token, &matricule, ttl).await {
+                warn!("⚠️ Error guardando token en Redis para {}: {}", key, e);
+            }
+        }
+
+        self.l1.write().await.insert(key, L1Entry::new(token));
+    }
+
+    /// Eliminar el token de L1 y Redis
+    pub async fn invalidate(&self, username: &str, societe: &str) {
+        self.l1.write().await.remove(&Self::key(username, societe));
+
+        if let Some(l2) = &self.l2 {
+            if let Err(e) = l2.invalidate_auth(username, societe).await {
+                warn!("⚠️ Error invalidando token en Redis para {}:{}: {}", societe, username, e);
+            }
+        }
+    }
+
+    /// Société de algún token vigente del usuario
+    pub async fn find_societe(&self, username: &str) -> Option<String> {
+        let from_l1 = self.l1
+            .read()
+            .await
+            .values()
+            .map(|entry| &entry.token)
+            .find(|token| token.username == username && !token.is_expired())
+            .map(|token| token.societe.clone());
+
+        if from_l1.is_some() {
+            return from_l1;
+        }
+
+        match &self.l2 {
+            Some(l2) => l2.find_societe(username).await.unwrap_or_else(|e| {
+                warn!("⚠️ Error buscando société de {} en Redis: {}", username, e);
+                None
+            }),
+            None => None,
+        }
+    }
+
+    /// Eliminar del L1 los tokens expirados (Redis los expira por TTL)
+    pub async fn cleanup_expired(&self) {
+        self.l1.write().await.retain(|_, entry| !entry.token.is_expired());
+    }
+
+    /// Devolver un token vigente o ejecutar `login` una sola vez por driver,
+    /// aunque lleguen varias requests concurrentes
+    pub async fn get_or_login<F, Fut, E>(
+        &self,
+        username: &str,
+        societe: &str,
+        login: F,
+    ) -> Result<AuthToken, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<AuthToken, E>>,
+    {
+        self.login_single_flight(username, societe, None, login).await
+    }
+
+    /// Sustituir un token que Colis Privé ha rechazado: se invalida y se hace
+    /// login una sola vez aunque varias requests lo detecten a la vez (las que
+    /// lleguen después reciben el token nuevo)
+    pub async fn replace_rejected<F, Fut, E>(
+        &self,
+        username: &str,
+        societe: &str,
+        rejected: &str,
+        login: F,
+    ) -> Result<AuthToken, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<AuthToken, E>>,
+    {
+        self.login_single_flight(username, societe, Some(rejected), login).await
+    }
+
+    async fn login_single_flight<F, Fut, E>(
+        &self,
+        username: &str,
+        societe: &str,
+        rejected: Option<&str>,
+        login: F,
+    ) -> Result<AuthToken, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<AuthToken, E>>,
+    {
+        let usable = |token: &AuthToken| rejected != Some(token.token.as_str());
+
+        if let Some(token) = self.get(username, societe).await.filter(usable) {
+            return Ok(token);
+        }
+
+        let key = Self::key(username, societe);
+        let flight = Flight::join(&self.inflight, key.clone());
+        let _guard = flight.lock.lock().await;
+
+        // Otra request pudo completar el login mientras esperábamos
+        match self.get(username, societe).await {
+            Some(token) if usable(&token) => {
+                debug!("🔑 Token obtenido por otra request para {}", key);
+                return Ok(token);
+            }
+            Some(_) => self.invalidate(username, societe).await,
+            None => {}
+        }
+
+        let remote_lock = match &self.l2 {
+            Some(l2) => match l2.try_lock_login(username, societe).await {
+                Ok(Some(owner)) => Some(owner),
+                Ok(None) => {
+                    if let Some(token) = self.wait_for_remote_login(username, societe).await {
+                        return Ok(token);
+                    }
+                    None
+                }
+                Err(e) => {
+                    warn!("⚠️ No se pudo tomar el lock de login en Redis para {}: {}", key, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let result = login().await;
+        if let Ok(token) = &result {
+            self.store(token.clone()).await;
+        }
+
+        if let (Some(l2), Some(owner)) = (&self.l2, remote_lock) {
+            if let Err(e) = l2.release_login_lock(username, societe, &owner).await {
+                warn!("⚠️ No se pudo liberar el lock de login en Redis para {}: {}", key, e);
+            }
+        }
+
+        result
+    }
+
+    /// Esperar el token que está obteniendo otra instancia
+    async fn wait_for_remote_login(&self, username: &str, societe: &str) -> Option<AuthToken> {
+        let deadline = tokio::time::Instant::now() + REMOTE_LOGIN_WAIT;
+
+        while tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(REMOTE_LOGIN_POLL).await;
+            if let Some(token) = self.get(username, societe).await {
+                debug!("🔑 Token obtenido por otra instancia para {}:{}", societe, username);
+                return Some(token);
+            }
+        }
+
+        warn!("⚠️ Timeout esperando login de otra instancia para {}:{}", societe, username);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn token(username: &str, hours: i32) -> AuthToken {
+        AuthToken::new(format!("token-{}", username), username.to_string(), "PCP0010699".to_string(), hours)
+    }
+
+    #[tokio::test]
+    async fn test_store_and_get_l1() {
+        let store = TokenStore::new(None);
+        store.store(token("A187518", 24)).await;
+
+        let found = store.get("A187518", "PCP0010699").await.unwrap();
+        assert_eq!(found.token, "token-A187518");
+        assert_eq!(store.find_societe("A187518").await.as_deref(), Some("PCP0010699"));
+
+        store.invalidate("A187518", "PCP0010699").await;
+        assert!(store.get("A187518", "PCP0010699").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_ignored() {
+        let store = TokenStore::new(None);
+        store.store(token("A187518", -1)).await;
+
+        assert!(store.get("A187518", "PCP0010699").await.is_none());
+        assert!(store.find_societe("A187518").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_login_once() {
+        let store = TokenStore::new(None);
+        let logins = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let store = store.clone();
+                let logins = logins.clone();
+                tokio::spawn(async move {
+                    store
+                        .get_or_login("A187518", "PCP0010699", || async move {
+                            logins.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok::<_, String>(token("A187518", 24))
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap().token, "token-A187518");
+        }
+        assert_eq!(logins.load(Ordering::SeqCst), 1);
+        assert!(store.inflight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_token_is_replaced_once() {
+        let store = TokenStore::new(None);
+        store.store(token("A187518", 24)).await;
+        let logins = Arc::new(AtomicUsize::new(0));
+
+        // Todas las requests ven el mismo token rechazado: un solo login
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let store = store.clone();
+                let logins = logins.clone();
+                tokio::spawn(async move {
+                    store
+                        .replace_rejected("A187518", "PCP0010699", "token-A187518", || async move {
+                            logins.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok::<_, String>(AuthToken::new(
+                                "token-nuevo".to_string(), "A187518".to_string(), "PCP0010699".to_string(), 24,
+                            ))
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap().token, "token-nuevo");
+        }
+        assert_eq!(logins.load(Ordering::SeqCst), 1);
+        assert_eq!(store.get("A187518", "PCP0010699").await.unwrap().token, "token-nuevo");
+    }
+
+    #[tokio::test]
+    async fn test_failed_login_is_not_cached() {
+        let store = TokenStore::new(None);
+
+        let err = store
+            .get_or_login("A187518", "PCP0010699", || async { Err::<AuthToken, _>("rechazado") })
+            .await
+            .unwrap_err();
+        assert_eq!(err, "rechazado");
+        assert!(store.get("A187518", "PCP0010699").await.is_none());
+        assert!(store.inflight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_login_leaves_no_flight() {
+        let store = TokenStore::new(None);
+
+        let login = store.get_or_login("A187518", "PCP0010699", || std::future::pending::<Result<AuthToken, String>>());
+        assert!(tokio::time::timeout(Duration::from_millis(20), login).await.is_err());
+        assert!(store.inflight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tokens_shared_through_redis() {
+        // Requiere un Redis local: REDIS_URL=redis://localhost:6379 cargo test
+        let Ok(redis_url) = std::env::var("REDIS_URL") else {
+            println!("⚠️ Skipping test: REDIS_URL not set");
+            return;
+        };
+
+        let config = super::super::CacheConfig { redis_url, ..Default::default() };
+        let redis = super::super::RedisClient::new(config).await.unwrap();
+        let instance_a = TokenStore::new(Some(AuthCache::new(redis.clone())));
+        let instance_b = TokenStore::new(Some(AuthCache::new(redis.clone())));
+
+        instance_a.store(token("TEST_SHARED", 1)).await;
+        let found = instance_b.get("TEST_SHARED", "PCP0010699").await.unwrap();
+        assert_eq!(found.token, "token-TEST_SHARED");
+        assert_eq!(instance_b.find_societe("TEST_SHARED").await.as_deref(), Some("PCP0010699"));
+
+        instance_b.invalidate("TEST_SHARED", "PCP0010699").await;
+        assert!(instance_b.get("TEST_SHARED", "PCP0010699").await.is_none());
+
+        // Una instancia recién arrancada ya no ve el token invalidado
+        let instance_c = TokenStore::new(Some(AuthCache::new(redis)));
+        assert!(instance_c.get("TEST_SHARED", "PCP0010699").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expired_login_lock_is_not_released_by_old_owner() {
+        // Requiere un Redis local: REDIS_URL=redis://localhost:6379 cargo test
+        let Ok(redis_url) = std::env::var("REDIS_URL") else {
+            println!("⚠️ Skipping test: REDIS_URL not set");
+            return;
+        };
+        use super::super::CacheOperations;
+
+        let config = super::super::CacheConfig { redis_url, ..Default::default() };
+        let redis = super::super::RedisClient::new(config).await.unwrap();
+        let auth = AuthCache::new(redis.clone());
+
+        let first = auth.try_lock_login("TEST_LOCK", "PCP0010699").await.unwrap().unwrap();
+        assert!(auth.try_lock_login("TEST_LOCK", "PCP0010699").await.unwrap().is_none());
+
+        // El lock expira durante un login largo y otra instancia lo toma
+        redis.delete(&redis.login_lock_key("TEST_LOCK", "PCP0010699")).await.unwrap();
+        let second = auth.try_lock_login("TEST_LOCK", "PCP0010699").await.unwrap().unwrap();
+
+        // El primer dueño no puede liberar el lock del segundo
+        auth.release_login_lock("TEST_LOCK", "PCP0010699", &first).await.unwrap();
+        assert!(auth.try_lock_login("TEST_LOCK", "PCP0010699").await.unwrap().is_none());
+
+        auth.release_login_lock("TEST_LOCK", "PCP0010699", &second).await.unwrap();
+        let third = auth.try_lock_login("TEST_LOCK", "PCP0010699").await.unwrap().unwrap();
+        auth.release_login_lock("TEST_LOCK", "PCP0010699", &third).await.unwrap();
+    }
+}
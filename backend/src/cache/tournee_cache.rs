@@ -1,25 +1,236 @@
-//! Cache de tournée mínima
-//! 
-//! Este módulo contiene la funcionalidad mínima de cache para tournées.
+//! Cache de tournées
+//!
+//! Guarda en Redis la respuesta de Colis Privé por société, driver y fecha con
+//! semántica stale-while-revalidate: durante `tournee_cache_ttl` la entrada es
+//! fresca; después se sigue sirviendo (hasta `tournee_stale_ttl`) mientras se
+//! refresca en segundo plano.
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Arc;
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use super::redis_client::tournee_key;
+use super::{CacheConfig, CacheOperations, RedisClient};
+
+/// Entrada cacheada de una tournée
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedTournee {
+    body: String,
+    fetched_at: i64,
+}
+
+/// Resultado de consultar el cache
+#[derive(Debug, Clone, PartialEq)]
+pub enum TourneeLookup {
+    Fresh(String),
+    Stale(String),
+    Miss,
+}
 
-/// Cache mínima para tournées
+/// Cache de tournées con stale-while-revalidate
 #[derive(Clone)]
-pub struct TourneeCache {
-    pub enabled: bool,
+pub struct TourneeCache<C = RedisClient> {
+    store: C,
+    ttl: u64,
+    stale_ttl: u64,
+    refreshing: Arc<Mutex<HashSet<String>>>,
 }
 
-impl TourneeCache {
-    pub fn new() -> Self {
-        Self { enabled: false }
+impl<C> TourneeCache<C>
+where
+    C: CacheOperations + Clone + Send + Sync + 'static,
+{
+    pub fn new(store: C, config: &CacheConfig) -> Self {
+        Self {
+            store,
+            ttl: config.tournee_cache_ttl,
+            stale_ttl: config.tournee_stale_ttl.max(config.tournee_cache_ttl),
+            refreshing: Arc::new(Mutex::new(HashSet::new())),
+        }
     }
-    
-    pub async fn get(&self, _key: &str) -> Result<Option<String>> {
-        Ok(None)
+
+    /// Consultar la tournée cacheada
+    pub async fn get(&self, societe: &str, matricule: &str, date: &str) -> Result<TourneeLookup> {
+        let key = tournee_key(societe, matricule, date);
+
+        let Some(cached) = self.store.get::<CachedTournee>(&key).await? else {
+            return Ok(TourneeLookup::Miss);
+        };
+
+        let age = chrono::Utc::now().timestamp() - cached.fetched_at;
+        if age < self.ttl as i64 {
+            Ok(TourneeLookup::Fresh(cached.body))
+        } else {
+            Ok(TourneeLookup::Stale(cached.body))
+        }
+    }
+
+    /// Guardar la respuesta de Colis Privé
+    pub async fn set(&self, societe: &str, matricule: &str, date: &str, body: &str) -> Result<()> {
+        let key = tournee_key(societe, matricule, date);
+        let cached = CachedTournee {
+            body: body.to_string(),
+            fetched_at: chrono::Utc::now().timestamp(),
+        };
+
+        self.store.set(&key, &cached, self.stale_ttl).await
+    }
+
+    /// Invalidar la tournée de un driver (p. ej. al marcar una entrega)
+    pub async fn invalidate(&self, societe: &str, matricule: &str, date: &str) -> Result<()> {
+        info!("🗑️ Invalidando tournée cacheada de {}:{} ({})", societe, matricule, date);
+        self.store.delete(&tournee_key(societe, matricule, date)).await
+    }
+
+    /// Devolver la tournée cacheada o pedirla a Colis Privé.
+    /// Si la entrada está vencida se devuelve igualmente y se refresca en segundo plano.
+    pub async fn get_or_fetch<F, Fut, E>(
+        &self,
+        societe: &str,
+        matricule: &str,
+        date: &str,
+        fetch: F,
+    ) -> Result<String, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, E>> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        let lookup = self.get(societe, matricule, date).await.unwrap_or_else(|e| {
+            warn!("⚠️ Error leyendo tournée cacheada de {}:{}: {}", societe, matricule, e);
+            TourneeLookup::Miss
+        });
+
+        match lookup {
+            TourneeLookup::Fresh(body) => {
+                debug!("📦 Tournée cache HIT para {}:{} ({})", societe, matricule, date);
+                Ok(body)
+            }
+            TourneeLookup::Stale(body) => {
+                debug!("⏰ Tournée cache STALE para {}:{} ({}), refrescando", societe, matricule, date);
+                self.spawn_refresh(societe, matricule, date, fetch()).await;
+                Ok(body)
+            }
+            TourneeLookup::Miss => {
+                let body = fetch().await?;
+                if let Err(e) = self.set(societe, matricule, date, &body).await {
+                    warn!("⚠️ Error cacheando tournée de {}:{}: {}", societe, matricule, e);
+                }
+                Ok(body)
+            }
+        }
+    }
+
+    /// Refrescar en segundo plano, una sola vez por clave
+    async fn spawn_refresh<Fut, E>(&self, societe: &str, matricule: &str, date: &str, refresh: Fut)
+    where
+        Fut: Future<Output = Result<String, E>> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        let key = tournee_key(societe, matricule, date);
+        if !self.refreshing.lock().await.insert(key.clone()) {
+            debug!("🔄 Refresco ya en curso para {}", key);
+            return;
+        }
+
+        let cache = self.clone();
+        let (societe, matricule, date) = (societe.to_string(), matricule.to_string(), date.to_string());
+
+        tokio::spawn(async move {
+            match refresh.await {
+                Ok(body) => {
+                    if let Err(e) = cache.set(&societe, &matricule, &date, &body).await {
+                        warn!("⚠️ Error cacheando tournée refrescada de {}:{}: {}", societe, matricule, e);
+                    }
+                }
+                Err(e) => warn!("⚠️ Error refrescando tournée de {}:{}: {}", societe, matricule, e),
+            }
+            cache.refreshing.lock().await.remove(&key);
+        });
     }
-    
-    pub async fn set(&self, _key: &str, _value: &str, _ttl: u64) -> Result<()> {
-        Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Envejecer la tournée cacheada `seconds` segundos
+    fn backdate(store: &MemoryStore, key: &str, seconds: i64) {
+        store.update::<CachedTournee>(key, |cached| cached.fetched_at -= seconds);
+    }
+
+    fn cache() -> (TourneeCache<MemoryStore>, MemoryStore) {
+        let store = MemoryStore::default();
+        let config = CacheConfig { tournee_cache_ttl: 60, tournee_stale_ttl: 3600, ..Default::default() };
+        (TourneeCache::new(store.clone(), &config), store)
+    }
+
+    #[tokio::test]
+    async fn test_miss_fetches_and_caches() {
+        let (cache, _) = cache();
+
+        let body = cache
+            .get_or_fetch("PCP0010699", "A187518", "2025-09-01", || async { Ok::<_, String>("v1".to_string()) })
+            .await
+            .unwrap();
+
+        assert_eq!(body, "v1");
+        assert_eq!(cache.get("PCP0010699", "A187518", "2025-09-01").await.unwrap(), TourneeLookup::Fresh("v1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_fresh_entry_skips_fetch() {
+        let (cache, _) = cache();
+        cache.set("PCP0010699", "A187518", "2025-09-01", "v1").await.unwrap();
+
+        let body = cache
+            .get_or_fetch("PCP0010699", "A187518", "2025-09-01", || async { Err::<String, _>("no debería llamarse") })
+            .await
+            .unwrap();
+
+        assert_eq!(body, "v1");
+    }
+
+    #[tokio::test]
+    async fn test_stale_entry_is_served_and_refreshed() {
+        let (cache, store) = cache();
+        cache.set("PCP0010699", "A187518", "2025-09-01", "v1").await.unwrap();
+        backdate(&store, &tournee_key("PCP0010699", "A187518", "2025-09-01"), 120);
+
+        let fetches = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let fetches = fetches.clone();
+            let body = cache
+                .get_or_fetch("PCP0010699", "A187518", "2025-09-01", || async move {
+                    fetches.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    Ok::<_, String>("v2".to_string())
+                })
+                .await
+                .unwrap();
+            assert_eq!(body, "v1");
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.get("PCP0010699", "A187518", "2025-09-01").await.unwrap(), TourneeLookup::Fresh("v2".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_forces_fetch() {
+        let (cache, _) = cache();
+        cache.set("PCP0010699", "A187518", "2025-09-01", "v1").await.unwrap();
+        cache.invalidate("PCP0010699", "A187518", "2025-09-01").await.unwrap();
+
+        assert_eq!(cache.get("PCP0010699", "A187518", "2025-09-01").await.unwrap(), TourneeLookup::Miss);
     }
 }
@@ -1,28 +1,418 @@
 //! Cliente HTTP para Colis Privé (API Web)
-//! 
-//! Este módulo contiene el cliente HTTP simplificado para la API web de Colis Privé.
+//!
+//! Este módulo contiene el cliente HTTP asíncrono para la API web de Colis Privé.
+//! Reemplaza las llamadas a `curl` por `reqwest`, reutilizando el `http_client`
+//! compartido de `AppState`, con reintentos y errores tipados.
 
-use anyhow::Result;
+use std::time::Duration;
+
+use axum::http::StatusCode;
 use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use thiserror::Error;
 
-/// Cliente HTTP simplificado para Colis Privé (API Web)
-pub struct ColisPriveWebClient {
-    pub client: Client,
+/// Ruta del endpoint de login de la API web
+const LOGIN_PATH: &str = "/api/auth/login/Membership";
+
+/// Ruta del endpoint de tournée de la API web
+const TOURNEE_PATH: &str = "/WS-TourneeColis/api/getTourneeByMatriculeDistributeurDateDebut_POST";
+
+/// Errores del cliente de Colis Privé
+#[derive(Error, Debug)]
+pub enum ColisPriveClientError {
+    #[error("Token SsoHopps expirado o inválido (HTTP {0})")]
+    AuthExpired(u16),
+
+    #[error("Credenciales rechazadas por Colis Privé (HTTP {0})")]
+    InvalidCredentials(u16),
+
+    #[error("Error del servidor de Colis Privé (HTTP {status}): {body}")]
+    Upstream { status: u16, body: String },
+
+    #[error("Request rechazada por Colis Privé (HTTP {status}): {body}")]
+    Rejected { status: u16, body: String },
+
+    #[error("Respuesta inválida de Colis Privé: {0}")]
+    MalformedPayload(String),
+
+    #[error("Error de red con Colis Privé: {0}")]
+    Network(#[from] reqwest::Error),
+}
+
+impl ColisPriveClientError {
+    /// Indica si vale la pena reintentar la request
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Upstream { .. } | Self::Network(_))
+    }
+
+    /// Código HTTP que deben devolver los handlers ante este error
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::AuthExpired(_) | Self::InvalidCredentials(_) => StatusCode::UNAUTHORIZED,
+            Self::Upstream { .. } | Self::MalformedPayload(_) => StatusCode::BAD_GATEWAY,
+            Self::Rejected { .. } => StatusCode::BAD_REQUEST,
+            Self::Network(e) if e.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+            Self::Network(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// Configuración del cliente de Colis Privé
+#[derive(Debug, Clone)]
+pub struct ColisPriveClientConfig {
     pub auth_base_url: String,
     pub tournee_base_url: String,
+    pub timeout: Duration,
+    pub max_retries: u32,
+    pub retry_backoff: Duration,
+}
+
+impl Default for ColisPriveClientConfig {
+    fn default() -> Self {
+        Self {
+            auth_base_url: std::env::var("COLIS_PRIVE_AUTH_URL")
+                .unwrap_or_else(|_| "https://wsauthentificationexterne.colisprive.com".to_string()),
+            tournee_base_url: std::env::var("COLIS_PRIVE_TOURNEE_URL")
+                .unwrap_or_else(|_| "https://wstournee-v2.colisprive.com".to_string()),
+            timeout: Duration::from_secs(
+                std::env::var("COLIS_PRIVE_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(30),
+            ),
+            max_retries: std::env::var("COLIS_PRIVE_MAX_RETRIES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(2),
+            retry_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl ColisPriveClientConfig {
+    /// Duración máxima de una llamada con todos sus reintentos y esperas
+    pub fn max_call_duration(&self) -> Duration {
+        let backoff: Duration = (0..self.max_retries).map(|attempt| self.retry_backoff * 2u32.pow(attempt)).sum();
+        self.timeout * (self.max_retries + 1) + backoff
+    }
+}
+
+/// Cliente HTTP asíncrono para Colis Privé (API Web)
+#[derive(Clone)]
+pub struct ColisPriveWebClient {
+    pub client: Client,
+    pub config: ColisPriveClientConfig,
 }
 
 impl ColisPriveWebClient {
-    /// Crear nuevo cliente HTTP para API web
-    pub fn new() -> Result<Self> {
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()?;
-
-        Ok(Self {
-            client,
-            auth_base_url: "https://wsauthentificationexterne.colisprive.com".to_string(),
-            tournee_base_url: "https://wstournee-v2.colisprive.com".to_string(),
+    /// Crear nuevo cliente reutilizando un `reqwest::Client` existente
+    pub fn new(client: Client, config: ColisPriveClientConfig) -> Self {
+        Self { client, config }
+    }
+
+    /// Autenticar con Colis Privé y devolver el token SsoHopps
+    pub async fn login(
+        &self,
+        societe: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<String, ColisPriveClientError> {
+        let url = format!("{}{}", self.config.auth_base_url, LOGIN_PATH);
+        let payload = json!({
+            "login": format!("{}_{}", societe, username),
+            "password": password,
+            "societe": societe,
+            "commun": {
+                "dureeTokenInHour": 24
+            }
+        });
+
+        log::info!("📤 Enviando autenticación a: {}", url);
+
+        let body = self
+            .post_with_retry(&url, &payload, None)
+            .await
+            .map_err(|e| match e {
+                ColisPriveClientError::AuthExpired(status) => ColisPriveClientError::InvalidCredentials(status),
+                other => other,
+            })?;
+
+        let auth_data: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|e| ColisPriveClientError::MalformedPayload(format!("login: {}", e)))?;
+
+        extract_sso_hopps(&auth_data)
+            .map(|token| token.to_string())
+            .ok_or_else(|| {
+                log::error!("❌ Token no encontrado en ningún campo. Campos disponibles: {:?}",
+                    auth_data.as_object().map(|obj| obj.keys().collect::<Vec<_>>()));
+                ColisPriveClientError::MalformedPayload("Token no encontrado en la respuesta".to_string())
+            })
+    }
+
+    /// Obtener la tournée de un distribuidor como texto (decodificado de base64 si aplica)
+    pub async fn get_tournee_raw(
+        &self,
+        sso_hopps: &str,
+        societe: &str,
+        username: &str,
+        date: &str,
+    ) -> Result<String, ColisPriveClientError> {
+        let url = format!("{}{}", self.config.tournee_base_url, TOURNEE_PATH);
+        let payload = json!({
+            "Matricule": format!("{}_{}", societe, username),
+            "DateDebut": date
+        });
+
+        log::info!("📤 Llamando a: {}", url);
+
+        let body = self.post_with_retry(&url, &payload, Some(sso_hopps)).await?;
+        log::info!("📥 Respuesta tournée recibida: {} bytes", body.len());
+
+        Ok(decode_base64_body(body))
+    }
+
+    /// POST JSON con reintentos y backoff exponencial
+    async fn post_with_retry(
+        &self,
+        url: &str,
+        payload: &serde_json::Value,
+        sso_hopps: Option<&str>,
+    ) -> Result<String, ColisPriveClientError> {
+        let mut attempt = 0;
+
+        loop {
+            match self.post_once(url, payload, sso_hopps).await {
+                Ok(body) => return Ok(body),
+                Err(e) if e.is_retryable() && attempt < self.config.max_retries => {
+                    let delay = self.config.retry_backoff * 2u32.pow(attempt);
+                    attempt += 1;
+                    log::warn!("⚠️ Intento {} fallido contra Colis Privé ({}), reintentando en {:?}", attempt, e, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Ejecutar un único POST y clasificar la respuesta
+    async fn post_once(
+        &self,
+        url: &str,
+        payload: &serde_json::Value,
+        sso_hopps: Option<&str>,
+    ) -> Result<String, ColisPriveClientError> {
+        let mut request = self
+            .client
+            .post(url)
+            .timeout(self.config.timeout)
+            .json(payload);
+
+        if let Some(token) = sso_hopps {
+            request = request.header("SsoHopps", token);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        if status.is_success() {
+            return Ok(body);
+        }
+
+        log::error!("❌ Colis Privé respondió HTTP {}: {}", status, excerpt(&body));
+
+        Err(match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ColisPriveClientError::AuthExpired(status.as_u16()),
+            s if s.is_server_error() => ColisPriveClientError::Upstream { status: s.as_u16(), body },
+            s => ColisPriveClientError::Rejected { status: s.as_u16(), body },
         })
     }
 }
+
+/// Deserializar el cuerpo de una tournée (p. ej. en `WebTourneeData`)
+pub fn parse_tournee<T: DeserializeOwned>(body: &str) -> Result<T, ColisPriveClientError> {
+    serde_json::from_str(body)
+        .map_err(|e| ColisPriveClientError::MalformedPayload(format!("tournée: {}", e)))
+}
+
+/// Buscar el token SsoHopps en los diferentes campos posibles de la respuesta de login
+fn extract_sso_hopps(auth_data: &serde_json::Value) -> Option<&str> {
+    auth_data.get("SsoHopps")
+        .or_else(|| auth_data.get("ssoHopps"))
+        .or_else(|| auth_data.get("token"))
+        .or_else(|| auth_data.get("Token"))
+        .or_else(|| auth_data.get("access_token"))
+        .or_else(|| auth_data.get("accessToken"))
+        .or_else(|| auth_data.get("tokens").and_then(|t| t.get("SsoHopps")))
+        .or_else(|| auth_data.get("shortToken").and_then(|t| t.get("SsoHopps")))
+        .or_else(|| auth_data.get("habilitationAD")
+            .and_then(|h| h.get("SsoHopps"))
+            .and_then(|s| s.as_array())
+            .and_then(|arr| arr.first())
+            .and_then(|item| item.get("valeur")))
+        .and_then(|v| v.as_str())
+}
+
+/// Primeros caracteres del cuerpo para los logs (el texto de Colis Privé lleva acentos)
+fn excerpt(body: &str) -> String {
+    body.chars().take(200).collect()
+}
+
+/// Decodificar el cuerpo si Colis Privé lo devuelve como string base64 entre comillas
+fn decode_base64_body(body: String) -> String {
+    if body.len() >= 2 && body.starts_with('"') && body.ends_with('"') {
+        let base64_content = &body[1..body.len() - 1];
+        if let Ok(decoded) = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, base64_content) {
+            if let Ok(text) = String::from_utf8(decoded) {
+                log::info!("✅ Datos decodificados de base64: {} bytes", text.len());
+                return text;
+            }
+        }
+        log::info!("ℹ️ No se pudo decodificar base64, usando texto original");
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Levantar un servidor local que simula Colis Privé
+    async fn spawn_mock(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    fn test_client(base_url: &str) -> ColisPriveWebClient {
+        ColisPriveWebClient::new(
+            Client::new(),
+            ColisPriveClientConfig {
+                auth_base_url: base_url.to_string(),
+                tournee_base_url: base_url.to_string(),
+                timeout: Duration::from_secs(2),
+                max_retries: 2,
+                retry_backoff: Duration::from_millis(10),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_login_extracts_nested_token() {
+        let router = Router::new().route(
+            LOGIN_PATH,
+            post(|| async { axum::Json(json!({ "isAuthentif": true, "tokens": { "SsoHopps": "abc123" } })) }),
+        );
+        let client = test_client(&spawn_mock(router).await);
+
+        let token = client.login("PCP0010699", "A187518", "secret").await.unwrap();
+        assert_eq!(token, "abc123");
+    }
+
+    #[tokio::test]
+    async fn test_login_rejected_credentials() {
+        let router = Router::new().route(LOGIN_PATH, post(|| async { StatusCode::UNAUTHORIZED }));
+        let client = test_client(&spawn_mock(router).await);
+
+        let err = client.login("PCP0010699", "A187518", "bad").await.unwrap_err();
+        assert!(matches!(err, ColisPriveClientError::InvalidCredentials(401)));
+    }
+
+    #[tokio::test]
+    async fn test_tournee_retries_on_upstream_error() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let router = Router::new().route(
+            TOURNEE_PATH,
+            post(move || {
+                let counter = counter.clone();
+                async move {
+                    if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                        (StatusCode::BAD_GATEWAY, "down".to_string())
+                    } else {
+                        (StatusCode::OK, r#"{"LstLieuArticle":[]}"#.to_string())
+                    }
+                }
+            }),
+        );
+        let client = test_client(&spawn_mock(router).await);
+
+        let body = client.get_tournee_raw("token", "PCP0010699", "A187518", "2025-09-01").await.unwrap();
+        let data: serde_json::Value = parse_tournee(&body).unwrap();
+        assert!(data.get("LstLieuArticle").is_some());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tournee_auth_expired_is_not_retried() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let router = Router::new().route(
+            TOURNEE_PATH,
+            post(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { StatusCode::UNAUTHORIZED }
+            }),
+        );
+        let client = test_client(&spawn_mock(router).await);
+
+        let err = client.get_tournee_raw("expired", "PCP0010699", "A187518", "2025-09-01").await.unwrap_err();
+        assert!(matches!(err, ColisPriveClientError::AuthExpired(401)));
+        assert_eq!(err.status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tournee_malformed_payload() {
+        let router = Router::new().route(TOURNEE_PATH, post(|| async { "<html>maintenance</html>" }));
+        let client = test_client(&spawn_mock(router).await);
+
+        let body = client.get_tournee_raw("token", "PCP0010699", "A187518", "2025-09-01").await.unwrap();
+        let err = parse_tournee::<serde_json::Value>(&body).unwrap_err();
+        assert!(matches!(err, ColisPriveClientError::MalformedPayload(_)));
+    }
+
+    #[tokio::test]
+    async fn test_network_failure_is_classified() {
+        // Puerto cerrado: no hay nadie escuchando
+        let client = test_client("http://127.0.0.1:1");
+
+        let err = client.get_tournee_raw("token", "PCP0010699", "A187518", "2025-09-01").await.unwrap_err();
+        assert!(matches!(err, ColisPriveClientError::Network(_)));
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_max_call_duration_covers_retries() {
+        let config = ColisPriveClientConfig {
+            auth_base_url: String::new(),
+            tournee_base_url: String::new(),
+            timeout: Duration::from_secs(30),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(500),
+        };
+        // 3 intentos de 30 s + esperas de 0,5 s y 1 s
+        assert_eq!(config.max_call_duration(), Duration::from_millis(91_500));
+    }
+
+    #[test]
+    fn test_excerpt_respects_char_boundaries() {
+        // 'é' ocupa dos bytes: el byte 200 cae en mitad de un carácter
+        let body = format!("x{}", "é".repeat(300));
+        assert_eq!(excerpt(&body).chars().count(), 200);
+        assert_eq!(excerpt("Adresse erronée"), "Adresse erronée");
+    }
+
+    #[test]
+    fn test_decode_base64_body() {
+        assert_eq!(decode_base64_body("\"eyJhIjoxfQ==\"".to_string()), r#"{"a":1}"#);
+        assert_eq!(decode_base64_body(r#"{"a":1}"#.to_string()), r#"{"a":1}"#);
+    }
+}
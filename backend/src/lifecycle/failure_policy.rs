@@ -0,0 +1,182 @@
+//! Política de entregas fallidas
+//!
+//! Cada empresa decide, por motivo de fallo, el siguiente paso: reprogramar
+//! para la próxima tournée, desviar a un punto relais o devolver al remitente.
+//! Al alcanzar `max_attempts` el paquete se devuelve al remitente.
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::models::package::DeliveryFailureReason;
+
+/// Siguiente paso configurado para un motivo de fallo
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureAction {
+    Reschedule,
+    RelayPoint,
+    ReturnToSender,
+}
+
+impl FailureAction {
+    /// Valor de `failure_policies.failure_action` y `packages.failure_action`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureAction::Reschedule => "reschedule",
+            FailureAction::RelayPoint => "relay_point",
+            FailureAction::ReturnToSender => "return_to_sender",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reschedule" => Some(FailureAction::Reschedule),
+            "relay_point" => Some(FailureAction::RelayPoint),
+            "return_to_sender" => Some(FailureAction::ReturnToSender),
+            _ => None,
+        }
+    }
+}
+
+/// Política de un motivo de fallo
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailurePolicy {
+    pub action: FailureAction,
+    pub max_attempts: i32,
+    pub relay_point_name: Option<String>,
+    pub relay_point_address: Option<String>,
+}
+
+impl FailurePolicy {
+    /// Política por defecto si la empresa no ha configurado el motivo
+    pub fn default_for(reason: DeliveryFailureReason) -> Self {
+        use DeliveryFailureReason::*;
+
+        let (action, max_attempts) = match reason {
+            RecipientNotHome | SecurityRestriction => (FailureAction::Reschedule, 3),
+            // Fallos del lado del repartidor: no deberían agotar los intentos del destinatario
+            WeatherConditions | VehicleBreakdown | DriverEmergency => (FailureAction::Reschedule, 5),
+            WrongAddress | PackageDamaged | RefusedDelivery => (FailureAction::ReturnToSender, 1),
+        };
+
+        Self { action, max_attempts, relay_point_name: None, relay_point_address: None }
+    }
+}
+
+/// Paso decidido para un paquete que acaba de fallar
+#[derive(Debug, Clone, PartialEq)]
+pub enum NextStep {
+    /// Cargar en la próxima tournée del driver a partir de `date`
+    Reschedule { date: NaiveDate },
+    /// Cargar en la próxima tournée con destino el punto relais
+    RelayPoint { date: NaiveDate, address: String },
+    /// Devolver al remitente (`max_attempts_reached` si es por agotar los intentos)
+    ReturnToSender { max_attempts_reached: bool },
+}
+
+impl NextStep {
+    pub fn action(&self) -> FailureAction {
+        match self {
+            NextStep::Reschedule { .. } => FailureAction::Reschedule,
+            NextStep::RelayPoint { .. } => FailureAction::RelayPoint,
+            NextStep::ReturnToSender { .. } => FailureAction::ReturnToSender,
+        }
+    }
+}
+
+/// Siguiente día de reparto (no se reparte en domingo)
+pub fn next_delivery_day(date: NaiveDate) -> NaiveDate {
+    let next = date + Duration::days(1);
+    if next.weekday() == Weekday::Sun {
+        next + Duration::days(1)
+    } else {
+        next
+    }
+}
+
+/// Decidir el siguiente paso tras `attempts` intentos fallidos en una tournée
+/// del día `tournee_date`. Una fecha pedida por el destinatario se respeta si
+/// es posterior al siguiente día de reparto.
+pub fn decide(
+    policy: &FailurePolicy,
+    attempts: i32,
+    tournee_date: NaiveDate,
+    requested_date: Option<NaiveDate>,
+) -> NextStep {
+    let date = next_delivery_day(tournee_date).max(requested_date.unwrap_or(NaiveDate::MIN));
+
+    match policy.action {
+        // La devolución por política no cuenta como intentos agotados
+        FailureAction::ReturnToSender => NextStep::ReturnToSender { max_attempts_reached: false },
+        _ if attempts >= policy.max_attempts => NextStep::ReturnToSender { max_attempts_reached: true },
+        FailureAction::RelayPoint => match &policy.relay_point_address {
+            Some(address) => NextStep::RelayPoint { date, address: address.clone() },
+            // Sin punto relais configurado se reprograma a la dirección del destinatario
+            None => NextStep::Reschedule { date },
+        },
+        FailureAction::Reschedule => NextStep::Reschedule { date },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_next_delivery_day_skips_sunday() {
+        // 2025-03-14 es viernes
+        assert_eq!(next_delivery_day(date(2025, 3, 14)), date(2025, 3, 15));
+        assert_eq!(next_delivery_day(date(2025, 3, 15)), date(2025, 3, 17));
+    }
+
+    #[test]
+    fn test_reschedule_until_max_attempts() {
+        let policy = FailurePolicy::default_for(DeliveryFailureReason::RecipientNotHome);
+        let friday = date(2025, 3, 14);
+
+        assert_eq!(decide(&policy, 1, friday, None), NextStep::Reschedule { date: date(2025, 3, 15) });
+        assert_eq!(decide(&policy, 2, friday, None), NextStep::Reschedule { date: date(2025, 3, 15) });
+        assert_eq!(decide(&policy, 3, friday, None), NextStep::ReturnToSender { max_attempts_reached: true });
+    }
+
+    #[test]
+    fn test_requested_date_only_if_later() {
+        let policy = FailurePolicy::default_for(DeliveryFailureReason::RecipientNotHome);
+        let friday = date(2025, 3, 14);
+
+        assert_eq!(
+            decide(&policy, 1, friday, Some(date(2025, 3, 20))),
+            NextStep::Reschedule { date: date(2025, 3, 20) }
+        );
+        assert_eq!(
+            decide(&policy, 1, friday, Some(date(2025, 3, 10))),
+            NextStep::Reschedule { date: date(2025, 3, 15) }
+        );
+    }
+
+    #[test]
+    fn test_relay_point_and_return_to_sender() {
+        let relay = FailurePolicy {
+            action: FailureAction::RelayPoint,
+            max_attempts: 2,
+            relay_point_name: Some("Tabac du Marché".to_string()),
+            relay_point_address: Some("4 place du Marché, 75011 Paris".to_string()),
+        };
+        assert_eq!(
+            decide(&relay, 1, date(2025, 3, 14), None),
+            NextStep::RelayPoint { date: date(2025, 3, 15), address: "4 place du Marché, 75011 Paris".to_string() }
+        );
+        assert_eq!(decide(&relay, 2, date(2025, 3, 14), None), NextStep::ReturnToSender { max_attempts_reached: true });
+
+        let without_address = FailurePolicy { relay_point_address: None, ..relay };
+        assert_eq!(decide(&without_address, 1, date(2025, 3, 14), None).action(), FailureAction::Reschedule);
+
+        // La devolución por política no cuenta como intentos agotados
+        let refused = FailurePolicy::default_for(DeliveryFailureReason::RefusedDelivery);
+        assert_eq!(decide(&refused, 1, date(2025, 3, 14), None), NextStep::ReturnToSender { max_attempts_reached: false });
+    }
+}
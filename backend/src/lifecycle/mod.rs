@@ -0,0 +1,39 @@
+//! Ciclo de vida de tournées y paquetes
+//!
+//! Reglas puras (sin base de datos) de los cambios de estado permitidos. Los
+//! handlers leen el estado actual con la fila bloqueada, validan aquí y
+//! registran la transición en el histórico.
+
+pub mod failure_policy;
+pub mod package;
+pub mod tournee;
+
+use thiserror::Error;
+
+use crate::utils::errors::AppError;
+
+pub use package::check_package_transition;
+pub use tournee::check_tournee_transition;
+
+/// Transición rechazada por la máquina de estados
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum TransitionError {
+    #[error("{entity}: transición no permitida de '{from}' a '{to}'")]
+    NotAllowed {
+        entity: &'static str,
+        from: &'static str,
+        to: &'static str,
+    },
+
+    #[error("La tournée tiene {0} paquete(s) en reparto")]
+    PackagesOutForDelivery(i64),
+
+    #[error("La tournée no está en curso (estado '{0}')")]
+    TourneeNotActive(&'static str),
+}
+
+impl From<TransitionError> for AppError {
+    fn from(error: TransitionError) -> Self {
+        AppError::Conflict(error.to_string())
+    }
+}
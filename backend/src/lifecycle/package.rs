@@ -0,0 +1,93 @@
+//! Máquina de estados del paquete
+//!
+//! `delivered`, `returned` y `cancelled` son finales. Un paquete fallido puede
+//! volver a reparto (nuevo intento) o devolverse.
+
+use crate::models::package::DeliveryStatus;
+use crate::models::tournee::TourneeStatus;
+
+use super::TransitionError;
+
+/// Transiciones permitidas, sin guardas
+pub fn can_transition(from: &DeliveryStatus, to: &DeliveryStatus) -> bool {
+    use DeliveryStatus::*;
+
+    match from {
+        Pending => matches!(to, InTransit | OutForDelivery | Delivered | Failed | Cancelled),
+        InTransit => matches!(to, OutForDelivery | Delivered | Failed | Returned | Cancelled),
+        OutForDelivery => matches!(to, Delivered | Failed | Returned),
+        Failed => matches!(to, OutForDelivery | Delivered | Failed | Returned | Cancelled),
+        Delivered | Returned | Cancelled => false,
+    }
+}
+
+/// Los estados de reparto solo se alcanzan con la tournée en curso
+fn requires_active_tournee(to: &DeliveryStatus) -> bool {
+    matches!(
+        to,
+        DeliveryStatus::OutForDelivery | DeliveryStatus::Delivered | DeliveryStatus::Failed
+    )
+}
+
+/// Validar una transición de paquete según el estado de su tournée
+pub fn check_package_transition(
+    from: &DeliveryStatus,
+    to: &DeliveryStatus,
+    tournee_status: &TourneeStatus,
+) -> Result<(), TransitionError> {
+    if !can_transition(from, to) {
+        return Err(TransitionError::NotAllowed {
+            entity: "Paquete",
+            from: from.as_str(),
+            to: to.as_str(),
+        });
+    }
+
+    if requires_active_tournee(to) && *tournee_status != TourneeStatus::InProgress {
+        return Err(TransitionError::TourneeNotActive(tournee_status.as_str()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DeliveryStatus::*;
+
+    #[test]
+    fn test_delivery_flow() {
+        let active = TourneeStatus::InProgress;
+        assert!(check_package_transition(&Pending, &OutForDelivery, &active).is_ok());
+        assert!(check_package_transition(&OutForDelivery, &Failed, &active).is_ok());
+        // Segundo intento tras un fallo
+        assert!(check_package_transition(&Failed, &OutForDelivery, &active).is_ok());
+        assert!(check_package_transition(&OutForDelivery, &Delivered, &active).is_ok());
+    }
+
+    #[test]
+    fn test_final_states() {
+        for to in [Pending, InTransit, OutForDelivery, Delivered, Failed, Returned, Cancelled] {
+            assert!(!can_transition(&Delivered, &to));
+            assert!(!can_transition(&Returned, &to));
+            assert!(!can_transition(&Cancelled, &to));
+        }
+        assert_eq!(
+            check_package_transition(&Delivered, &Failed, &TourneeStatus::InProgress),
+            Err(TransitionError::NotAllowed { entity: "Paquete", from: "delivered", to: "failed" })
+        );
+    }
+
+    #[test]
+    fn test_delivery_requires_tournee_in_progress() {
+        for tournee in [TourneeStatus::Pending, TourneeStatus::Paused, TourneeStatus::Completed] {
+            assert_eq!(
+                check_package_transition(&Pending, &Delivered, &tournee),
+                Err(TransitionError::TourneeNotActive(tournee.as_str()))
+            );
+        }
+        // Cancelar o devolver no depende de la tournée
+        assert!(check_package_transition(&Pending, &Cancelled, &TourneeStatus::Pending).is_ok());
+        assert!(check_package_transition(&Failed, &Returned, &TourneeStatus::Completed).is_ok());
+    }
+}
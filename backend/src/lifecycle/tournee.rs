@@ -0,0 +1,95 @@
+//! Máquina de estados de la tournée
+//!
+//! ```text
+//! pending ──► in_progress ◄──► paused
+//!    │            │               │
+//!    ▼            ▼               ▼
+//! cancelled   completed       cancelled
+//! ```
+
+use crate::models::tournee::TourneeStatus;
+
+use super::TransitionError;
+
+/// Transiciones permitidas, sin guardas
+pub fn can_transition(from: &TourneeStatus, to: &TourneeStatus) -> bool {
+    use TourneeStatus::*;
+
+    matches!(
+        (from, to),
+        (Pending, InProgress)
+            | (Pending, Cancelled)
+            | (InProgress, Paused)
+            | (InProgress, Completed)
+            | (InProgress, Cancelled)
+            | (Paused, InProgress)
+            | (Paused, Cancelled)
+    )
+}
+
+/// Validar una transición de tournée
+///
+/// `packages_out_for_delivery` es el número de paquetes de la tournée en
+/// `out_for_delivery`: no se puede completar mientras quede alguno.
+pub fn check_tournee_transition(
+    from: &TourneeStatus,
+    to: &TourneeStatus,
+    packages_out_for_delivery: i64,
+) -> Result<(), TransitionError> {
+    if !can_transition(from, to) {
+        return Err(TransitionError::NotAllowed {
+            entity: "Tournée",
+            from: from.as_str(),
+            to: to.as_str(),
+        });
+    }
+
+    if *to == TourneeStatus::Completed && packages_out_for_delivery > 0 {
+        return Err(TransitionError::PackagesOutForDelivery(packages_out_for_delivery));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TourneeStatus::*;
+
+    #[test]
+    fn test_lifecycle_with_pause() {
+        assert!(check_tournee_transition(&Pending, &InProgress, 0).is_ok());
+        assert!(check_tournee_transition(&InProgress, &Paused, 0).is_ok());
+        assert!(check_tournee_transition(&Paused, &InProgress, 0).is_ok());
+        assert!(check_tournee_transition(&InProgress, &Completed, 0).is_ok());
+    }
+
+    #[test]
+    fn test_terminal_states_are_final() {
+        for to in [Pending, InProgress, Paused, Completed, Cancelled] {
+            assert!(!can_transition(&Completed, &to));
+            assert!(!can_transition(&Cancelled, &to));
+        }
+    }
+
+    #[test]
+    fn test_rejected_transitions() {
+        assert_eq!(
+            check_tournee_transition(&Pending, &Completed, 0),
+            Err(TransitionError::NotAllowed { entity: "Tournée", from: "pending", to: "completed" })
+        );
+        // Una tournée pausada se reanuda antes de terminarla
+        assert!(check_tournee_transition(&Paused, &Completed, 0).is_err());
+        assert!(check_tournee_transition(&Pending, &Paused, 0).is_err());
+    }
+
+    #[test]
+    fn test_cannot_complete_with_packages_out_for_delivery() {
+        assert_eq!(
+            check_tournee_transition(&InProgress, &Completed, 2),
+            Err(TransitionError::PackagesOutForDelivery(2))
+        );
+        // La guarda solo afecta a la finalización
+        assert!(check_tournee_transition(&InProgress, &Cancelled, 2).is_ok());
+    }
+}
@@ -9,11 +9,14 @@ mod models;
 mod external_models;
 mod cache;
 mod migration;
+mod address;
+mod lifecycle;
+mod middleware;
 
 use anyhow::Result;
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     response::Json,
 };
 use std::net::SocketAddr;
@@ -52,6 +55,17 @@ async fn main() -> Result<()> {
         }
     };
 
+    // 📥 Importación de la BAN: `delivery-optimizer import-ban adresses-75.csv`
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("import-ban") {
+        let Some(path) = args.get(2) else {
+            return Err(anyhow::anyhow!("Uso: delivery-optimizer import-ban <adresses-XX.csv>"));
+        };
+        let summary = services::ban_import::import_ban_csv(&pool, std::path::Path::new(path)).await?;
+        info!("✅ BAN importada: {} direcciones, {} líneas ignoradas", summary.imported, summary.skipped);
+        return Ok(());
+    }
+
     // Inicializar Redis y cache
     let cache_config = CacheConfig::default();
     let redis_client = match RedisClient::new(cache_config.clone()).await {
@@ -67,6 +81,7 @@ async fn main() -> Result<()> {
 
     // Crear router de la API
     let app_state = AppState::new(pool, EnvironmentConfig::default(), redis_client);
+    let fleet_state = FleetState::from(&app_state);
     
     let app = Router::new()
         .route("/test", get(test_endpoint))
@@ -75,13 +90,37 @@ async fn main() -> Result<()> {
         .route("/api/colis-prive/packages-test", get(api::colis_prive::test_packages_endpoint))
         .route("/api/colis-prive/packages", post(api::colis_prive::get_packages))
         .route("/api/colis-prive/tournee", post(api::colis_prive::get_tournee_data))
+        .route("/api/colis-prive/tournee/invalidate", post(api::colis_prive::invalidate_tournee_cache))
+        .route("/api/address-validation/jobs/:id", get(api::validation_jobs::get_validation_job))
+        .route("/api/address-validation/jobs/:id/events", get(api::validation_jobs::validation_job_events))
+        .route("/api/admin/integrations/colis-prive/credentials", get(api::integrations::list_credentials).put(api::integrations::rotate_credentials))
+        .route("/api/admin/integrations/colis-prive/credentials/test", post(api::integrations::test_credentials))
+        .route("/api/geocoding/reverse", post(api::geocoding::reverse_geocode))
+        .route("/api/geocoding/usage", get(api::geocoding::geocoding_usage))
+        .route("/api/address-corrections", post(api::address_corrections::confirm_correction))
+        .route("/api/admin/address-corrections", get(api::address_corrections::list_corrections))
+        .route("/api/admin/address-corrections/:id", delete(api::address_corrections::revoke_correction))
+        .route("/api/admin/sectors", get(api::sectors::list_sectors).post(api::sectors::create_sector))
+        .route(
+            "/api/admin/sectors/:id",
+            get(api::sectors::get_sector).put(api::sectors::update_sector).delete(api::sectors::delete_sector),
+        )
+        .route("/api/admin/drivers/:id/sector", put(api::sectors::assign_driver_sector))
+        .route("/api/admin/tournees/:id/sector", put(api::sectors::assign_tournee_sector))
+        .route("/api/admin/geocoding/cache", delete(api::geocoding::invalidate_geocoding_cache))
+        .route("/api/admin/geocoding/cache/stats", get(api::geocoding::geocoding_cache_stats))
+        .route("/api/tournees/:id/optimize", post(api::route_optimization::optimize_tournee))
+        .route("/api/tournees/:id/schedule", post(api::route_optimization::schedule_tournee))
+        .route("/api/tournees/:id/zones", post(api::route_optimization::zone_tournee))
+        .route("/api/tournees/:id/matrix", get(api::route_optimization::tournee_matrix))
         .route("/api/migration/status", get(migration::api::get_migration_status))
         .route("/api/migration/strategy", post(migration::api::change_migration_strategy))
         .route("/api/migration/metrics", get(migration::api::get_migration_metrics))
         .route("/api/migration/progress", post(migration::api::force_migration_progress))
         .route("/api/migration/rollback", post(migration::api::force_migration_rollback))
         .route("/api/migration/health", get(migration::api::migration_health_check))
-        .with_state(app_state);
+        .with_state(app_state)
+        .nest("/api/v1", api::create_v1_router(fleet_state));
 
     // Puerto del servidor
     let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
@@ -95,12 +134,47 @@ async fn main() -> Result<()> {
     info!("   GET  /api/colis-prive/packages-test - Test endpoint");
     info!("   POST /api/colis-prive/packages - Obtener paquetes");
     info!("   POST /api/colis-prive/tournee - Tournée Colis Privé (API Web)");
+    info!("   POST /api/colis-prive/tournee/invalidate - Invalidar tournée cacheada");
+    info!("   GET  /api/address-validation/jobs/:id - Progreso de la validación de direcciones");
+    info!("   GET  /api/address-validation/jobs/:id/events - Direcciones validadas en tiempo real (SSE)");
+    info!("   GET  /api/admin/integrations/colis-prive/credentials - Listar credenciales (admin)");
+    info!("   PUT  /api/admin/integrations/colis-prive/credentials - Rotar credenciales (admin)");
+    info!("   POST /api/admin/integrations/colis-prive/credentials/test - Probar credenciales (admin)");
+    info!("   POST /api/geocoding/reverse - Geocoding inverso (posición del driver)");
+    info!("   GET  /api/geocoding/usage - Consumo y presupuesto de geocoding de la empresa");
+    info!("   POST /api/address-corrections - Confirmar posición de una dirección (driver)");
+    info!("   GET  /api/admin/address-corrections - Listar correcciones (admin)");
+    info!("   DELETE /api/admin/address-corrections/:id - Revocar corrección (admin)");
+    info!("   GET|POST /api/admin/sectors - Listar / crear sectores (admin)");
+    info!("   GET|PUT|DELETE /api/admin/sectors/:id - Detalle / modificar / eliminar sector (admin)");
+    info!("   PUT  /api/admin/drivers/:id/sector - Asignar sector a un driver (admin)");
+    info!("   PUT  /api/admin/tournees/:id/sector - Asignar sector a una tournée (admin)");
+    info!("   DELETE /api/admin/geocoding/cache - Invalidar dirección geocodificada (admin)");
+    info!("   GET  /api/admin/geocoding/cache/stats - Estadísticas del cache de geocoding (admin)");
+    info!("   POST /api/tournees/:id/optimize - Optimizar ruta de una tournée");
+    info!("   POST /api/tournees/:id/schedule - Secuenciar con ventanas horarias (ETAs)");
+    info!("   POST /api/tournees/:id/zones - Agrupar en zonas park-and-walk");
+    info!("   GET  /api/tournees/:id/matrix - Matriz de distancias de una tournée");
     info!("   GET  /api/migration/status - Estado de migración");
     info!("   POST /api/migration/strategy - Cambiar estrategia");
     info!("   GET  /api/migration/metrics - Métricas de migración");
     info!("   POST /api/migration/progress - Forzar progresión");
     info!("   POST /api/migration/rollback - Forzar rollback");
     info!("   GET  /api/migration/health - Health check migración");
+    info!("   POST /api/v1/auth/login|register - Login / alta de empresa y admin");
+    info!("   GET  /api/v1/auth/me - Usuario autenticado (JWT)");
+    info!("   GET|POST /api/v1/companies|users|vehicles|tournees|packages - API de flota (JWT)");
+    info!("   GET|PUT|DELETE /api/v1/{{recurso}}/:id - Detalle / modificar / eliminar (JWT, admin)");
+    info!("   POST /api/v1/tournees/:id/start|pause|resume|end|cancel - Ciclo de vida de la tournée (JWT)");
+    info!("   POST /api/v1/packages/:id/out-for-delivery|delivered|failed - Reparto / entrega / fallo de paquete (JWT)");
+    info!("   GET  /api/v1/tournees|packages/:id/history - Histórico de estados (JWT)");
+    info!("   POST /api/v1/tournees|packages/:id/uploads - Subir fotos de inspección / entrega y firmas (JWT, multipart)");
+    info!("   POST /api/v1/sync - Sincronización offline de la app del driver (JWT)");
+    info!("   GET  /api/v1/files/*key - Descargar foto o miniatura de la empresa (JWT)");
+    info!("   GET  /api/v1/analytics/dashboard|tournees|drivers|vehicles - Métricas de la empresa (JWT)");
+    info!("   GET  /api/v1/analytics/max-attempts - Paquetes devueltos por agotar los intentos (JWT)");
+    info!("   GET  /api/v1/failure-policies - Políticas de entrega fallida por motivo (JWT)");
+    info!("   PUT|DELETE /api/v1/failure-policies/:reason - Configurar / restablecer política (JWT, admin)");
 
     // Iniciar servidor en background
     let server_handle = tokio::spawn(async move {
@@ -17,7 +17,7 @@ use uuid::Uuid;
 
 use crate::{
     config::EnvironmentConfig,
-    user::{User, UserType, UserStatus},
+    models::user::{UserType, UserStatus},
     utils::errors::AppError,
 };
 
@@ -66,44 +66,23 @@ pub async fn auth_middleware(
 
     let claims = token_data.claims;
 
-    // Verificar que el usuario existe en la base de datos
-    let user_row = sqlx::query!(
-        r#"
-        SELECT 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", username, 
-            password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE id = $1 
-        AND company_id = $2 
-        AND deleted_at IS NULL
-        "#,
-        Uuid::parse_str(&claims.sub).map_err(|_| {
-            AppError::Unauthorized("ID de usuario inválido".to_string())
-        })?,
-        Uuid::parse_str(&claims.company_id).map_err(|_| {
-            AppError::Unauthorized("ID de empresa inválido".to_string())
-        })?
-    )
-    .fetch_optional(&pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
-    .ok_or_else(|| AppError::Unauthorized("Usuario no encontrado".to_string()))?;
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::Unauthorized("ID de usuario inválido".to_string()))?;
+    let company_id = Uuid::parse_str(&claims.company_id)
+        .map_err(|_| AppError::Unauthorized("ID de empresa inválido".to_string()))?;
 
-    // Usar los enums directamente
-    let user_type = user_row.user_type;
-    let user_status = user_row.user_status;
+    // Verificar que el usuario existe en la base de datos y sigue activo
+    let (user_type, user_status) = find_user(&pool, user_id, company_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Usuario no encontrado".to_string()))?;
 
-
-
-    // Verificar que el usuario esté activo
     if user_status != UserStatus::Active {
         return Err(AppError::Unauthorized("Usuario inactivo o suspendido".to_string()));
     }
 
-    // Crear usuario autenticado
     let authenticated_user = AuthenticatedUser {
-        user_id: user_row.id,
-        company_id: user_row.company_id,
+        user_id,
+        company_id,
         user_type,
     };
 
@@ -135,37 +114,15 @@ pub async fn optional_auth_middleware(
         ) {
             let claims = token_data.claims;
 
-            // Verificar que el usuario existe
-            if let Ok(user_row) = sqlx::query!(
-                r#"
-                SELECT 
-                    id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", username, 
-                    password_hash, created_at, updated_at, deleted_at
-                FROM users 
-                WHERE id = $1 
-                AND company_id = $2 
-                AND deleted_at IS NULL
-                "#,
-                Uuid::parse_str(&claims.sub).unwrap_or_default(),
-                Uuid::parse_str(&claims.company_id).unwrap_or_default()
-            )
-            .fetch_optional(&pool)
-            .await
-            {
-                if let Some(user_row) = user_row {
-                    // Usar los enums directamente
-                    let user_status = user_row.user_status;
-
-                    if user_status == UserStatus::Active {
-                        let user_type = user_row.user_type;
-
-                        let authenticated_user = AuthenticatedUser {
-                            user_id: user_row.id,
-                            company_id: user_row.company_id,
-                            user_type,
-                        };
-                        request.extensions_mut().insert(authenticated_user);
-                    }
+            // Verificar que el usuario existe y está activo
+            let ids = Uuid::parse_str(&claims.sub).ok().zip(Uuid::parse_str(&claims.company_id).ok());
+            if let Some((user_id, company_id)) = ids {
+                if let Ok(Some((user_type, UserStatus::Active))) = find_user(&pool, user_id, company_id).await {
+                    request.extensions_mut().insert(AuthenticatedUser {
+                        user_id,
+                        company_id,
+                        user_type,
+                    });
                 }
             }
         }
@@ -174,6 +131,29 @@ pub async fn optional_auth_middleware(
     Ok(next.run(request).await)
 }
 
+/// Tipo y estado de un usuario no eliminado de la empresa
+pub(crate) async fn find_user(
+    pool: &PgPool,
+    user_id: Uuid,
+    company_id: Uuid,
+) -> Result<Option<(UserType, UserStatus)>, AppError> {
+    let row = sqlx::query_as::<_, (UserType, UserStatus)>(
+        r#"
+        SELECT user_type, user_status
+        FROM users
+        WHERE id = $1
+        AND company_id = $2
+        AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(company_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row)
+}
+
 /// Middleware para verificar permisos de admin
 pub async fn admin_only_middleware(
     Extension(user): Extension<AuthenticatedUser>,
@@ -181,7 +161,7 @@ pub async fn admin_only_middleware(
     next: Next,
 ) -> Result<Response, AppError> {
     if user.user_type != UserType::Admin {
-        return Err(AppError::Unauthorized(
+        return Err(AppError::Forbidden(
             "Se requieren permisos de administrador".to_string(),
         ));
     }
@@ -6,6 +6,7 @@
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use uuid::Uuid;
 use validator::Validate;
 
@@ -189,7 +190,7 @@ pub struct UpdateAnalyticsRequest {
 }
 
 /// Response de analytics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct AnalyticsResponse {
     pub id: Uuid,
     pub company_id: Uuid,
@@ -233,7 +234,6 @@ pub struct AnalyticsResponse {
 /// Filtros para analytics
 #[derive(Debug, Clone, Deserialize)]
 pub struct AnalyticsFilters {
-    pub company_id: Uuid,
     pub tournee_id: Option<Uuid>,
     pub driver_id: Option<Uuid>,
     pub vehicle_id: Option<Uuid>,
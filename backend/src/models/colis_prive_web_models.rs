@@ -50,32 +50,118 @@ pub struct WebTourneeRequest {
     pub Concentrateur: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct WebTourneeResponse {
-    pub success: bool,
-    pub data: Option<Vec<WebPackage>>,
-    pub message: Option<String>,
-    pub total_packages: Option<i32>,
-    pub total_weight: Option<f64>,
+// ============================================================================
+// TOURNÉE DISTRIBUTEUR (getTourneeByMatriculeDistributeurDateDebut_POST)
+// ============================================================================
+
+/// Respuesta de tournée de un distribuidor
+///
+/// Los artículos de `LstLieuArticle` se conservan como JSON crudo para poder
+/// mapearlos uno a uno y reportar los que fallan en lugar de descartarlos.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebTourneeData {
+    #[serde(rename = "InfosTournee", default)]
+    pub infos_tournee: Option<WebInfosTournee>,
+    #[serde(rename = "LstLieuArticle", default, deserialize_with = "lenient_vec")]
+    pub lst_lieu_article: Vec<serde_json::Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct WebPackage {
-    pub id: String,
-    pub reference: String,
-    pub barcode: String,
-    pub weight: f64,
-    pub address: String,
-    pub postal_code: String,
-    pub city: String,
-    pub status: String,
-    pub coordinates: Option<WebCoordinates>,
+/// Información general de la tournée
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebInfosTournee {
+    #[serde(rename = "codeTourneeDistribution", default, deserialize_with = "lenient_string")]
+    pub code_tournee_distribution: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct WebCoordinates {
-    pub latitude: f64,
-    pub longitude: f64,
+/// Artículo (paquete) de `LstLieuArticle`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebArticle {
+    #[serde(rename = "idArticle", default, deserialize_with = "lenient_string")]
+    pub id_article: Option<String>,
+    #[serde(rename = "refExterneArticle", default, deserialize_with = "lenient_string")]
+    pub ref_externe_article: Option<String>,
+    #[serde(rename = "metier", default, deserialize_with = "lenient_string")]
+    pub metier: Option<String>,
+    #[serde(rename = "codeStatutArticle", default, deserialize_with = "lenient_string")]
+    pub code_statut_article: Option<String>,
+    #[serde(rename = "PreferenceLivraison", default, deserialize_with = "lenient_string")]
+    pub preference_livraison: Option<String>,
+    #[serde(rename = "priorite", default, deserialize_with = "lenient_string")]
+    pub priorite: Option<String>,
+
+    // Destinatario
+    #[serde(rename = "nomDestinataire", default, deserialize_with = "lenient_string")]
+    pub nom_destinataire: Option<String>,
+    #[serde(rename = "telephoneMobileDestinataire", default, deserialize_with = "lenient_string")]
+    pub telephone_mobile_destinataire: Option<String>,
+
+    // Dirección
+    #[serde(rename = "LibelleVoieOrigineDestinataire", default, deserialize_with = "lenient_string")]
+    pub libelle_voie_origine_destinataire: Option<String>,
+    #[serde(rename = "codePostalOrigineDestinataire", default, deserialize_with = "lenient_string")]
+    pub code_postal_origine_destinataire: Option<String>,
+    #[serde(rename = "LibelleLocaliteOrigineDestinataire", default, deserialize_with = "lenient_string")]
+    pub libelle_localite_origine_destinataire: Option<String>,
+
+    /// Campos no modelados explícitamente
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl WebArticle {
+    /// Indica si el artículo es un paquete a distribuir (metier COLIS)
+    pub fn is_colis(&self) -> bool {
+        self.metier.as_deref() == Some("COLIS")
+    }
+
+    /// Dirección completa "voie, code postal localité" con las partes disponibles
+    pub fn full_address(&self) -> Option<String> {
+        let voie = self.libelle_voie_origine_destinataire.as_deref()?.trim();
+        if voie.is_empty() {
+            return None;
+        }
+
+        let locality = [
+            self.code_postal_origine_destinataire.as_deref(),
+            self.libelle_localite_origine_destinataire.as_deref(),
+        ]
+        .iter()
+        .flatten()
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+        if locality.is_empty() {
+            Some(voie.to_string())
+        } else {
+            Some(format!("{}, {}", voie, locality))
+        }
+    }
+}
+
+/// Aceptar strings, números o booleanos como `Option<String>`
+fn lenient_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(match value {
+        Some(serde_json::Value::String(s)) => Some(s),
+        Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+        Some(serde_json::Value::Bool(b)) => Some(b.to_string()),
+        _ => None,
+    })
+}
+
+/// Aceptar `null` como lista vacía
+fn lenient_vec<'de, D>(deserializer: D) -> Result<Vec<serde_json::Value>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<Vec<serde_json::Value>>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 // ============================================================================
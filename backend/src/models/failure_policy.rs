@@ -0,0 +1,77 @@
+//! Modelos de las políticas de entrega fallida
+//!
+//! Configuración por empresa y motivo de fallo, y el informe de paquetes que
+//! han agotado sus intentos.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::lifecycle::failure_policy::FailurePolicy;
+use crate::models::package::DeliveryFailureReason;
+
+/// Request para configurar la política de un motivo de fallo
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpsertFailurePolicyRequest {
+    /// `reschedule`, `relay_point` o `return_to_sender`
+    pub failure_action: String,
+
+    #[validate(range(min = 1, max = 10))]
+    pub max_attempts: i32,
+
+    #[validate(length(min = 2, max = 255))]
+    pub relay_point_name: Option<String>,
+
+    #[validate(length(min = 5, max = 500))]
+    pub relay_point_address: Option<String>,
+}
+
+/// Política efectiva de un motivo (la configurada o la de por defecto)
+#[derive(Debug, Serialize)]
+pub struct FailurePolicyResponse {
+    pub failure_reason: String,
+    pub failure_action: String,
+    pub max_attempts: i32,
+    pub relay_point_name: Option<String>,
+    pub relay_point_address: Option<String>,
+    pub is_default: bool,
+}
+
+impl FailurePolicyResponse {
+    pub fn new(reason: DeliveryFailureReason, policy: FailurePolicy, is_default: bool) -> Self {
+        Self {
+            failure_reason: reason.as_str().to_string(),
+            failure_action: policy.action.as_str().to_string(),
+            max_attempts: policy.max_attempts,
+            relay_point_name: policy.relay_point_name,
+            relay_point_address: policy.relay_point_address,
+            is_default,
+        }
+    }
+}
+
+/// Filtros del informe de intentos agotados
+#[derive(Debug, Deserialize)]
+pub struct MaxAttemptsFilters {
+    pub date_from: Option<NaiveDate>,
+    pub date_to: Option<NaiveDate>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Paquete devuelto al remitente por agotar los intentos
+#[derive(Debug, Serialize, FromRow)]
+pub struct MaxAttemptsEntry {
+    pub package_id: Uuid,
+    pub tracking_number: String,
+    pub recipient_name: Option<String>,
+    pub delivery_address: String,
+    pub delivery_attempts: i32,
+    pub failure_reason: Option<String>,
+    pub tournee_id: Uuid,
+    pub tournee_date: NaiveDate,
+    pub driver_id: Uuid,
+    pub max_attempts_reached_at: DateTime<Utc>,
+}
@@ -9,6 +9,8 @@ pub mod user;
 pub mod vehicle;
 pub mod tournee;
 pub mod package;
+pub mod sync;
+pub mod failure_policy;
 pub mod analytics;
 pub mod colis_prive_web_models;
 pub mod colis_prive_v3_models;
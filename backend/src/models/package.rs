@@ -12,7 +12,7 @@ use rust_decimal::Decimal;
 
 /// Estado de entrega - mapea al ENUM delivery_status
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
-#[sqlx(type_name = "delivery_status", rename_all = "lowercase")]
+#[sqlx(type_name = "delivery_status", rename_all = "snake_case")]
 pub enum DeliveryStatus {
     Pending,
     InTransit,
@@ -23,9 +23,25 @@ pub enum DeliveryStatus {
     Cancelled,
 }
 
+impl DeliveryStatus {
+    /// Valor del ENUM en PostgreSQL
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::InTransit => "in_transit",
+            DeliveryStatus::OutForDelivery => "out_for_delivery",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Returned => "returned",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
 /// Razón de fallo en entrega - mapea al ENUM delivery_failure_reason
-#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
-#[sqlx(type_name = "delivery_failure_reason", rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq)]
+#[sqlx(type_name = "delivery_failure_reason", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum DeliveryFailureReason {
     RecipientNotHome,
     WrongAddress,
@@ -37,6 +53,38 @@ pub enum DeliveryFailureReason {
     DriverEmergency,
 }
 
+impl DeliveryFailureReason {
+    /// Todas las razones, en el orden del ENUM
+    pub const ALL: [DeliveryFailureReason; 8] = [
+        DeliveryFailureReason::RecipientNotHome,
+        DeliveryFailureReason::WrongAddress,
+        DeliveryFailureReason::PackageDamaged,
+        DeliveryFailureReason::RefusedDelivery,
+        DeliveryFailureReason::SecurityRestriction,
+        DeliveryFailureReason::WeatherConditions,
+        DeliveryFailureReason::VehicleBreakdown,
+        DeliveryFailureReason::DriverEmergency,
+    ];
+
+    /// Valor del ENUM en PostgreSQL
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryFailureReason::RecipientNotHome => "recipient_not_home",
+            DeliveryFailureReason::WrongAddress => "wrong_address",
+            DeliveryFailureReason::PackageDamaged => "package_damaged",
+            DeliveryFailureReason::RefusedDelivery => "refused_delivery",
+            DeliveryFailureReason::SecurityRestriction => "security_restriction",
+            DeliveryFailureReason::WeatherConditions => "weather_conditions",
+            DeliveryFailureReason::VehicleBreakdown => "vehicle_breakdown",
+            DeliveryFailureReason::DriverEmergency => "driver_emergency",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.as_str() == value)
+    }
+}
+
 /// Origen del paquete - mapea al campo package_origin
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum PackageOrigin {
@@ -78,6 +126,9 @@ pub struct Package {
     pub failure_reason: Option<DeliveryFailureReason>,
     pub failure_notes: Option<String>,
     pub reschedule_date: Option<NaiveDate>,
+    pub failure_action: Option<String>,
+    pub relay_point_address: Option<String>,
+    pub carried_over_from: Option<Uuid>,
     
     // Evidencia de entrega
     pub delivery_photo: Option<String>,
@@ -85,7 +136,8 @@ pub struct Package {
     pub signature_image: Option<String>,
     pub signature_photo: Option<String>,
     
-    // Ubicación y tiempo de entrega
+    // Ubicación y tiempo de entrega (POINT no se lee con FromRow)
+    #[sqlx(skip)]
     pub delivery_coordinates: Option<Point>,
     pub delivery_duration_minutes: Option<i32>,
     
@@ -154,7 +206,7 @@ pub struct UpdatePackageRequest {
 }
 
 /// Request para marcar paquete como entregado
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Clone, Deserialize, Validate)]
 pub struct MarkDeliveredRequest {
     pub delivery_photo: Option<String>,
     pub signature_image: Option<String>,
@@ -165,7 +217,7 @@ pub struct MarkDeliveredRequest {
 }
 
 /// Request para marcar paquete como fallido
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Clone, Deserialize, Validate)]
 pub struct MarkFailedRequest {
     pub failure_reason: String,
     pub failure_notes: Option<String>,
@@ -195,6 +247,9 @@ pub struct PackageResponse {
     pub failure_reason: Option<String>,
     pub failure_notes: Option<String>,
     pub reschedule_date: Option<String>,
+    pub failure_action: Option<String>,
+    pub relay_point_address: Option<String>,
+    pub carried_over_from: Option<String>,
     pub delivery_photo: Option<String>,
     pub signature_required: bool,
     pub signature_image: Option<String>,
@@ -252,7 +307,7 @@ impl From<Package> for PackageResponse {
             package_type: package.package_type,
             package_weight: package.package_weight.map(|w| w.to_string()),
             package_dimensions: package.package_dimensions,
-            delivery_status: format!("{:?}", package.delivery_status).to_lowercase(),
+            delivery_status: package.delivery_status.as_str().to_string(),
             delivery_date: package.delivery_date.map(|d| d.to_string()),
             delivery_time: package.delivery_time.map(|t| t.to_string()),
             delivery_attempts: package.delivery_attempts,
@@ -260,9 +315,12 @@ impl From<Package> for PackageResponse {
             recipient_phone: package.recipient_phone,
             delivery_address: package.delivery_address,
             delivery_instructions: package.delivery_instructions,
-            failure_reason: package.failure_reason.map(|r| format!("{:?}", r).to_lowercase()),
+            failure_reason: package.failure_reason.map(|r| r.as_str().to_string()),
             failure_notes: package.failure_notes,
             reschedule_date: package.reschedule_date.map(|d| d.to_string()),
+            failure_action: package.failure_action,
+            relay_point_address: package.relay_point_address,
+            carried_over_from: package.carried_over_from.map(|id| id.to_string()),
             delivery_photo: package.delivery_photo,
             signature_required: package.signature_required,
             signature_image: package.signature_image,
@@ -284,12 +342,12 @@ impl From<Package> for PackageListResponse {
             id: package.id.to_string(),
             tracking_number: package.tracking_number,
             external_tracking_number: package.external_tracking_number,
-            delivery_status: format!("{:?}", package.delivery_status).to_lowercase(),
+            delivery_status: package.delivery_status.as_str().to_string(),
             delivery_date: package.delivery_date.map(|d| d.to_string()),
             delivery_attempts: package.delivery_attempts,
             recipient_name: package.recipient_name,
             delivery_address: package.delivery_address,
-            failure_reason: package.failure_reason.map(|r| format!("{:?}", r).to_lowercase()),
+            failure_reason: package.failure_reason.map(|r| r.as_str().to_string()),
             tournee_id: package.tournee_id.to_string(),
             created_at: package.created_at.map(|dt| dt.to_rfc3339()),
         }
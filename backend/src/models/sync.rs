@@ -0,0 +1,147 @@
+//! Modelos de la sincronización offline
+//!
+//! La app del driver acumula las operaciones hechas sin cobertura (escaleras,
+//! sótanos) y las envía en lote a `POST /api/v1/sync` junto con el cursor de
+//! su última sincronización.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::package::{MarkDeliveredRequest, MarkFailedRequest, PackageResponse};
+use crate::models::tournee::TourneeResponse;
+
+/// Request de sincronización
+#[derive(Debug, Deserialize)]
+pub struct SyncRequest {
+    /// Cursor devuelto por la sincronización anterior (`None` en la primera)
+    pub cursor: Option<DateTime<Utc>>,
+
+    /// Operaciones en el orden en que se hicieron en el dispositivo
+    #[serde(default)]
+    pub operations: Vec<SyncOperation>,
+}
+
+/// Operación generada por el cliente
+#[derive(Debug, Deserialize, Validate)]
+pub struct SyncOperation {
+    /// Clave única por usuario: reenviar la misma clave devuelve el resultado guardado
+    #[validate(length(min = 1, max = 100))]
+    pub idempotency_key: String,
+
+    /// Hora del dispositivo en la que se hizo la operación
+    pub client_timestamp: DateTime<Utc>,
+
+    #[serde(flatten)]
+    pub action: SyncAction,
+}
+
+/// Acción de una operación (`"type": "deliver" | "fail" | "note" | "location"`)
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncAction {
+    Deliver {
+        package_id: Uuid,
+        #[serde(flatten)]
+        delivery: MarkDeliveredRequest,
+    },
+    Fail {
+        package_id: Uuid,
+        #[serde(flatten)]
+        failure: MarkFailedRequest,
+    },
+    Note {
+        package_id: Uuid,
+        driver_notes: String,
+    },
+    Location {
+        latitude: f64,
+        longitude: f64,
+    },
+}
+
+impl SyncAction {
+    /// Valor de `sync_operations.operation_type`
+    pub fn operation_type(&self) -> &'static str {
+        match self {
+            SyncAction::Deliver { .. } => "deliver",
+            SyncAction::Fail { .. } => "fail",
+            SyncAction::Note { .. } => "note",
+            SyncAction::Location { .. } => "location",
+        }
+    }
+
+    /// Paquete afectado (las posiciones son del driver)
+    pub fn package_id(&self) -> Option<Uuid> {
+        match self {
+            SyncAction::Deliver { package_id, .. }
+            | SyncAction::Fail { package_id, .. }
+            | SyncAction::Note { package_id, .. } => Some(*package_id),
+            SyncAction::Location { .. } => None,
+        }
+    }
+}
+
+/// Resultado global de una operación
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncOutcome {
+    /// Todos los campos aplicados
+    Applied,
+    /// Al menos un campo se ha quedado con el valor del servidor
+    Conflict,
+    /// Operación inválida (paquete inexistente, firma que falta...): no se aplica nada
+    Rejected,
+}
+
+/// Resolución de un campo
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldResolution {
+    Applied,
+    /// Regla server-wins: el estado del servidor no admite el cambio
+    ServerWins,
+    /// Regla last-writer-wins: el servidor tiene una escritura más reciente
+    Superseded,
+}
+
+/// Resolución de un campo de la operación
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FieldResult {
+    pub field: String,
+    pub resolution: FieldResolution,
+    pub message: Option<String>,
+}
+
+/// Resultado de una operación (se guarda y se repite en cada reenvío)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SyncOperationResult {
+    pub idempotency_key: String,
+    pub outcome: SyncOutcome,
+    pub fields: Vec<FieldResult>,
+    pub message: Option<String>,
+
+    /// `true` si la clave ya se había procesado y no se ha vuelto a aplicar
+    #[serde(default)]
+    pub replayed: bool,
+}
+
+/// Cambios del servidor desde el cursor del cliente (se aplican como upsert)
+#[derive(Debug, Default, Serialize)]
+pub struct SyncChanges {
+    pub tournees: Vec<TourneeResponse>,
+    pub packages: Vec<PackageResponse>,
+    pub deleted_tournee_ids: Vec<Uuid>,
+    pub deleted_package_ids: Vec<Uuid>,
+}
+
+/// Response de sincronización
+#[derive(Debug, Serialize)]
+pub struct SyncResponse {
+    pub results: Vec<SyncOperationResult>,
+    pub changes: SyncChanges,
+
+    /// Cursor a enviar en la próxima sincronización
+    pub cursor: DateTime<Utc>,
+}
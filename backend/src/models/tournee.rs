@@ -12,7 +12,7 @@ use rust_decimal::Decimal;
 
 /// Estado de la tournée - mapea al ENUM tournee_status
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
-#[sqlx(type_name = "tournee_status", rename_all = "lowercase")]
+#[sqlx(type_name = "tournee_status", rename_all = "snake_case")]
 pub enum TourneeStatus {
     Pending,
     InProgress,
@@ -21,6 +21,19 @@ pub enum TourneeStatus {
     Paused,
 }
 
+impl TourneeStatus {
+    /// Valor del ENUM en PostgreSQL
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TourneeStatus::Pending => "pending",
+            TourneeStatus::InProgress => "in_progress",
+            TourneeStatus::Completed => "completed",
+            TourneeStatus::Cancelled => "cancelled",
+            TourneeStatus::Paused => "paused",
+        }
+    }
+}
+
 /// Origen de la tournée - mapea al campo tournee_origin
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TourneeOrigin {
@@ -153,6 +166,23 @@ pub struct EndTourneeRequest {
     pub post_inspection_photos: Option<Vec<String>>,
 }
 
+/// Request para pausar, reanudar o cancelar (cuerpo opcional)
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct StatusChangeRequest {
+    #[validate(length(max = 1000))]
+    pub notes: Option<String>,
+}
+
+/// Entrada del histórico de estados (tournées y paquetes)
+#[derive(Debug, Serialize, FromRow)]
+pub struct StatusHistoryEntry {
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub changed_by: Option<Uuid>,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
 /// Response de tournée para la API
 #[derive(Debug, Serialize)]
 pub struct TourneeResponse {
@@ -221,7 +251,7 @@ impl From<Tournee> for TourneeResponse {
             tournee_date: tournee.tournee_date.to_string(),
             start_location: tournee.start_location,
             end_location: tournee.end_location,
-            tournee_status: format!("{:?}", tournee.tournee_status).to_lowercase(),
+            tournee_status: tournee.tournee_status.as_str().to_string(),
             start_time: tournee.start_time.map(|dt| dt.to_rfc3339()),
             end_time: tournee.end_time.map(|dt| dt.to_rfc3339()),
             start_mileage: tournee.start_mileage.map(|m| m.to_string()),
@@ -249,7 +279,7 @@ impl From<Tournee> for TourneeListResponse {
             id: tournee.id.to_string(),
             tournee_number: tournee.tournee_number,
             tournee_date: tournee.tournee_date.to_string(),
-            tournee_status: format!("{:?}", tournee.tournee_status).to_lowercase(),
+            tournee_status: tournee.tournee_status.as_str().to_string(),
             start_location: tournee.start_location,
             end_location: tournee.end_location,
             total_distance: tournee.total_distance.map(|d| d.to_string()),
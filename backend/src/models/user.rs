@@ -12,6 +12,7 @@ use uuid::Uuid;
 /// Tipo de usuario - mapea al ENUM user_type
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
 #[sqlx(type_name = "user_type", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum UserType {
     Admin,
     Driver,
@@ -20,6 +21,7 @@ pub enum UserType {
 /// Estado del usuario - mapea al ENUM user_status
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
 #[sqlx(type_name = "user_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum UserStatus {
     Active,
     Inactive,
@@ -35,6 +37,8 @@ pub struct User {
     pub user_status: UserStatus,
     pub username: String,
     pub password_hash: String,
+    pub full_name: String,
+    pub email: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
@@ -49,6 +53,9 @@ pub struct CreateUserRequest {
     #[validate(length(min = 6, max = 100))]
     pub password: String,
     
+    #[validate(length(min = 2, max = 255))]
+    pub full_name: String,
+    
     #[validate(email)]
     pub email: String,
     
@@ -79,6 +86,7 @@ pub struct UserResponse {
     pub user_type: UserType,
     pub user_status: UserStatus,
     pub username: String,
+    pub full_name: String,
     pub email: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -133,7 +141,8 @@ impl From<User> for UserResponse {
             user_type: user.user_type,
             user_status: user.user_status,
             username: user.username,
-            email: None, // Campo no existe en el schema actual
+            full_name: user.full_name,
+            email: user.email,
             created_at: user.created_at,
             updated_at: user.updated_at,
         }
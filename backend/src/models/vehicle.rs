@@ -12,7 +12,7 @@ use rust_decimal::Decimal;
 
 /// Estado del vehículo - mapea al ENUM vehicle_status
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
-#[sqlx(type_name = "vehicle_status", rename_all = "lowercase")]
+#[sqlx(type_name = "vehicle_status", rename_all = "snake_case")]
 pub enum VehicleStatus {
     Active,
     Maintenance,
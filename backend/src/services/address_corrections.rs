@@ -0,0 +1,209 @@
+//! Correcciones de direcciones confirmadas por drivers
+//!
+//! Cuando un driver corrige a mano la posición de una dirección de Colis Privé
+//! (`LibelleVoieOrigineDestinataire` + código postal), la corrección se guarda
+//! por empresa y `AddressValidator` la usa antes de llamar a ningún geocoder.
+//! Cada nueva confirmación en el mismo sitio suma al contador; un admin puede
+//! revocarla.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::services::geocoding_cache::normalize_address;
+
+/// Distancia máxima (grados, ~50 m) para contar una confirmación en el mismo sitio
+const SAME_PLACE_DEGREES: f64 = 0.0005;
+
+/// Clave de una dirección de Colis Privé: calle normalizada + código postal
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrectionKey {
+    pub street: String,
+    pub postal_code: String,
+}
+
+impl CorrectionKey {
+    /// A partir de la dirección completa `"voie, code postal localité"`:
+    /// lo que precede al código postal es la calle
+    pub fn from_address(address: &str) -> Option<Self> {
+        let normalized = normalize_address(address);
+        let tokens: Vec<&str> = normalized.split_whitespace().collect();
+        let index = tokens
+            .iter()
+            .rposition(|t| t.len() == 5 && t.chars().all(|c| c.is_ascii_digit()))?;
+        let (street, postal_code) = (tokens[..index].join(" "), tokens[index].to_string());
+
+        (!street.is_empty()).then_some(Self { street, postal_code })
+    }
+}
+
+/// Dirección y posición confirmadas por un driver
+#[derive(Debug, Clone)]
+pub struct ConfirmedAddress<'a> {
+    pub key: CorrectionKey,
+    pub address: &'a str,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub formatted_address: Option<&'a str>,
+}
+
+/// Corrección guardada
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AddressCorrection {
+    pub id: Uuid,
+    pub raw_address: String,
+    pub postal_code: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub formatted_address: Option<String>,
+    pub confirmation_count: i32,
+    pub last_confirmed_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+const CORRECTION_COLUMNS: &str = r#"
+    id, raw_address, postal_code, latitude::float8 AS latitude, longitude::float8 AS longitude,
+    formatted_address, confirmation_count, last_confirmed_at, revoked_at, created_at
+"#;
+
+/// Almacén de correcciones (tabla `address_corrections`)
+#[derive(Debug, Clone)]
+pub struct AddressCorrectionStore {
+    pool: PgPool,
+}
+
+impl AddressCorrectionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Corrección vigente para una dirección
+    pub async fn find(&self, company_id: Uuid, address: &str) -> Result<Option<AddressCorrection>, sqlx::Error> {
+        let Some(key) = CorrectionKey::from_address(address) else {
+            return Ok(None);
+        };
+
+        sqlx::query_as::<_, AddressCorrection>(&format!(
+            "SELECT {} FROM address_corrections
+             WHERE company_id = $1 AND address_key = $2 AND postal_code = $3 AND revoked_at IS NULL",
+            CORRECTION_COLUMNS
+        ))
+        .bind(company_id)
+        .bind(&key.street)
+        .bind(&key.postal_code)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Registrar la posición confirmada por un driver, en la transacción del llamador.
+    /// En el mismo sitio suma una confirmación; en otro sitio (o tras revocar) vuelve a empezar.
+    pub async fn confirm(
+        &self,
+        conn: &mut PgConnection,
+        company_id: Uuid,
+        driver_id: Option<Uuid>,
+        confirmed: &ConfirmedAddress<'_>,
+    ) -> Result<AddressCorrection, sqlx::Error> {
+        sqlx::query_as::<_, AddressCorrection>(&format!(
+            r#"
+            INSERT INTO address_corrections (
+                company_id, address_key, postal_code, raw_address, latitude, longitude,
+                formatted_address, created_by, last_confirmed_by, last_confirmed_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8, NOW())
+            ON CONFLICT (company_id, address_key, postal_code) DO UPDATE SET
+                confirmation_count = CASE
+                    WHEN address_corrections.revoked_at IS NULL
+                     AND ABS(address_corrections.latitude - EXCLUDED.latitude) <= $9
+                     AND ABS(address_corrections.longitude - EXCLUDED.longitude) <= $9
+                    THEN address_corrections.confirmation_count + 1
+                    ELSE 1
+                END,
+                raw_address = EXCLUDED.raw_address,
+                latitude = EXCLUDED.latitude,
+                longitude = EXCLUDED.longitude,
+                formatted_address = COALESCE(EXCLUDED.formatted_address, address_corrections.formatted_address),
+                last_confirmed_by = EXCLUDED.last_confirmed_by,
+                last_confirmed_at = NOW(),
+                revoked_at = NULL,
+                revoked_by = NULL,
+                updated_at = NOW()
+            RETURNING {}
+            "#,
+            CORRECTION_COLUMNS
+        ))
+        .bind(company_id)
+        .bind(&confirmed.key.street)
+        .bind(&confirmed.key.postal_code)
+        .bind(confirmed.address)
+        .bind(confirmed.latitude)
+        .bind(confirmed.longitude)
+        .bind(confirmed.formatted_address)
+        .bind(driver_id)
+        .bind(SAME_PLACE_DEGREES)
+        .fetch_one(conn)
+        .await
+    }
+
+    /// Revocar una corrección; `false` si no existe o ya estaba revocada
+    pub async fn revoke(&self, company_id: Uuid, id: Uuid, revoked_by: Option<Uuid>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE address_corrections
+            SET revoked_at = NOW(), revoked_by = $3, updated_at = NOW()
+            WHERE id = $1 AND company_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(company_id)
+        .bind(revoked_by)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Correcciones de una empresa, las más confirmadas primero
+    pub async fn list(&self, company_id: Uuid, include_revoked: bool) -> Result<Vec<AddressCorrection>, sqlx::Error> {
+        sqlx::query_as::<_, AddressCorrection>(&format!(
+            "SELECT {} FROM address_corrections
+             WHERE company_id = $1 AND ($2 OR revoked_at IS NULL)
+             ORDER BY confirmation_count DESC, last_confirmed_at DESC",
+            CORRECTION_COLUMNS
+        ))
+        .bind(company_id)
+        .bind(include_revoked)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correction_key_from_colis_prive_address() {
+        let key = CorrectionKey::from_address("12 RUE JEAN COTTIN, 75018 PARIS").unwrap();
+        assert_eq!(key.street, "12 RUE JEAN COTTIN");
+        assert_eq!(key.postal_code, "75018");
+
+        // Misma dirección con otra puntuación y acentos: misma clave
+        assert_eq!(CorrectionKey::from_address("12, Rue Jean-Cottin 75018 Paris"), Some(CorrectionKey {
+            street: "12 RUE JEAN COTTIN".to_string(),
+            postal_code: "75018".to_string(),
+        }));
+        assert_eq!(
+            CorrectionKey::from_address("3 IMP. DU CURÉ, 75018 PARIS").unwrap().street,
+            "3 IMPASSE DU CURE"
+        );
+    }
+
+    #[test]
+    fn test_correction_key_requires_postal_code() {
+        assert!(CorrectionKey::from_address("12 RUE JEAN COTTIN, PARIS").is_none());
+        assert!(CorrectionKey::from_address("12 RUE JEAN COTTIN").is_none());
+        assert!(CorrectionKey::from_address(", 75018 PARIS").is_none());
+    }
+}
@@ -1,10 +1,16 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use regex::Regex;
-use crate::services::geocoding_service::{GeocodingService, GeocodingResponse};
-
-#[derive(Debug, Serialize, Deserialize)]
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::address::parse_address;
+use crate::services::address_corrections::AddressCorrectionStore;
+use crate::services::geocoder::Geocoder;
+use crate::services::geocoding_service::GeocodingResponse;
+use crate::services::plausibility::{BoundingBox, PlausibilityChecker, PlausibilityConfig, TourneeArea};
+use crate::services::route_optimizer::GeoPoint;
+use crate::services::sectors::Sector;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidatedAddress {
     pub success: bool,
     pub latitude: Option<f64>,
@@ -15,10 +21,32 @@ pub struct ValidatedAddress {
     pub confidence: ValidationConfidence,
     pub warnings: Vec<String>,
     pub error: Option<String>,
+    /// Puntuación de plausibilidad geográfica (ver `plausibility`)
+    #[serde(default)]
+    pub plausibility: Option<f64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ValidatedAddress {
+    /// Resultado para una dirección cuya validación falló: queda para revisión manual
+    pub fn failed(address: impl Into<String>, error: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            latitude: None,
+            longitude: None,
+            formatted_address: None,
+            original_address: address.into(),
+            validation_method: ValidationMethod::ManualRequired,
+            confidence: ValidationConfidence::None,
+            warnings: vec![],
+            error: Some(error.into()),
+            plausibility: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ValidationMethod {
+    DriverConfirmed,
     Original,
     Cleaned,
     CompletedWithSector,
@@ -26,9 +54,9 @@ pub enum ValidationMethod {
     ManualRequired,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ValidationConfidence {
-    High,    // Dirección original válida
+    High,    // Dirección original válida o confirmada por drivers
     Medium,  // Dirección limpiada o completada
     Low,     // Búsqueda parcial
     None,    // Requiere intervención manual
@@ -37,74 +65,95 @@ pub enum ValidationConfidence {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AddressValidationResult {
     pub total_addresses: usize,
+    pub driver_confirmed: usize,
     pub auto_validated: usize,
     pub cleaned_auto: usize,
     pub completed_auto: usize,
     pub partial_found: usize,
     pub requires_manual: usize,
+    pub low_plausibility: usize,
     pub validated_addresses: Vec<ValidatedAddress>,
     pub warnings: Vec<String>,
 }
 
 pub struct AddressValidator {
-    geocoding_service: GeocodingService,
-    client_names: Vec<String>,
-    sector_mapping: HashMap<String, String>,
-    street_regex: Regex,
-    number_regex: Regex,
-    duplicate_number_regex: Regex,
-    district_in_middle_regex: Regex,
-    incomplete_address_regex: Regex,
-    separated_numbers_regex: Regex,
+    geocoder: Arc<dyn Geocoder>,
+    corrections: Option<(AddressCorrectionStore, Uuid)>,
+    sector: Option<Sector>,
+    plausibility: PlausibilityConfig,
 }
 
 impl AddressValidator {
-    pub fn new(geocoding_service: GeocodingService) -> Self {
-        let client_names = vec![
-            "MARTIN".to_string(), "DUBOIS".to_string(), "DURAND".to_string(), "MOREAU".to_string(), "LAURENT".to_string(), "BERNARD".to_string(), "THOMAS".to_string(), "PETIT".to_string(),
-            "ROBERT".to_string(), "RICHARD".to_string(), "DURAND".to_string(), "DUBOIS".to_string(), "MOREAU".to_string(), "LAURENT".to_string(), "SIMON".to_string(), "MICHEL".to_string(),
-            "LEFEBVRE".to_string(), "LEROY".to_string(), "ROUX".to_string(), "DAVID".to_string(), "BERTRAND".to_string(), "MOREL".to_string(), "FOURNIER".to_string(), "GIRARD".to_string(),
-            "BONNET".to_string(), "DUPONT".to_string(), "LAMBERT".to_string(), "FONTAINE".to_string(), "ROUSSEAU".to_string(), "VINCENT".to_string(), "MULLER".to_string(), "LEFEVRE".to_string(),
-            "ANDRE".to_string(), "LEROY".to_string(), "MARTINEZ".to_string(), "LEGALL".to_string(), "GARCIA".to_string(), "DAVID".to_string(), "BERNARD".to_string(), "THOMAS".to_string()
-        ];
-
-        let mut sector_mapping = HashMap::new();
-        // Mapeo de sectores basado en el username
-        sector_mapping.insert("A187518".to_string(), "CE18".to_string());
-        sector_mapping.insert("CE18".to_string(), "CE18".to_string());
-        // Agregar más mapeos según sea necesario
-
-        // 🆕 REGEX para reconocer calles francesas
-        let street_regex = Regex::new(r"(?i)(rue|avenue|boulevard|place|impasse|allée|chemin|route|passage|square|quai|esplanade|cours|villa|résidence|lotissement|zone|parc|cité|hameau|lieu-dit)\s+([^,]+)").unwrap();
-        
-        // 🆕 REGEX para detectar números al final
-        let number_regex = Regex::new(r"(.+?)\s+(\d+)\s*$").unwrap();
-        
-        // 🆕 REGEX para detectar números duplicados (ej: "35 35 RUE")
-        let duplicate_number_regex = Regex::new(r"(\d+)\s+\1\s+").unwrap();
-        
-        // 🆕 REGEX para detectar distrito en medio (ej: "18EME ARRONDISSEMENT")
-        let district_in_middle_regex = Regex::new(r"(?i)(\d+EME\s+ARRONDISSEMENT)").unwrap();
-        
-        // 🆕 REGEX para detectar direcciones incompletas (solo número y código postal)
-        let incomplete_address_regex = Regex::new(r"^(\d+),\s*(\d{5})\s+PARIS$").unwrap();
-        
-        // 🆕 REGEX para detectar números separados (ej: "6 7 IMP" -> tomar el último)
-        let separated_numbers_regex = Regex::new(r"(\d+)\s+(\d+)\s+").unwrap();
-
+    pub fn new(geocoder: Arc<dyn Geocoder>) -> Self {
         Self {
-            geocoding_service,
-            client_names,
-            sector_mapping,
-            street_regex,
-            number_regex,
-            duplicate_number_regex,
-            district_in_middle_regex,
-            incomplete_address_regex,
-            separated_numbers_regex,
+            geocoder,
+            corrections: None,
+            sector: None,
+            plausibility: PlausibilityConfig::default(),
         }
     }
 
+    /// Consultar primero las correcciones confirmadas por los drivers de la empresa
+    pub fn with_corrections(mut self, store: AddressCorrectionStore, company_id: Uuid) -> Self {
+        self.corrections = Some((store, company_id));
+        self
+    }
+
+    /// Sector asignado al driver o a la tournée: sus códigos postales completan las direcciones
+    pub fn with_sector(mut self, sector: Sector) -> Self {
+        self.sector = Some(sector);
+        self
+    }
+
+    /// Umbrales de plausibilidad distintos de los del entorno
+    pub fn with_plausibility(mut self, config: PlausibilityConfig) -> Self {
+        self.plausibility = config;
+        self
+    }
+
+    /// Puntuar la plausibilidad de los resultados de una tournée y rebajar a `Low`
+    /// los poco plausibles. Las posiciones confirmadas por drivers no se cuestionan.
+    /// Devuelve cuántos resultados se rebajaron.
+    pub fn apply_plausibility<'a>(&self, results: impl IntoIterator<Item = &'a mut ValidatedAddress>) -> usize {
+        let mut located: Vec<(&'a mut ValidatedAddress, GeoPoint)> = results
+            .into_iter()
+            .filter_map(|r| match (r.latitude, r.longitude) {
+                (Some(latitude), Some(longitude)) if r.success => Some((r, GeoPoint { latitude, longitude })),
+                _ => None,
+            })
+            .collect();
+
+        let points: Vec<GeoPoint> = located.iter().map(|(_, point)| *point).collect();
+        let sector_bounds = self
+            .sector
+            .as_ref()
+            .and_then(|s| s.boundary.as_ref())
+            .and_then(BoundingBox::from_geojson);
+        let tournee = TourneeArea::from_points(&points, self.plausibility.min_tournee_points);
+        let checker = PlausibilityChecker::new(&self.plausibility, sector_bounds, tournee);
+
+        let mut downgraded = 0;
+        for (result, point) in located.iter_mut() {
+            if matches!(result.validation_method, ValidationMethod::DriverConfirmed) {
+                continue;
+            }
+
+            let report = checker.check(&result.original_address, *point, result.formatted_address.as_deref());
+            result.plausibility = Some(report.score);
+            result.warnings.extend(report.failures.iter().cloned());
+
+            if !report.is_plausible(self.plausibility.min_score) && !matches!(result.confidence, ValidationConfidence::Low) {
+                log::warn!("⚠️ Resultado poco plausible para '{}' ({:.2}): {}",
+                    result.original_address, report.score, report.failures.join("; "));
+                result.confidence = ValidationConfidence::Low;
+                result.warnings.push(format!("Resultado poco plausible (puntuación {:.2}), confianza rebajada", report.score));
+                downgraded += 1;
+            }
+        }
+
+        downgraded
+    }
+
     /// Validación inteligente de una dirección con múltiples intentos
     pub async fn validate_address(
         &self,
@@ -113,11 +162,34 @@ impl AddressValidator {
     ) -> Result<ValidatedAddress> {
         log::info!("🔍 Validando dirección: '{}' para usuario: '{}'", address, username);
 
+        // 👷 PASO PREVIO: Posición confirmada por drivers (sin llamar a ningún geocoder)
+        if let Some((store, company_id)) = &self.corrections {
+            match store.find(*company_id, address).await {
+                Ok(Some(correction)) => {
+                    log::info!("✅ Dirección corregida por drivers ({} confirmaciones): {}", correction.confirmation_count, address);
+                    return Ok(ValidatedAddress {
+                        success: true,
+                        latitude: Some(correction.latitude),
+                        longitude: Some(correction.longitude),
+                        formatted_address: correction.formatted_address,
+                        original_address: address.to_string(),
+                        validation_method: ValidationMethod::DriverConfirmed,
+                        confidence: ValidationConfidence::High,
+                        warnings: vec![format!("Posición confirmada por drivers ({} veces)", correction.confirmation_count)],
+                        error: None,
+                        plausibility: None,
+                    });
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("⚠️ Error consultando correcciones de '{}': {}", address, e),
+            }
+        }
+
         // 🆕 PASO 0: Verificar si es una dirección incompleta
-        let (preprocessed_address, warnings) = self.handle_incomplete_address(address, username);
+        let (preprocessed_address, warnings) = self.handle_incomplete_address(address);
         
         // 🎯 INTENTO 1: Dirección original (o preprocesada)
-        if let Ok(result) = self.geocoding_service.geocode_address(&preprocessed_address).await {
+        if let Ok(result) = self.geocoder.geocode(&preprocessed_address).await {
             if self.is_valid_result(&result) {
                 log::info!("✅ Dirección original válida: {}", address);
                 return Ok(ValidatedAddress {
@@ -130,14 +202,15 @@ impl AddressValidator {
                     confidence: ValidationConfidence::High,
                     warnings,
                     error: None,
+                    plausibility: None,
                 });
             }
         }
 
-        // 🧹 INTENTO 2: Limpiar dirección (quitar nombre cliente)
+        // 🧹 INTENTO 2: Limpiar dirección (destinatario, números, abreviaturas)
         let cleaned_address = self.clean_address(address);
         if cleaned_address != address {
-            if let Ok(result) = self.geocoding_service.geocode_address(&cleaned_address).await {
+            if let Ok(result) = self.geocoder.geocode(&cleaned_address).await {
                 if self.is_valid_result(&result) {
                     log::info!("✅ Dirección limpiada válida: {} -> {}", address, cleaned_address);
                     return Ok(ValidatedAddress {
@@ -148,17 +221,19 @@ impl AddressValidator {
                         original_address: address.to_string(),
                         validation_method: ValidationMethod::Cleaned,
                         confidence: ValidationConfidence::Medium,
-                        warnings: vec!["Dirección limpiada automáticamente".to_string()],
+                        warnings: std::iter::once("Dirección limpiada automáticamente".to_string())
+                            .chain(parse_address(address).warnings)
+                            .collect(),
                         error: None,
+                        plausibility: None,
                     });
                 }
             }
         }
 
-        // 🏢 INTENTO 3: Completar con sector del username
-        let sector_address = self.complete_with_sector(&cleaned_address, username);
-        if sector_address != cleaned_address {
-            if let Ok(result) = self.geocoding_service.geocode_address(&sector_address).await {
+        // 🏢 INTENTO 3: Completar con los códigos postales del sector asignado
+        for sector_address in self.complete_with_sector(&cleaned_address) {
+            if let Ok(result) = self.geocoder.geocode(&sector_address).await {
                 if self.is_valid_result(&result) {
                     log::info!("✅ Dirección completada con sector válida: {} -> {}", address, sector_address);
                     return Ok(ValidatedAddress {
@@ -171,15 +246,15 @@ impl AddressValidator {
                         confidence: ValidationConfidence::Medium,
                         warnings: vec!["Dirección completada con sector automáticamente".to_string()],
                         error: None,
+                        plausibility: None,
                     });
                 }
             }
         }
 
-        // 🔍 INTENTO 4: Búsqueda parcial (solo calle + distrito)
-        let partial_address = self.extract_street_and_district(&cleaned_address, username);
-        if partial_address != cleaned_address {
-            if let Ok(result) = self.geocoding_service.geocode_address(&partial_address).await {
+        // 🔍 INTENTO 4: Búsqueda parcial (solo calle + distrito del sector)
+        for partial_address in self.extract_street_and_district(&cleaned_address) {
+            if let Ok(result) = self.geocoder.geocode(&partial_address).await {
                 if self.is_valid_result(&result) {
                     log::info!("✅ Dirección encontrada por búsqueda parcial: {} -> {}", address, partial_address);
                     return Ok(ValidatedAddress {
@@ -192,6 +267,7 @@ impl AddressValidator {
                         confidence: ValidationConfidence::Low,
                         warnings: vec!["Dirección encontrada por búsqueda parcial".to_string()],
                         error: None,
+                        plausibility: None,
                     });
                 }
             }
@@ -209,6 +285,7 @@ impl AddressValidator {
             confidence: ValidationConfidence::None,
             warnings: vec![],
             error: Some("No se pudo validar automáticamente. Requiere verificación manual.".to_string()),
+            plausibility: None,
         })
     }
 
@@ -222,6 +299,7 @@ impl AddressValidator {
         log::info!("🔍 Validando {} direcciones en lote para usuario: '{}'", total_addresses, username);
 
         let mut validated_addresses = Vec::new();
+        let mut driver_confirmed = 0;
         let mut auto_validated = 0;
         let mut cleaned_auto = 0;
         let mut completed_auto = 0;
@@ -233,6 +311,7 @@ impl AddressValidator {
             match self.validate_address(&address, username).await {
                 Ok(validated) => {
                     match validated.validation_method {
+                        ValidationMethod::DriverConfirmed => driver_confirmed += 1,
                         ValidationMethod::Original => auto_validated += 1,
                         ValidationMethod::Cleaned => cleaned_auto += 1,
                         ValidationMethod::CompletedWithSector => completed_auto += 1,
@@ -249,22 +328,18 @@ impl AddressValidator {
                 Err(e) => {
                     log::error!("❌ Error validando dirección '{}': {}", address, e);
                     requires_manual += 1;
-                    validated_addresses.push(ValidatedAddress {
-                        success: false,
-                        latitude: None,
-                        longitude: None,
-                        formatted_address: None,
-                        original_address: address,
-                        validation_method: ValidationMethod::ManualRequired,
-                        confidence: ValidationConfidence::None,
-                        warnings: vec![],
-                        error: Some(e.to_string()),
-                    });
+                    validated_addresses.push(ValidatedAddress::failed(address, e.to_string()));
                 }
             }
         }
 
+        // 🗺️ Plausibilidad geográfica sobre el conjunto de la tournée
+        let low_plausibility = self.apply_plausibility(validated_addresses.iter_mut());
+
         // Generar resumen de warnings
+        if low_plausibility > 0 {
+            warnings.push(format!("{} direcciones con resultado poco plausible", low_plausibility));
+        }
         if cleaned_auto > 0 {
             warnings.push(format!("{} direcciones limpiadas automáticamente", cleaned_auto));
         }
@@ -277,11 +352,13 @@ impl AddressValidator {
 
         Ok(AddressValidationResult {
             total_addresses,
+            driver_confirmed,
             auto_validated,
             cleaned_auto,
             completed_auto,
             partial_found,
             requires_manual,
+            low_plausibility,
             validated_addresses,
             warnings,
         })
@@ -296,90 +373,11 @@ impl AddressValidator {
         result.longitude.unwrap() != 0.0
     }
 
-    /// Limpiar dirección removiendo nombres de clientes comunes
+    /// Limpiar dirección con el parser: sin destinatario, complemento ni arrondissement,
+    /// números corregidos y abreviaturas expandidas
     fn clean_address(&self, address: &str) -> String {
-        let mut cleaned = address.to_uppercase();
-        let mut warnings = Vec::new();
-        
-        // 🆕 PASO 1: Detectar y corregir números al final
-        cleaned = self.fix_number_at_end(&cleaned);
-        
-        // 🆕 PASO 2: Detectar y corregir números duplicados (ej: "35 35 RUE")
-        if let Some(captures) = self.duplicate_number_regex.captures(cleaned.as_str()) {
-            if let Some(first_number) = captures.get(1) {
-                let number = first_number.as_str().to_string();
-                let pattern = format!("{} {}", number, number);
-                cleaned = cleaned.replace(&pattern, &number);
-                warnings.push(format!("Números duplicados detectados y corregidos: {} {}", number, number));
-            }
-        }
-        
-        // 🆕 PASO 3: Detectar y corregir números separados (ej: "6 7 IMP" -> "7 IMP")
-        if let Some(captures) = self.separated_numbers_regex.captures(cleaned.as_str()) {
-            if let Some(first_num) = captures.get(1) {
-                if let Some(second_num) = captures.get(2) {
-                    let first = first_num.as_str().to_string();
-                    let second = second_num.as_str().to_string();
-                    // Tomar el último número (segundo)
-                    let pattern = format!("{} {}", first, second);
-                    cleaned = cleaned.replace(&pattern, &second);
-                    warnings.push(format!("Números separados detectados ({} {}), tomando el último: {}", first, second, second));
-                }
-            }
-        }
-        
-        // 🆕 PASO 4: Remover distrito del medio (ej: "18EME ARRONDISSEMENT")
-        if let Some(captures) = self.district_in_middle_regex.captures(cleaned.as_str()) {
-            if let Some(district) = captures.get(1) {
-                let district_str = district.as_str().to_string();
-                cleaned = cleaned.replace(&district_str, "");
-                warnings.push(format!("Distrito en medio removido: {}", district_str));
-            }
-        }
-        
-        // 🆕 PASO 5: Usar regex para extraer solo la calle
-        if let Some(captures) = self.street_regex.captures(&cleaned) {
-            if let Some(street_type) = captures.get(1) {
-                if let Some(street_name) = captures.get(2) {
-                    let street_type_str = street_type.as_str();
-                    let street_name_str = street_name.as_str();
-                    
-                    // Remover nombres de clientes del nombre de la calle
-                    let mut clean_street_name = street_name_str.to_string();
-                    for name in &self.client_names {
-                        clean_street_name = clean_street_name.replace(name, "");
-                    }
-                    
-                    // Limpiar espacios extra
-                    clean_street_name = clean_street_name
-                        .split_whitespace()
-                        .collect::<Vec<&str>>()
-                        .join(" ");
-                    
-                    // Si el nombre de la calle no está vacío, usar solo la calle
-                    if !clean_street_name.trim().is_empty() {
-                        cleaned = format!("{} {}", street_type_str, clean_street_name);
-                    }
-                }
-            }
-        }
-        
-        // 🆕 PASO 6: Si no se pudo extraer con regex, usar método anterior
-        if cleaned == address.to_uppercase() {
-            // Remover nombres comunes de clientes
-            for name in &self.client_names {
-                cleaned = cleaned.replace(name, "");
-            }
-        }
-        
-        // Limpiar espacios extra y caracteres especiales
-        cleaned = cleaned
-            .replace("  ", " ")
-            .replace(" ,", ",")
-            .replace(", ", ",")
-            .trim()
-            .to_string();
-        
+        let cleaned = parse_address(address).to_query();
+
         // Si la dirección está muy vacía, devolver la original
         if cleaned.len() < 10 {
             address.to_string()
@@ -388,117 +386,54 @@ impl AddressValidator {
         }
     }
 
-    /// Completar dirección con información del sector
-    fn complete_with_sector(&self, address: &str, username: &str) -> String {
-        // Extraer sector del username
-        let _sector = self.extract_sector_from_username(username);
-        let district = self.extract_district_from_username(username);
-        
-        // Si la dirección no contiene el distrito, agregarlo
-        if !address.to_uppercase().contains(&district.to_uppercase()) {
-            format!("{}, {}", address, district)
-        } else {
-            address.to_string()
+    /// Completar dirección con cada distrito del sector ("75018 Paris").
+    /// Sin sector, o si la dirección ya lleva uno de sus códigos postales, no hay variantes.
+    fn complete_with_sector(&self, address: &str) -> Vec<String> {
+        match &self.sector {
+            Some(sector) if !sector.covers(address) => sector
+                .districts()
+                .into_iter()
+                .map(|district| format!("{}, {}", address, district))
+                .collect(),
+            _ => Vec::new(),
         }
     }
 
-    /// Extraer calle y distrito para búsqueda parcial
-    fn extract_street_and_district(&self, address: &str, username: &str) -> String {
-        let district = self.extract_district_from_username(username);
-        
-        // Intentar extraer solo la calle (antes de la primera coma)
-        if let Some(comma_pos) = address.find(',') {
-            let street = &address[..comma_pos];
-            format!("{}, {}", street.trim(), district)
-        } else {
-            // Si no hay coma, usar toda la dirección con el distrito
-            format!("{}, {}", address, district)
-        }
+    /// Extraer la calle (antes de la primera coma) y combinarla con cada distrito del sector
+    fn extract_street_and_district(&self, address: &str) -> Vec<String> {
+        let Some(sector) = &self.sector else {
+            return Vec::new();
+        };
+        let parsed = parse_address(address);
+        let street = parsed
+            .street_line()
+            .unwrap_or_else(|| address.split(',').next().unwrap_or(address).trim().to_string());
+
+        sector
+            .districts()
+            .into_iter()
+            .map(|district| format!("{}, {}", street, district))
+            .filter(|partial| partial != address)
+            .collect()
     }
 
-    /// Extraer sector del username
-    fn extract_sector_from_username(&self, username: &str) -> String {
-        // Mapeo directo si existe
-        if let Some(sector) = self.sector_mapping.get(username) {
-            return sector.clone();
-        }
-        
-        // 🆕 PARSING INTELIGENTE: A187518 -> A (sector) + 7518 (código postal)
-        if username.len() >= 6 {
-            let sector_letter = &username[0..1]; // A
-            let postal_code_part = &username[2..]; // 7518
-            
-            // Formar código postal válido: 7518 -> 75018
-            if postal_code_part.len() == 4 {
-                let postal_code = format!("75{}", postal_code_part); // 75018
-                return format!("{}{}", sector_letter, postal_code); // A75018
-            }
-        }
-        
-        "A75018".to_string() // Default
-    }
+    /// 🆕 Manejar direcciones incompletas (ej: "75, 75018 PARIS")
+    fn handle_incomplete_address(&self, address: &str) -> (String, Vec<String>) {
+        let mut warnings = Vec::new();
+        let parsed = parse_address(address);
 
-    /// Extraer distrito del username
-    fn extract_district_from_username(&self, username: &str) -> String {
-        // 🆕 PARSING INTELIGENTE: A187518 -> 75018 Paris
-        if username.len() >= 6 {
-            let postal_code_part = &username[2..]; // 7518
-            
-            // Formar código postal válido: 7518 -> 75018
-            if postal_code_part.len() == 4 {
-                let postal_code = format!("75{}", postal_code_part); // 75018
-                return format!("{} Paris", postal_code);
-            }
-        }
-        
-        "75018 Paris".to_string() // Default
-    }
+        if let (true, Some(number), Some(locality)) = (parsed.is_missing_street(), parsed.number, parsed.locality()) {
+            // Intentar completar con información del sector
+            let completed = format!("{} RUE INCONNUE, {}", number, locality);
 
-    /// 🆕 Corregir números al final de la dirección
-    /// Ejemplo: "Rue Jean Cottin 3" -> "3 Rue Jean Cottin"
-    fn fix_number_at_end(&self, address: &str) -> String {
-        if let Some(captures) = self.number_regex.captures(address) {
-            if let Some(rest) = captures.get(1) {
-                if let Some(number) = captures.get(2) {
-                    let rest_str = rest.as_str().trim();
-                    let number_str = number.as_str();
-                    
-                    // Verificar si el resto contiene una palabra de calle
-                    if self.street_regex.is_match(rest_str) {
-                        // Reorganizar: "Rue Jean Cottin 3" -> "3 Rue Jean Cottin"
-                        return format!("{} {}", number_str, rest_str);
-                    }
-                }
+            warnings.push(format!("Dirección incompleta detectada: '{}', completada con 'RUE INCONNUE'", address));
+            if let Some(sector) = &self.sector {
+                warnings.push(format!("Usar información del sector: {} ({})", sector.code, sector.districts().join(", ")));
             }
-        }
-        
-        address.to_string()
-    }
 
-    /// 🆕 Manejar direcciones incompletas (ej: "75, 75018 PARIS")
-    fn handle_incomplete_address(&self, address: &str, username: &str) -> (String, Vec<String>) {
-        let mut warnings = Vec::new();
-        
-        if let Some(captures) = self.incomplete_address_regex.captures(address) {
-            if let Some(number) = captures.get(1) {
-                if let Some(postal_code) = captures.get(2) {
-                    let num = number.as_str();
-                    let code = postal_code.as_str();
-                    
-                    // Extraer distrito del username para completar
-                    let district = self.extract_district_from_username(username);
-                    
-                    // Intentar completar con información del sector
-                    let completed = format!("{} RUE INCONNUE, {} PARIS", num, code);
-                    
-                    warnings.push(format!("Dirección incompleta detectada: '{}', completada con 'RUE INCONNUE'", address));
-                    warnings.push(format!("Usar información del sector: {}", district));
-                    
-                    return (completed, warnings);
-                }
-            }
+            return (completed, warnings);
         }
-        
+
         (address.to_string(), warnings)
     }
 }
@@ -506,11 +441,11 @@ impl AddressValidator {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::services::geocoding_service::GeocodingService;
+    use crate::services::geocoder::GeocoderChain;
 
     #[test]
     fn test_clean_address() {
-        let service = GeocodingService::new("test_token".to_string());
+        let service: Arc<dyn Geocoder> = Arc::new(GeocoderChain::new(Vec::new(), 0.5));
         let validator = AddressValidator::new(service);
         
         // Test limpieza básica
@@ -538,46 +473,49 @@ mod tests {
         );
     }
 
-    #[test]
-    fn test_extract_sector_from_username() {
-        let service = GeocodingService::new("test_token".to_string());
-        let validator = AddressValidator::new(service);
-        
-        // Test parsing inteligente del username
-        assert_eq!(validator.extract_sector_from_username("A187518"), "A75018");
-        assert_eq!(validator.extract_sector_from_username("A197519"), "A75019");
-        assert_eq!(validator.extract_sector_from_username("B123456"), "B75123");
+    fn sector(postal_codes: &[&str], communes: &[&str]) -> Sector {
+        Sector {
+            id: Uuid::new_v4(),
+            company_id: Uuid::new_v4(),
+            code: "CE18".to_string(),
+            name: "Paris 18".to_string(),
+            postal_codes: postal_codes.iter().map(|c| c.to_string()).collect(),
+            communes: communes.iter().map(|c| c.to_string()).collect(),
+            boundary: None,
+            is_active: true,
+            created_at: None,
+            updated_at: None,
+        }
     }
 
     #[test]
-    fn test_extract_district_from_username() {
-        let service = GeocodingService::new("test_token".to_string());
+    fn test_complete_with_sector() {
+        let service: Arc<dyn Geocoder> = Arc::new(GeocoderChain::new(Vec::new(), 0.5));
         let validator = AddressValidator::new(service);
-        
-        // Test parsing inteligente del distrito
-        assert_eq!(validator.extract_district_from_username("A187518"), "75018 Paris");
-        assert_eq!(validator.extract_district_from_username("A197519"), "75019 Paris");
-        assert_eq!(validator.extract_district_from_username("B123456"), "75123 Paris");
-    }
 
-    #[test]
-    fn test_fix_number_at_end() {
-        let service = GeocodingService::new("test_token".to_string());
-        let validator = AddressValidator::new(service);
-        
-        // Test corrección de número al final
-        assert_eq!(validator.fix_number_at_end("Rue Jean Cottin 3"), "3 Rue Jean Cottin");
-        assert_eq!(validator.fix_number_at_end("Avenue des Champs 25"), "25 Avenue des Champs");
-        assert_eq!(validator.fix_number_at_end("15 Rue de la Paix"), "15 Rue de la Paix"); // No cambia
+        // Sin sector asignado no se inventa el distrito
+        assert!(validator.complete_with_sector("16 RUE JEAN COTTIN").is_empty());
+        assert!(validator.extract_street_and_district("16 RUE JEAN COTTIN, PARIS").is_empty());
+
+        let validator = validator.with_sector(sector(&["93400", "93200"], &["Saint-Ouen", "Saint-Denis"]));
+        assert_eq!(
+            validator.complete_with_sector("12 RUE DES ROSIERS"),
+            vec!["12 RUE DES ROSIERS, 93400 Saint-Ouen", "12 RUE DES ROSIERS, 93200 Saint-Denis"]
+        );
+        assert!(validator.complete_with_sector("12 RUE DES ROSIERS, 93400 SAINT OUEN").is_empty());
+        assert_eq!(
+            validator.extract_street_and_district("12 RUE DES ROSIERS, BAT B"),
+            vec!["12 RUE DES ROSIERS, 93400 Saint-Ouen", "12 RUE DES ROSIERS, 93200 Saint-Denis"]
+        );
     }
 
     #[test]
     fn test_handle_incomplete_address() {
-        let service = GeocodingService::new("test_token".to_string());
+        let service: Arc<dyn Geocoder> = Arc::new(GeocoderChain::new(Vec::new(), 0.5));
         let validator = AddressValidator::new(service);
         
         // Test dirección incompleta
-        let (result, warnings) = validator.handle_incomplete_address("75, 75018 PARIS", "A187518");
+        let (result, warnings) = validator.handle_incomplete_address("75, 75018 PARIS");
         assert!(result.contains("RUE INCONNUE"));
         assert!(warnings.len() > 0);
         assert!(warnings[0].contains("Dirección incompleta detectada"));
@@ -585,7 +523,7 @@ mod tests {
 
     #[test]
     fn test_clean_address_improvements() {
-        let service = GeocodingService::new("test_token".to_string());
+        let service: Arc<dyn Geocoder> = Arc::new(GeocoderChain::new(Vec::new(), 0.5));
         let validator = AddressValidator::new(service);
         
         // Test números duplicados
@@ -594,10 +532,10 @@ mod tests {
             "35 RUE MARC SEGUIN"
         );
         
-        // Test números separados (tomar el último)
+        // Test números separados (tomar el último) y abreviatura expandida
         assert_eq!(
             validator.clean_address("6 7 IMP. DU CURE"),
-            "7 IMP. DU CURE"
+            "7 IMPASSE DU CURE"
         );
         
         // Test distrito en medio
@@ -0,0 +1,234 @@
+//! Importación de la Base Adresse Nationale
+//!
+//! Carga un extracto departamental del CSV de la BAN
+//! (`adresses-75.csv` de adresse.data.gouv.fr) en la tabla PostGIS
+//! `ban_addresses`, que usa `LocalBanGeocoder` para geocodificar sin red.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use sqlx::PgPool;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::{info, warn};
+
+use crate::services::geocoding_cache::normalize_address;
+
+/// Filas por INSERT
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// Columnas del CSV que se importan
+const REQUIRED_COLUMNS: [&str; 9] = [
+    "id", "numero", "rep", "nom_voie", "code_postal", "code_insee", "nom_commune", "lon", "lat",
+];
+
+/// Una dirección de la BAN
+#[derive(Debug, Clone, PartialEq)]
+pub struct BanRecord {
+    pub id: String,
+    pub numero: i32,
+    pub rep: Option<String>,
+    pub nom_voie: String,
+    pub code_postal: String,
+    pub code_insee: String,
+    pub nom_commune: String,
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+/// Posición de cada columna según la cabecera del CSV
+#[derive(Debug, Clone)]
+pub struct BanColumns {
+    positions: HashMap<&'static str, usize>,
+}
+
+impl BanColumns {
+    /// Leer la cabecera (separador `;`)
+    pub fn from_header(header: &str) -> Result<Self> {
+        let names: Vec<&str> = split_fields(header).collect();
+        let mut positions = HashMap::new();
+
+        for column in REQUIRED_COLUMNS {
+            let index = names
+                .iter()
+                .position(|name| *name == column)
+                .ok_or_else(|| anyhow!("Columna '{}' no encontrada en la cabecera BAN", column))?;
+            positions.insert(column, index);
+        }
+
+        Ok(Self { positions })
+    }
+
+    /// Parsear una línea; `None` si está incompleta o mal formada
+    pub fn parse(&self, line: &str) -> Option<BanRecord> {
+        let fields: Vec<&str> = split_fields(line).collect();
+        let field = |column: &str| fields.get(self.positions[column]).copied().unwrap_or("");
+
+        let id = field("id");
+        let nom_voie = field("nom_voie");
+        let code_postal = field("code_postal");
+        if id.is_empty() || nom_voie.is_empty() || code_postal.len() != 5 {
+            return None;
+        }
+
+        let rep = field("rep");
+        Some(BanRecord {
+            id: id.to_string(),
+            numero: field("numero").parse().ok()?,
+            rep: (!rep.is_empty()).then(|| rep.to_uppercase()),
+            nom_voie: nom_voie.to_string(),
+            code_postal: code_postal.to_string(),
+            code_insee: field("code_insee").to_string(),
+            nom_commune: field("nom_commune").to_string(),
+            longitude: field("lon").parse().ok()?,
+            latitude: field("lat").parse().ok()?,
+        })
+    }
+}
+
+fn split_fields(line: &str) -> impl Iterator<Item = &str> {
+    line.trim_end_matches(['\r', '\n'])
+        .split(';')
+        .map(|field| field.trim().trim_matches('"'))
+}
+
+/// Resultado de una importación
+#[derive(Debug, Default, Serialize)]
+pub struct BanImportSummary {
+    pub imported: usize,
+    pub skipped: usize,
+}
+
+/// Importar un CSV de la BAN (se puede relanzar: las filas existentes se actualizan)
+pub async fn import_ban_csv(pool: &PgPool, path: &Path) -> Result<BanImportSummary> {
+    info!("📥 Importando BAN desde {}", path.display());
+
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("No se pudo abrir {}", path.display()))?;
+    let mut lines = BufReader::new(file).lines();
+
+    let header = lines.next_line().await?.ok_or_else(|| anyhow!("CSV BAN vacío"))?;
+    let columns = BanColumns::from_header(&header)?;
+
+    let mut summary = BanImportSummary::default();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut tx = pool.begin().await?;
+
+    while let Some(line) = lines.next_line().await? {
+        match columns.parse(&line) {
+            Some(record) => batch.push(record),
+            None => {
+                summary.skipped += 1;
+                continue;
+            }
+        }
+
+        if batch.len() == IMPORT_BATCH_SIZE {
+            insert_batch(&mut tx, &batch).await?;
+            summary.imported += batch.len();
+            batch.clear();
+
+            if summary.imported % 50_000 == 0 {
+                info!("📥 {} direcciones importadas...", summary.imported);
+            }
+        }
+    }
+
+    if !batch.is_empty() {
+        insert_batch(&mut tx, &batch).await?;
+        summary.imported += batch.len();
+    }
+
+    tx.commit().await?;
+
+    if summary.skipped > 0 {
+        warn!("⚠️ {} líneas BAN ignoradas (incompletas o mal formadas)", summary.skipped);
+    }
+    info!("✅ Importación BAN completada: {} direcciones", summary.imported);
+
+    Ok(summary)
+}
+
+async fn insert_batch(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, batch: &[BanRecord]) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO ban_addresses (
+            id, numero, rep, nom_voie, nom_voie_normalized,
+            code_postal, code_insee, nom_commune, location
+        )
+        SELECT r.id, r.numero, r.rep, r.nom_voie, r.nom_voie_normalized,
+               r.code_postal, r.code_insee, r.nom_commune,
+               ST_SetSRID(ST_MakePoint(r.lon, r.lat), 4326)::geography
+        FROM UNNEST(
+            $1::text[], $2::int4[], $3::text[], $4::text[], $5::text[],
+            $6::text[], $7::text[], $8::text[], $9::float8[], $10::float8[]
+        ) AS r(id, numero, rep, nom_voie, nom_voie_normalized, code_postal, code_insee, nom_commune, lon, lat)
+        ON CONFLICT (id) DO UPDATE SET
+            numero = EXCLUDED.numero,
+            rep = EXCLUDED.rep,
+            nom_voie = EXCLUDED.nom_voie,
+            nom_voie_normalized = EXCLUDED.nom_voie_normalized,
+            code_postal = EXCLUDED.code_postal,
+            code_insee = EXCLUDED.code_insee,
+            nom_commune = EXCLUDED.nom_commune,
+            location = EXCLUDED.location,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(batch.iter().map(|r| r.id.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|r| r.numero).collect::<Vec<_>>())
+    .bind(batch.iter().map(|r| r.rep.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|r| r.nom_voie.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|r| normalize_address(&r.nom_voie)).collect::<Vec<_>>())
+    .bind(batch.iter().map(|r| r.code_postal.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|r| r.code_insee.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|r| r.nom_commune.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|r| r.longitude).collect::<Vec<_>>())
+    .bind(batch.iter().map(|r| r.latitude).collect::<Vec<_>>())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "id;id_fantoir;numero;rep;nom_voie;code_postal;code_insee;nom_commune;code_insee_ancienne_commune;nom_ancienne_commune;x;y;lon;lat;type_position;alias;nom_ld;libelle_acheminement;nom_afnor;source_position;source_nom_voie;certification_commune;cad_parcelles";
+
+    #[test]
+    fn test_parse_ban_line() {
+        let columns = BanColumns::from_header(HEADER).unwrap();
+
+        let record = columns
+            .parse("75118_5054_00016_bis;75118_5054;16;bis;Rue Jean Cottin;75018;75118;Paris 18e Arrondissement;;;652986.73;6866105.24;2.361288;48.892105;entrée;;;PARIS;RUE JEAN COTTIN;commune;commune;1;")
+            .unwrap();
+
+        assert_eq!(record.id, "75118_5054_00016_bis");
+        assert_eq!(record.numero, 16);
+        assert_eq!(record.rep.as_deref(), Some("BIS"));
+        assert_eq!(record.nom_voie, "Rue Jean Cottin");
+        assert_eq!(record.code_postal, "75018");
+        assert_eq!(record.longitude, 2.361288);
+        assert_eq!(record.latitude, 48.892105);
+    }
+
+    #[test]
+    fn test_parse_rejects_incomplete_lines() {
+        let columns = BanColumns::from_header(HEADER).unwrap();
+
+        // Sin coordenadas
+        assert!(columns.parse("75118_5054_00016;75118_5054;16;;Rue Jean Cottin;75018;75118;Paris;;;;;;;").is_none());
+        // Línea truncada
+        assert!(columns.parse("75118_5054_00016;75118_5054;16").is_none());
+    }
+
+    #[test]
+    fn test_header_without_required_column() {
+        let err = BanColumns::from_header("id;numero;nom_voie").unwrap_err();
+        assert!(err.to_string().contains("rep"));
+    }
+}
@@ -0,0 +1,431 @@
+//! Almacenamiento de ficheros (fotos de entrega, firmas, inspecciones)
+//!
+//! `BlobStore` abstrae el backend: un directorio local (por defecto) o un
+//! bucket S3 compatible (AWS, MinIO...). Las claves son rutas relativas como
+//! `companies/{id}/packages/{id}/{uuid}.jpg`.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{Client, Method, StatusCode, Url};
+use ring::digest::{digest, SHA256};
+use ring::hmac;
+
+/// Timeout por petición al backend S3
+const S3_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Backend de almacenamiento de ficheros
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Nombre corto del backend
+    fn name(&self) -> &'static str;
+
+    /// Guardar (o reemplazar) un fichero
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()>;
+
+    /// Leer un fichero; `None` si no existe
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Comprobar si existe un fichero
+    async fn exists(&self, key: &str) -> Result<bool>;
+
+    /// Eliminar un fichero (no falla si no existe)
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Claves relativas con segmentos `[A-Za-z0-9._-]`, sin `..` ni rutas absolutas
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        })
+}
+
+fn check_key(key: &str) -> Result<()> {
+    if is_valid_key(key) {
+        Ok(())
+    } else {
+        Err(anyhow!("Clave de fichero inválida: {}", key))
+    }
+}
+
+/// Configuración del almacenamiento
+#[derive(Debug, Clone)]
+pub struct BlobStoreConfig {
+    /// `local` o `s3`
+    pub backend: String,
+    pub local_path: PathBuf,
+    /// URL del servicio S3, p. ej. `http://localhost:9000` para MinIO
+    pub s3_endpoint: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_region: String,
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+}
+
+impl Default for BlobStoreConfig {
+    fn default() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        Self {
+            backend: var("BLOB_STORE").unwrap_or_else(|| "local".to_string()).to_lowercase(),
+            local_path: PathBuf::from(var("BLOB_STORE_PATH").unwrap_or_else(|| "./data/blobs".to_string())),
+            s3_endpoint: var("S3_ENDPOINT"),
+            s3_bucket: var("S3_BUCKET"),
+            s3_region: var("S3_REGION").unwrap_or_else(|| "us-east-1".to_string()),
+            s3_access_key: var("S3_ACCESS_KEY"),
+            s3_secret_key: var("S3_SECRET_KEY"),
+        }
+    }
+}
+
+/// Construir el backend configurado (S3 incompleto → directorio local)
+pub fn blob_store_from_config(config: BlobStoreConfig, client: Client) -> Arc<dyn BlobStore> {
+    if config.backend == "s3" {
+        match (&config.s3_endpoint, &config.s3_bucket, &config.s3_access_key, &config.s3_secret_key) {
+            (Some(endpoint), Some(bucket), Some(access_key), Some(secret_key)) => {
+                match S3BlobStore::new(client, endpoint, bucket, &config.s3_region, access_key, secret_key) {
+                    Ok(store) => {
+                        log::info!("🗄️ Ficheros en S3: {}/{}", endpoint, bucket);
+                        return Arc::new(store);
+                    }
+                    Err(e) => log::warn!("⚠️ Configuración S3 inválida ({}), se usa el directorio local", e),
+                }
+            }
+            _ => log::warn!("⚠️ BLOB_STORE=s3 sin S3_ENDPOINT/S3_BUCKET/S3_ACCESS_KEY/S3_SECRET_KEY, se usa el directorio local"),
+        }
+    } else if config.backend != "local" {
+        log::warn!("⚠️ BLOB_STORE desconocido: {}, se usa el directorio local", config.backend);
+    }
+
+    log::info!("🗄️ Ficheros en {}", config.local_path.display());
+    Arc::new(LocalBlobStore::new(config.local_path))
+}
+
+/// Ficheros en un directorio local
+#[derive(Debug, Clone)]
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        check_key(key)?;
+        Ok(self.root.join(Path::new(key)))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, _content_type: &str) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Escribir aparte y renombrar: un lector nunca ve un fichero a medias
+        let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4().simple()));
+        tokio::fs::write(&tmp, data).await?;
+        if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(tokio::fs::try_exists(self.path(key)?).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Bucket S3 compatible, con direcciones `endpoint/bucket/clave` (válidas en MinIO)
+/// y firma AWS Signature V4
+#[derive(Debug, Clone)]
+pub struct S3BlobStore {
+    client: Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3BlobStore {
+    pub fn new(
+        client: Client,
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self> {
+        let endpoint = Url::parse(endpoint)?;
+        if endpoint.host_str().is_none() {
+            return Err(anyhow!("S3_ENDPOINT sin host: {}", endpoint));
+        }
+        Ok(Self {
+            client,
+            endpoint,
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+        })
+    }
+
+    /// Ruta firmada: `/bucket/segmentos/codificados`
+    fn object_path(&self, key: &str) -> String {
+        let segments: Vec<String> = key.split('/').map(|s| urlencoding::encode(s).into_owned()).collect();
+        format!("/{}/{}", urlencoding::encode(&self.bucket), segments.join("/"))
+    }
+
+    async fn send(&self, method: Method, key: &str, body: Vec<u8>, content_type: Option<&str>) -> Result<reqwest::Response> {
+        check_key(key)?;
+
+        let path = self.object_path(key);
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex(digest(&SHA256, &body).as_ref());
+
+        let authorization = sign_v4(&SigningRequest {
+            method: method.as_str(),
+            path: &path,
+            host: &host,
+            amz_date: &amz_date,
+            payload_hash: &payload_hash,
+            region: &self.region,
+            access_key: &self.access_key,
+            secret_key: &self.secret_key,
+        });
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .timeout(S3_TIMEOUT)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization)
+            .body(body);
+        if let Some(content_type) = content_type {
+            request = request.header("content-type", content_type);
+        }
+
+        Ok(request.send().await?)
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()> {
+        let response = self.send(Method::PUT, key, data, Some(content_type)).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("S3 PUT {} → {}: {}", key, response.status(), response.text().await.unwrap_or_default()));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let response = self.send(Method::GET, key, Vec::new(), None).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response.bytes().await?.to_vec())),
+            status => Err(anyhow!("S3 GET {} → {}", key, status)),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        let response = self.send(Method::HEAD, key, Vec::new(), None).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(anyhow!("S3 HEAD {} → {}", key, status)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let response = self.send(Method::DELETE, key, Vec::new(), None).await?;
+        if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+            return Err(anyhow!("S3 DELETE {} → {}", key, response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Datos de una petición S3 a firmar (sin query string)
+struct SigningRequest<'a> {
+    method: &'a str,
+    path: &'a str,
+    host: &'a str,
+    /// `YYYYMMDDTHHMMSSZ`
+    amz_date: &'a str,
+    payload_hash: &'a str,
+    region: &'a str,
+    access_key: &'a str,
+    secret_key: &'a str,
+}
+
+/// Cabecera `Authorization` AWS Signature V4 (cabeceras firmadas: host, x-amz-content-sha256, x-amz-date)
+fn sign_v4(request: &SigningRequest) -> String {
+    const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+    let date = &request.amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, request.region);
+
+    let canonical_request = format!(
+        "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        request.method,
+        request.path,
+        request.host,
+        request.payload_hash,
+        request.amz_date,
+        SIGNED_HEADERS,
+        request.payload_hash,
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        request.amz_date,
+        scope,
+        hex(digest(&SHA256, canonical_request.as_bytes()).as_ref()),
+    );
+
+    let key = signing_key(request.secret_key, date, request.region, "s3");
+    let signature = hex(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), string_to_sign.as_bytes()).as_ref());
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        request.access_key, scope, SIGNED_HEADERS, signature
+    )
+}
+
+/// Clave de firma derivada: HMAC encadenado de fecha, región, servicio y `aws4_request`
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let mut key = format!("AWS4{}", secret_key).into_bytes();
+    for part in [date, region, service, "aws4_request"] {
+        key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), part.as_bytes()).as_ref().to_vec();
+    }
+    key
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_validation() {
+        assert!(is_valid_key("companies/abc/packages/def/photo_thumb.jpg"));
+        assert!(!is_valid_key(""));
+        assert!(!is_valid_key("/etc/passwd"));
+        assert!(!is_valid_key("companies/../../etc/passwd"));
+        assert!(!is_valid_key("companies//photo.jpg"));
+        assert!(!is_valid_key("companies/photo 1.jpg"));
+    }
+
+    #[test]
+    fn test_signing_key_aws_example() {
+        // Ejemplo de la documentación de AWS ("Deriving the signing key")
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(hex(&key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+    }
+
+    #[test]
+    fn test_sign_v4_header_shape() {
+        let header = sign_v4(&SigningRequest {
+            method: "PUT",
+            path: "/bucket/companies/a/photo.jpg",
+            host: "localhost:9000",
+            amz_date: "20240101T120000Z",
+            payload_hash: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            region: "us-east-1",
+            access_key: "minioadmin",
+            secret_key: "minioadmin",
+        });
+        assert!(header.starts_with(
+            "AWS4-HMAC-SHA256 Credential=minioadmin/20240101/us-east-1/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature="
+        ));
+        assert_eq!(header.rsplit('=').next().unwrap().len(), 64);
+    }
+
+    #[tokio::test]
+    async fn test_local_store_roundtrip() {
+        let root = std::env::temp_dir().join(format!("blobs_{}", uuid::Uuid::new_v4().simple()));
+        let store = LocalBlobStore::new(&root);
+
+        store.put("companies/a/photo.jpg", b"jpeg".to_vec(), "image/jpeg").await.unwrap();
+        assert!(store.exists("companies/a/photo.jpg").await.unwrap());
+        assert_eq!(store.get("companies/a/photo.jpg").await.unwrap(), Some(b"jpeg".to_vec()));
+        assert_eq!(store.get("companies/a/missing.jpg").await.unwrap(), None);
+        assert!(store.put("../escape.jpg", Vec::new(), "image/jpeg").await.is_err());
+
+        store.delete("companies/a/photo.jpg").await.unwrap();
+        store.delete("companies/a/photo.jpg").await.unwrap();
+        assert!(!store.exists("companies/a/photo.jpg").await.unwrap());
+
+        let _ = tokio::fs::remove_dir_all(&root).await;
+    }
+
+    /// Contra un MinIO local: `TEST_S3_ENDPOINT=http://localhost:9000 TEST_S3_BUCKET=test`
+    /// (credenciales `TEST_S3_ACCESS_KEY`/`TEST_S3_SECRET_KEY`, por defecto las de MinIO)
+    #[tokio::test]
+    async fn test_s3_store_roundtrip() {
+        let (Ok(endpoint), Ok(bucket)) = (std::env::var("TEST_S3_ENDPOINT"), std::env::var("TEST_S3_BUCKET")) else {
+            eprintln!("⏭️ TEST_S3_ENDPOINT/TEST_S3_BUCKET no definidas, test de S3 omitido");
+            return;
+        };
+        let access_key = std::env::var("TEST_S3_ACCESS_KEY").unwrap_or_else(|_| "minioadmin".to_string());
+        let secret_key = std::env::var("TEST_S3_SECRET_KEY").unwrap_or_else(|_| "minioadmin".to_string());
+        let store = S3BlobStore::new(Client::new(), &endpoint, &bucket, "us-east-1", &access_key, &secret_key).unwrap();
+
+        let key = format!("tests/{}.png", uuid::Uuid::new_v4());
+        store.put(&key, b"png".to_vec(), "image/png").await.unwrap();
+        assert!(store.exists(&key).await.unwrap());
+        assert_eq!(store.get(&key).await.unwrap(), Some(b"png".to_vec()));
+
+        store.delete(&key).await.unwrap();
+        assert!(!store.exists(&key).await.unwrap());
+        assert_eq!(store.get(&key).await.unwrap(), None);
+    }
+}
@@ -3,6 +3,8 @@
 //! Este módulo contiene los servicios mínimos necesarios para la API web de Colis Privé.
 
 use serde::{Deserialize, Serialize};
+use crate::models::colis_prive_web_models::{WebArticle, WebTourneeData};
+use crate::services::validation_jobs::ValidationJobInfo;
 
 /// Request de autenticación para Colis Privé
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +38,19 @@ pub struct GetTourneeRequest {
 pub struct GetPackagesRequest {
     pub matricule: String,
     pub date: Option<String>, // Campo opcional para fecha
+    /// Société del driver: obligatoria si su username existe en varias
+    #[serde(default)]
+    pub societe: Option<String>,
+}
+
+/// Request para invalidar la tournée cacheada de un driver
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvalidateTourneeRequest {
+    pub matricule: String,
+    pub date: Option<String>, // Hoy si no se especifica
+    /// Société del driver: obligatoria si su username existe en varias
+    #[serde(default)]
+    pub societe: Option<String>,
 }
 
 /// Datos de un paquete
@@ -72,16 +87,196 @@ pub struct GetPackagesResponse {
     pub packages: Option<Vec<PackageData>>,
     pub error: Option<ErrorData>,
     pub address_validation: Option<AddressValidationSummary>,
+    pub unmapped_packages: Option<Vec<UnmappedPackage>>,
+    /// Tarea de validación de direcciones en segundo plano
+    #[serde(default)]
+    pub validation_job: Option<ValidationJobInfo>,
 }
 
 /// Resumen de validación de direcciones
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddressValidationSummary {
     pub total_packages: usize,
+    #[serde(default)]
+    pub driver_confirmed: usize,
     pub auto_validated: usize,
     pub cleaned_auto: usize,
     pub completed_auto: usize,
     pub partial_found: usize,
     pub requires_manual: usize,
+    #[serde(default)]
+    pub low_plausibility: usize,
     pub warnings: Vec<String>,
 }
+
+/// Artículo de `LstLieuArticle` que no se pudo convertir en `PackageData`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnmappedPackage {
+    pub index: usize,
+    pub id_article: Option<String>,
+    pub reason: String,
+}
+
+/// Resultado de mapear los artículos de una tournée
+#[derive(Debug, Clone, Default)]
+pub struct TourneePackagesMapping {
+    pub packages: Vec<PackageData>,
+    pub unmapped: Vec<UnmappedPackage>,
+    pub skipped_non_colis: usize,
+}
+
+impl TryFrom<WebArticle> for PackageData {
+    type Error = String;
+
+    fn try_from(article: WebArticle) -> Result<Self, Self::Error> {
+        let address = article.full_address()
+            .ok_or_else(|| "LibelleVoieOrigineDestinataire ausente".to_string())?;
+        let id = article.id_article
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| "idArticle ausente".to_string())?;
+
+        Ok(PackageData {
+            tracking_number: article.ref_externe_article.unwrap_or_else(|| id.clone()),
+            id,
+            recipient_name: article.nom_destinataire.unwrap_or_default(),
+            address,
+            status: article.code_statut_article.unwrap_or_default(),
+            instructions: article.preference_livraison.unwrap_or_default(),
+            phone: article.telephone_mobile_destinataire.unwrap_or_default(),
+            priority: article.priorite.unwrap_or_else(|| "0".to_string()),
+            latitude: None,
+            longitude: None,
+            formatted_address: None,
+            validation_method: None,
+            validation_confidence: None,
+            validation_warnings: None,
+        })
+    }
+}
+
+/// Extraer los paquetes COLIS de una tournée, reportando los que no se pudieron mapear
+pub fn map_tournee_packages(tournee: &WebTourneeData) -> TourneePackagesMapping {
+    let mut mapping = TourneePackagesMapping::default();
+
+    for (index, raw) in tournee.lst_lieu_article.iter().enumerate() {
+        let raw_id = raw.get("idArticle").and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        let article: WebArticle = match serde_json::from_value(raw.clone()) {
+            Ok(article) => article,
+            Err(e) => {
+                mapping.unmapped.push(UnmappedPackage {
+                    index,
+                    id_article: raw_id,
+                    reason: format!("Artículo con formato inválido: {}", e),
+                });
+                continue;
+            }
+        };
+
+        match article.metier.as_deref() {
+            None => {
+                mapping.unmapped.push(UnmappedPackage {
+                    index,
+                    id_article: article.id_article.clone(),
+                    reason: "metier ausente".to_string(),
+                });
+                continue;
+            }
+            Some(_) if !article.is_colis() => {
+                mapping.skipped_non_colis += 1;
+                continue;
+            }
+            Some(_) => {}
+        }
+
+        let id_article = article.id_article.clone();
+        match PackageData::try_from(article) {
+            Ok(package) => mapping.packages.push(package),
+            Err(reason) => mapping.unmapped.push(UnmappedPackage { index, id_article, reason }),
+        }
+    }
+
+    mapping
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tournee(articles: serde_json::Value) -> WebTourneeData {
+        serde_json::from_value(json!({
+            "InfosTournee": { "codeTourneeDistribution": "A187518" },
+            "LstLieuArticle": articles
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_map_complete_article() {
+        let data = tournee(json!([{
+            "idArticle": "123",
+            "refExterneArticle": "CP123FR",
+            "metier": "COLIS",
+            "codeStatutArticle": "EN_COURS",
+            "PreferenceLivraison": "Digicode 1234",
+            "priorite": 2,
+            "nomDestinataire": "DUPONT",
+            "telephoneMobileDestinataire": "0600000000",
+            "LibelleVoieOrigineDestinataire": "12 RUE JEAN COTTIN",
+            "codePostalOrigineDestinataire": "75018",
+            "LibelleLocaliteOrigineDestinataire": "PARIS"
+        }]));
+
+        let mapping = map_tournee_packages(&data);
+        assert_eq!(mapping.packages.len(), 1);
+        assert!(mapping.unmapped.is_empty());
+
+        let package = &mapping.packages[0];
+        assert_eq!(package.tracking_number, "CP123FR");
+        assert_eq!(package.address, "12 RUE JEAN COTTIN, 75018 PARIS");
+        assert_eq!(package.priority, "2");
+    }
+
+    #[test]
+    fn test_missing_optional_fields_are_tolerated() {
+        // Antes, un teléfono o una preferencia ausente descartaba el paquete
+        let data = tournee(json!([{
+            "idArticle": "456",
+            "metier": "COLIS",
+            "LibelleVoieOrigineDestinataire": "3 IMP. DU CURE",
+            "codePostalOrigineDestinataire": 75018,
+            "telephoneMobileDestinataire": null
+        }]));
+
+        let mapping = map_tournee_packages(&data);
+        assert_eq!(mapping.packages.len(), 1);
+        assert_eq!(mapping.packages[0].tracking_number, "456");
+        assert_eq!(mapping.packages[0].address, "3 IMP. DU CURE, 75018");
+        assert_eq!(mapping.packages[0].phone, "");
+    }
+
+    #[test]
+    fn test_unmapped_articles_are_reported() {
+        let data = tournee(json!([
+            { "idArticle": "1", "metier": "COLIS" },
+            { "idArticle": "2", "LibelleVoieOrigineDestinataire": "1 RUE X" },
+            { "idArticle": "3", "metier": "COLLECTE", "LibelleVoieOrigineDestinataire": "1 RUE X" },
+            "no-es-un-objeto"
+        ]));
+
+        let mapping = map_tournee_packages(&data);
+        assert!(mapping.packages.is_empty());
+        assert_eq!(mapping.skipped_non_colis, 1);
+        assert_eq!(mapping.unmapped.len(), 3);
+        assert_eq!(mapping.unmapped[0].id_article.as_deref(), Some("1"));
+        assert_eq!(mapping.unmapped[1].reason, "metier ausente");
+        assert_eq!(mapping.unmapped[2].index, 3);
+    }
+
+    #[test]
+    fn test_null_article_list() {
+        let data: WebTourneeData = serde_json::from_value(json!({ "LstLieuArticle": null })).unwrap();
+        assert!(map_tournee_packages(&data).packages.is_empty());
+    }
+}
@@ -0,0 +1,484 @@
+//! Bóveda de credenciales de integraciones
+//!
+//! Guarda las credenciales de los transportistas por empresa y por driver en
+//! `api_integrations.api_credentials`, cifradas con AES-256-GCM usando una
+//! clave del servidor (`CREDENTIALS_ENCRYPTION_KEY`).
+//!
+//! Formato del JSONB:
+//! `{"societe": "...", "drivers": {"<username>": {"nonce", "ciphertext", "rotated_at"}}}`
+
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Utc};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::services::tournee_import_service::COLIS_PRIVE_PROVIDER;
+use crate::utils::errors::AppError;
+
+/// Errores de la bóveda de credenciales
+#[derive(Error, Debug)]
+pub enum VaultError {
+    #[error("CREDENTIALS_ENCRYPTION_KEY no configurada")]
+    KeyNotConfigured,
+
+    #[error("Clave de cifrado inválida: debe ser base64 de 32 bytes")]
+    InvalidKey,
+
+    #[error("No se pudo cifrar/descifrar la credencial")]
+    Crypto,
+
+    #[error("Credencial almacenada con formato inválido: {0}")]
+    Malformed(String),
+
+    #[error("El driver {0} tiene credenciales en varias sociétés: indica la société")]
+    AmbiguousDriver(String),
+
+    #[error("La société {0} tiene credenciales del driver en varias empresas")]
+    AmbiguousSociete(String),
+
+    #[error("Error de base de datos: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<VaultError> for AppError {
+    fn from(e: VaultError) -> Self {
+        match e {
+            VaultError::Database(e) => AppError::Database(e),
+            VaultError::KeyNotConfigured => AppError::ServiceUnavailable(e.to_string()),
+            VaultError::AmbiguousDriver(_) | VaultError::AmbiguousSociete(_) => AppError::Conflict(e.to_string()),
+            other => AppError::Internal(other.to_string()),
+        }
+    }
+}
+
+/// Secreto cifrado tal como se guarda en el JSONB
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedSecret {
+    pub nonce: String,
+    pub ciphertext: String,
+    pub rotated_at: DateTime<Utc>,
+}
+
+/// Cifrador AES-256-GCM con la clave del servidor
+pub struct CredentialCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl CredentialCipher {
+    /// Crear el cifrador a partir de una clave de 32 bytes en base64
+    pub fn from_base64(key_b64: &str) -> Result<Self, VaultError> {
+        let bytes = STANDARD.decode(key_b64.trim()).map_err(|_| VaultError::InvalidKey)?;
+        let unbound = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| VaultError::InvalidKey)?;
+
+        Ok(Self {
+            key: LessSafeKey::new(unbound),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Cifrar `plaintext`; `aad` liga el secreto a su empresa/driver
+    pub fn encrypt(&self, plaintext: &str, aad: &str) -> Result<EncryptedSecret, VaultError> {
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce_bytes).map_err(|_| VaultError::Crypto)?;
+
+        let mut in_out = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce_bytes),
+                Aad::from(aad.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| VaultError::Crypto)?;
+
+        Ok(EncryptedSecret {
+            nonce: STANDARD.encode(nonce_bytes),
+            ciphertext: STANDARD.encode(in_out),
+            rotated_at: Utc::now(),
+        })
+    }
+
+    /// Descifrar un secreto cifrado con el mismo `aad`
+    pub fn decrypt(&self, secret: &EncryptedSecret, aad: &str) -> Result<String, VaultError> {
+        let nonce_bytes: [u8; NONCE_LEN] = STANDARD
+            .decode(&secret.nonce)
+            .ok()
+            .and_then(|n| n.try_into().ok())
+            .ok_or_else(|| VaultError::Malformed("nonce".to_string()))?;
+        let mut in_out = STANDARD
+            .decode(&secret.ciphertext)
+            .map_err(|_| VaultError::Malformed("ciphertext".to_string()))?;
+
+        let plaintext = self
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce_bytes),
+                Aad::from(aad.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| VaultError::Crypto)?;
+
+        String::from_utf8(plaintext.to_vec()).map_err(|_| VaultError::Malformed("utf-8".to_string()))
+    }
+}
+
+/// Credenciales de un driver sin el secreto (para listados de admin)
+#[derive(Debug, Clone, Serialize)]
+pub struct DriverCredentialInfo {
+    pub username: String,
+    pub rotated_at: DateTime<Utc>,
+}
+
+/// Credenciales descifradas de un driver
+#[derive(Debug, Clone)]
+pub struct DriverCredentials {
+    pub societe: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// Bóveda de credenciales de Colis Privé respaldada por `api_integrations`
+#[derive(Clone)]
+pub struct CredentialVault {
+    pool: PgPool,
+    cipher: Option<Arc<CredentialCipher>>,
+}
+
+impl CredentialVault {
+    pub fn new(pool: PgPool, cipher: Option<CredentialCipher>) -> Self {
+        Self {
+            pool,
+            cipher: cipher.map(Arc::new),
+        }
+    }
+
+    /// Crear la bóveda leyendo la clave de `CREDENTIALS_ENCRYPTION_KEY`
+    pub fn from_env(pool: PgPool) -> Self {
+        let cipher = match std::env::var("CREDENTIALS_ENCRYPTION_KEY") {
+            Ok(key) => match CredentialCipher::from_base64(&key) {
+                Ok(cipher) => Some(cipher),
+                Err(e) => {
+                    log::error!("❌ {}", e);
+                    None
+                }
+            },
+            Err(_) => {
+                log::warn!("⚠️ CREDENTIALS_ENCRYPTION_KEY no configurada, bóveda de credenciales deshabilitada");
+                None
+            }
+        };
+
+        Self::new(pool, cipher)
+    }
+
+    fn cipher(&self) -> Result<&CredentialCipher, VaultError> {
+        self.cipher.as_deref().ok_or(VaultError::KeyNotConfigured)
+    }
+
+    /// Société de la empresa que tiene credenciales para este driver.
+    /// Error si el username está registrado en varias sociétés.
+    pub async fn find_societe_for_driver(&self, username: &str) -> Result<Option<String>, VaultError> {
+        let societes: Vec<Option<String>> = sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT api_credentials->>'societe'
+            FROM api_integrations
+            WHERE provider_name = $1
+              AND api_credentials->'drivers' ? $2
+              AND deleted_at IS NULL
+            LIMIT 2
+            "#,
+        )
+        .bind(COLIS_PRIVE_PROVIDER)
+        .bind(username)
+        .fetch_all(&self.pool)
+        .await?;
+
+        match societes.as_slice() {
+            [] => Ok(None),
+            [societe] => Ok(societe.clone()),
+            _ => Err(VaultError::AmbiguousDriver(username.to_string())),
+        }
+    }
+
+    /// Credenciales descifradas de un driver, buscando por société.
+    /// Error si varias empresas guardan credenciales del driver en esa société.
+    pub async fn get_by_societe(
+        &self,
+        societe: &str,
+        username: &str,
+    ) -> Result<Option<DriverCredentials>, VaultError> {
+        let mut rows = sqlx::query(
+            r#"
+            SELECT company_id, api_credentials->>'societe' AS societe, api_credentials->'drivers'->$3 AS secret
+            FROM api_integrations
+            WHERE provider_name = $1
+              AND api_credentials->>'societe' = $2
+              AND api_credentials->'drivers' ? $3
+              AND deleted_at IS NULL
+            LIMIT 2
+            "#,
+        )
+        .bind(COLIS_PRIVE_PROVIDER)
+        .bind(societe)
+        .bind(username)
+        .fetch_all(&self.pool)
+        .await?;
+
+        if rows.len() > 1 {
+            return Err(VaultError::AmbiguousSociete(societe.to_string()));
+        }
+        self.decrypt_row(rows.pop(), username)
+    }
+
+    /// Société de una empresa para uno de sus drivers (con credenciales en la
+    /// bóveda o usuario driver de la empresa). `None` si la société indicada no
+    /// es de la empresa o el driver no pertenece a ella.
+    pub async fn find_company_societe(
+        &self,
+        company_id: Uuid,
+        username: &str,
+        requested: Option<&str>,
+    ) -> Result<Option<String>, VaultError> {
+        let societes: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT i.api_credentials->>'societe'
+            FROM api_integrations i
+            WHERE i.provider_name = $1
+              AND i.company_id = $2
+              AND i.deleted_at IS NULL
+              AND i.api_credentials->>'societe' IS NOT NULL
+              AND ($4::text IS NULL OR i.api_credentials->>'societe' = $4)
+              AND (
+                  i.api_credentials->'drivers' ? $3
+                  OR EXISTS (
+                      SELECT 1 FROM users u
+                      WHERE u.company_id = i.company_id
+                        AND u.user_type = 'driver'
+                        AND (u.username = $3 OR u.tournee_number = $3)
+                        AND u.deleted_at IS NULL
+                  )
+              )
+            LIMIT 2
+            "#,
+        )
+        .bind(COLIS_PRIVE_PROVIDER)
+        .bind(company_id)
+        .bind(username)
+        .bind(requested)
+        .fetch_all(&self.pool)
+        .await?;
+
+        match societes.as_slice() {
+            [] => Ok(None),
+            [societe] => Ok(Some(societe.clone())),
+            _ => Err(VaultError::AmbiguousDriver(username.to_string())),
+        }
+    }
+
+    /// Credenciales descifradas de un driver dentro de una empresa
+    pub async fn get_by_company(
+        &self,
+        company_id: Uuid,
+        username: &str,
+    ) -> Result<Option<DriverCredentials>, VaultError> {
+        let row = sqlx::query(
+            r#"
+            SELECT company_id, api_credentials->>'societe' AS societe, api_credentials->'drivers'->$3 AS secret
+            FROM api_integrations
+            WHERE provider_name = $1
+              AND company_id = $2
+              AND deleted_at IS NULL
+            "#,
+        )
+        .bind(COLIS_PRIVE_PROVIDER)
+        .bind(company_id)
+        .bind(username)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        self.decrypt_row(row, username)
+    }
+
+    /// Drivers con credenciales almacenadas en la empresa (sin secretos)
+    pub async fn list_drivers(&self, company_id: Uuid) -> Result<Vec<DriverCredentialInfo>, VaultError> {
+        let drivers: Option<serde_json::Value> = sqlx::query_scalar(
+            r#"
+            SELECT api_credentials->'drivers'
+            FROM api_integrations
+            WHERE provider_name = $1 AND company_id = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(COLIS_PRIVE_PROVIDER)
+        .bind(company_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .flatten();
+
+        let drivers: std::collections::BTreeMap<String, EncryptedSecret> = match drivers {
+            Some(value) => serde_json::from_value(value).map_err(|e| VaultError::Malformed(e.to_string()))?,
+            None => Default::default(),
+        };
+
+        Ok(drivers
+            .into_iter()
+            .map(|(username, secret)| DriverCredentialInfo { username, rotated_at: secret.rotated_at })
+            .collect())
+    }
+
+    /// Guardar (o rotar) la contraseña de un driver
+    pub async fn store(
+        &self,
+        company_id: Uuid,
+        societe: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<DriverCredentialInfo, VaultError> {
+        let secret = self.cipher()?.encrypt(password, &aad(company_id, username))?;
+        let rotated_at = secret.rotated_at;
+        let secret = serde_json::to_value(secret).map_err(|e| VaultError::Malformed(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO api_integrations (company_id, provider_name, provider_display_name, api_credentials)
+            VALUES ($1, $2, 'Colis Privé', jsonb_build_object('societe', $3::text, 'drivers', jsonb_build_object($4::text, $5::jsonb)))
+            ON CONFLICT (company_id, provider_name) DO UPDATE SET
+                api_credentials = api_integrations.api_credentials || jsonb_build_object(
+                    'societe', $3::text,
+                    'drivers', COALESCE(api_integrations.api_credentials->'drivers', '{}'::jsonb)
+                        || jsonb_build_object($4::text, $5::jsonb)
+                ),
+                deleted_at = NULL
+            "#,
+        )
+        .bind(company_id)
+        .bind(COLIS_PRIVE_PROVIDER)
+        .bind(societe)
+        .bind(username)
+        .bind(secret)
+        .execute(&self.pool)
+        .await?;
+
+        log::info!("🔐 Credenciales de {}:{} rotadas", societe, username);
+
+        Ok(DriverCredentialInfo {
+            username: username.to_string(),
+            rotated_at,
+        })
+    }
+
+    fn decrypt_row(
+        &self,
+        row: Option<sqlx::postgres::PgRow>,
+        username: &str,
+    ) -> Result<Option<DriverCredentials>, VaultError> {
+        let Some(row) = row else { return Ok(None) };
+        let Some(secret) = row.try_get::<Option<serde_json::Value>, _>("secret")? else {
+            return Ok(None);
+        };
+
+        let company_id: Uuid = row.try_get("company_id")?;
+        let societe: Option<String> = row.try_get("societe")?;
+        let secret: EncryptedSecret =
+            serde_json::from_value(secret).map_err(|e| VaultError::Malformed(e.to_string()))?;
+        let password = self.cipher()?.decrypt(&secret, &aad(company_id, username))?;
+
+        Ok(Some(DriverCredentials {
+            societe: societe.unwrap_or_default(),
+            username: username.to_string(),
+            password,
+        }))
+    }
+}
+
+/// Datos asociados del AEAD: un secreto no se puede mover a otro driver/empresa
+fn aad(company_id: Uuid, username: &str) -> String {
+    format!("{}:{}:{}", COLIS_PRIVE_PROVIDER, company_id, username)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::test_support::{register, TestDb};
+
+    const TEST_KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let cipher = CredentialCipher::from_base64(TEST_KEY).unwrap();
+        let secret = cipher.encrypt("s3cr3t", "colis_prive:c1:A187518").unwrap();
+
+        assert!(!secret.ciphertext.contains("s3cr3t"));
+        assert_eq!(cipher.decrypt(&secret, "colis_prive:c1:A187518").unwrap(), "s3cr3t");
+    }
+
+    #[test]
+    fn test_secret_is_bound_to_driver() {
+        let cipher = CredentialCipher::from_base64(TEST_KEY).unwrap();
+        let secret = cipher.encrypt("s3cr3t", "colis_prive:c1:A187518").unwrap();
+
+        assert!(matches!(cipher.decrypt(&secret, "colis_prive:c1:B000000"), Err(VaultError::Crypto)));
+    }
+
+    #[test]
+    fn test_wrong_key_fails() {
+        let cipher = CredentialCipher::from_base64(TEST_KEY).unwrap();
+        let other = CredentialCipher::from_base64(&STANDARD.encode([7u8; 32])).unwrap();
+        let secret = cipher.encrypt("s3cr3t", "aad").unwrap();
+
+        assert!(matches!(other.decrypt(&secret, "aad"), Err(VaultError::Crypto)));
+    }
+
+    #[test]
+    fn test_invalid_key_is_rejected() {
+        assert!(matches!(CredentialCipher::from_base64("corta"), Err(VaultError::InvalidKey)));
+        assert!(matches!(CredentialCipher::from_base64(&STANDARD.encode([1u8; 16])), Err(VaultError::InvalidKey)));
+    }
+
+    #[tokio::test]
+    async fn test_driver_societe_lookup_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (_, eta) = register(&app, "Eta").await;
+        let (_, theta) = register(&app, "Theta").await;
+
+        let cipher = CredentialCipher::from_base64(TEST_KEY).unwrap();
+        let vault = CredentialVault::new(db.pool.clone(), Some(cipher));
+
+        assert_eq!(vault.find_societe_for_driver("A187518").await.unwrap(), None);
+        vault.store(Uuid::parse_str(&eta).unwrap(), "PCP0010699", "A187518", "secret").await.unwrap();
+        assert_eq!(vault.find_societe_for_driver("A187518").await.unwrap().as_deref(), Some("PCP0010699"));
+
+        // El mismo username en otra empresa: no se elige una société al azar
+        vault.store(Uuid::parse_str(&theta).unwrap(), "PCP0020001", "A187518", "secret").await.unwrap();
+        assert!(matches!(
+            vault.find_societe_for_driver("A187518").await,
+            Err(VaultError::AmbiguousDriver(_))
+        ));
+
+        // Con la empresa del usuario solo se ven su société y sus drivers
+        let (eta, theta) = (Uuid::parse_str(&eta).unwrap(), Uuid::parse_str(&theta).unwrap());
+        assert_eq!(vault.find_company_societe(eta, "A187518", None).await.unwrap().as_deref(), Some("PCP0010699"));
+        assert_eq!(vault.find_company_societe(eta, "A187518", Some("PCP0020001")).await.unwrap(), None);
+        assert_eq!(vault.find_company_societe(eta, "B000001", None).await.unwrap(), None);
+        assert_eq!(vault.find_company_societe(theta, "A187518", None).await.unwrap().as_deref(), Some("PCP0020001"));
+
+        // Dos empresas con credenciales del driver en la misma société
+        assert_eq!(vault.get_by_societe("PCP0010699", "A187518").await.unwrap().unwrap().password, "secret");
+        vault.store(theta, "PCP0010699", "A187518", "otro").await.unwrap();
+        assert!(matches!(
+            vault.get_by_societe("PCP0010699", "A187518").await,
+            Err(VaultError::AmbiguousSociete(_))
+        ));
+
+        db.drop().await;
+    }
+}
@@ -0,0 +1,708 @@
+//! Matrices de distancia y duración
+//!
+//! `DistanceMatrixProvider` abstrae el origen de las distancias por carretera:
+//! haversine con factor de desvío (sin red), OSRM (p. ej. un contenedor local)
+//! y Mapbox Matrix. `DistanceMatrixService` cachea cada par de coordenadas en
+//! Redis y vuelve a haversine si el proveedor falla.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::cache::redis_client::matrix_pair_key;
+use crate::cache::{CacheOperations, RedisClient};
+use crate::services::route_optimizer::{DistanceMetric, GeoPoint, Haversine};
+
+/// Errores de los proveedores de matrices
+#[derive(Debug, Error)]
+pub enum DistanceMatrixError {
+    #[error("Error HTTP con {provider}: {source}")]
+    Http {
+        provider: &'static str,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("Respuesta inválida de {provider}: {message}")]
+    InvalidResponse { provider: &'static str, message: String },
+}
+
+/// Distancias (km) y duraciones (min) entre todos los puntos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DistanceMatrix {
+    pub provider: String,
+    pub distances_km: Vec<Vec<f64>>,
+    pub durations_minutes: Vec<Vec<f64>>,
+}
+
+impl DistanceMatrix {
+    fn empty(provider: &str, n: usize) -> Self {
+        Self {
+            provider: provider.to_string(),
+            distances_km: vec![vec![0.0; n]; n],
+            durations_minutes: vec![vec![0.0; n]; n],
+        }
+    }
+}
+
+/// Submatriz orígenes × destinos
+#[derive(Debug, Clone, Default)]
+pub struct MatrixTable {
+    pub distances_km: Vec<Vec<f64>>,
+    pub durations_minutes: Vec<Vec<f64>>,
+}
+
+/// Proveedor de distancias por carretera
+#[async_trait]
+pub trait DistanceMatrixProvider: Send + Sync {
+    /// Nombre corto, también usado en las claves de cache
+    fn name(&self) -> &'static str;
+
+    /// Máximo de coordenadas (orígenes + destinos) por petición
+    fn max_coordinates(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Distancias y duraciones de cada origen a cada destino
+    async fn table(&self, sources: &[GeoPoint], destinations: &[GeoPoint]) -> Result<MatrixTable, DistanceMatrixError>;
+}
+
+/// Matriz completa, en bloques si el proveedor limita las coordenadas por petición
+pub async fn full_matrix(
+    provider: &dyn DistanceMatrixProvider,
+    points: &[GeoPoint],
+) -> Result<DistanceMatrix, DistanceMatrixError> {
+    let n = points.len();
+    let mut matrix = DistanceMatrix::empty(provider.name(), n);
+    if n < 2 {
+        return Ok(matrix);
+    }
+
+    let block = (provider.max_coordinates() / 2).clamp(1, n);
+    for row_start in (0..n).step_by(block) {
+        let rows = row_start..(row_start + block).min(n);
+        for col_start in (0..n).step_by(block) {
+            let cols = col_start..(col_start + block).min(n);
+            let table = provider.table(&points[rows.clone()], &points[cols.clone()]).await?;
+
+            if table.distances_km.len() != rows.len() || table.distances_km.iter().any(|row| row.len() != cols.len()) {
+                return Err(DistanceMatrixError::InvalidResponse {
+                    provider: provider.name(),
+                    message: "dimensiones de la tabla inesperadas".to_string(),
+                });
+            }
+
+            for (i, row) in rows.clone().enumerate() {
+                for (j, col) in cols.clone().enumerate() {
+                    if row != col {
+                        matrix.distances_km[row][col] = table.distances_km[i][j];
+                        matrix.durations_minutes[row][col] = table.durations_minutes[i][j];
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(matrix)
+}
+
+/// Haversine × factor de desvío, a velocidad media constante
+#[derive(Debug, Clone)]
+pub struct HaversineMatrix {
+    pub detour_factor: f64,
+    pub average_speed_kmh: f64,
+}
+
+impl Default for HaversineMatrix {
+    fn default() -> Self {
+        Self {
+            detour_factor: 1.3, // calles vs línea recta en ciudad
+            average_speed_kmh: 20.0,
+        }
+    }
+}
+
+impl HaversineMatrix {
+    fn estimate(&self, from: GeoPoint, to: GeoPoint) -> (f64, f64) {
+        let km = Haversine.distance_km(from, to) * self.detour_factor;
+        (km, km / self.average_speed_kmh * 60.0)
+    }
+}
+
+#[async_trait]
+impl DistanceMatrixProvider for HaversineMatrix {
+    fn name(&self) -> &'static str {
+        "haversine"
+    }
+
+    async fn table(&self, sources: &[GeoPoint], destinations: &[GeoPoint]) -> Result<MatrixTable, DistanceMatrixError> {
+        let mut table = MatrixTable::default();
+        for &from in sources {
+            let (distances, durations) = destinations.iter().map(|&to| self.estimate(from, to)).unzip();
+            table.distances_km.push(distances);
+            table.durations_minutes.push(durations);
+        }
+        Ok(table)
+    }
+}
+
+/// Respuesta `table` de OSRM (Mapbox Matrix usa el mismo formato)
+#[derive(Debug, Deserialize)]
+struct TableResponse {
+    code: String,
+    message: Option<String>,
+    durations: Option<Vec<Vec<Option<f64>>>>,
+    distances: Option<Vec<Vec<Option<f64>>>>,
+}
+
+/// Coordenadas `lon,lat;...` y parámetros `sources`/`destinations`
+fn table_query(sources: &[GeoPoint], destinations: &[GeoPoint]) -> (String, String, String) {
+    let coordinates = sources
+        .iter()
+        .chain(destinations)
+        .map(|p| format!("{:.6},{:.6}", p.longitude, p.latitude))
+        .collect::<Vec<_>>()
+        .join(";");
+    let join = |range: std::ops::Range<usize>| range.map(|i| i.to_string()).collect::<Vec<_>>().join(";");
+    let total = sources.len() + destinations.len();
+
+    (coordinates, join(0..sources.len()), join(sources.len()..total))
+}
+
+/// Pedir y convertir una tabla; los pares sin ruta se estiman con haversine
+async fn fetch_table(
+    provider: &'static str,
+    request: reqwest::RequestBuilder,
+    sources: &[GeoPoint],
+    destinations: &[GeoPoint],
+) -> Result<MatrixTable, DistanceMatrixError> {
+    let http = |source| DistanceMatrixError::Http { provider, source };
+    let invalid = |message: String| DistanceMatrixError::InvalidResponse { provider, message };
+
+    let response = request.send().await.map_err(http)?;
+    let status = response.status();
+    let body: TableResponse = response.json().await.map_err(http)?;
+
+    if !status.is_success() || body.code != "Ok" {
+        return Err(invalid(format!("{} {} {}", status, body.code, body.message.unwrap_or_default())));
+    }
+
+    let (Some(durations), Some(distances)) = (body.durations, body.distances) else {
+        return Err(invalid("faltan durations o distances".to_string()));
+    };
+
+    let fallback = HaversineMatrix::default();
+    let mut table = MatrixTable::default();
+    for (i, (distance_row, duration_row)) in distances.into_iter().zip(durations).enumerate() {
+        let mut km_row = Vec::with_capacity(distance_row.len());
+        let mut min_row = Vec::with_capacity(distance_row.len());
+        for (j, (meters, seconds)) in distance_row.into_iter().zip(duration_row).enumerate() {
+            match (meters, seconds, sources.get(i), destinations.get(j)) {
+                (Some(meters), Some(seconds), _, _) => {
+                    km_row.push(meters / 1000.0);
+                    min_row.push(seconds / 60.0);
+                }
+                (_, _, Some(&from), Some(&to)) => {
+                    warn!("⚠️ {} sin ruta entre {:?} y {:?}, estimando con haversine", provider, from, to);
+                    let (km, minutes) = fallback.estimate(from, to);
+                    km_row.push(km);
+                    min_row.push(minutes);
+                }
+                _ => return Err(invalid("tabla mayor que la pedida".to_string())),
+            }
+        }
+        table.distances_km.push(km_row);
+        table.durations_minutes.push(min_row);
+    }
+
+    Ok(table)
+}
+
+/// Adaptador OSRM (`/table/v1`), p. ej. `docker run osrm/osrm-backend osrm-routed`
+#[derive(Debug, Clone)]
+pub struct OsrmMatrix {
+    client: Client,
+    base_url: String,
+    profile: String,
+}
+
+impl OsrmMatrix {
+    pub fn new(client: Client, base_url: impl Into<String>, profile: impl Into<String>) -> Self {
+        Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            profile: profile.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl DistanceMatrixProvider for OsrmMatrix {
+    fn name(&self) -> &'static str {
+        "osrm"
+    }
+
+    /// Límite por defecto de `osrm-routed --max-table-size`
+    fn max_coordinates(&self) -> usize {
+        100
+    }
+
+    async fn table(&self, sources: &[GeoPoint], destinations: &[GeoPoint]) -> Result<MatrixTable, DistanceMatrixError> {
+        let (coordinates, sources_param, destinations_param) = table_query(sources, destinations);
+        let url = format!("{}/table/v1/{}/{}", self.base_url, self.profile, coordinates);
+
+        let request = self.client
+            .get(url)
+            .query(&[
+                ("sources", sources_param.as_str()),
+                ("destinations", destinations_param.as_str()),
+                ("annotations", "duration,distance"),
+            ])
+            .timeout(Duration::from_secs(30));
+
+        fetch_table(self.name(), request, sources, destinations).await
+    }
+}
+
+/// Adaptador Mapbox Matrix (`/directions-matrix/v1`)
+#[derive(Debug, Clone)]
+pub struct MapboxMatrix {
+    client: Client,
+    access_token: String,
+    base_url: String,
+    profile: String,
+}
+
+impl MapboxMatrix {
+    pub fn new(client: Client, base_url: impl Into<String>, access_token: impl Into<String>) -> Self {
+        Self {
+            client,
+            access_token: access_token.into(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            profile: "mapbox/driving".to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl DistanceMatrixProvider for MapboxMatrix {
+    fn name(&self) -> &'static str {
+        "mapbox"
+    }
+
+    /// Límite de coordenadas de Mapbox Matrix por petición
+    fn max_coordinates(&self) -> usize {
+        25
+    }
+
+    async fn table(&self, sources: &[GeoPoint], destinations: &[GeoPoint]) -> Result<MatrixTable, DistanceMatrixError> {
+        let (coordinates, sources_param, destinations_param) = table_query(sources, destinations);
+        let url = format!("{}/directions-matrix/v1/{}/{}", self.base_url, self.profile, coordinates);
+
+        let request = self.client
+            .get(url)
+            .query(&[
+                ("sources", sources_param.as_str()),
+                ("destinations", destinations_param.as_str()),
+                ("annotations", "duration,distance"),
+                ("access_token", self.access_token.as_str()),
+            ])
+            .timeout(Duration::from_secs(30));
+
+        fetch_table(self.name(), request, sources, destinations).await
+    }
+}
+
+/// Configuración del servicio de matrices
+#[derive(Debug, Clone)]
+pub struct DistanceMatrixConfig {
+    /// `haversine`, `osrm` o `mapbox`
+    pub provider: String,
+    pub osrm_url: String,
+    pub osrm_profile: String,
+    pub mapbox_url: String,
+    pub mapbox_token: Option<String>,
+    pub detour_factor: f64,
+    pub cache_ttl: u64,
+}
+
+impl Default for DistanceMatrixConfig {
+    fn default() -> Self {
+        Self {
+            provider: std::env::var("DISTANCE_MATRIX_PROVIDER").unwrap_or_else(|_| "haversine".to_string()),
+            osrm_url: std::env::var("OSRM_URL").unwrap_or_else(|_| "http://localhost:5000".to_string()),
+            osrm_profile: std::env::var("OSRM_PROFILE").unwrap_or_else(|_| "driving".to_string()),
+            mapbox_url: "https://api.mapbox.com".to_string(),
+            mapbox_token: std::env::var("MAPBOX_TOKEN").ok(),
+            detour_factor: std::env::var("ROUTE_DETOUR_FACTOR")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1.3),
+            cache_ttl: 604800, // 7 días: la red viaria cambia poco
+        }
+    }
+}
+
+/// Entrada cacheada de un par de coordenadas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedLeg {
+    distance_km: f64,
+    duration_minutes: f64,
+}
+
+/// Servicio de matrices con cache por par de coordenadas y fallback haversine
+#[derive(Clone)]
+pub struct DistanceMatrixService<C = RedisClient> {
+    primary: Arc<dyn DistanceMatrixProvider>,
+    fallback: HaversineMatrix,
+    cache: Option<C>,
+    cache_ttl: u64,
+}
+
+impl DistanceMatrixService<RedisClient> {
+    /// Elegir el proveedor según `DistanceMatrixConfig`
+    pub fn from_config(config: DistanceMatrixConfig, client: Client, cache: Option<RedisClient>) -> Self {
+        let fallback = HaversineMatrix { detour_factor: config.detour_factor, ..Default::default() };
+
+        let primary: Arc<dyn DistanceMatrixProvider> = match (config.provider.as_str(), config.mapbox_token) {
+            ("osrm", _) => Arc::new(OsrmMatrix::new(client, config.osrm_url, config.osrm_profile)),
+            ("mapbox", Some(token)) => Arc::new(MapboxMatrix::new(client, config.mapbox_url, token)),
+            ("mapbox", None) => {
+                warn!("⚠️ DISTANCE_MATRIX_PROVIDER=mapbox sin MAPBOX_TOKEN, usando haversine");
+                Arc::new(fallback.clone())
+            }
+            _ => Arc::new(fallback.clone()),
+        };
+        info!("🛣️ Proveedor de matrices de distancia: {}", primary.name());
+
+        Self::new(primary, fallback, cache, config.cache_ttl)
+    }
+}
+
+impl<C> DistanceMatrixService<C>
+where
+    C: CacheOperations + Send + Sync,
+{
+    pub fn new(primary: Arc<dyn DistanceMatrixProvider>, fallback: HaversineMatrix, cache: Option<C>, cache_ttl: u64) -> Self {
+        Self { primary, fallback, cache, cache_ttl }
+    }
+
+    /// Matriz entre todos los puntos: cache, luego proveedor, luego haversine
+    pub async fn matrix(&self, points: &[GeoPoint]) -> DistanceMatrix {
+        let pairs: Vec<(usize, usize)> = (0..points.len())
+            .flat_map(|i| (0..points.len()).filter(move |&j| j != i).map(move |j| (i, j)))
+            .collect();
+        let keys: Vec<String> = pairs
+            .iter()
+            .map(|&(i, j)| self.pair_key(points[i], points[j]))
+            .collect();
+
+        if let Some(matrix) = self.cached_matrix(points.len(), &pairs, &keys).await {
+            debug!("🛣️ Matriz {}x{} desde cache ({})", points.len(), points.len(), self.primary.name());
+            return matrix;
+        }
+
+        match full_matrix(self.primary.as_ref(), points).await {
+            Ok(matrix) => {
+                self.store(&matrix, &pairs, &keys).await;
+                matrix
+            }
+            Err(e) => {
+                warn!("⚠️ Error obteniendo matriz de {}: {}, usando haversine", self.primary.name(), e);
+                full_matrix(&self.fallback, points)
+                    .await
+                    .unwrap_or_else(|_| DistanceMatrix::empty(self.fallback.name(), points.len()))
+            }
+        }
+    }
+
+    fn pair_key(&self, from: GeoPoint, to: GeoPoint) -> String {
+        matrix_pair_key(self.primary.name(), (from.latitude, from.longitude), (to.latitude, to.longitude))
+    }
+
+    async fn cached_matrix(&self, n: usize, pairs: &[(usize, usize)], keys: &[String]) -> Option<DistanceMatrix> {
+        let cache = self.cache.as_ref()?;
+        let cached: Vec<Option<CachedLeg>> = match cache.get_many(keys).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                warn!("⚠️ Error leyendo matriz cacheada: {}", e);
+                return None;
+            }
+        };
+
+        let mut matrix = DistanceMatrix::empty(self.primary.name(), n);
+        for (&(i, j), leg) in pairs.iter().zip(cached) {
+            let leg = leg?;
+            matrix.distances_km[i][j] = leg.distance_km;
+            matrix.durations_minutes[i][j] = leg.duration_minutes;
+        }
+        Some(matrix)
+    }
+
+    async fn store(&self, matrix: &DistanceMatrix, pairs: &[(usize, usize)], keys: &[String]) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+
+        let entries: Vec<(String, CachedLeg)> = pairs
+            .iter()
+            .zip(keys)
+            .map(|(&(i, j), key)| {
+                let leg = CachedLeg {
+                    distance_km: matrix.distances_km[i][j],
+                    duration_minutes: matrix.durations_minutes[i][j],
+                };
+                (key.clone(), leg)
+            })
+            .collect();
+
+        if let Err(e) = cache.set_many(&entries, self.cache_ttl).await {
+            warn!("⚠️ Error cacheando matriz: {}", e);
+        }
+    }
+}
+
+/// `DistanceMetric` sobre una matriz ya calculada, para los optimizadores
+pub struct MatrixMetric {
+    index: HashMap<(u64, u64), usize>,
+    distances_km: Vec<Vec<f64>>,
+}
+
+impl MatrixMetric {
+    pub fn new(points: &[GeoPoint], matrix: &DistanceMatrix) -> Self {
+        let index = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| ((p.latitude.to_bits(), p.longitude.to_bits()), i))
+            .collect();
+
+        Self { index, distances_km: matrix.distances_km.clone() }
+    }
+
+    fn position(&self, point: GeoPoint) -> Option<usize> {
+        self.index.get(&(point.latitude.to_bits(), point.longitude.to_bits())).copied()
+    }
+}
+
+impl DistanceMetric for MatrixMetric {
+    /// Puntos fuera de la matriz: haversine
+    fn distance_km(&self, from: GeoPoint, to: GeoPoint) -> f64 {
+        match (self.position(from), self.position(to)) {
+            (Some(i), Some(j)) => self.distances_km[i][j],
+            _ => Haversine.distance_km(from, to),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::{Path, Query}, routing::get, Json, Router};
+    use crate::cache::MemoryStore;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn spawn_mock(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    fn point(latitude: f64, longitude: f64) -> GeoPoint {
+        GeoPoint { latitude, longitude }
+    }
+
+    /// Tabla OSRM: 1000 m y 60 s por unidad de índice entre origen y destino
+    fn mock_table(coordinates: &str, params: &HashMap<String, String>) -> Value {
+        let parse = |key: &str| -> Vec<usize> {
+            params[key].split(';').map(|i| i.parse().unwrap()).collect()
+        };
+        let (sources, destinations) = (parse("sources"), parse("destinations"));
+        assert_eq!(coordinates.split(';').count(), sources.len() + destinations.len());
+
+        let cell = |s: usize, d: usize, unit: f64| json!((s as f64 - d as f64).abs() * unit);
+        json!({
+            "code": "Ok",
+            "distances": sources.iter().map(|&s| destinations.iter().map(|&d| cell(s, d, 1000.0)).collect::<Vec<_>>()).collect::<Vec<_>>(),
+            "durations": sources.iter().map(|&s| destinations.iter().map(|&d| cell(s, d, 60.0)).collect::<Vec<_>>()).collect::<Vec<_>>(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_haversine_applies_detour_factor() {
+        let provider = HaversineMatrix { detour_factor: 1.5, average_speed_kmh: 30.0 };
+        let (a, b) = (point(48.85, 2.35), point(48.86, 2.35));
+
+        let matrix = full_matrix(&provider, &[a, b]).await.unwrap();
+
+        let straight = Haversine.distance_km(a, b);
+        assert!((matrix.distances_km[0][1] - straight * 1.5).abs() < 1e-9);
+        assert!((matrix.durations_minutes[0][1] - straight * 1.5 / 30.0 * 60.0).abs() < 1e-9);
+        assert_eq!(matrix.distances_km[0][0], 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_osrm_table_request_and_parsing() {
+        let router = Router::new().route(
+            "/table/v1/driving/:coordinates",
+            get(|Path(coordinates): Path<String>, Query(params): Query<HashMap<String, String>>| async move {
+                assert!(coordinates.starts_with("2.350000,48.850000;"), "lon,lat: {}", coordinates);
+                assert_eq!(params["annotations"], "duration,distance");
+                Json(mock_table(&coordinates, &params))
+            }),
+        );
+        let provider = OsrmMatrix::new(Client::new(), spawn_mock(router).await, "driving");
+
+        let points = [point(48.85, 2.35), point(48.86, 2.36), point(48.87, 2.37)];
+        let matrix = full_matrix(&provider, &points).await.unwrap();
+
+        assert_eq!(matrix.provider, "osrm");
+        // sources 0..3, destinations 3..6: |s - d| km
+        assert_eq!(matrix.distances_km[0][1], 4.0);
+        assert_eq!(matrix.durations_minutes[2][0], 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_osrm_unreachable_pair_is_estimated() {
+        let router = Router::new().route(
+            "/table/v1/driving/:coordinates",
+            get(|| async {
+                Json(json!({
+                    "code": "Ok",
+                    "distances": [[0.0, null], [500.0, 0.0]],
+                    "durations": [[0.0, null], [50.0, 0.0]]
+                }))
+            }),
+        );
+        let provider = OsrmMatrix::new(Client::new(), spawn_mock(router).await, "driving");
+
+        let matrix = full_matrix(&provider, &[point(48.85, 2.35), point(48.86, 2.35)]).await.unwrap();
+
+        assert!(matrix.distances_km[0][1] > 1.0, "estimado con haversine");
+        assert_eq!(matrix.distances_km[1][0], 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_mapbox_is_tiled_by_coordinate_limit() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let router = Router::new().route(
+            "/directions-matrix/v1/mapbox/driving/:coordinates",
+            get(move |Path(coordinates): Path<String>, Query(params): Query<HashMap<String, String>>| {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    assert_eq!(params["access_token"], "pk.test");
+                    assert!(coordinates.split(';').count() <= 25);
+                    Json(mock_table(&coordinates, &params))
+                }
+            }),
+        );
+        let provider = MapboxMatrix::new(Client::new(), spawn_mock(router).await, "pk.test");
+
+        let points: Vec<GeoPoint> = (0..30).map(|i| point(48.85 + i as f64 * 0.001, 2.35)).collect();
+        let matrix = full_matrix(&provider, &points).await.unwrap();
+
+        // Bloques de 12: 3 x 3 peticiones
+        assert_eq!(calls.load(Ordering::SeqCst), 9);
+        assert_eq!(matrix.distances_km.len(), 30);
+        assert!(matrix.distances_km.iter().all(|row| row.len() == 30));
+        assert_eq!(matrix.distances_km[5][5], 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_provider_error_is_reported() {
+        let router = Router::new().route(
+            "/table/v1/driving/:coordinates",
+            get(|| async { Json(json!({ "code": "TooBig", "message": "Too many table coordinates" })) }),
+        );
+        let provider = OsrmMatrix::new(Client::new(), spawn_mock(router).await, "driving");
+
+        let err = full_matrix(&provider, &[point(48.85, 2.35), point(48.86, 2.35)]).await.unwrap_err();
+        assert!(err.to_string().contains("TooBig"), "{}", err);
+    }
+
+    /// Proveedor que cuenta llamadas y puede fallar
+    struct CountingProvider {
+        calls: AtomicUsize,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl DistanceMatrixProvider for CountingProvider {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        async fn table(&self, sources: &[GeoPoint], destinations: &[GeoPoint]) -> Result<MatrixTable, DistanceMatrixError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.fail {
+                return Err(DistanceMatrixError::InvalidResponse { provider: "counting", message: "caído".to_string() });
+            }
+            Ok(MatrixTable {
+                distances_km: vec![vec![7.0; destinations.len()]; sources.len()],
+                durations_minutes: vec![vec![9.0; destinations.len()]; sources.len()],
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_service_caches_pairs() {
+        let provider = Arc::new(CountingProvider { calls: AtomicUsize::new(0), fail: false });
+        let store = MemoryStore::default();
+        let service = DistanceMatrixService::new(provider.clone(), HaversineMatrix::default(), Some(store.clone()), 60);
+        let points = [point(48.85, 2.35), point(48.86, 2.36), point(48.87, 2.37)];
+
+        let first = service.matrix(&points).await;
+        let second = service.matrix(&points).await;
+
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(store.len(), 6);
+        assert_eq!(first.distances_km, second.distances_km);
+        assert_eq!(second.distances_km[1][2], 7.0);
+        assert_eq!(second.distances_km[1][1], 0.0);
+
+        // Un punto nuevo invalida la consulta completa
+        let more = [points[0], points[1], points[2], point(48.88, 2.38)];
+        service.matrix(&more).await;
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_service_falls_back_to_haversine() {
+        let provider = Arc::new(CountingProvider { calls: AtomicUsize::new(0), fail: true });
+        let store = MemoryStore::default();
+        let service = DistanceMatrixService::new(provider, HaversineMatrix::default(), Some(store.clone()), 60);
+
+        let matrix = service.matrix(&[point(48.85, 2.35), point(48.86, 2.35)]).await;
+
+        assert_eq!(matrix.provider, "haversine");
+        assert!(matrix.distances_km[0][1] > 1.0);
+        assert_eq!(store.len(), 0, "el fallback no se cachea");
+    }
+
+    #[test]
+    fn test_matrix_metric_lookup() {
+        let points = [point(48.85, 2.35), point(48.86, 2.36)];
+        let matrix = DistanceMatrix {
+            provider: "test".to_string(),
+            distances_km: vec![vec![0.0, 5.0], vec![6.0, 0.0]],
+            durations_minutes: vec![vec![0.0, 1.0], vec![1.0, 0.0]],
+        };
+        let metric = MatrixMetric::new(&points, &matrix);
+
+        assert_eq!(metric.distance_km(points[0], points[1]), 5.0);
+        assert_eq!(metric.distance_km(points[1], points[0]), 6.0);
+        let outside = point(48.90, 2.40);
+        assert_eq!(metric.distance_km(points[0], outside), Haversine.distance_km(points[0], outside));
+    }
+}
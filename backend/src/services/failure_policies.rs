@@ -0,0 +1,257 @@
+//! Políticas de entrega fallida y reprogramación automática
+//!
+//! Al marcar un paquete como fallido se aplica la política de la empresa
+//! (`lifecycle::failure_policy`). Los paquetes reprogramados o desviados a un
+//! punto relais se copian como paquetes nuevos (`carried_over_from`) en la
+//! siguiente tournée del mismo driver cuando su tournée se completa o se
+//! cancela, o cuando se crea (o se importa de Colis Privé) la tournée de
+//! destino, lo que ocurra después.
+
+use chrono::NaiveDate;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::lifecycle::failure_policy::{FailureAction, FailurePolicy};
+use crate::models::failure_policy::{MaxAttemptsEntry, MaxAttemptsFilters, UpsertFailurePolicyRequest};
+use crate::models::package::{DeliveryFailureReason, DeliveryStatus};
+use crate::services::status_history;
+
+/// Fila de `failure_policies`
+type PolicyRow = (DeliveryFailureReason, String, i32, Option<String>, Option<String>);
+
+fn policy_from_row((reason, action, max_attempts, relay_point_name, relay_point_address): PolicyRow) -> (DeliveryFailureReason, FailurePolicy) {
+    let policy = FailurePolicy {
+        // El CHECK de la tabla solo admite las tres acciones
+        action: FailureAction::parse(&action).unwrap_or(FailureAction::Reschedule),
+        max_attempts,
+        relay_point_name,
+        relay_point_address,
+    };
+    (reason, policy)
+}
+
+/// Política de la empresa para un motivo (o la de por defecto)
+pub async fn effective_policy(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    reason: DeliveryFailureReason,
+) -> Result<FailurePolicy, sqlx::Error> {
+    let row = sqlx::query_as::<_, PolicyRow>(
+        r#"
+        SELECT failure_reason, failure_action, max_attempts, relay_point_name, relay_point_address
+        FROM failure_policies
+        WHERE company_id = $1 AND failure_reason = $2
+        "#,
+    )
+    .bind(company_id)
+    .bind(reason)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(row
+        .map(|row| policy_from_row(row).1)
+        .unwrap_or_else(|| FailurePolicy::default_for(reason)))
+}
+
+/// Políticas configuradas por la empresa
+pub async fn configured_policies(
+    pool: &PgPool,
+    company_id: Uuid,
+) -> Result<Vec<(DeliveryFailureReason, FailurePolicy)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, PolicyRow>(
+        r#"
+        SELECT failure_reason, failure_action, max_attempts, relay_point_name, relay_point_address
+        FROM failure_policies
+        WHERE company_id = $1
+        "#,
+    )
+    .bind(company_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(policy_from_row).collect())
+}
+
+/// Crear o reemplazar la política de un motivo
+pub async fn upsert_policy(
+    pool: &PgPool,
+    company_id: Uuid,
+    reason: DeliveryFailureReason,
+    action: FailureAction,
+    request: &UpsertFailurePolicyRequest,
+    updated_by: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO failure_policies (
+            company_id, failure_reason, failure_action, max_attempts,
+            relay_point_name, relay_point_address, updated_by
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (company_id, failure_reason) DO UPDATE SET
+            failure_action = EXCLUDED.failure_action,
+            max_attempts = EXCLUDED.max_attempts,
+            relay_point_name = EXCLUDED.relay_point_name,
+            relay_point_address = EXCLUDED.relay_point_address,
+            updated_by = EXCLUDED.updated_by
+        "#,
+    )
+    .bind(company_id)
+    .bind(reason)
+    .bind(action.as_str())
+    .bind(request.max_attempts)
+    .bind(&request.relay_point_name)
+    .bind(&request.relay_point_address)
+    .bind(updated_by)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Volver a la política por defecto. `false` si no había ninguna configurada.
+pub async fn delete_policy(
+    pool: &PgPool,
+    company_id: Uuid,
+    reason: DeliveryFailureReason,
+) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query("DELETE FROM failure_policies WHERE company_id = $1 AND failure_reason = $2")
+        .bind(company_id)
+        .bind(reason)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+    Ok(deleted > 0)
+}
+
+/// Copiar los paquetes fallidos pendientes de reprogramar de las tournées
+/// completadas o canceladas del driver a su siguiente tournée (pendiente o en
+/// curso, con fecha igual o posterior a `reschedule_date`). Devuelve los
+/// paquetes creados. `changed_by` es `None` en las importaciones.
+pub async fn carry_over_failed(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    driver_id: Uuid,
+    changed_by: Option<Uuid>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let carried = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        WITH candidates AS (
+            SELECT p.id, (
+                SELECT n.id FROM tournees n
+                WHERE n.company_id = p.company_id
+                AND n.driver_id = t.driver_id
+                AND n.deleted_at IS NULL
+                AND n.tournee_status IN ('pending', 'in_progress')
+                AND n.tournee_date > t.tournee_date
+                AND n.tournee_date >= COALESCE(p.reschedule_date, t.tournee_date)
+                ORDER BY n.tournee_date, n.created_at
+                LIMIT 1
+            ) AS target_id
+            FROM packages p
+            JOIN tournees t ON t.id = p.tournee_id
+            WHERE p.company_id = $1
+            AND t.driver_id = $2
+            AND t.tournee_status IN ('completed', 'cancelled')
+            AND p.deleted_at IS NULL
+            AND p.delivery_status = 'failed'
+            AND p.failure_action IN ('reschedule', 'relay_point')
+            AND NOT EXISTS (SELECT 1 FROM packages c WHERE c.carried_over_from = p.id)
+        )
+        INSERT INTO packages (
+            company_id, tournee_id, tracking_number, external_tracking_number,
+            package_origin, external_package_id, integration_id, package_type,
+            package_weight, package_dimensions, delivery_status, delivery_attempts,
+            recipient_name, recipient_phone, delivery_address, delivery_instructions,
+            delivery_coordinates, signature_required, relay_point_address, carried_over_from
+        )
+        SELECT
+            p.company_id, c.target_id, p.tracking_number, p.external_tracking_number,
+            p.package_origin, p.external_package_id, p.integration_id, p.package_type,
+            p.package_weight, p.package_dimensions, 'pending', COALESCE(p.delivery_attempts, 0),
+            p.recipient_name, p.recipient_phone,
+            CASE WHEN p.failure_action = 'relay_point' THEN p.relay_point_address ELSE p.delivery_address END,
+            p.delivery_instructions,
+            CASE WHEN p.failure_action = 'relay_point' THEN NULL ELSE p.delivery_coordinates END,
+            p.signature_required,
+            CASE WHEN p.failure_action = 'relay_point' THEN p.relay_point_address END,
+            p.id
+        FROM candidates c
+        JOIN packages p ON p.id = c.id
+        WHERE c.target_id IS NOT NULL
+        ON CONFLICT DO NOTHING
+        RETURNING id
+        "#,
+    )
+    .bind(company_id)
+    .bind(driver_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for package_id in &carried {
+        status_history::record_package_status(
+            conn, company_id, *package_id, None, &DeliveryStatus::Pending, changed_by,
+            Some("Reprogramado tras una entrega fallida"),
+        )
+        .await?;
+    }
+
+    if !carried.is_empty() {
+        log::info!("📦 {} paquete(s) fallidos reprogramados para el driver {}", carried.len(), driver_id);
+    }
+
+    Ok(carried)
+}
+
+/// Paquetes devueltos al remitente por agotar los intentos
+pub async fn max_attempts_report(
+    pool: &PgPool,
+    company_id: Uuid,
+    filters: &MaxAttemptsFilters,
+) -> Result<Vec<MaxAttemptsEntry>, sqlx::Error> {
+    let limit = filters.limit.unwrap_or(100).min(500);
+    let offset = filters.offset.unwrap_or(0);
+
+    sqlx::query_as::<_, MaxAttemptsEntry>(
+        r#"
+        SELECT
+            p.id AS package_id, p.tracking_number, p.recipient_name, p.delivery_address,
+            COALESCE(p.delivery_attempts, 0) AS delivery_attempts,
+            p.failure_reason::text AS failure_reason,
+            t.id AS tournee_id, t.tournee_date, t.driver_id,
+            p.max_attempts_reached_at
+        FROM packages p
+        JOIN tournees t ON t.id = p.tournee_id
+        WHERE p.company_id = $1
+        AND p.deleted_at IS NULL
+        AND p.max_attempts_reached_at IS NOT NULL
+        AND ($2::date IS NULL OR t.tournee_date >= $2)
+        AND ($3::date IS NULL OR t.tournee_date <= $3)
+        ORDER BY p.max_attempts_reached_at DESC
+        LIMIT $4 OFFSET $5
+        "#,
+    )
+    .bind(company_id)
+    .bind(filters.date_from)
+    .bind(filters.date_to)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await
+}
+
+/// Fecha de la tournée del paquete y sus intentos antes de este fallo
+pub async fn failure_context(conn: &mut PgConnection, package_id: Uuid) -> Result<(i32, NaiveDate), sqlx::Error> {
+    sqlx::query_as::<_, (i32, NaiveDate)>(
+        r#"
+        SELECT COALESCE(p.delivery_attempts, 0), t.tournee_date
+        FROM packages p
+        JOIN tournees t ON t.id = p.tournee_id
+        WHERE p.id = $1
+        "#,
+    )
+    .bind(package_id)
+    .fetch_one(conn)
+    .await
+}
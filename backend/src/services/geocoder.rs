@@ -0,0 +1,874 @@
+//! Proveedores de geocoding
+//!
+//! `Geocoder` abstrae el proveedor: la BAN importada en local, Mapbox, la
+//! Base Adresse Nationale (api-adresse.data.gouv.fr) y Nominatim.
+//! `GeocoderChain` los prueba en el orden configurado y pasa al siguiente
+//! cuando uno no encuentra la dirección o la encuentra con poca confianza.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::sync::Mutex;
+use tokio::time::{sleep_until, Instant};
+use uuid::Uuid;
+
+use crate::services::geocoding_service::GeocodingResponse;
+use crate::services::geocoding_usage::{GeocodingUsage, MeteredGeocoder};
+use crate::services::local_geocoder::LocalBanGeocoder;
+use crate::services::route_optimizer::{DistanceMetric, GeoPoint, Haversine};
+
+/// Timeout por petición de geocoding
+const GEOCODING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// User-Agent identificable (obligatorio para Nominatim)
+const USER_AGENT: &str = "DeliveryRouting/1.0";
+
+/// Dirección candidata cercana a una posición (geocoding inverso)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReverseCandidate {
+    pub address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Distancia a la posición consultada (metros)
+    pub distance_m: f64,
+    pub provider: String,
+}
+
+impl ReverseCandidate {
+    pub fn new(provider: &str, address: String, latitude: f64, longitude: f64, origin: GeoPoint) -> Self {
+        let distance_km = Haversine.distance_km(origin, GeoPoint { latitude, longitude });
+        Self {
+            address,
+            latitude,
+            longitude,
+            distance_m: (distance_km * 1000.0).round(),
+            provider: provider.to_string(),
+        }
+    }
+}
+
+/// Proveedor de geocoding
+#[async_trait]
+pub trait Geocoder: Send + Sync {
+    /// Nombre corto del proveedor
+    fn name(&self) -> &'static str;
+
+    /// Proveedor externo cuyas llamadas cuentan para el presupuesto
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    /// Geocodificar una dirección; `confidence` va de 0.0 a 1.0
+    async fn geocode(&self, address: &str) -> Result<GeocodingResponse>;
+
+    /// Direcciones más cercanas a una posición, de la más cercana a la más lejana.
+    /// Por defecto el proveedor no soporta geocoding inverso.
+    async fn reverse(&self, _position: GeoPoint, _limit: usize) -> Result<Vec<ReverseCandidate>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Enviar la petición y devolver el cuerpo si el estado es 2xx
+async fn fetch(provider: &str, request: reqwest::RequestBuilder) -> Result<Option<String>> {
+    let response = request
+        .header("User-Agent", USER_AGENT)
+        .timeout(GEOCODING_TIMEOUT)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        log::error!("❌ Geocoding {} failed with status {}: {}", provider, status, error_text);
+        return Ok(None);
+    }
+
+    Ok(Some(response.text().await?))
+}
+
+// ============================================================================
+// MAPBOX
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+struct MapboxGeocodingResponse {
+    features: Vec<MapboxFeature>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapboxFeature {
+    geometry: MapboxGeometry,
+    properties: MapboxProperties,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapboxGeometry {
+    coordinates: Vec<f64>, // [longitude, latitude]
+}
+
+#[derive(Debug, Deserialize)]
+struct MapboxProperties {
+    feature_type: Option<String>,
+    full_address: Option<String>,
+    name: Option<String>,
+    place_name: Option<String>,
+    match_code: Option<MapboxMatchCode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapboxMatchCode {
+    confidence: Option<String>,
+}
+
+/// Mapbox Geocoding v6 (forward), limitado a Francia
+#[derive(Debug, Clone)]
+pub struct MapboxGeocoder {
+    client: Client,
+    base_url: String,
+    access_token: String,
+}
+
+impl MapboxGeocoder {
+    pub fn new(client: Client, base_url: impl Into<String>, access_token: impl Into<String>) -> Self {
+        Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            access_token: access_token.into(),
+        }
+    }
+
+    /// `match_code.confidence` o, si no viene, el tipo de resultado
+    fn confidence(properties: &MapboxProperties) -> f64 {
+        let by_match = properties.match_code.as_ref().and_then(|m| m.confidence.as_deref());
+        match (by_match, properties.feature_type.as_deref()) {
+            (Some("exact"), _) => 1.0,
+            (Some("high"), _) => 0.9,
+            (Some("medium"), _) => 0.6,
+            (Some(_), _) => 0.3,
+            (None, Some("address")) => 0.8,
+            (None, Some("street")) => 0.5,
+            (None, _) => 0.3,
+        }
+    }
+}
+
+#[async_trait]
+impl Geocoder for MapboxGeocoder {
+    fn name(&self) -> &'static str {
+        "mapbox"
+    }
+
+    async fn geocode(&self, address: &str) -> Result<GeocodingResponse> {
+        let request = self.client
+            .get(format!("{}/search/geocode/v6/forward", self.base_url))
+            .query(&[
+                ("q", address),
+                ("access_token", self.access_token.as_str()),
+                ("country", "fr"),
+                ("limit", "1"),
+            ]);
+
+        let Some(body) = fetch(self.name(), request).await? else {
+            return Ok(GeocodingResponse::failed(self.name(), "Geocoding failed"));
+        };
+
+        let parsed: MapboxGeocodingResponse = serde_json::from_str(&body)
+            .map_err(|e| anyhow!("Failed to parse geocoding response: {}", e))?;
+
+        Ok(match parsed.features.first() {
+            Some(feature) if feature.geometry.coordinates.len() >= 2 => {
+                let properties = &feature.properties;
+                let formatted_address = properties.full_address.clone()
+                    .or_else(|| properties.place_name.clone())
+                    .or_else(|| properties.name.clone());
+
+                GeocodingResponse::found(
+                    self.name(),
+                    feature.geometry.coordinates[1],
+                    feature.geometry.coordinates[0],
+                    formatted_address,
+                    Self::confidence(properties),
+                )
+            }
+            _ => GeocodingResponse::not_found(self.name()),
+        })
+    }
+}
+
+// ============================================================================
+// BASE ADRESSE NATIONALE
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+struct BanResponse {
+    features: Vec<BanFeature>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BanFeature {
+    geometry: BanGeometry,
+    properties: BanProperties,
+}
+
+#[derive(Debug, Deserialize)]
+struct BanGeometry {
+    coordinates: Vec<f64>, // [longitude, latitude]
+}
+
+#[derive(Debug, Deserialize)]
+struct BanProperties {
+    label: Option<String>,
+    score: Option<f64>,
+}
+
+/// API de la Base Adresse Nationale (sin token)
+#[derive(Debug, Clone)]
+pub struct BanGeocoder {
+    client: Client,
+    base_url: String,
+}
+
+impl BanGeocoder {
+    pub fn new(client: Client, base_url: impl Into<String>) -> Self {
+        Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Geocoder for BanGeocoder {
+    fn name(&self) -> &'static str {
+        "ban"
+    }
+
+    async fn geocode(&self, address: &str) -> Result<GeocodingResponse> {
+        let request = self.client
+            .get(format!("{}/search/", self.base_url))
+            .query(&[("q", address), ("limit", "1")]);
+
+        let Some(body) = fetch(self.name(), request).await? else {
+            return Ok(GeocodingResponse::failed(self.name(), "Geocoding failed"));
+        };
+
+        let parsed: BanResponse = serde_json::from_str(&body)
+            .map_err(|e| anyhow!("Failed to parse BAN response: {}", e))?;
+
+        Ok(match parsed.features.first() {
+            Some(feature) if feature.geometry.coordinates.len() >= 2 => GeocodingResponse::found(
+                self.name(),
+                feature.geometry.coordinates[1],
+                feature.geometry.coordinates[0],
+                feature.properties.label.clone(),
+                feature.properties.score.unwrap_or(0.0).clamp(0.0, 1.0),
+            ),
+            _ => GeocodingResponse::not_found(self.name()),
+        })
+    }
+
+    async fn reverse(&self, position: GeoPoint, limit: usize) -> Result<Vec<ReverseCandidate>> {
+        let request = self.client
+            .get(format!("{}/reverse/", self.base_url))
+            .query(&[
+                ("lat", position.latitude.to_string()),
+                ("lon", position.longitude.to_string()),
+                ("limit", limit.to_string()),
+            ]);
+
+        let Some(body) = fetch(self.name(), request).await? else {
+            return Ok(Vec::new());
+        };
+
+        let parsed: BanResponse = serde_json::from_str(&body)
+            .map_err(|e| anyhow!("Failed to parse BAN response: {}", e))?;
+
+        let mut candidates: Vec<ReverseCandidate> = parsed
+            .features
+            .into_iter()
+            .filter(|feature| feature.geometry.coordinates.len() >= 2)
+            .filter_map(|feature| {
+                let address = feature.properties.label?;
+                Some(ReverseCandidate::new(
+                    self.name(),
+                    address,
+                    feature.geometry.coordinates[1],
+                    feature.geometry.coordinates[0],
+                    position,
+                ))
+            })
+            .collect();
+
+        candidates.sort_by(|a, b| a.distance_m.total_cmp(&b.distance_m));
+        Ok(candidates)
+    }
+}
+
+// ============================================================================
+// NOMINATIM
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+struct NominatimPlace {
+    lat: String,
+    lon: String,
+    display_name: Option<String>,
+    place_rank: Option<u32>,
+}
+
+/// Intervalo mínimo entre peticiones: la política de uso de Nominatim
+/// permite como máximo una por segundo
+const NOMINATIM_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Nominatim (OpenStreetMap), limitado a Francia
+#[derive(Debug, Clone)]
+pub struct NominatimGeocoder {
+    client: Client,
+    base_url: String,
+    min_interval: Duration,
+    /// Momento a partir del cual sale la siguiente petición (compartido entre clones)
+    next_slot: Arc<Mutex<Instant>>,
+}
+
+impl NominatimGeocoder {
+    pub fn new(client: Client, base_url: impl Into<String>) -> Self {
+        Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            min_interval: NOMINATIM_MIN_INTERVAL,
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Cambiar el intervalo mínimo (instancias propias sin límite de uso)
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Esperar el turno: las peticiones concurrentes salen de una en una
+    async fn throttle(&self) {
+        let mut next_slot = self.next_slot.lock().await;
+        sleep_until(*next_slot).await;
+        *next_slot = Instant::now() + self.min_interval;
+    }
+
+    /// Nominatim no da score: se usa el nivel del resultado (30 = edificio, 26-27 = calle)
+    fn confidence(place_rank: Option<u32>) -> f64 {
+        match place_rank.unwrap_or(0) {
+            28.. => 0.8,
+            26..=27 => 0.5,
+            _ => 0.2,
+        }
+    }
+}
+
+#[async_trait]
+impl Geocoder for NominatimGeocoder {
+    fn name(&self) -> &'static str {
+        "nominatim"
+    }
+
+    async fn geocode(&self, address: &str) -> Result<GeocodingResponse> {
+        self.throttle().await;
+        let request = self.client
+            .get(format!("{}/search", self.base_url))
+            .query(&[("q", address), ("format", "jsonv2"), ("countrycodes", "fr"), ("limit", "1")]);
+
+        let Some(body) = fetch(self.name(), request).await? else {
+            return Ok(GeocodingResponse::failed(self.name(), "Geocoding failed"));
+        };
+
+        let places: Vec<NominatimPlace> = serde_json::from_str(&body)
+            .map_err(|e| anyhow!("Failed to parse Nominatim response: {}", e))?;
+
+        let Some(place) = places.first() else {
+            return Ok(GeocodingResponse::not_found(self.name()));
+        };
+
+        match (place.lat.parse::<f64>(), place.lon.parse::<f64>()) {
+            (Ok(latitude), Ok(longitude)) => Ok(GeocodingResponse::found(
+                self.name(),
+                latitude,
+                longitude,
+                place.display_name.clone(),
+                Self::confidence(place.place_rank),
+            )),
+            _ => Err(anyhow!("Invalid Nominatim coordinates: {}, {}", place.lat, place.lon)),
+        }
+    }
+
+    /// Nominatim devuelve un único resultado por posición
+    async fn reverse(&self, position: GeoPoint, _limit: usize) -> Result<Vec<ReverseCandidate>> {
+        self.throttle().await;
+        let request = self.client
+            .get(format!("{}/reverse", self.base_url))
+            .query(&[
+                ("lat", position.latitude.to_string()),
+                ("lon", position.longitude.to_string()),
+                ("format", "jsonv2".to_string()),
+            ]);
+
+        let Some(body) = fetch(self.name(), request).await? else {
+            return Ok(Vec::new());
+        };
+
+        // Sin resultado Nominatim responde `{"error": "Unable to geocode"}`
+        let Ok(place) = serde_json::from_str::<NominatimPlace>(&body) else {
+            return Ok(Vec::new());
+        };
+
+        match (place.lat.parse::<f64>(), place.lon.parse::<f64>(), place.display_name) {
+            (Ok(latitude), Ok(longitude), Some(address)) => Ok(vec![ReverseCandidate::new(
+                self.name(),
+                address,
+                latitude,
+                longitude,
+                position,
+            )]),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+// ============================================================================
+// CADENA DE FALLBACK
+// ============================================================================
+
+/// Configuración de la cadena de geocoders
+#[derive(Debug, Clone)]
+pub struct GeocoderConfig {
+    /// Orden de prueba, p. ej. `local,mapbox,ban`; Nominatim solo si se añade
+    pub order: Vec<String>,
+    /// Confianza mínima para aceptar un resultado sin probar el siguiente
+    pub min_confidence: f64,
+    pub mapbox_url: String,
+    pub mapbox_token: Option<String>,
+    pub ban_url: String,
+    pub nominatim_url: String,
+    pub nominatim_min_interval: Duration,
+}
+
+impl Default for GeocoderConfig {
+    fn default() -> Self {
+        Self {
+            order: std::env::var("GEOCODER_ORDER")
+                .unwrap_or_else(|_| "local,mapbox,ban".to_string())
+                .split(',')
+                .map(|name| name.trim().to_lowercase())
+                .filter(|name| !name.is_empty())
+                .collect(),
+            min_confidence: std::env::var("GEOCODER_MIN_CONFIDENCE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0.5),
+            mapbox_url: "https://api.mapbox.com".to_string(),
+            mapbox_token: std::env::var("MAPBOX_TOKEN").ok().filter(|t| !t.is_empty()),
+            ban_url: std::env::var("BAN_URL").unwrap_or_else(|_| "https://api-adresse.data.gouv.fr".to_string()),
+            nominatim_url: std::env::var("NOMINATIM_URL").unwrap_or_else(|_| "https://nominatim.openstreetmap.org".to_string()),
+            nominatim_min_interval: std::env::var("NOMINATIM_MIN_INTERVAL_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(NOMINATIM_MIN_INTERVAL),
+        }
+    }
+}
+
+/// Prueba los geocoders en orden hasta obtener un resultado con confianza suficiente
+#[derive(Clone)]
+pub struct GeocoderChain {
+    geocoders: Vec<Arc<dyn Geocoder>>,
+    min_confidence: f64,
+}
+
+impl GeocoderChain {
+    pub fn new(geocoders: Vec<Arc<dyn Geocoder>>, min_confidence: f64) -> Self {
+        Self { geocoders, min_confidence }
+    }
+
+    /// Construir la cadena en el orden configurado
+    /// (Mapbox solo con token, la BAN local solo con base de datos)
+    pub fn from_config(config: GeocoderConfig, client: Client, pool: Option<PgPool>) -> Self {
+        let mut geocoders: Vec<Arc<dyn Geocoder>> = Vec::new();
+
+        for name in &config.order {
+            match name.as_str() {
+                "local" => match &pool {
+                    Some(pool) => geocoders.push(Arc::new(LocalBanGeocoder::new(pool.clone()))),
+                    None => log::warn!("⚠️ Sin base de datos, BAN local fuera de la cadena de geocoding"),
+                },
+                "mapbox" => match &config.mapbox_token {
+                    Some(token) => geocoders.push(Arc::new(MapboxGeocoder::new(client.clone(), &config.mapbox_url, token))),
+                    None => log::warn!("⚠️ MAPBOX_TOKEN no configurado, Mapbox fuera de la cadena de geocoding"),
+                },
+                "ban" => geocoders.push(Arc::new(BanGeocoder::new(client.clone(), &config.ban_url))),
+                "nominatim" => geocoders.push(Arc::new(
+                    NominatimGeocoder::new(client.clone(), &config.nominatim_url)
+                        .with_min_interval(config.nominatim_min_interval),
+                )),
+                other => log::warn!("⚠️ Geocoder desconocido en GEOCODER_ORDER: {}", other),
+            }
+        }
+
+        let names: Vec<&str> = geocoders.iter().map(|g| g.name()).collect();
+        log::info!("🗺️ Cadena de geocoding: {} (confianza mínima {})", names.join(" → "), config.min_confidence);
+
+        Self::new(geocoders, config.min_confidence)
+    }
+
+    /// Misma cadena con los proveedores remotos contados y sujetos a presupuesto
+    pub fn metered(&self, usage: &GeocodingUsage, company_id: Option<Uuid>) -> Self {
+        let geocoders = self
+            .geocoders
+            .iter()
+            .map(|geocoder| -> Arc<dyn Geocoder> {
+                if geocoder.is_remote() {
+                    Arc::new(MeteredGeocoder::new(geocoder.clone(), usage.clone(), company_id))
+                } else {
+                    geocoder.clone()
+                }
+            })
+            .collect();
+
+        Self::new(geocoders, self.min_confidence)
+    }
+}
+
+#[async_trait]
+impl Geocoder for GeocoderChain {
+    fn name(&self) -> &'static str {
+        "chain"
+    }
+
+    async fn geocode(&self, address: &str) -> Result<GeocodingResponse> {
+        let mut best: Option<GeocodingResponse> = None;
+        let mut last_error = None;
+
+        for geocoder in &self.geocoders {
+            match geocoder.geocode(address).await {
+                Ok(response) if response.is_match() => {
+                    let confidence = response.confidence.unwrap_or(0.0);
+                    if confidence >= self.min_confidence {
+                        return Ok(response);
+                    }
+                    log::info!("🔁 {} con confianza baja ({:.2}) para '{}', probando el siguiente", geocoder.name(), confidence, address);
+                    if best.as_ref().is_none_or(|b| b.confidence.unwrap_or(0.0) < confidence) {
+                        best = Some(response);
+                    }
+                }
+                Ok(_) => log::info!("🔁 {} sin resultado para '{}', probando el siguiente", geocoder.name(), address),
+                Err(e) => {
+                    log::warn!("⚠️ Error de {} geocodificando '{}': {}", geocoder.name(), address, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        // Ninguno superó el umbral: mejor resultado de baja confianza, si lo hay
+        match (best, last_error) {
+            (Some(best), _) => Ok(best),
+            (None, Some(e)) if self.geocoders.len() == 1 => Err(e),
+            _ => Ok(GeocodingResponse::not_found(self.name())),
+        }
+    }
+
+    /// Primer proveedor que devuelva candidatos
+    async fn reverse(&self, position: GeoPoint, limit: usize) -> Result<Vec<ReverseCandidate>> {
+        for geocoder in &self.geocoders {
+            match geocoder.reverse(position, limit).await {
+                Ok(candidates) if !candidates.is_empty() => return Ok(candidates),
+                Ok(_) => log::info!("🔁 {} sin candidatos en ({}, {})", geocoder.name(), position.latitude, position.longitude),
+                Err(e) => log::warn!("⚠️ Error de {} en geocoding inverso: {}", geocoder.name(), e),
+            }
+        }
+
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Query, http::HeaderMap, routing::get, Json, Router};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    async fn spawn_mock(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_mapbox_adapter() {
+        let router = Router::new().route(
+            "/search/geocode/v6/forward",
+            get(|Query(params): Query<HashMap<String, String>>| async move {
+                assert_eq!(params["q"], "15 Rue de la Paix, 75001 Paris");
+                assert_eq!(params["access_token"], "pk.test");
+                assert_eq!(params["country"], "fr");
+                Json(json!({
+                    "type": "FeatureCollection",
+                    "features": [{
+                        "type": "Feature",
+                        "geometry": { "type": "Point", "coordinates": [2.3310, 48.8686] },
+                        "properties": {
+                            "feature_type": "address",
+                            "full_address": "15 Rue de la Paix, 75002 Paris, France",
+                            "match_code": { "confidence": "high" }
+                        }
+                    }]
+                }))
+            }),
+        );
+        let geocoder = MapboxGeocoder::new(Client::new(), spawn_mock(router).await, "pk.test");
+
+        let response = geocoder.geocode("15 Rue de la Paix, 75001 Paris").await.unwrap();
+
+        assert!(response.success);
+        assert_eq!(response.latitude, Some(48.8686));
+        assert_eq!(response.longitude, Some(2.3310));
+        assert_eq!(response.confidence, Some(0.9));
+        assert_eq!(response.provider.as_deref(), Some("mapbox"));
+    }
+
+    #[tokio::test]
+    async fn test_ban_adapter() {
+        let router = Router::new().route(
+            "/search/",
+            get(|Query(params): Query<HashMap<String, String>>| async move {
+                if params["q"].contains("INTROUVABLE") {
+                    return Json(json!({ "type": "FeatureCollection", "features": [] }));
+                }
+                Json(json!({
+                    "type": "FeatureCollection",
+                    "features": [{
+                        "type": "Feature",
+                        "geometry": { "type": "Point", "coordinates": [2.3522, 48.8917] },
+                        "properties": { "label": "16 Rue Jean Cottin 75018 Paris", "score": 0.87, "type": "housenumber" }
+                    }]
+                }))
+            }),
+        );
+        let geocoder = BanGeocoder::new(Client::new(), spawn_mock(router).await);
+
+        let found = geocoder.geocode("16 RUE JEAN COTTIN 75018 PARIS").await.unwrap();
+        assert!(found.success);
+        assert_eq!(found.formatted_address.as_deref(), Some("16 Rue Jean Cottin 75018 Paris"));
+        assert_eq!(found.confidence, Some(0.87));
+
+        let missing = geocoder.geocode("INTROUVABLE").await.unwrap();
+        assert!(!missing.success);
+        assert!(!missing.is_match());
+    }
+
+    #[tokio::test]
+    async fn test_nominatim_adapter() {
+        let router = Router::new().route(
+            "/search",
+            get(|headers: HeaderMap, Query(params): Query<HashMap<String, String>>| async move {
+                assert_eq!(headers["user-agent"], USER_AGENT);
+                assert_eq!(params["format"], "jsonv2");
+                assert_eq!(params["countrycodes"], "fr");
+                Json(json!([{
+                    "lat": "48.8917", "lon": "2.3522",
+                    "display_name": "Rue Jean Cottin, Paris", "place_rank": 26
+                }]))
+            }),
+        );
+        let geocoder = NominatimGeocoder::new(Client::new(), spawn_mock(router).await);
+
+        let response = geocoder.geocode("Rue Jean Cottin, Paris").await.unwrap();
+
+        assert!(response.success);
+        assert_eq!(response.latitude, Some(48.8917));
+        assert_eq!(response.confidence, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn test_nominatim_requests_are_spaced() {
+        let router = Router::new().route("/search", get(|| async { Json(json!([])) }));
+        let geocoder = NominatimGeocoder::new(Client::new(), spawn_mock(router).await)
+            .with_min_interval(Duration::from_millis(200));
+
+        let shared = geocoder.clone();
+        let started = std::time::Instant::now();
+        let (first, second) = tokio::join!(geocoder.geocode("a"), shared.geocode("b"));
+        first.unwrap();
+        second.unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_nominatim_is_opt_in() {
+        if std::env::var("GEOCODER_ORDER").is_err() {
+            assert!(!GeocoderConfig::default().order.contains(&"nominatim".to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_adapter_http_error_is_not_a_match() {
+        let router = Router::new().route("/search/", get(|| async { axum::http::StatusCode::SERVICE_UNAVAILABLE }));
+        let geocoder = BanGeocoder::new(Client::new(), spawn_mock(router).await);
+
+        let response = geocoder.geocode("16 RUE JEAN COTTIN").await.unwrap();
+        assert!(!response.success);
+        assert!(response.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_ban_reverse_sorted_by_distance() {
+        let router = Router::new().route(
+            "/reverse/",
+            get(|Query(params): Query<HashMap<String, String>>| async move {
+                assert_eq!(params["lat"], "48.8921");
+                assert_eq!(params["limit"], "2");
+                Json(json!({
+                    "type": "FeatureCollection",
+                    "features": [
+                        {
+                            "geometry": { "type": "Point", "coordinates": [2.3625, 48.8921] },
+                            "properties": { "label": "18 Rue Jean Cottin 75018 Paris", "score": 0.99 }
+                        },
+                        {
+                            "geometry": { "type": "Point", "coordinates": [2.3613, 48.8921] },
+                            "properties": { "label": "16 Rue Jean Cottin 75018 Paris", "score": 0.99 }
+                        }
+                    ]
+                }))
+            }),
+        );
+        let geocoder = BanGeocoder::new(Client::new(), spawn_mock(router).await);
+
+        let position = GeoPoint { latitude: 48.8921, longitude: 2.3612 };
+        let candidates = geocoder.reverse(position, 2).await.unwrap();
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].address, "16 Rue Jean Cottin 75018 Paris");
+        assert!(candidates[0].distance_m < candidates[1].distance_m);
+        assert_eq!(candidates[0].provider, "ban");
+    }
+
+    #[tokio::test]
+    async fn test_nominatim_reverse_without_result() {
+        let router = Router::new().route(
+            "/reverse",
+            get(|| async { Json(json!({ "error": "Unable to geocode" })) }),
+        );
+        let geocoder = NominatimGeocoder::new(Client::new(), spawn_mock(router).await);
+
+        let candidates = geocoder
+            .reverse(GeoPoint { latitude: 0.0, longitude: 0.0 }, 5)
+            .await
+            .unwrap();
+        assert!(candidates.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chain_reverse_skips_providers_without_candidates() {
+        // Mapbox no implementa geocoding inverso: la cadena pasa a la BAN
+        let router = Router::new().route(
+            "/reverse/",
+            get(|| async {
+                Json(json!({
+                    "type": "FeatureCollection",
+                    "features": [{
+                        "geometry": { "type": "Point", "coordinates": [2.3613, 48.8921] },
+                        "properties": { "label": "16 Rue Jean Cottin 75018 Paris" }
+                    }]
+                }))
+            }),
+        );
+        let base_url = spawn_mock(router).await;
+        let chain = GeocoderChain::new(
+            vec![
+                Arc::new(MapboxGeocoder::new(Client::new(), &base_url, "pk.test")),
+                Arc::new(BanGeocoder::new(Client::new(), &base_url)),
+            ],
+            0.5,
+        );
+
+        let candidates = chain
+            .reverse(GeoPoint { latitude: 48.8921, longitude: 2.3612 }, 5)
+            .await
+            .unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].provider, "ban");
+    }
+
+    /// Geocoder fijo para probar la cadena
+    struct Fixed {
+        name: &'static str,
+        confidence: Option<f64>,
+    }
+
+    #[async_trait]
+    impl Geocoder for Fixed {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn geocode(&self, _address: &str) -> Result<GeocodingResponse> {
+            Ok(match self.confidence {
+                Some(confidence) => GeocodingResponse::found(self.name, 48.89, 2.35, None, confidence),
+                None => GeocodingResponse::not_found(self.name),
+            })
+        }
+    }
+
+    fn chain(links: Vec<(&'static str, Option<f64>)>) -> GeocoderChain {
+        let geocoders = links
+            .into_iter()
+            .map(|(name, confidence)| Arc::new(Fixed { name, confidence }) as Arc<dyn Geocoder>)
+            .collect();
+        GeocoderChain::new(geocoders, 0.5)
+    }
+
+    #[tokio::test]
+    async fn test_chain_falls_back_on_missing_or_low_confidence() {
+        let response = chain(vec![("mapbox", None), ("ban", Some(0.3)), ("nominatim", Some(0.8))])
+            .geocode("x")
+            .await
+            .unwrap();
+        assert_eq!(response.provider.as_deref(), Some("nominatim"));
+
+        // Sin ninguno por encima del umbral: el mejor de baja confianza
+        let response = chain(vec![("mapbox", Some(0.2)), ("ban", Some(0.4)), ("nominatim", None)])
+            .geocode("x")
+            .await
+            .unwrap();
+        assert_eq!(response.provider.as_deref(), Some("ban"));
+
+        let response = chain(vec![("mapbox", None), ("ban", None)]).geocode("x").await.unwrap();
+        assert!(!response.success);
+    }
+
+    #[tokio::test]
+    async fn test_chain_stops_at_first_confident_result() {
+        let response = chain(vec![("ban", Some(0.9)), ("mapbox", Some(1.0))]).geocode("x").await.unwrap();
+        assert_eq!(response.provider.as_deref(), Some("ban"));
+    }
+
+    #[test]
+    fn test_chain_from_config_order() {
+        let config = GeocoderConfig {
+            order: vec!["nominatim".to_string(), "local".to_string(), "mapbox".to_string(), "ban".to_string()],
+            mapbox_token: None,
+            ..GeocoderConfig::default()
+        };
+
+        let chain = GeocoderChain::from_config(config, Client::new(), None);
+        let names: Vec<&str> = chain.geocoders.iter().map(|g| g.name()).collect();
+        assert_eq!(names, vec!["nominatim", "ban"]);
+    }
+}
@@ -0,0 +1,445 @@
+//! Cache persistente de geocoding
+//!
+//! `CachedGeocoder` envuelve la cadena de geocoders: busca primero en Redis,
+//! después en la tabla `geocoding_cache` de Postgres y solo en último caso
+//! llama al proveedor. La clave es la dirección normalizada, así que
+//! "15, rue de la Paix" y "15 RUE DE LA PAIX" comparten entrada.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{debug, info, warn};
+
+use crate::address::parser::{expand_abbreviation, fold_accents};
+use crate::cache::redis_client::geocode_key;
+use crate::cache::{CacheOperations, RedisClient};
+use crate::services::geocoder::{Geocoder, ReverseCandidate};
+use crate::services::geocoding_service::GeocodingResponse;
+use crate::services::route_optimizer::GeoPoint;
+
+/// Normalizar una dirección para usarla como clave de cache:
+/// mayúsculas, sin acentos ni puntuación y con las abreviaturas de vía expandidas
+pub fn normalize_address(address: &str) -> String {
+    let folded: String = fold_accents(address)
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+
+    let mut tokens: Vec<&str> = folded
+        .split_whitespace()
+        .flat_map(|token| expand_abbreviation(token).unwrap_or(token).split('-'))
+        .collect();
+
+    if tokens.last() == Some(&"FRANCE") {
+        tokens.pop();
+    }
+
+    tokens.join(" ")
+}
+
+/// Configuración del cache de geocoding
+#[derive(Debug, Clone)]
+pub struct GeocodingCacheConfig {
+    /// Vida de una entrada en Postgres (días)
+    pub ttl_days: i32,
+    /// Vida de una entrada en Redis (segundos)
+    pub redis_ttl: u64,
+}
+
+impl Default for GeocodingCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_days: std::env::var("GEOCODING_CACHE_TTL_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(90),
+            redis_ttl: 86400, // 1 día en Redis, el resto lo sirve Postgres
+        }
+    }
+}
+
+/// Resultado cacheado de una dirección
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedGeocode {
+    latitude: f64,
+    longitude: f64,
+    formatted_address: Option<String>,
+    provider: String,
+    confidence: Option<f64>,
+    geocoded_at: i64,
+}
+
+impl CachedGeocode {
+    fn from_response(response: &GeocodingResponse) -> Option<Self> {
+        Some(Self {
+            latitude: response.latitude?,
+            longitude: response.longitude?,
+            formatted_address: response.formatted_address.clone(),
+            provider: response.provider.clone().unwrap_or_else(|| "unknown".to_string()),
+            confidence: response.confidence,
+            geocoded_at: chrono::Utc::now().timestamp(),
+        })
+    }
+
+    fn into_response(self) -> GeocodingResponse {
+        let mut response = GeocodingResponse::found(
+            &self.provider,
+            self.latitude,
+            self.longitude,
+            self.formatted_address,
+            self.confidence.unwrap_or(0.0),
+        );
+        response.confidence = self.confidence;
+        response.message = Some("Geocoding from cache".to_string());
+        response
+    }
+}
+
+/// Contadores de uso del cache desde el arranque
+#[derive(Debug, Default)]
+struct CacheCounters {
+    redis_hits: AtomicU64,
+    database_hits: AtomicU64,
+    misses: AtomicU64,
+    stored: AtomicU64,
+}
+
+/// Estadísticas del cache de geocoding
+#[derive(Debug, Clone, Serialize)]
+pub struct GeocodingCacheStats {
+    pub redis_hits: u64,
+    pub database_hits: u64,
+    pub misses: u64,
+    pub stored: u64,
+    /// Fracción de búsquedas resueltas sin llamar al proveedor
+    pub hit_rate: f64,
+    /// Entradas vigentes en Postgres
+    pub entries: Option<i64>,
+    /// Aciertos acumulados en Postgres (histórico, sobrevive a reinicios)
+    pub database_hits_total: Option<i64>,
+}
+
+/// Geocoder con cache Redis + Postgres delante del proveedor
+#[derive(Clone)]
+pub struct CachedGeocoder<C = RedisClient> {
+    inner: Arc<dyn Geocoder>,
+    store: Option<C>,
+    pool: Option<PgPool>,
+    config: GeocodingCacheConfig,
+    counters: Arc<CacheCounters>,
+}
+
+impl<C> CachedGeocoder<C>
+where
+    C: CacheOperations + Clone + Send + Sync + 'static,
+{
+    pub fn new(inner: Arc<dyn Geocoder>, store: Option<C>, pool: Option<PgPool>, config: GeocodingCacheConfig) -> Self {
+        Self {
+            inner,
+            store,
+            pool,
+            config,
+            counters: Arc::new(CacheCounters::default()),
+        }
+    }
+
+    /// Mismo caché (almacenes y contadores) delante de otro geocoder
+    pub fn with_inner(&self, inner: Arc<dyn Geocoder>) -> Self {
+        Self { inner, ..self.clone() }
+    }
+
+    /// Buscar en Redis y después en Postgres (rellenando Redis)
+    async fn lookup(&self, key: &str) -> Option<CachedGeocode> {
+        if let Some(store) = &self.store {
+            match store.get::<CachedGeocode>(&geocode_key(key)).await {
+                Ok(Some(cached)) => {
+                    self.counters.redis_hits.fetch_add(1, Ordering::Relaxed);
+                    return Some(cached);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("⚠️ Error leyendo geocoding cacheado en Redis: {}", e),
+            }
+        }
+
+        let pool = self.pool.as_ref()?;
+        let row = sqlx::query_as::<_, (f64, f64, Option<String>, String, Option<f64>, chrono::DateTime<chrono::Utc>, i64)>(
+            r#"
+            UPDATE geocoding_cache
+            SET hit_count = hit_count + 1, last_hit_at = NOW()
+            WHERE normalized_address = $1 AND expires_at > NOW()
+            RETURNING latitude::float8, longitude::float8, formatted_address, provider, confidence,
+                      COALESCE(updated_at, created_at, NOW()),
+                      EXTRACT(EPOCH FROM expires_at - NOW())::bigint
+            "#,
+        )
+        .bind(key)
+        .fetch_optional(pool)
+        .await;
+
+        match row {
+            Ok(Some((latitude, longitude, formatted_address, provider, confidence, geocoded_at, remaining))) => {
+                self.counters.database_hits.fetch_add(1, Ordering::Relaxed);
+                let cached = CachedGeocode {
+                    latitude,
+                    longitude,
+                    formatted_address,
+                    provider,
+                    confidence,
+                    geocoded_at: geocoded_at.timestamp(),
+                };
+                self.store_redis(key, &cached, remaining.max(0) as u64).await;
+                Some(cached)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("⚠️ Error leyendo geocoding_cache: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn store_redis(&self, key: &str, cached: &CachedGeocode, max_ttl: u64) {
+        let ttl = self.config.redis_ttl.min(max_ttl);
+        if ttl == 0 {
+            return;
+        }
+        if let Some(store) = &self.store {
+            if let Err(e) = store.set(&geocode_key(key), cached, ttl).await {
+                warn!("⚠️ Error cacheando geocoding en Redis: {}", e);
+            }
+        }
+    }
+
+    /// Guardar un resultado en Postgres y Redis
+    async fn save(&self, key: &str, address: &str, cached: &CachedGeocode) {
+        if let Some(pool) = &self.pool {
+            let result = sqlx::query(
+                r#"
+                INSERT INTO geocoding_cache (
+                    normalized_address, original_address, latitude, longitude,
+                    formatted_address, provider, confidence, expires_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, NOW() + make_interval(days => $8))
+                ON CONFLICT (normalized_address) DO UPDATE SET
+                    original_address = EXCLUDED.original_address,
+                    latitude = EXCLUDED.latitude,
+                    longitude = EXCLUDED.longitude,
+                    formatted_address = EXCLUDED.formatted_address,
+                    provider = EXCLUDED.provider,
+                    confidence = EXCLUDED.confidence,
+                    expires_at = EXCLUDED.expires_at,
+                    updated_at = NOW()
+                "#,
+            )
+            .bind(key)
+            .bind(address)
+            .bind(cached.latitude)
+            .bind(cached.longitude)
+            .bind(&cached.formatted_address)
+            .bind(&cached.provider)
+            .bind(cached.confidence)
+            .bind(self.config.ttl_days)
+            .execute(pool)
+            .await;
+
+            if let Err(e) = result {
+                warn!("⚠️ Error guardando geocoding_cache para '{}': {}", key, e);
+            }
+        }
+
+        let ttl_seconds = u64::try_from(self.config.ttl_days).unwrap_or(0) * 86400;
+        self.store_redis(key, cached, ttl_seconds).await;
+        self.counters.stored.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Invalidar manualmente una dirección; devuelve si había entrada en Postgres
+    pub async fn invalidate(&self, address: &str) -> Result<bool> {
+        let key = normalize_address(address);
+        info!("🗑️ Invalidando geocoding cacheado de '{}'", key);
+
+        if let Some(store) = &self.store {
+            store.delete(&geocode_key(&key)).await?;
+        }
+
+        let Some(pool) = &self.pool else {
+            return Ok(false);
+        };
+
+        let deleted = sqlx::query("DELETE FROM geocoding_cache WHERE normalized_address = $1")
+            .bind(&key)
+            .execute(pool)
+            .await?
+            .rows_affected();
+
+        Ok(deleted > 0)
+    }
+
+    /// Contadores desde el arranque más los totales de Postgres
+    pub async fn stats(&self) -> GeocodingCacheStats {
+        let redis_hits = self.counters.redis_hits.load(Ordering::Relaxed);
+        let database_hits = self.counters.database_hits.load(Ordering::Relaxed);
+        let misses = self.counters.misses.load(Ordering::Relaxed);
+        let lookups = redis_hits + database_hits + misses;
+
+        let totals = match &self.pool {
+            Some(pool) => sqlx::query_as::<_, (i64, i64)>(
+                "SELECT COUNT(*), COALESCE(SUM(hit_count), 0)::bigint FROM geocoding_cache WHERE expires_at > NOW()",
+            )
+            .fetch_one(pool)
+            .await
+            .map_err(|e| warn!("⚠️ Error leyendo estadísticas de geocoding_cache: {}", e))
+            .ok(),
+            None => None,
+        };
+
+        GeocodingCacheStats {
+            redis_hits,
+            database_hits,
+            misses,
+            stored: self.counters.stored.load(Ordering::Relaxed),
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                ((redis_hits + database_hits) as f64 / lookups as f64 * 100.0).round() / 100.0
+            },
+            entries: totals.map(|(entries, _)| entries),
+            database_hits_total: totals.map(|(_, hits)| hits),
+        }
+    }
+}
+
+#[async_trait]
+impl<C> Geocoder for CachedGeocoder<C>
+where
+    C: CacheOperations + Clone + Send + Sync + 'static,
+{
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn geocode(&self, address: &str) -> Result<GeocodingResponse> {
+        let key = normalize_address(address);
+        if key.is_empty() {
+            return self.inner.geocode(address).await;
+        }
+
+        if let Some(cached) = self.lookup(&key).await {
+            debug!("📍 Geocoding cache HIT para '{}'", key);
+            return Ok(cached.into_response());
+        }
+
+        self.counters.misses.fetch_add(1, Ordering::Relaxed);
+        debug!("🌐 Geocoding cache MISS para '{}'", key);
+
+        let response = self.inner.geocode(address).await?;
+
+        // Solo se cachean resultados con coordenadas: un "no encontrado" se reintenta
+        if let Some(cached) = CachedGeocode::from_response(&response).filter(|_| response.is_match()) {
+            self.save(&key, address, &cached).await;
+        }
+
+        Ok(response)
+    }
+
+    /// Las posiciones GPS casi nunca se repiten: sin cache
+    async fn reverse(&self, position: GeoPoint, limit: usize) -> Result<Vec<ReverseCandidate>> {
+        self.inner.reverse(position, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryStore;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_normalize_address() {
+        let cases = [
+            ("15, rue de la Paix 75002 Paris", "15 RUE DE LA PAIX 75002 PARIS"),
+            ("15 RUE DE LA PAIX, 75002 PARIS, France", "15 RUE DE LA PAIX 75002 PARIS"),
+            ("3 Bd de l'Hôpital", "3 BOULEVARD DE L HOPITAL"),
+            ("12 av. Émile Zola", "12 AVENUE EMILE ZOLA"),
+            ("8 imp. du Curé  ", "8 IMPASSE DU CURE"),
+            ("1 rue St-Éloi", "1 RUE SAINT ELOI"),
+            ("  ", ""),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(normalize_address(input), expected, "input: {:?}", input);
+        }
+    }
+
+    /// Geocoder que cuenta llamadas; no encuentra direcciones con "INCONNUE"
+    #[derive(Default)]
+    struct CountingGeocoder {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Geocoder for CountingGeocoder {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        async fn geocode(&self, address: &str) -> Result<GeocodingResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if address.to_uppercase().contains("INCONNUE") {
+                return Ok(GeocodingResponse::not_found("counting"));
+            }
+            Ok(GeocodingResponse::found("counting", 48.8917, 2.3522, Some(address.to_string()), 0.9))
+        }
+    }
+
+    fn cached(inner: Arc<CountingGeocoder>) -> CachedGeocoder<MemoryStore> {
+        CachedGeocoder::new(inner, Some(MemoryStore::default()), None, GeocodingCacheConfig::default())
+    }
+
+    #[tokio::test]
+    async fn test_second_lookup_hits_cache() {
+        let inner = Arc::new(CountingGeocoder::default());
+        let geocoder = cached(inner.clone());
+
+        let first = geocoder.geocode("16 rue Jean Cottin, 75018 Paris").await.unwrap();
+        let second = geocoder.geocode("16 RUE JEAN COTTIN 75018 PARIS").await.unwrap();
+
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(second.latitude, first.latitude);
+        assert_eq!(second.provider.as_deref(), Some("counting"));
+        assert_eq!(second.confidence, Some(0.9));
+
+        let stats = geocoder.stats().await;
+        assert_eq!((stats.redis_hits, stats.misses, stats.stored), (1, 1, 1));
+        assert_eq!(stats.hit_rate, 0.5);
+        assert_eq!(stats.entries, None);
+    }
+
+    #[tokio::test]
+    async fn test_not_found_is_not_cached() {
+        let inner = Arc::new(CountingGeocoder::default());
+        let geocoder = cached(inner.clone());
+
+        for _ in 0..2 {
+            let response = geocoder.geocode("75 RUE INCONNUE, 75018 PARIS").await.unwrap();
+            assert!(!response.success);
+        }
+
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(geocoder.stats().await.stored, 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_forces_new_lookup() {
+        let inner = Arc::new(CountingGeocoder::default());
+        let geocoder = cached(inner.clone());
+
+        geocoder.geocode("16 rue Jean Cottin").await.unwrap();
+        geocoder.invalidate("16 RUE JEAN COTTIN").await.unwrap();
+        geocoder.geocode("16 rue Jean Cottin").await.unwrap();
+
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,6 +1,8 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::services::geocoder::Geocoder;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GeocodingRequest {
@@ -15,135 +17,86 @@ pub struct GeocodingResponse {
     pub formatted_address: Option<String>,
     pub message: Option<String>,
     pub error: Option<String>,
+    /// Confianza del proveedor (0.0 - 1.0)
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    /// Proveedor que resolvió la dirección
+    #[serde(default)]
+    pub provider: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct MapboxGeocodingResponse {
-    #[serde(rename = "type")]
-    response_type: String,
-    features: Vec<MapboxFeature>,
-}
+impl GeocodingResponse {
+    /// Dirección encontrada por `provider`
+    pub fn found(provider: &str, latitude: f64, longitude: f64, formatted_address: Option<String>, confidence: f64) -> Self {
+        Self {
+            success: true,
+            latitude: Some(latitude),
+            longitude: Some(longitude),
+            formatted_address,
+            message: Some("Geocoding successful".to_string()),
+            error: None,
+            confidence: Some(confidence),
+            provider: Some(provider.to_string()),
+        }
+    }
 
-#[derive(Debug, Deserialize)]
-struct MapboxFeature {
-    #[serde(rename = "type")]
-    feature_type: String,
-    geometry: MapboxGeometry,
-    properties: MapboxProperties,
-}
+    /// El proveedor respondió pero sin resultado
+    pub fn not_found(provider: &str) -> Self {
+        Self {
+            success: false,
+            latitude: None,
+            longitude: None,
+            formatted_address: None,
+            message: Some("No coordinates found for this address".to_string()),
+            error: None,
+            confidence: None,
+            provider: Some(provider.to_string()),
+        }
+    }
 
-#[derive(Debug, Deserialize)]
-struct MapboxGeometry {
-    #[serde(rename = "type")]
-    geometry_type: String,
-    coordinates: Vec<f64>, // [longitude, latitude]
-}
+    /// Error del proveedor (HTTP, red...)
+    pub fn failed(provider: &str, error: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            latitude: None,
+            longitude: None,
+            formatted_address: None,
+            message: None,
+            error: Some(error.into()),
+            confidence: None,
+            provider: Some(provider.to_string()),
+        }
+    }
 
-#[derive(Debug, Deserialize)]
-struct MapboxProperties {
-    #[serde(rename = "full_address")]
-    full_address: Option<String>,
-    name: Option<String>,
-    #[serde(rename = "place_name")]
-    place_name: Option<String>,
+    /// Resultado con coordenadas
+    pub fn is_match(&self) -> bool {
+        self.success && self.latitude.is_some() && self.longitude.is_some()
+    }
 }
 
 pub struct GeocodingService {
-    mapbox_token: String,
-    client: reqwest::Client,
+    geocoder: Arc<dyn Geocoder>,
 }
 
 impl GeocodingService {
-    pub fn new(mapbox_token: String) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(10))
-            .build()
-            .expect("Failed to create HTTP client");
-
-        Self {
-            mapbox_token,
-            client,
-        }
+    pub fn new(geocoder: Arc<dyn Geocoder>) -> Self {
+        Self { geocoder }
     }
 
     pub async fn geocode_address(&self, address: &str) -> Result<GeocodingResponse> {
         log::info!("🗺️ Geocoding address: {}", address);
 
-        // URL encode la dirección
-        let encoded_address = urlencoding::encode(address);
-        
-        // Construir la URL según la documentación oficial
-        let url = format!(
-            "https://api.mapbox.com/search/geocode/v6/forward?q={}&access_token={}&country=fr&limit=1",
-            encoded_address,
-            self.mapbox_token
-        );
-
-        log::info!("🌐 Making request to: {}", url);
-
-        // Hacer la petición HTTP
-        let response = self.client
-            .get(&url)
-            .header("User-Agent", "DeliveryRouting/1.0")
-            .send()
-            .await?;
-
-        let status = response.status();
-        log::info!("📡 Response status: {}", status);
-
-        if !status.is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            log::error!("❌ Geocoding failed with status {}: {}", status, error_text);
-            return Ok(GeocodingResponse {
-                success: false,
-                latitude: None,
-                longitude: None,
-                formatted_address: None,
-                message: None,
-                error: Some(format!("Geocoding failed: {}", status)),
-            });
-        }
+        let response = self.geocoder.geocode(address).await?;
 
-        let response_text = response.text().await?;
-        log::info!("📄 Response body: {}", response_text);
-
-        // Parsear la respuesta JSON
-        let mapbox_response: MapboxGeocodingResponse = serde_json::from_str(&response_text)
-            .map_err(|e| anyhow!("Failed to parse geocoding response: {}", e))?;
-
-        // Extraer la primera feature (resultado más relevante)
-        if let Some(feature) = mapbox_response.features.first() {
-            if feature.geometry.coordinates.len() >= 2 {
-                let longitude = feature.geometry.coordinates[0];
-                let latitude = feature.geometry.coordinates[1];
-                
-                let formatted_address = feature.properties.full_address.clone()
-                    .or_else(|| feature.properties.place_name.clone())
-                    .or_else(|| feature.properties.name.clone());
-
-                log::info!("✅ Geocoding successful: {} -> ({}, {})", 
-                    address, latitude, longitude);
-
-                return Ok(GeocodingResponse {
-                    success: true,
-                    latitude: Some(latitude),
-                    longitude: Some(longitude),
-                    formatted_address,
-                    message: Some("Geocoding successful".to_string()),
-                    error: None,
-                });
-            }
+        if response.is_match() {
+            log::info!("✅ Geocoding successful ({}): {} -> ({:?}, {:?})",
+                response.provider.as_deref().unwrap_or(self.geocoder.name()),
+                address, response.latitude, response.longitude);
+        } else {
+            log::warn!("⚠️ No coordinates found for address: {}", address);
         }
 
-        log::warn!("⚠️ No coordinates found for address: {}", address);
-        Ok(GeocodingResponse {
-            success: false,
-            latitude: None,
-            longitude: None,
-            formatted_address: None,
-            message: Some("No coordinates found for this address".to_string()),
-            error: None,
-        })
+        Ok(response)
     }
 
     pub async fn batch_geocode(&self, addresses: Vec<String>) -> Result<Vec<GeocodingResponse>> {
@@ -168,14 +121,7 @@ impl GeocodingService {
                     Ok(response) => results.push(response),
                     Err(e) => {
                         log::error!("❌ Batch geocoding error: {}", e);
-                        results.push(GeocodingResponse {
-                            success: false,
-                            latitude: None,
-                            longitude: None,
-                            formatted_address: None,
-                            message: None,
-                            error: Some(e.to_string()),
-                        });
+                        results.push(GeocodingResponse::failed(self.geocoder.name(), e.to_string()));
                     }
                 }
             }
@@ -192,6 +138,7 @@ impl GeocodingService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::geocoder::MapboxGeocoder;
 
     #[tokio::test]
     async fn test_geocoding_service() {
@@ -203,7 +150,8 @@ mod tests {
             return;
         }
 
-        let service = GeocodingService::new(token);
+        let geocoder = MapboxGeocoder::new(reqwest::Client::new(), "https://api.mapbox.com", token);
+        let service = GeocodingService::new(Arc::new(geocoder));
         let result = service.geocode_address("15 Rue de la Paix, 75001 Paris").await;
         
         match result {
@@ -0,0 +1,438 @@
+//! Consumo de geocoding por empresa y proveedor
+//!
+//! Cada llamada a un proveedor remoto se cuenta por día en `geocoding_usage`;
+//! los totales del mes salen de sumar los días. Los presupuestos se aplican al
+//! total del proveedor (la capa gratuita de Mapbox es por token): pasado el
+//! umbral blando se avisa y pasado el duro se deja de llamar al proveedor.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::services::geocoder::{Geocoder, ReverseCandidate};
+use crate::services::geocoding_service::GeocodingResponse;
+use crate::services::route_optimizer::GeoPoint;
+
+/// Periodo de un presupuesto
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Daily,
+    Monthly,
+}
+
+impl BudgetPeriod {
+    /// Primer día del periodo que contiene `date`
+    pub fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            BudgetPeriod::Daily => date,
+            BudgetPeriod::Monthly => date.with_day(1).unwrap_or(date),
+        }
+    }
+}
+
+/// Estado de un proveedor frente a su presupuesto
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetStatus {
+    Ok,
+    Warning,
+    Exceeded,
+}
+
+/// Presupuesto de un proveedor: `mapbox:monthly:80000:100000`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GeocodingBudget {
+    pub provider: String,
+    pub period: BudgetPeriod,
+    pub soft_limit: i64,
+    pub hard_limit: i64,
+}
+
+impl GeocodingBudget {
+    /// `proveedor:daily|monthly:blando:duro`
+    pub fn parse(spec: &str) -> Option<Self> {
+        let parts: Vec<&str> = spec.trim().split(':').map(str::trim).collect();
+        let [provider, period, soft, hard] = parts.as_slice() else {
+            return None;
+        };
+        let period = match *period {
+            "daily" => BudgetPeriod::Daily,
+            "monthly" => BudgetPeriod::Monthly,
+            _ => return None,
+        };
+        let (soft_limit, hard_limit) = (soft.parse().ok()?, hard.parse().ok()?);
+        if provider.is_empty() || soft_limit > hard_limit {
+            return None;
+        }
+
+        Some(Self { provider: provider.to_string(), period, soft_limit, hard_limit })
+    }
+
+    pub fn status(&self, used: i64) -> BudgetStatus {
+        if used >= self.hard_limit {
+            BudgetStatus::Exceeded
+        } else if used >= self.soft_limit {
+            BudgetStatus::Warning
+        } else {
+            BudgetStatus::Ok
+        }
+    }
+}
+
+/// Presupuestos configurados
+#[derive(Debug, Clone)]
+pub struct GeocodingUsageConfig {
+    pub budgets: Vec<GeocodingBudget>,
+}
+
+impl Default for GeocodingUsageConfig {
+    fn default() -> Self {
+        let spec = std::env::var("GEOCODING_BUDGETS").unwrap_or_else(|_| "mapbox:monthly:80000:100000".to_string());
+        let budgets = spec
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .filter_map(|s| {
+                let budget = GeocodingBudget::parse(s);
+                if budget.is_none() {
+                    log::warn!("⚠️ Presupuesto de geocoding inválido en GEOCODING_BUDGETS: {}", s);
+                }
+                budget
+            })
+            .collect();
+
+        Self { budgets }
+    }
+}
+
+/// Llamadas de un día
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct UsageRow {
+    pub company_id: Option<Uuid>,
+    pub provider: String,
+    pub usage_date: NaiveDate,
+    pub requests: i64,
+}
+
+/// Consumo de la empresa en un proveedor. El consumo de las demás empresas no
+/// se expone: del presupuesto global solo se da el estado
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderUsage {
+    pub provider: String,
+    pub company_today: i64,
+    pub company_month: i64,
+    pub budget_status: Option<BudgetStatus>,
+}
+
+/// Informe de `GET /api/geocoding/usage`
+#[derive(Debug, Clone, Serialize)]
+pub struct GeocodingUsageReport {
+    pub date: NaiveDate,
+    pub month_start: NaiveDate,
+    pub providers: Vec<ProviderUsage>,
+    /// Días del mes en curso de la empresa
+    pub daily: Vec<UsageRow>,
+}
+
+type MemoryKey = (Option<Uuid>, String, NaiveDate);
+
+/// Contadores de llamadas (tabla `geocoding_usage`; en memoria sin base de datos)
+#[derive(Clone)]
+pub struct GeocodingUsage {
+    pool: Option<PgPool>,
+    config: Arc<GeocodingUsageConfig>,
+    memory: Arc<Mutex<HashMap<MemoryKey, i64>>>,
+}
+
+impl GeocodingUsage {
+    pub fn new(pool: Option<PgPool>, config: GeocodingUsageConfig) -> Self {
+        Self {
+            pool,
+            config: Arc::new(config),
+            memory: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn budget(&self, provider: &str) -> Option<&GeocodingBudget> {
+        self.config.budgets.iter().find(|b| b.provider == provider)
+    }
+
+    /// Contar una llamada al proveedor
+    pub async fn record(&self, company_id: Option<Uuid>, provider: &str) -> Result<()> {
+        let today = Utc::now().date_naive();
+
+        match &self.pool {
+            Some(pool) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO geocoding_usage (company_id, provider, usage_date, requests)
+                    VALUES ($1, $2, $3, 1)
+                    ON CONFLICT (company_id, provider, usage_date) DO UPDATE SET
+                        requests = geocoding_usage.requests + 1,
+                        updated_at = NOW()
+                    "#,
+                )
+                .bind(company_id)
+                .bind(provider)
+                .bind(today)
+                .execute(pool)
+                .await?;
+            }
+            None => {
+                let mut memory = self.memory.lock().unwrap_or_else(|e| e.into_inner());
+                *memory.entry((company_id, provider.to_string(), today)).or_insert(0) += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Días desde `since` (incluido), opcionalmente de un solo proveedor
+    async fn rows_since(&self, since: NaiveDate, provider: Option<&str>) -> Result<Vec<UsageRow>> {
+        match &self.pool {
+            Some(pool) => Ok(sqlx::query_as::<_, UsageRow>(
+                r#"
+                SELECT company_id, provider, usage_date, requests::bigint AS requests
+                FROM geocoding_usage
+                WHERE usage_date >= $1 AND ($2::text IS NULL OR provider = $2)
+                ORDER BY usage_date DESC, provider
+                "#,
+            )
+            .bind(since)
+            .bind(provider)
+            .fetch_all(pool)
+            .await?),
+            None => {
+                let memory = self.memory.lock().unwrap_or_else(|e| e.into_inner());
+                let mut rows: Vec<UsageRow> = memory
+                    .iter()
+                    .filter(|((_, p, date), _)| *date >= since && provider.is_none_or(|provider| p == provider))
+                    .map(|((company_id, provider, usage_date), requests)| UsageRow {
+                        company_id: *company_id,
+                        provider: provider.clone(),
+                        usage_date: *usage_date,
+                        requests: *requests,
+                    })
+                    .collect();
+                rows.sort_by(|a, b| b.usage_date.cmp(&a.usage_date).then_with(|| a.provider.cmp(&b.provider)));
+                Ok(rows)
+            }
+        }
+    }
+
+    /// Estado del proveedor en su periodo actual (`Ok` si no tiene presupuesto)
+    pub async fn check(&self, provider: &str) -> Result<BudgetStatus> {
+        let Some(budget) = self.budget(provider) else {
+            return Ok(BudgetStatus::Ok);
+        };
+
+        let since = budget.period.start(Utc::now().date_naive());
+        let used: i64 = self.rows_since(since, Some(provider)).await?.iter().map(|r| r.requests).sum();
+        Ok(budget.status(used))
+    }
+
+    /// Informe del día y del mes para una empresa
+    pub async fn report(&self, company_id: Uuid) -> Result<GeocodingUsageReport> {
+        let today = Utc::now().date_naive();
+        let month_start = BudgetPeriod::Monthly.start(today);
+        let rows = self.rows_since(month_start, None).await?;
+
+        let mut providers: Vec<String> = rows
+            .iter()
+            .filter(|r| r.company_id == Some(company_id))
+            .map(|r| r.provider.clone())
+            .collect();
+        providers.extend(self.config.budgets.iter().map(|b| b.provider.clone()));
+        providers.sort();
+        providers.dedup();
+
+        let sum = |provider: &str, since: NaiveDate, company: Option<Uuid>| -> i64 {
+            rows.iter()
+                .filter(|r| r.provider == provider && r.usage_date >= since)
+                .filter(|r| company.is_none() || r.company_id == company)
+                .map(|r| r.requests)
+                .sum()
+        };
+
+        let providers = providers
+            .into_iter()
+            .map(|provider| {
+                let budget_status = self
+                    .budget(&provider)
+                    .map(|budget| budget.status(sum(&provider, budget.period.start(today), None)));
+                ProviderUsage {
+                    company_today: sum(&provider, today, Some(company_id)),
+                    company_month: sum(&provider, month_start, Some(company_id)),
+                    budget_status,
+                    provider,
+                }
+            })
+            .collect();
+
+        let daily = rows.into_iter().filter(|r| r.company_id == Some(company_id)).collect();
+
+        Ok(GeocodingUsageReport { date: today, month_start, providers, daily })
+    }
+}
+
+/// Geocoder remoto con contador y presupuesto
+pub struct MeteredGeocoder {
+    inner: Arc<dyn Geocoder>,
+    usage: GeocodingUsage,
+    company_id: Option<Uuid>,
+}
+
+impl MeteredGeocoder {
+    pub fn new(inner: Arc<dyn Geocoder>, usage: GeocodingUsage, company_id: Option<Uuid>) -> Self {
+        Self { inner, usage, company_id }
+    }
+
+    /// `false` si el proveedor ya agotó su presupuesto
+    async fn allowed(&self) -> bool {
+        let provider = self.inner.name();
+        match self.usage.check(provider).await {
+            Ok(BudgetStatus::Exceeded) => {
+                log::warn!("🚫 Presupuesto de geocoding de {} agotado, no se llama al proveedor", provider);
+                false
+            }
+            Ok(BudgetStatus::Warning) => {
+                log::warn!("⚠️ {} cerca de su presupuesto de geocoding", provider);
+                true
+            }
+            Ok(BudgetStatus::Ok) => true,
+            Err(e) => {
+                log::warn!("⚠️ Error consultando el consumo de {}: {}", provider, e);
+                true
+            }
+        }
+    }
+
+    async fn record(&self) {
+        if let Err(e) = self.usage.record(self.company_id, self.inner.name()).await {
+            log::warn!("⚠️ Error registrando el consumo de {}: {}", self.inner.name(), e);
+        }
+    }
+}
+
+#[async_trait]
+impl Geocoder for MeteredGeocoder {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn geocode(&self, address: &str) -> Result<GeocodingResponse> {
+        if !self.allowed().await {
+            return Ok(GeocodingResponse::failed(self.name(), "Presupuesto de geocoding agotado".to_string()));
+        }
+
+        let result = self.inner.geocode(address).await;
+        self.record().await;
+        result
+    }
+
+    async fn reverse(&self, position: GeoPoint, limit: usize) -> Result<Vec<ReverseCandidate>> {
+        if !self.allowed().await {
+            return Ok(Vec::new());
+        }
+
+        let result = self.inner.reverse(position, limit).await;
+        self.record().await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counting {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Geocoder for Counting {
+        fn name(&self) -> &'static str {
+            "mapbox"
+        }
+
+        async fn geocode(&self, _address: &str) -> Result<GeocodingResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(GeocodingResponse::found(self.name(), 48.89, 2.36, None, 1.0))
+        }
+    }
+
+    fn usage(spec: &str) -> GeocodingUsage {
+        GeocodingUsage::new(None, GeocodingUsageConfig { budgets: vec![GeocodingBudget::parse(spec).unwrap()] })
+    }
+
+    #[test]
+    fn test_parse_budget() {
+        let budget = GeocodingBudget::parse("mapbox:monthly:80000:100000").unwrap();
+        assert_eq!(budget.period, BudgetPeriod::Monthly);
+        assert_eq!(budget.status(79_999), BudgetStatus::Ok);
+        assert_eq!(budget.status(80_000), BudgetStatus::Warning);
+        assert_eq!(budget.status(100_000), BudgetStatus::Exceeded);
+
+        assert!(GeocodingBudget::parse("nominatim:daily:800:1000").is_some());
+        assert!(GeocodingBudget::parse("mapbox:weekly:1:2").is_none());
+        assert!(GeocodingBudget::parse("mapbox:daily:10:5").is_none());
+        assert!(GeocodingBudget::parse("mapbox:daily:10").is_none());
+    }
+
+    #[test]
+    fn test_period_start() {
+        let date = NaiveDate::from_ymd_opt(2025, 9, 17).unwrap();
+        assert_eq!(BudgetPeriod::Daily.start(date), date);
+        assert_eq!(BudgetPeriod::Monthly.start(date), NaiveDate::from_ymd_opt(2025, 9, 1).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_metered_geocoder_stops_at_hard_limit() {
+        let usage = usage("mapbox:daily:1:2");
+        let inner = Arc::new(Counting { calls: AtomicUsize::new(0) });
+        let company_id = Uuid::new_v4();
+        let geocoder = MeteredGeocoder::new(inner.clone(), usage.clone(), Some(company_id));
+
+        assert!(geocoder.geocode("16 RUE JEAN COTTIN 75018 PARIS").await.unwrap().is_match());
+        assert_eq!(usage.check("mapbox").await.unwrap(), BudgetStatus::Warning);
+        assert!(geocoder.geocode("3 RUE ORDENER 75018 PARIS").await.unwrap().is_match());
+        assert_eq!(usage.check("mapbox").await.unwrap(), BudgetStatus::Exceeded);
+
+        // Agotado: no se llama al proveedor
+        let response = geocoder.geocode("12 RUE MARCADET 75018 PARIS").await.unwrap();
+        assert!(!response.success);
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+
+        let report = usage.report(company_id).await.unwrap();
+        let mapbox = &report.providers[0];
+        assert_eq!((mapbox.company_today, mapbox.company_month), (2, 2));
+        assert_eq!(mapbox.budget_status, Some(BudgetStatus::Exceeded));
+        assert_eq!(report.daily.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_report_separates_companies() {
+        let usage = usage("mapbox:monthly:10:20");
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        usage.record(Some(a), "mapbox").await.unwrap();
+        usage.record(Some(b), "mapbox").await.unwrap();
+        usage.record(Some(b), "ban").await.unwrap();
+
+        // Ni el consumo ni los proveedores de la otra empresa aparecen en el informe
+        let report = usage.report(a).await.unwrap();
+        let providers: Vec<(&str, i64)> = report.providers.iter().map(|p| (p.provider.as_str(), p.company_month)).collect();
+        assert_eq!(providers, vec![("mapbox", 1)]);
+        assert_eq!(report.providers[0].budget_status, Some(BudgetStatus::Ok));
+        assert!(report.daily.iter().all(|r| r.company_id == Some(a)));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["providers"][0].get("total_month").is_none());
+    }
+}
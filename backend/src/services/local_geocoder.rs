@@ -0,0 +1,275 @@
+//! Geocoding local sobre la BAN importada
+//!
+//! `LocalBanGeocoder` resuelve número, calle y código postal contra la tabla
+//! `ban_addresses` (ver `ban_import`) sin salir a la red. El nombre de la
+//! calle se compara por trigramas para tolerar erratas y abreviaturas.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::services::geocoder::{Geocoder, ReverseCandidate};
+use crate::services::geocoding_cache::normalize_address;
+use crate::services::geocoding_service::GeocodingResponse;
+use crate::services::route_optimizer::GeoPoint;
+
+/// Similitud mínima entre calles para aceptar la coincidencia
+const MIN_STREET_SIMILARITY: f64 = 0.6;
+
+/// Radio de búsqueda del geocoding inverso (metros)
+const REVERSE_RADIUS_M: f64 = 250.0;
+
+/// Indicativos de repetición de la BAN (16 BIS, 3 TER...)
+const REPETITIONS: [&str; 7] = ["BIS", "TER", "QUATER", "A", "B", "C", "D"];
+
+/// Dirección descompuesta para la búsqueda local
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressQuery {
+    pub number: Option<i32>,
+    pub repetition: Option<String>,
+    pub street: String,
+    pub postal_code: String,
+}
+
+impl AddressQuery {
+    /// `16 BIS RUE JEAN COTTIN 75018 PARIS` → número, calle y código postal.
+    /// Sin código postal no se puede acotar la búsqueda: `None`.
+    pub fn parse(address: &str) -> Option<Self> {
+        let normalized = normalize_address(address);
+        let tokens: Vec<&str> = normalized.split_whitespace().collect();
+
+        let postal_index = tokens
+            .iter()
+            .rposition(|t| t.len() == 5 && t.chars().all(|c| c.is_ascii_digit()))?;
+        let mut before = &tokens[..postal_index];
+
+        let mut number = None;
+        let mut repetition = None;
+        if let Some(first) = before.first() {
+            // "16" o "16B"
+            let digits: String = first.chars().take_while(|c| c.is_ascii_digit()).collect();
+            if !digits.is_empty() {
+                number = digits.parse().ok();
+                let suffix = &first[digits.len()..];
+                if REPETITIONS.contains(&suffix) {
+                    repetition = Some(suffix.to_string());
+                }
+                before = &before[1..];
+            }
+        }
+        if number.is_some() && repetition.is_none() {
+            if let Some(next) = before.first().filter(|t| REPETITIONS.contains(t) && before.len() > 1) {
+                repetition = Some(next.to_string());
+                before = &before[1..];
+            }
+        }
+
+        if before.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            number,
+            repetition,
+            street: before.join(" "),
+            postal_code: tokens[postal_index].to_string(),
+        })
+    }
+}
+
+fn trigrams(text: &str) -> HashSet<String> {
+    let mut grams = HashSet::new();
+    for word in text.split_whitespace() {
+        let padded: Vec<char> = format!("  {} ", word).chars().collect();
+        for window in padded.windows(3) {
+            grams.insert(window.iter().collect());
+        }
+    }
+    grams
+}
+
+/// Similitud por trigramas (coeficiente de Dice, 0.0 - 1.0)
+pub fn street_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let shared = a.intersection(&b).count();
+    2.0 * shared as f64 / (a.len() + b.len()) as f64
+}
+
+/// `16 bis Rue Jean Cottin, 75018 Paris`
+fn format_ban_address(numero: i32, rep: Option<&str>, nom_voie: &str, code_postal: &str, nom_commune: &str) -> String {
+    match rep {
+        Some(rep) => format!("{} {} {}, {} {}", numero, rep.to_lowercase(), nom_voie, code_postal, nom_commune),
+        None => format!("{} {}, {} {}", numero, nom_voie, code_postal, nom_commune),
+    }
+}
+
+/// Geocoder sobre la tabla `ban_addresses`
+#[derive(Debug, Clone)]
+pub struct LocalBanGeocoder {
+    pool: PgPool,
+}
+
+impl LocalBanGeocoder {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Confianza según la calle encontrada y la precisión del número
+    fn confidence(similarity: f64, query: &AddressQuery, numero: i32, rep: Option<&str>) -> f64 {
+        let precision = match query.number {
+            Some(n) if n == numero && query.repetition.as_deref() == rep => 1.0,
+            Some(n) if n == numero => 0.9,
+            Some(_) => 0.7, // número cercano de la misma calle
+            None => 0.5,    // solo la calle
+        };
+        ((similarity * precision) * 100.0).round() / 100.0
+    }
+}
+
+#[async_trait]
+impl Geocoder for LocalBanGeocoder {
+    fn name(&self) -> &'static str {
+        "ban_local"
+    }
+
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    async fn geocode(&self, address: &str) -> Result<GeocodingResponse> {
+        let Some(query) = AddressQuery::parse(address) else {
+            return Ok(GeocodingResponse::not_found(self.name()));
+        };
+
+        let streets: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT nom_voie_normalized FROM ban_addresses WHERE code_postal = $1",
+        )
+        .bind(&query.postal_code)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let best = streets
+            .into_iter()
+            .map(|street| (street_similarity(&query.street, &street), street))
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+
+        let Some((similarity, street)) = best.filter(|(similarity, _)| *similarity >= MIN_STREET_SIMILARITY) else {
+            return Ok(GeocodingResponse::not_found(self.name()));
+        };
+
+        let row = sqlx::query_as::<_, (i32, Option<String>, String, String, String, f64, f64)>(
+            r#"
+            SELECT numero, rep, nom_voie, code_postal, nom_commune,
+                   ST_Y(location::geometry), ST_X(location::geometry)
+            FROM ban_addresses
+            WHERE code_postal = $1 AND nom_voie_normalized = $2
+            ORDER BY ABS(numero - $3), rep IS DISTINCT FROM $4, rep NULLS FIRST
+            LIMIT 1
+            "#,
+        )
+        .bind(&query.postal_code)
+        .bind(&street)
+        .bind(query.number.unwrap_or(0))
+        .bind(&query.repetition)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((numero, rep, nom_voie, code_postal, nom_commune, latitude, longitude)) = row else {
+            return Ok(GeocodingResponse::not_found(self.name()));
+        };
+
+        let formatted_address = format_ban_address(numero, rep.as_deref(), &nom_voie, &code_postal, &nom_commune);
+
+        Ok(GeocodingResponse::found(
+            self.name(),
+            latitude,
+            longitude,
+            Some(formatted_address),
+            Self::confidence(similarity, &query, numero, rep.as_deref()),
+        ))
+    }
+
+    async fn reverse(&self, position: GeoPoint, limit: usize) -> Result<Vec<ReverseCandidate>> {
+        let rows = sqlx::query_as::<_, (i32, Option<String>, String, String, String, f64, f64, f64)>(
+            r#"
+            WITH origin AS (
+                SELECT ST_SetSRID(ST_MakePoint($2, $1), 4326)::geography AS point
+            )
+            SELECT numero, rep, nom_voie, code_postal, nom_commune,
+                   ST_Y(location::geometry), ST_X(location::geometry),
+                   ST_Distance(location, origin.point)
+            FROM ban_addresses, origin
+            WHERE ST_DWithin(location, origin.point, $3)
+            ORDER BY ST_Distance(location, origin.point)
+            LIMIT $4
+            "#,
+        )
+        .bind(position.latitude)
+        .bind(position.longitude)
+        .bind(REVERSE_RADIUS_M)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(numero, rep, nom_voie, code_postal, nom_commune, latitude, longitude, distance_m)| ReverseCandidate {
+                address: format_ban_address(numero, rep.as_deref(), &nom_voie, &code_postal, &nom_commune),
+                latitude,
+                longitude,
+                distance_m: distance_m.round(),
+                provider: self.name().to_string(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address_query() {
+        let cases = [
+            ("16 rue Jean Cottin, 75018 Paris", Some(16), None, "RUE JEAN COTTIN"),
+            ("16 bis rue Jean Cottin 75018 PARIS", Some(16), Some("BIS"), "RUE JEAN COTTIN"),
+            ("16B RUE JEAN COTTIN 75018 PARIS", Some(16), Some("B"), "RUE JEAN COTTIN"),
+            ("Rue Jean Cottin, 75018 Paris", None, None, "RUE JEAN COTTIN"),
+            ("3 Bd Barbès, 75018 Paris, France", Some(3), None, "BOULEVARD BARBES"),
+        ];
+
+        for (input, number, repetition, street) in cases {
+            let query = AddressQuery::parse(input).unwrap();
+            assert_eq!(query.number, number, "{}", input);
+            assert_eq!(query.repetition.as_deref(), repetition, "{}", input);
+            assert_eq!(query.street, street, "{}", input);
+            assert_eq!(query.postal_code, "75018", "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_requires_postal_code_and_street() {
+        assert!(AddressQuery::parse("16 rue Jean Cottin, Paris").is_none());
+        assert!(AddressQuery::parse("75, 75018 PARIS").is_none());
+    }
+
+    #[test]
+    fn test_street_similarity_tolerates_typos() {
+        assert_eq!(street_similarity("RUE JEAN COTTIN", "RUE JEAN COTTIN"), 1.0);
+        assert!(street_similarity("RUE JEAN COTIN", "RUE JEAN COTTIN") >= MIN_STREET_SIMILARITY);
+        assert!(street_similarity("RUE JEAN COTTIN", "RUE MARC SEGUIN") < MIN_STREET_SIMILARITY);
+    }
+
+    #[test]
+    fn test_confidence_by_number_precision() {
+        let query = AddressQuery::parse("16 bis rue Jean Cottin 75018 Paris").unwrap();
+        assert_eq!(LocalBanGeocoder::confidence(1.0, &query, 16, Some("BIS")), 1.0);
+        assert_eq!(LocalBanGeocoder::confidence(1.0, &query, 16, None), 0.9);
+        assert_eq!(LocalBanGeocoder::confidence(1.0, &query, 18, None), 0.7);
+    }
+}
@@ -10,7 +10,27 @@ pub mod colis_prive_service;
 // pub mod colis_prive_complete_flow_service; // Comentado temporalmente por errores de compilación
 pub mod colis_prive_web_service;
 pub mod geocoding_service;
+pub mod geocoder;
+pub mod geocoding_cache;
+pub mod geocoding_usage;
+pub mod local_geocoder;
+pub mod ban_import;
 pub mod address_validation;
+pub mod address_corrections;
+pub mod sectors;
+pub mod plausibility;
+pub mod tournee_import_service;
+pub mod validation_jobs;
+pub mod status_history;
+pub mod blob_store;
+pub mod proof_of_delivery;
+pub mod offline_sync;
+pub mod failure_policies;
+pub mod credential_vault;
+pub mod route_optimizer;
+pub mod time_window_scheduler;
+pub mod zone_clustering;
+pub mod distance_matrix;
 
 pub use colis_prive_service::*;
 // pub use app_version_service::*; // Comentado temporalmente
@@ -18,4 +38,6 @@ pub use colis_prive_service::*;
 // pub use colis_prive_complete_flow_service::*; // Comentado temporalmente
 pub use colis_prive_web_service::*;
 pub use geocoding_service::*;
-pub use address_validation::*;
\ No newline at end of file
+pub use address_validation::*;
+pub use tournee_import_service::*;
+pub use credential_vault::*;
\ No newline at end of file
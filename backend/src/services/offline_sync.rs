@@ -0,0 +1,360 @@
+//! Sincronización offline de la app de drivers
+//!
+//! Reglas de conflicto por campo:
+//! - `delivery_status` (y fotos, firmas, motivo de fallo que van con él):
+//!   server-wins, decide la máquina de estados de `lifecycle` con el estado actual.
+//! - `driver_notes`, `package_condition` y la posición del driver:
+//!   last-writer-wins según la hora del dispositivo, con un reloj por campo.
+//!
+//! Cada operación se guarda en `sync_operations` con su resultado, en la misma
+//! transacción que sus cambios: reenviar la misma clave no vuelve a aplicar nada.
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::models::sync::{
+    FieldResolution, FieldResult, SyncOperation, SyncOperationResult, SyncOutcome,
+};
+
+/// Operaciones como máximo por petición
+pub const MAX_OPERATIONS: usize = 500;
+
+/// Solape al leer el delta: una transacción que escribió antes del cursor pero
+/// hizo commit después sigue apareciendo (el cliente aplica los cambios como upsert)
+pub const CURSOR_OVERLAP_SECONDS: i64 = 30;
+
+/// Días de tournées que se envían en la primera sincronización (sin cursor)
+pub const INITIAL_SYNC_DAYS: i32 = 7;
+
+/// Campos que escribe la sincronización (`DeliveryStatus` es server-wins, el resto last-writer-wins)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncField {
+    DeliveryStatus,
+    DriverNotes,
+    PackageCondition,
+    DriverLocation,
+}
+
+impl SyncField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncField::DeliveryStatus => "delivery_status",
+            SyncField::DriverNotes => "driver_notes",
+            SyncField::PackageCondition => "package_condition",
+            SyncField::DriverLocation => "driver_location",
+        }
+    }
+
+    /// Columna de `packages` de los campos last-writer-wins del paquete
+    pub fn package_column(&self) -> Option<&'static str> {
+        match self {
+            SyncField::DriverNotes => Some("driver_notes"),
+            SyncField::PackageCondition => Some("package_condition"),
+            SyncField::DeliveryStatus | SyncField::DriverLocation => None,
+        }
+    }
+}
+
+impl FieldResult {
+    pub fn applied(field: SyncField) -> Self {
+        Self { field: field.as_str().to_string(), resolution: FieldResolution::Applied, message: None }
+    }
+
+    pub fn server_wins(field: SyncField, message: String) -> Self {
+        Self { field: field.as_str().to_string(), resolution: FieldResolution::ServerWins, message: Some(message) }
+    }
+
+    pub fn superseded(field: SyncField) -> Self {
+        Self {
+            field: field.as_str().to_string(),
+            resolution: FieldResolution::Superseded,
+            message: Some("El servidor tiene una escritura más reciente".to_string()),
+        }
+    }
+}
+
+impl SyncOperationResult {
+    /// Resultado de una operación aplicada campo a campo
+    pub fn resolved(idempotency_key: &str, fields: Vec<FieldResult>) -> Self {
+        Self {
+            idempotency_key: idempotency_key.to_string(),
+            outcome: outcome(&fields),
+            fields,
+            message: None,
+            replayed: false,
+        }
+    }
+
+    /// Resultado de una operación inválida
+    pub fn rejected(idempotency_key: &str, message: String) -> Self {
+        Self {
+            idempotency_key: idempotency_key.to_string(),
+            outcome: SyncOutcome::Rejected,
+            fields: Vec::new(),
+            message: Some(message),
+            replayed: false,
+        }
+    }
+}
+
+/// `Applied` si todos los campos se han aplicado, `Conflict` si alguno no
+pub fn outcome(fields: &[FieldResult]) -> SyncOutcome {
+    if fields.iter().all(|f| f.resolution == FieldResolution::Applied) {
+        SyncOutcome::Applied
+    } else {
+        SyncOutcome::Conflict
+    }
+}
+
+/// Hora con la que compite la operación: un reloj adelantado no puede ganar al futuro
+pub fn effective_timestamp(client_timestamp: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+    client_timestamp.min(now)
+}
+
+/// Last-writer-wins: gana la escritura con hora igual o posterior a la guardada
+pub fn last_writer_wins(at: DateTime<Utc>, current: Option<DateTime<Utc>>) -> bool {
+    current.is_none_or(|current| at >= current)
+}
+
+/// Inicio de la ventana del delta para un cursor
+pub fn delta_since(cursor: DateTime<Utc>) -> DateTime<Utc> {
+    cursor - Duration::seconds(CURSOR_OVERLAP_SECONDS)
+}
+
+/// Resultado guardado de una clave ya procesada (marcado como `replayed`)
+pub async fn find_result(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    idempotency_key: &str,
+) -> Result<Option<SyncOperationResult>, sqlx::Error> {
+    let stored = sqlx::query_scalar::<_, serde_json::Value>(
+        "SELECT result FROM sync_operations WHERE user_id = $1 AND idempotency_key = $2",
+    )
+    .bind(user_id)
+    .bind(idempotency_key)
+    .fetch_optional(conn)
+    .await?;
+
+    stored
+        .map(|value| {
+            serde_json::from_value::<SyncOperationResult>(value)
+                .map(|result| SyncOperationResult { replayed: true, ..result })
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        })
+        .transpose()
+}
+
+/// Guardar el resultado de una operación. `false` si otra petición con la misma
+/// clave lo guardó antes (la transacción actual debe deshacerse).
+pub async fn store_result(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    user_id: Uuid,
+    operation: &SyncOperation,
+    result: &SyncOperationResult,
+) -> Result<bool, sqlx::Error> {
+    let outcome = match result.outcome {
+        SyncOutcome::Applied => "applied",
+        SyncOutcome::Conflict => "conflict",
+        SyncOutcome::Rejected => "rejected",
+    };
+    let value = serde_json::to_value(result).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO sync_operations (
+            company_id, user_id, idempotency_key, operation_type, package_id,
+            client_timestamp, outcome, result
+        )
+        VALUES (
+            $1, $2, $3, $4, (SELECT id FROM packages WHERE id = $5 AND company_id = $1),
+            $6, $7, $8
+        )
+        ON CONFLICT (user_id, idempotency_key) DO NOTHING
+        "#,
+    )
+    .bind(company_id)
+    .bind(user_id)
+    .bind(&operation.idempotency_key)
+    .bind(operation.action.operation_type())
+    .bind(operation.action.package_id())
+    .bind(operation.client_timestamp)
+    .bind(outcome)
+    .bind(value)
+    .execute(conn)
+    .await?
+    .rows_affected();
+
+    Ok(inserted == 1)
+}
+
+/// Anotar la hora de escritura de campos last-writer-wins de un paquete
+/// (también desde los endpoints online, para que compitan con las operaciones offline)
+pub async fn record_field_versions(
+    conn: &mut PgConnection,
+    package_id: Uuid,
+    fields: &[SyncField],
+    written_at: DateTime<Utc>,
+    written_by: Uuid,
+) -> Result<(), sqlx::Error> {
+    for field in fields {
+        sqlx::query(
+            r#"
+            INSERT INTO package_field_versions (package_id, field, written_at, written_by)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (package_id, field) DO UPDATE SET
+                written_at = GREATEST(package_field_versions.written_at, EXCLUDED.written_at),
+                written_by = CASE
+                    WHEN EXCLUDED.written_at >= package_field_versions.written_at THEN EXCLUDED.written_by
+                    ELSE package_field_versions.written_by
+                END
+            "#,
+        )
+        .bind(package_id)
+        .bind(field.as_str())
+        .bind(written_at)
+        .bind(written_by)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Escribir un campo last-writer-wins de un paquete ya bloqueado.
+/// Devuelve `false` si el servidor tiene una escritura posterior a `at`.
+pub async fn write_package_field(
+    conn: &mut PgConnection,
+    package_id: Uuid,
+    field: SyncField,
+    value: &str,
+    at: DateTime<Utc>,
+    written_by: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let column = field
+        .package_column()
+        .expect("solo driver_notes y package_condition se escriben campo a campo");
+
+    let current = sqlx::query_scalar::<_, DateTime<Utc>>(
+        "SELECT written_at FROM package_field_versions WHERE package_id = $1 AND field = $2",
+    )
+    .bind(package_id)
+    .bind(field.as_str())
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if !last_writer_wins(at, current) {
+        return Ok(false);
+    }
+
+    sqlx::query(&format!("UPDATE packages SET {} = $2, updated_at = NOW() WHERE id = $1", column))
+        .bind(package_id)
+        .bind(value)
+        .execute(&mut *conn)
+        .await?;
+
+    record_field_versions(conn, package_id, &[field], at, written_by).await?;
+    Ok(true)
+}
+
+/// Actualizar la última posición del driver si `at` es posterior a la guardada
+pub async fn update_driver_location(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    latitude: f64,
+    longitude: f64,
+    at: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query(
+        r#"
+        UPDATE users SET last_location = POINT($2, $3), last_location_at = $4
+        WHERE id = $1 AND (last_location_at IS NULL OR last_location_at <= $4)
+        "#,
+    )
+    .bind(user_id)
+    .bind(longitude)
+    .bind(latitude)
+    .bind(at)
+    .execute(conn)
+    .await?
+    .rows_affected();
+
+    Ok(updated == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 14, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_last_writer_wins() {
+        assert!(last_writer_wins(at(10, 0), None));
+        assert!(last_writer_wins(at(10, 5), Some(at(10, 0))));
+        assert!(last_writer_wins(at(10, 0), Some(at(10, 0))));
+        assert!(!last_writer_wins(at(9, 55), Some(at(10, 0))));
+    }
+
+    #[test]
+    fn test_effective_timestamp_clamps_future_clocks() {
+        let now = at(12, 0);
+        assert_eq!(effective_timestamp(at(11, 30), now), at(11, 30));
+        assert_eq!(effective_timestamp(at(18, 0), now), now);
+    }
+
+    #[test]
+    fn test_outcome() {
+        let applied = vec![FieldResult::applied(SyncField::DeliveryStatus), FieldResult::applied(SyncField::DriverNotes)];
+        assert_eq!(outcome(&applied), SyncOutcome::Applied);
+        assert_eq!(outcome(&[]), SyncOutcome::Applied);
+
+        let mixed = vec![
+            FieldResult::server_wins(SyncField::DeliveryStatus, "cancelado".to_string()),
+            FieldResult::applied(SyncField::DriverNotes),
+        ];
+        assert_eq!(outcome(&mixed), SyncOutcome::Conflict);
+        assert_eq!(outcome(&[FieldResult::superseded(SyncField::DriverNotes)]), SyncOutcome::Conflict);
+    }
+
+    #[test]
+    fn test_delta_since_overlaps_cursor() {
+        assert_eq!(delta_since(at(10, 0)), at(10, 0) - Duration::seconds(CURSOR_OVERLAP_SECONDS));
+    }
+
+    #[test]
+    fn test_operation_deserialization() {
+        let operation: SyncOperation = serde_json::from_value(serde_json::json!({
+            "idempotency_key": "k-1",
+            "client_timestamp": "2025-03-14T10:00:00Z",
+            "type": "deliver",
+            "package_id": "6f1c1c1e-8a2b-4c3d-9e4f-5a6b7c8d9e0f",
+            "driver_notes": "Dejado en conserjería",
+            "signature_image": "companies/x/signature.png"
+        }))
+        .unwrap();
+
+        assert_eq!(operation.action.operation_type(), "deliver");
+        match operation.action {
+            crate::models::sync::SyncAction::Deliver { delivery, .. } => {
+                assert_eq!(delivery.driver_notes.as_deref(), Some("Dejado en conserjería"));
+                assert_eq!(delivery.signature_image.as_deref(), Some("companies/x/signature.png"));
+            }
+            other => panic!("acción inesperada: {:?}", other),
+        }
+
+        let location: SyncOperation = serde_json::from_value(serde_json::json!({
+            "idempotency_key": "k-2",
+            "client_timestamp": "2025-03-14T10:01:00Z",
+            "type": "location",
+            "latitude": 48.8566,
+            "longitude": 2.3522
+        }))
+        .unwrap();
+        assert_eq!(location.action.package_id(), None);
+    }
+}
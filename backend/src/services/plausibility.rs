@@ -0,0 +1,333 @@
+//! Plausibilidad geográfica de los resultados de geocoding
+//!
+//! Un geocoder puede devolver una coincidencia "válida" a 300 km. Cada
+//! resultado se puntúa con varias comprobaciones (código postal, sector o zona
+//! de la tournée, distancia al centro de la tournée); por debajo del umbral la
+//! validación baja a `ValidationConfidence::Low`.
+
+use serde_json::Value;
+
+use crate::services::route_optimizer::{DistanceMetric, GeoPoint, Haversine};
+
+/// Km por grado de latitud
+const KM_PER_DEGREE: f64 = 111.32;
+
+/// Umbrales de plausibilidad
+#[derive(Debug, Clone)]
+pub struct PlausibilityConfig {
+    /// Distancia máxima al centro de la tournée (km)
+    pub max_centroid_distance_km: f64,
+    /// Margen alrededor del sector o de la zona de la tournée (km)
+    pub bounds_margin_km: f64,
+    /// Fracción mínima de comprobaciones superadas (0.0 - 1.0)
+    pub min_score: f64,
+    /// Puntos necesarios para calcular la zona de la tournée
+    pub min_tournee_points: usize,
+}
+
+impl Default for PlausibilityConfig {
+    fn default() -> Self {
+        fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+            std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+
+        Self {
+            max_centroid_distance_km: env_or("PLAUSIBILITY_MAX_CENTROID_KM", 15.0),
+            bounds_margin_km: env_or("PLAUSIBILITY_BOUNDS_MARGIN_KM", 2.0),
+            min_score: env_or("PLAUSIBILITY_MIN_SCORE", 0.5),
+            min_tournee_points: env_or("PLAUSIBILITY_MIN_TOURNEE_POINTS", 5),
+        }
+    }
+}
+
+/// Rectángulo envolvente en grados
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_latitude: f64,
+    pub max_latitude: f64,
+    pub min_longitude: f64,
+    pub max_longitude: f64,
+}
+
+impl BoundingBox {
+    pub fn from_points(points: &[GeoPoint]) -> Option<Self> {
+        let first = points.first()?;
+        let mut bounds = Self {
+            min_latitude: first.latitude,
+            max_latitude: first.latitude,
+            min_longitude: first.longitude,
+            max_longitude: first.longitude,
+        };
+        for point in &points[1..] {
+            bounds.min_latitude = bounds.min_latitude.min(point.latitude);
+            bounds.max_latitude = bounds.max_latitude.max(point.latitude);
+            bounds.min_longitude = bounds.min_longitude.min(point.longitude);
+            bounds.max_longitude = bounds.max_longitude.max(point.longitude);
+        }
+        Some(bounds)
+    }
+
+    /// Envolvente de un Polygon o MultiPolygon GeoJSON (`[lon, lat]`)
+    pub fn from_geojson(geometry: &Value) -> Option<Self> {
+        fn collect(value: &Value, points: &mut Vec<GeoPoint>) {
+            let Some(items) = value.as_array() else { return };
+            match (items.first().and_then(Value::as_f64), items.get(1).and_then(Value::as_f64)) {
+                (Some(longitude), Some(latitude)) => points.push(GeoPoint { latitude, longitude }),
+                _ => items.iter().for_each(|item| collect(item, points)),
+            }
+        }
+
+        let mut points = Vec::new();
+        collect(geometry.get("coordinates")?, &mut points);
+        Self::from_points(&points)
+    }
+
+    /// El punto cae dentro, ampliando el rectángulo `margin_km` por cada lado
+    pub fn contains(&self, point: GeoPoint, margin_km: f64) -> bool {
+        let margin_lat = margin_km / KM_PER_DEGREE;
+        let margin_lon = margin_km / (KM_PER_DEGREE * point.latitude.to_radians().cos().max(0.01));
+
+        point.latitude >= self.min_latitude - margin_lat
+            && point.latitude <= self.max_latitude + margin_lat
+            && point.longitude >= self.min_longitude - margin_lon
+            && point.longitude <= self.max_longitude + margin_lon
+    }
+}
+
+/// Zona de la tournée: mediana y percentiles 10-90 de los puntos geocodificados,
+/// para que unos pocos resultados erróneos no la deformen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TourneeArea {
+    pub centroid: GeoPoint,
+    pub bounds: BoundingBox,
+}
+
+impl TourneeArea {
+    pub fn from_points(points: &[GeoPoint], min_points: usize) -> Option<Self> {
+        if points.is_empty() || points.len() < min_points {
+            return None;
+        }
+
+        let mut latitudes: Vec<f64> = points.iter().map(|p| p.latitude).collect();
+        let mut longitudes: Vec<f64> = points.iter().map(|p| p.longitude).collect();
+        latitudes.sort_by(f64::total_cmp);
+        longitudes.sort_by(f64::total_cmp);
+
+        let percentile = |values: &[f64], p: f64| values[((values.len() - 1) as f64 * p).round() as usize];
+
+        Some(Self {
+            centroid: GeoPoint {
+                latitude: percentile(&latitudes, 0.5),
+                longitude: percentile(&longitudes, 0.5),
+            },
+            bounds: BoundingBox {
+                min_latitude: percentile(&latitudes, 0.1),
+                max_latitude: percentile(&latitudes, 0.9),
+                min_longitude: percentile(&longitudes, 0.1),
+                max_longitude: percentile(&longitudes, 0.9),
+            },
+        })
+    }
+}
+
+/// Último código postal (5 dígitos) de un texto
+pub fn extract_postal_code(text: &str) -> Option<&str> {
+    text.split(|c: char| !c.is_ascii_digit())
+        .rfind(|token| token.len() == 5)
+}
+
+/// Resultado de las comprobaciones de un punto
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlausibilityReport {
+    /// Fracción de comprobaciones aplicables superadas (1.0 si no aplica ninguna)
+    pub score: f64,
+    pub failures: Vec<String>,
+}
+
+impl PlausibilityReport {
+    pub fn is_plausible(&self, min_score: f64) -> bool {
+        self.score >= min_score
+    }
+}
+
+/// Comprobaciones de plausibilidad para los resultados de una tournée
+#[derive(Debug, Clone)]
+pub struct PlausibilityChecker<'a> {
+    config: &'a PlausibilityConfig,
+    sector_bounds: Option<BoundingBox>,
+    tournee: Option<TourneeArea>,
+}
+
+impl<'a> PlausibilityChecker<'a> {
+    pub fn new(config: &'a PlausibilityConfig, sector_bounds: Option<BoundingBox>, tournee: Option<TourneeArea>) -> Self {
+        Self { config, sector_bounds, tournee }
+    }
+
+    pub fn check(&self, input_address: &str, point: GeoPoint, formatted_address: Option<&str>) -> PlausibilityReport {
+        let mut applicable = 0;
+        let mut failures = Vec::new();
+
+        // 📮 El código postal devuelto coincide con el de la dirección
+        if let (Some(expected), Some(found)) = (
+            extract_postal_code(input_address),
+            formatted_address.and_then(extract_postal_code),
+        ) {
+            applicable += 1;
+            if expected != found {
+                failures.push(format!("Código postal {} distinto del de la dirección ({})", found, expected));
+            }
+        }
+
+        // 🗺️ Dentro del sector (o, sin polígono, de la zona de la tournée)
+        let bounds = self
+            .sector_bounds
+            .map(|b| (b, "del sector"))
+            .or_else(|| self.tournee.map(|t| (t.bounds, "de la zona de la tournée")));
+        if let Some((bounds, label)) = bounds {
+            applicable += 1;
+            if !bounds.contains(point, self.config.bounds_margin_km) {
+                failures.push(format!("Posición fuera {}", label));
+            }
+        }
+
+        // 📍 Cerca del centro de la tournée
+        if let Some(tournee) = &self.tournee {
+            applicable += 1;
+            let distance = Haversine.distance_km(tournee.centroid, point);
+            if distance > self.config.max_centroid_distance_km {
+                failures.push(format!("A {:.1} km del centro de la tournée", distance));
+            }
+        }
+
+        let score = if applicable == 0 {
+            1.0
+        } else {
+            (applicable - failures.len()) as f64 / applicable as f64
+        };
+
+        PlausibilityReport { score, failures }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(latitude: f64, longitude: f64) -> GeoPoint {
+        GeoPoint { latitude, longitude }
+    }
+
+    fn config() -> PlausibilityConfig {
+        PlausibilityConfig {
+            max_centroid_distance_km: 15.0,
+            bounds_margin_km: 2.0,
+            min_score: 0.5,
+            min_tournee_points: 5,
+        }
+    }
+
+    /// Tournée en el 18e: Jean Cottin, Marx Dormoy, Ordener, Marcadet, Championnet
+    fn paris_18() -> Vec<GeoPoint> {
+        vec![
+            point(48.8936, 2.3629),
+            point(48.8895, 2.3597),
+            point(48.8920, 2.3480),
+            point(48.8905, 2.3440),
+            point(48.8960, 2.3420),
+        ]
+    }
+
+    #[test]
+    fn test_extract_postal_code() {
+        assert_eq!(extract_postal_code("16 RUE JEAN COTTIN, 75018 PARIS"), Some("75018"));
+        assert_eq!(extract_postal_code("16 Rue Jean Cottin, 75018 Paris, France"), Some("75018"));
+        assert_eq!(extract_postal_code("16 RUE JEAN COTTIN"), None);
+        assert_eq!(extract_postal_code("123456 RUE X"), None);
+    }
+
+    #[test]
+    fn test_bounding_box_from_geojson() {
+        let polygon = serde_json::json!({
+            "type": "MultiPolygon",
+            "coordinates": [[[[2.33, 48.88], [2.37, 48.88], [2.37, 48.90], [2.33, 48.90], [2.33, 48.88]]]]
+        });
+        let bounds = BoundingBox::from_geojson(&polygon).unwrap();
+        assert_eq!(bounds.min_longitude, 2.33);
+        assert_eq!(bounds.max_latitude, 48.90);
+
+        assert!(bounds.contains(point(48.8936, 2.3629), 0.0));
+        // 1 km al norte: fuera sin margen, dentro con 2 km
+        assert!(!bounds.contains(point(48.909, 2.35), 0.0));
+        assert!(bounds.contains(point(48.909, 2.35), 2.0));
+    }
+
+    #[test]
+    fn test_tournee_area_ignores_outliers() {
+        let mut points = paris_18();
+        points.push(point(45.7640, 4.8357)); // Lyon
+
+        let area = TourneeArea::from_points(&points, 5).unwrap();
+        assert!(Haversine.distance_km(area.centroid, point(48.8920, 2.3500)) < 2.0);
+        assert!(!area.bounds.contains(point(45.7640, 4.8357), 2.0));
+
+        assert!(TourneeArea::from_points(&points[..4], 5).is_none());
+    }
+
+    #[test]
+    fn test_check_fixture_addresses() {
+        let config = config();
+        let tournee = TourneeArea::from_points(&paris_18(), config.min_tournee_points);
+        let checker = PlausibilityChecker::new(&config, None, tournee);
+
+        // Coincidencia correcta
+        let report = checker.check(
+            "16 RUE JEAN COTTIN, 75018 PARIS",
+            point(48.8936, 2.3629),
+            Some("16 Rue Jean Cottin, 75018 Paris"),
+        );
+        assert_eq!(report.score, 1.0);
+        assert!(report.failures.is_empty());
+
+        // Homónima en Lyon: falla todo
+        let report = checker.check(
+            "12 RUE MARCADET, 75018 PARIS",
+            point(45.7640, 4.8357),
+            Some("12 Rue Marcadet, 69001 Lyon"),
+        );
+        assert_eq!(report.score, 0.0);
+        assert_eq!(report.failures.len(), 3);
+        assert!(!report.is_plausible(config.min_score));
+
+        // Código postal vecino pero posición coherente: sigue siendo plausible
+        let report = checker.check(
+            "3 RUE ORDENER, 75018 PARIS",
+            point(48.8920, 2.3480),
+            Some("3 Rue Ordener, 75019 Paris"),
+        );
+        assert!((report.score - 2.0 / 3.0).abs() < 1e-9);
+        assert!(report.is_plausible(config.min_score));
+    }
+
+    #[test]
+    fn test_check_without_context() {
+        let config = config();
+        let checker = PlausibilityChecker::new(&config, None, None);
+
+        // Sin código postal ni zona no hay nada que comprobar
+        assert_eq!(checker.check("RUE JEAN COTTIN", point(45.76, 4.83), None).score, 1.0);
+
+        // Solo el código postal: un fallo basta para rebajar
+        let report = checker.check("RUE JEAN COTTIN 75018 PARIS", point(45.76, 4.83), Some("69001 Lyon"));
+        assert_eq!(report.score, 0.0);
+    }
+
+    #[test]
+    fn test_sector_bounds_take_precedence() {
+        let config = config();
+        let sector = BoundingBox { min_latitude: 48.88, max_latitude: 48.90, min_longitude: 2.33, max_longitude: 2.37 };
+        let checker = PlausibilityChecker::new(&config, Some(sector), None);
+
+        let report = checker.check("RUE X", point(48.95, 2.35), None);
+        assert_eq!(report.failures, vec!["Posición fuera del sector".to_string()]);
+    }
+}
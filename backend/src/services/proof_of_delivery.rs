@@ -0,0 +1,344 @@
+//! Fotos de entrega, firmas y fotos de inspección
+//!
+//! Valida el tipo (JPEG o PNG, comprobado por los bytes y no solo por el
+//! `Content-Type` declarado) y el tamaño, genera una miniatura y guarda
+//! original y miniatura en el `BlobStore`.
+
+use std::io::Cursor;
+
+use image::{ImageFormat, ImageOutputFormat};
+use serde::Serialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::services::blob_store::{is_valid_key, BlobStore};
+use crate::utils::errors::AppError;
+
+/// Configuración de las subidas
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    /// Tamaño máximo por fichero (bytes)
+    pub max_file_bytes: usize,
+    /// Ficheros como máximo por petición
+    pub max_files: usize,
+    /// Lado máximo de la miniatura (píxeles)
+    pub thumbnail_size: u32,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: std::env::var("UPLOAD_MAX_FILE_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5 * 1024 * 1024),
+            max_files: std::env::var("UPLOAD_MAX_FILES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(6),
+            thumbnail_size: std::env::var("UPLOAD_THUMBNAIL_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(256),
+        }
+    }
+}
+
+impl UploadConfig {
+    /// Límite del cuerpo multipart (todos los ficheros más las cabeceras de cada parte)
+    pub fn max_request_bytes(&self) -> usize {
+        self.max_file_bytes * self.max_files + 64 * 1024
+    }
+}
+
+/// Errores de subida
+#[derive(Error, Debug)]
+pub enum UploadError {
+    #[error("Fichero vacío")]
+    Empty,
+
+    #[error("El fichero ocupa más de {max} bytes")]
+    TooLarge { max: usize },
+
+    #[error("Tipo de fichero no admitido: {0} (solo image/jpeg e image/png)")]
+    UnsupportedType(String),
+
+    #[error("El contenido no corresponde a {declared}")]
+    TypeMismatch { declared: String },
+
+    #[error("Imagen ilegible: {0}")]
+    InvalidImage(String),
+
+    #[error("Error guardando el fichero: {0}")]
+    Storage(#[from] anyhow::Error),
+}
+
+impl From<UploadError> for AppError {
+    fn from(error: UploadError) -> Self {
+        match error {
+            UploadError::Storage(e) => AppError::Internal(format!("Error guardando el fichero: {}", e)),
+            other => AppError::BadRequest(other.to_string()),
+        }
+    }
+}
+
+/// Formatos de imagen admitidos
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageKind {
+    Jpeg,
+    Png,
+}
+
+impl ImageKind {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.split(';').next().unwrap_or_default().trim().to_lowercase().as_str() {
+            "image/jpeg" | "image/jpg" => Some(ImageKind::Jpeg),
+            "image/png" => Some(ImageKind::Png),
+            _ => None,
+        }
+    }
+
+    /// Detectar el formato por la firma del fichero
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageKind::Jpeg)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageKind::Png)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key.rsplit('.').next()? {
+            "jpg" => Some(ImageKind::Jpeg),
+            "png" => Some(ImageKind::Png),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ImageKind::Jpeg => "image/jpeg",
+            ImageKind::Png => "image/png",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageKind::Jpeg => "jpg",
+            ImageKind::Png => "png",
+        }
+    }
+
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            ImageKind::Jpeg => ImageFormat::Jpeg,
+            ImageKind::Png => ImageFormat::Png,
+        }
+    }
+}
+
+/// Validar tamaño y tipo: el tipo declarado tiene que ser JPEG o PNG y coincidir con el contenido
+pub fn validate_image(data: &[u8], declared: Option<&str>, config: &UploadConfig) -> Result<ImageKind, UploadError> {
+    if data.is_empty() {
+        return Err(UploadError::Empty);
+    }
+    if data.len() > config.max_file_bytes {
+        return Err(UploadError::TooLarge { max: config.max_file_bytes });
+    }
+
+    let declared = declared.unwrap_or("application/octet-stream");
+    let kind = ImageKind::from_content_type(declared)
+        .ok_or_else(|| UploadError::UnsupportedType(declared.to_string()))?;
+
+    if ImageKind::sniff(data) != Some(kind) {
+        return Err(UploadError::TypeMismatch { declared: declared.to_string() });
+    }
+
+    Ok(kind)
+}
+
+/// Miniatura en el mismo formato (PNG conserva la transparencia de las firmas)
+pub fn make_thumbnail(data: &[u8], kind: ImageKind, max_side: u32) -> Result<Vec<u8>, UploadError> {
+    let image = image::load_from_memory_with_format(data, kind.image_format())
+        .map_err(|e| UploadError::InvalidImage(e.to_string()))?;
+
+    let thumbnail = image.thumbnail(max_side, max_side);
+    let format = match kind {
+        ImageKind::Jpeg => ImageOutputFormat::Jpeg(80),
+        ImageKind::Png => ImageOutputFormat::Png,
+    };
+
+    let mut output = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut output), format)
+        .map_err(|e| UploadError::InvalidImage(e.to_string()))?;
+    Ok(output)
+}
+
+/// Prefijo de los ficheros de un paquete
+pub fn package_prefix(company_id: Uuid, package_id: Uuid) -> String {
+    format!("companies/{}/packages/{}", company_id, package_id)
+}
+
+/// Prefijo de los ficheros de una tournée
+pub fn tournee_prefix(company_id: Uuid, tournee_id: Uuid) -> String {
+    format!("companies/{}/tournees/{}", company_id, tournee_id)
+}
+
+/// La clave es una imagen original (no miniatura) subida bajo el prefijo
+pub fn is_upload_key(key: &str, prefix: &str) -> bool {
+    is_valid_key(key)
+        && key.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/') && !rest[1..].contains('/'))
+        && !key.contains("_thumb.")
+        && ImageKind::from_key(key).is_some()
+}
+
+/// Clave de la miniatura de una imagen original
+pub fn thumbnail_key(key: &str) -> String {
+    match key.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}_thumb.{}", stem, extension),
+        None => format!("{}_thumb", key),
+    }
+}
+
+/// Imagen guardada
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredImage {
+    pub key: String,
+    pub thumbnail_key: String,
+    pub content_type: String,
+    pub size_bytes: usize,
+}
+
+/// Validar, generar la miniatura y guardar original y miniatura bajo `prefix`
+pub async fn store_image(
+    store: &dyn BlobStore,
+    prefix: &str,
+    data: Vec<u8>,
+    declared: Option<&str>,
+    config: &UploadConfig,
+) -> Result<StoredImage, UploadError> {
+    let kind = validate_image(&data, declared, config)?;
+
+    // Decodificar y redimensionar es CPU: fuera del runtime async
+    let thumbnail_size = config.thumbnail_size;
+    let (data, thumbnail) = tokio::task::spawn_blocking(move || {
+        let thumbnail = make_thumbnail(&data, kind, thumbnail_size);
+        (data, thumbnail)
+    })
+    .await
+    .map_err(|e| UploadError::Storage(e.into()))?;
+    let thumbnail = thumbnail?;
+
+    let key = format!("{}/{}.{}", prefix, Uuid::new_v4().simple(), kind.extension());
+    let thumbnail_key = thumbnail_key(&key);
+    let size_bytes = data.len();
+
+    store.put(&thumbnail_key, thumbnail, kind.content_type()).await?;
+    store.put(&key, data, kind.content_type()).await?;
+
+    Ok(StoredImage {
+        key,
+        thumbnail_key,
+        content_type: kind.content_type().to_string(),
+        size_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::blob_store::LocalBlobStore;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::RgbaImage::from_pixel(width, height, image::Rgba([0, 0, 0, 255]));
+        let mut output = Vec::new();
+        image::DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut output), ImageOutputFormat::Png)
+            .unwrap();
+        output
+    }
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let image = image::RgbImage::from_pixel(width, height, image::Rgb([200, 10, 10]));
+        let mut output = Vec::new();
+        image::DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut output), ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn test_validate_image() {
+        let config = UploadConfig { max_file_bytes: 10_000, ..UploadConfig::default() };
+
+        assert_eq!(validate_image(&png(4, 4), Some("image/png"), &config).unwrap(), ImageKind::Png);
+        assert_eq!(validate_image(&jpeg(4, 4), Some("image/jpeg"), &config).unwrap(), ImageKind::Jpeg);
+        assert!(matches!(validate_image(&[], Some("image/png"), &config), Err(UploadError::Empty)));
+        assert!(matches!(
+            validate_image(b"%PDF-1.4", Some("application/pdf"), &config),
+            Err(UploadError::UnsupportedType(_))
+        ));
+        // Un PNG declarado como JPEG (o un ejecutable con Content-Type de imagen) se rechaza
+        assert!(matches!(
+            validate_image(&png(4, 4), Some("image/jpeg"), &config),
+            Err(UploadError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            validate_image(b"MZ\x90\x00", Some("image/png"), &config),
+            Err(UploadError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            validate_image(&vec![0xFF; 10_001], Some("image/jpeg"), &config),
+            Err(UploadError::TooLarge { max: 10_000 })
+        ));
+    }
+
+    #[test]
+    fn test_thumbnail_keeps_aspect_ratio() {
+        let thumbnail = make_thumbnail(&jpeg(800, 400), ImageKind::Jpeg, 256).unwrap();
+        let decoded = image::load_from_memory(&thumbnail).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (256, 128));
+
+        assert!(matches!(
+            make_thumbnail(b"\x89PNG\r\n\x1a\nbroken", ImageKind::Png, 256),
+            Err(UploadError::InvalidImage(_))
+        ));
+    }
+
+    #[test]
+    fn test_upload_keys() {
+        let company = Uuid::new_v4();
+        let package = Uuid::new_v4();
+        let prefix = package_prefix(company, package);
+        let key = format!("{}/abc.png", prefix);
+
+        assert!(is_upload_key(&key, &prefix));
+        assert_eq!(thumbnail_key(&key), format!("{}/abc_thumb.png", prefix));
+        assert!(!is_upload_key(&thumbnail_key(&key), &prefix));
+        assert!(!is_upload_key(&format!("{}/abc.png", package_prefix(company, Uuid::new_v4())), &prefix));
+        assert!(!is_upload_key(&format!("{}/../x/abc.png", prefix), &prefix));
+        assert!(!is_upload_key("https://example.com/photo.jpg", &prefix));
+    }
+
+    #[tokio::test]
+    async fn test_store_image_saves_original_and_thumbnail() {
+        let root = std::env::temp_dir().join(format!("pod_{}", Uuid::new_v4().simple()));
+        let store = LocalBlobStore::new(&root);
+        let data = png(600, 300);
+
+        let stored = store_image(&store, "companies/a/packages/b", data.clone(), Some("image/png"), &UploadConfig::default())
+            .await
+            .unwrap();
+
+        assert!(stored.key.starts_with("companies/a/packages/b/") && stored.key.ends_with(".png"));
+        assert_eq!(stored.size_bytes, data.len());
+        assert_eq!(store.get(&stored.key).await.unwrap(), Some(data));
+        let thumbnail = image::load_from_memory(&store.get(&stored.thumbnail_key).await.unwrap().unwrap()).unwrap();
+        assert_eq!(thumbnail.width(), 256);
+
+        let _ = tokio::fs::remove_dir_all(&root).await;
+    }
+}
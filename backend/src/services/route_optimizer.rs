@@ -0,0 +1,442 @@
+//! Optimización de rutas
+//!
+//! Calcula el orden de paradas de una tournée: construcción por vecino más
+//! cercano y mejora local con 2-opt y Or-opt. La distancia es intercambiable
+//! (`DistanceMetric`), haversine por defecto. El núcleo trabaja sobre una
+//! matriz de distancias, que puede ser asimétrica (p. ej. distancias por calle).
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+/// Radio medio de la Tierra en km
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Mejora mínima para aceptar un movimiento (evita ciclos por redondeo)
+const EPSILON: f64 = 1e-9;
+
+/// Longitud máxima de los segmentos que mueve Or-opt
+const OR_OPT_MAX_SEGMENT: usize = 3;
+
+/// Coordenada geográfica
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Parada a visitar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stop {
+    pub id: String,
+    pub location: GeoPoint,
+}
+
+/// Función de distancia entre dos puntos, en km
+pub trait DistanceMetric: Send + Sync {
+    fn distance_km(&self, from: GeoPoint, to: GeoPoint) -> f64;
+}
+
+/// Distancia ortodrómica (haversine)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Haversine;
+
+impl DistanceMetric for Haversine {
+    fn distance_km(&self, from: GeoPoint, to: GeoPoint) -> f64 {
+        let (lat1, lat2) = (from.latitude.to_radians(), to.latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (to.longitude - from.longitude).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+/// Resultado de la optimización
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizedRoute {
+    /// IDs de las paradas en el orden optimizado
+    pub sequence: Vec<String>,
+    pub total_distance_km: f64,
+    /// Distancia siguiendo el orden original (Colis Privé)
+    pub original_distance_km: f64,
+    /// Fracción de distancia ahorrada respecto al orden original (0.00 - 1.00)
+    pub score: f64,
+    /// Paradas sin coordenadas, que quedan al final en su orden original
+    pub unrouted: Vec<String>,
+}
+
+/// Optimizador de rutas con distancia intercambiable
+#[derive(Clone)]
+pub struct RouteOptimizer {
+    metric: Arc<dyn DistanceMetric>,
+    max_passes: usize,
+}
+
+impl Default for RouteOptimizer {
+    fn default() -> Self {
+        Self::new(Arc::new(Haversine))
+    }
+}
+
+impl RouteOptimizer {
+    pub fn new(metric: Arc<dyn DistanceMetric>) -> Self {
+        Self { metric, max_passes: 50 }
+    }
+
+    /// Optimizar paradas saliendo de `depot` y terminando en `end` (o en la última parada)
+    pub fn optimize(&self, depot: GeoPoint, end: Option<GeoPoint>, stops: &[Stop]) -> OptimizedRoute {
+        let mut points = Vec::with_capacity(stops.len() + 2);
+        points.push(depot);
+        points.extend(stops.iter().map(|s| s.location));
+        points.extend(end);
+
+        let matrix: Vec<Vec<f64>> = points
+            .iter()
+            .map(|&from| points.iter().map(|&to| self.metric.distance_km(from, to)).collect())
+            .collect();
+
+        let problem = RouteProblem::new(&matrix, stops.len(), end.is_some());
+        let original: Vec<usize> = (1..=stops.len()).collect();
+        let original_distance = problem.cost(&original);
+        let order = problem.solve(self.max_passes);
+        let total_distance = problem.cost(&order);
+
+        OptimizedRoute {
+            sequence: order.iter().map(|&node| stops[node - 1].id.clone()).collect(),
+            total_distance_km: round(total_distance, 3),
+            original_distance_km: round(original_distance, 3),
+            score: improvement_score(original_distance, total_distance),
+            unrouted: Vec::new(),
+        }
+    }
+
+    /// Optimizar paquetes identificados por `(id, latitud, longitud)`, p. ej. los
+    /// `PackageData` de `get_packages`; los no geocodificados van a `unrouted`
+    pub fn optimize_located<I>(&self, depot: GeoPoint, end: Option<GeoPoint>, packages: I) -> OptimizedRoute
+    where
+        I: IntoIterator<Item = (String, Option<f64>, Option<f64>)>,
+    {
+        let mut stops = Vec::new();
+        let mut unrouted = Vec::new();
+
+        for (id, latitude, longitude) in packages {
+            match (latitude, longitude) {
+                (Some(latitude), Some(longitude)) => stops.push(Stop {
+                    id,
+                    location: GeoPoint { latitude, longitude },
+                }),
+                _ => unrouted.push(id),
+            }
+        }
+
+        let mut route = self.optimize(depot, end, &stops);
+        route.unrouted = unrouted;
+        route
+    }
+}
+
+/// Problema sobre matriz: nodo 0 = depósito, 1..=n = paradas, n+1 = destino opcional
+pub struct RouteProblem<'a> {
+    matrix: &'a [Vec<f64>],
+    stops: usize,
+    end: Option<usize>,
+}
+
+impl<'a> RouteProblem<'a> {
+    pub fn new(matrix: &'a [Vec<f64>], stops: usize, has_end: bool) -> Self {
+        Self {
+            matrix,
+            stops,
+            end: has_end.then_some(stops + 1),
+        }
+    }
+
+    fn d(&self, from: usize, to: usize) -> f64 {
+        self.matrix[from][to]
+    }
+
+    /// Distancia al nodo siguiente, que puede no existir (ruta abierta)
+    fn d_next(&self, from: usize, to: Option<usize>) -> f64 {
+        to.map_or(0.0, |to| self.d(from, to))
+    }
+
+    /// Coste total de un orden de paradas
+    pub fn cost(&self, order: &[usize]) -> f64 {
+        let Some((&first, _)) = order.split_first() else {
+            return self.end.map_or(0.0, |end| self.d(0, end));
+        };
+
+        let inner: f64 = order.windows(2).map(|w| self.d(w[0], w[1])).sum();
+        self.d(0, first) + inner + self.d_next(order[order.len() - 1], self.end)
+    }
+
+    /// Vecino más cercano + 2-opt + Or-opt hasta no mejorar
+    pub fn solve(&self, max_passes: usize) -> Vec<usize> {
+        let mut order = self.nearest_neighbor();
+
+        for _ in 0..max_passes {
+            let improved_2opt = self.two_opt(&mut order);
+            let improved_or = self.or_opt(&mut order);
+            if !improved_2opt && !improved_or {
+                break;
+            }
+        }
+
+        order
+    }
+
+    fn nearest_neighbor(&self) -> Vec<usize> {
+        let mut remaining: Vec<usize> = (1..=self.stops).collect();
+        let mut order = Vec::with_capacity(self.stops);
+        let mut current = 0;
+
+        while !remaining.is_empty() {
+            let (pos, _) = remaining
+                .iter()
+                .enumerate()
+                .min_by(|(_, &a), (_, &b)| self.d(current, a).total_cmp(&self.d(current, b)))
+                .expect("remaining no está vacío");
+            current = remaining.swap_remove(pos);
+            order.push(current);
+        }
+
+        order
+    }
+
+    fn prev(&self, order: &[usize], i: usize) -> usize {
+        if i == 0 { 0 } else { order[i - 1] }
+    }
+
+    fn next(&self, order: &[usize], j: usize) -> Option<usize> {
+        order.get(j + 1).copied().or(self.end)
+    }
+
+    /// Invertir segmentos `order[i..=j]` mientras mejore (válido con matrices asimétricas)
+    fn two_opt(&self, order: &mut [usize]) -> bool {
+        let n = order.len();
+        let mut improved = false;
+
+        loop {
+            // Costes acumulados hacia delante y hacia atrás a lo largo del orden actual
+            let mut forward = vec![0.0; n];
+            let mut backward = vec![0.0; n];
+            for k in 1..n {
+                forward[k] = forward[k - 1] + self.d(order[k - 1], order[k]);
+                backward[k] = backward[k - 1] + self.d(order[k], order[k - 1]);
+            }
+
+            let mut best: Option<(usize, usize, f64)> = None;
+            for i in 0..n {
+                let prev = self.prev(order, i);
+                for j in (i + 1)..n {
+                    let next = self.next(order, j);
+                    let before = self.d(prev, order[i]) + (forward[j] - forward[i]) + self.d_next(order[j], next);
+                    let after = self.d(prev, order[j]) + (backward[j] - backward[i]) + self.d_next(order[i], next);
+                    let delta = after - before;
+                    if delta < -EPSILON && best.is_none_or(|(_, _, b)| delta < b) {
+                        best = Some((i, j, delta));
+                    }
+                }
+            }
+
+            match best {
+                Some((i, j, _)) => {
+                    order[i..=j].reverse();
+                    improved = true;
+                }
+                None => return improved,
+            }
+        }
+    }
+
+    /// Mover segmentos de 1 a 3 paradas a otra posición mientras mejore
+    fn or_opt(&self, order: &mut Vec<usize>) -> bool {
+        let mut improved = false;
+
+        'restart: loop {
+            let n = order.len();
+            for len in 1..=OR_OPT_MAX_SEGMENT.min(n.saturating_sub(1)) {
+                for i in 0..=(n - len) {
+                    let j = i + len - 1;
+                    let (first, last) = (order[i], order[j]);
+                    let prev = self.prev(order, i);
+                    let next = self.next(order, j);
+
+                    let removal_gain = self.d(prev, first) + self.d_next(last, next) - self.d_next(prev, next);
+
+                    // Posiciones de inserción en el orden sin el segmento
+                    let rest: Vec<usize> = order[..i].iter().chain(&order[j + 1..]).copied().collect();
+                    for k in 0..=rest.len() {
+                        if k == i {
+                            continue;
+                        }
+                        let a = if k == 0 { 0 } else { rest[k - 1] };
+                        let b = rest.get(k).copied().or(self.end);
+                        let insertion_cost = self.d(a, first) + self.d_next(last, b) - self.d_next(a, b);
+
+                        if insertion_cost - removal_gain < -EPSILON {
+                            let segment: Vec<usize> = order[i..=j].to_vec();
+                            let mut reordered = rest;
+                            reordered.splice(k..k, segment);
+                            *order = reordered;
+                            improved = true;
+                            continue 'restart;
+                        }
+                    }
+                }
+            }
+            return improved;
+        }
+    }
+}
+
+/// Fracción de distancia ahorrada, redondeada a 2 decimales (`tournees.route_optimization_score`)
+fn improvement_score(original: f64, optimized: f64) -> f64 {
+    if original <= 0.0 {
+        return 0.0;
+    }
+    round(((original - optimized) / original).clamp(0.0, 1.0), 2)
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(latitude: f64, longitude: f64) -> GeoPoint {
+        GeoPoint { latitude, longitude }
+    }
+
+    fn stop(id: &str, latitude: f64, longitude: f64) -> Stop {
+        Stop { id: id.to_string(), location: point(latitude, longitude) }
+    }
+
+    /// Distancia euclídea sobre las coordenadas, para casos de prueba exactos
+    struct Euclidean;
+
+    impl DistanceMetric for Euclidean {
+        fn distance_km(&self, from: GeoPoint, to: GeoPoint) -> f64 {
+            ((from.latitude - to.latitude).powi(2) + (from.longitude - to.longitude).powi(2)).sqrt()
+        }
+    }
+
+    #[test]
+    fn test_haversine_paris_lyon() {
+        let paris = point(48.8566, 2.3522);
+        let lyon = point(45.7640, 4.8357);
+        let d = Haversine.distance_km(paris, lyon);
+        assert!((d - 391.5).abs() < 2.0, "distancia inesperada: {}", d);
+        assert_eq!(Haversine.distance_km(paris, paris), 0.0);
+    }
+
+    #[test]
+    fn test_collinear_stops_are_sorted() {
+        let optimizer = RouteOptimizer::new(Arc::new(Euclidean));
+        let stops = vec![stop("c", 0.0, 3.0), stop("a", 0.0, 1.0), stop("d", 0.0, 4.0), stop("b", 0.0, 2.0)];
+
+        let route = optimizer.optimize(point(0.0, 0.0), None, &stops);
+
+        assert_eq!(route.sequence, vec!["a", "b", "c", "d"]);
+        assert!((route.total_distance_km - 4.0).abs() < 1e-9);
+        assert!(route.total_distance_km < route.original_distance_km);
+    }
+
+    #[test]
+    fn test_end_point_is_respected() {
+        let optimizer = RouteOptimizer::new(Arc::new(Euclidean));
+        let stops = vec![stop("far", 0.0, 3.0), stop("near", 0.0, 1.0)];
+
+        // Volver al depósito: el recorrido es simétrico, 6 en total
+        let round_trip = optimizer.optimize(point(0.0, 0.0), Some(point(0.0, 0.0)), &stops);
+        assert!((round_trip.total_distance_km - 6.0).abs() < 1e-9);
+
+        // Terminar más allá de "far": hay que visitar "near" primero
+        let one_way = optimizer.optimize(point(0.0, 0.0), Some(point(0.0, 5.0)), &stops);
+        assert_eq!(one_way.sequence, vec!["near", "far"]);
+    }
+
+    #[test]
+    fn test_two_opt_removes_crossing() {
+        // Cuadrado: el vecino más cercano con desempates puede cruzarse; el resultado no
+        let optimizer = RouteOptimizer::new(Arc::new(Euclidean));
+        let stops = vec![
+            stop("a", 0.0, 1.0),
+            stop("c", 1.0, 0.0),
+            stop("b", 1.0, 1.0),
+            stop("d", 2.0, 1.0),
+            stop("e", 2.0, 0.0),
+        ];
+
+        let route = optimizer.optimize(point(0.0, 0.0), Some(point(0.0, 0.0)), &stops);
+        // Perímetro del rectángulo 2x1
+        assert!((route.total_distance_km - 6.0).abs() < 1e-9, "{:?}", route);
+    }
+
+    #[test]
+    fn test_asymmetric_matrix() {
+        // Ir 1 -> 2 es caro, 2 -> 1 barato: debe visitar 2 antes que 1
+        let matrix = vec![
+            vec![0.0, 1.0, 1.0],
+            vec![1.0, 0.0, 10.0],
+            vec![1.0, 1.0, 0.0],
+        ];
+        let problem = RouteProblem::new(&matrix, 2, false);
+        let order = problem.solve(10);
+        assert_eq!(order, vec![2, 1]);
+        assert_eq!(problem.cost(&order), 2.0);
+    }
+
+    #[test]
+    fn test_packages_without_coordinates_are_unrouted() {
+        use crate::services::PackageData;
+
+        let package = |id: &str, lat: Option<f64>, lon: Option<f64>| PackageData {
+            id: id.to_string(),
+            tracking_number: id.to_string(),
+            recipient_name: String::new(),
+            address: String::new(),
+            status: String::new(),
+            instructions: String::new(),
+            phone: String::new(),
+            priority: "0".to_string(),
+            latitude: lat,
+            longitude: lon,
+            formatted_address: None,
+            validation_method: None,
+            validation_confidence: None,
+            validation_warnings: None,
+        };
+        let packages = vec![
+            package("p1", Some(48.86), Some(2.35)),
+            package("p2", None, None),
+            package("p3", Some(48.87), Some(2.36)),
+        ];
+
+        let route = RouteOptimizer::default().optimize_located(
+            point(48.85, 2.34),
+            None,
+            packages.iter().map(|p| (p.id.clone(), p.latitude, p.longitude)),
+        );
+
+        assert_eq!(route.sequence, vec!["p1", "p3"]);
+        assert_eq!(route.unrouted, vec!["p2"]);
+        assert!((0.0..=1.0).contains(&route.score));
+    }
+
+    #[test]
+    fn test_empty_and_single_stop() {
+        let optimizer = RouteOptimizer::default();
+        let empty = optimizer.optimize(point(48.85, 2.34), None, &[]);
+        assert!(empty.sequence.is_empty());
+        assert_eq!(empty.score, 0.0);
+
+        let single = optimizer.optimize(point(48.85, 2.34), None, &[stop("a", 48.86, 2.35)]);
+        assert_eq!(single.sequence, vec!["a"]);
+        assert_eq!(single.score, 0.0);
+    }
+}
@@ -0,0 +1,444 @@
+//! Sectores de reparto por empresa
+//!
+//! Cada sector agrupa códigos postales, comunas y, opcionalmente, un polígono
+//! (GeoJSON, guardado como `GEOGRAPHY(MULTIPOLYGON)`). Drivers y tournées se
+//! asignan a un sector; la validación de direcciones completa las direcciones
+//! con los códigos postales del sector asignado.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+/// Sector guardado
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Sector {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub code: String,
+    pub name: String,
+    pub postal_codes: Vec<String>,
+    pub communes: Vec<String>,
+    /// Polígono en GeoJSON
+    pub boundary: Option<serde_json::Value>,
+    pub is_active: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Sector {
+    /// `"75018 Paris"` por cada código postal; la comuna es la de la misma
+    /// posición o, si hay menos comunas, la primera
+    pub fn districts(&self) -> Vec<String> {
+        self.postal_codes
+            .iter()
+            .enumerate()
+            .map(|(i, postal_code)| match self.communes.get(i).or(self.communes.first()) {
+                Some(commune) => format!("{} {}", postal_code, commune),
+                None => postal_code.clone(),
+            })
+            .collect()
+    }
+
+    /// La dirección ya lleva uno de los códigos postales del sector
+    pub fn covers(&self, address: &str) -> bool {
+        address
+            .split(|c: char| !c.is_ascii_digit())
+            .any(|token| self.postal_codes.iter().any(|code| code == token))
+    }
+}
+
+/// Alta o modificación de un sector
+#[derive(Debug, Clone, Deserialize)]
+pub struct SectorInput {
+    pub code: String,
+    pub name: String,
+    #[serde(default)]
+    pub postal_codes: Vec<String>,
+    #[serde(default)]
+    pub communes: Vec<String>,
+    /// Polígono o multipolígono GeoJSON
+    pub boundary: Option<serde_json::Value>,
+    pub is_active: Option<bool>,
+}
+
+impl SectorInput {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.code.trim().is_empty() || self.name.trim().is_empty() {
+            return Err("code y name son obligatorios".to_string());
+        }
+        if let Some(invalid) = self
+            .postal_codes
+            .iter()
+            .find(|c| c.len() != 5 || !c.chars().all(|ch| ch.is_ascii_digit()))
+        {
+            return Err(format!("Código postal inválido: '{}'", invalid));
+        }
+        if self.postal_codes.is_empty() && self.boundary.is_none() {
+            return Err("El sector necesita códigos postales o un polígono".to_string());
+        }
+        if let Some(boundary) = &self.boundary {
+            validate_boundary(boundary)?;
+        }
+        Ok(())
+    }
+}
+
+/// Comprobar el GeoJSON antes de `ST_GeomFromGeoJSON`, que con una geometría
+/// mal formada falla con un error interno de PostGIS
+fn validate_boundary(boundary: &serde_json::Value) -> Result<(), String> {
+    let coordinates = boundary.get("coordinates");
+    let valid = match boundary.get("type").and_then(|t| t.as_str()) {
+        Some("Polygon") => coordinates.is_some_and(is_polygon),
+        Some("MultiPolygon") => coordinates
+            .and_then(|c| c.as_array())
+            .is_some_and(|polygons| !polygons.is_empty() && polygons.iter().all(is_polygon)),
+        _ => return Err("boundary debe ser un Polygon o MultiPolygon GeoJSON".to_string()),
+    };
+
+    if !valid {
+        return Err("boundary: cada anillo necesita al menos 4 posiciones [lon, lat] válidas y cerrarse en el primer punto".to_string());
+    }
+    Ok(())
+}
+
+/// Polígono: uno o más anillos cerrados de posiciones [lon, lat]
+fn is_polygon(polygon: &serde_json::Value) -> bool {
+    let Some(rings) = polygon.as_array() else {
+        return false;
+    };
+
+    !rings.is_empty()
+        && rings.iter().all(|ring| {
+            let Some(positions) = ring.as_array() else {
+                return false;
+            };
+            let points: Option<Vec<(f64, f64)>> = positions.iter().map(position).collect();
+            matches!(points, Some(points) if points.len() >= 4 && points.first() == points.last())
+        })
+}
+
+/// Posición GeoJSON `[lon, lat]` (la altitud opcional se ignora) dentro de rango
+fn position(value: &serde_json::Value) -> Option<(f64, f64)> {
+    let values = value.as_array()?;
+    if !(2..=3).contains(&values.len()) {
+        return None;
+    }
+    let longitude = values[0].as_f64()?;
+    let latitude = values[1].as_f64()?;
+    ((-180.0..=180.0).contains(&longitude) && (-90.0..=90.0).contains(&latitude)).then_some((longitude, latitude))
+}
+
+const SECTOR_COLUMNS: &str = r#"
+    id, company_id, code, name, postal_codes, communes,
+    ST_AsGeoJSON(boundary)::jsonb AS boundary, is_active, created_at, updated_at
+"#;
+
+/// Almacén de sectores (tabla `sectors`)
+#[derive(Debug, Clone)]
+pub struct SectorStore {
+    pool: PgPool,
+}
+
+impl SectorStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Sectores de una empresa
+    pub async fn list(&self, company_id: Uuid) -> Result<Vec<Sector>, sqlx::Error> {
+        sqlx::query_as::<_, Sector>(&format!(
+            "SELECT {} FROM sectors WHERE company_id = $1 AND deleted_at IS NULL ORDER BY code",
+            SECTOR_COLUMNS
+        ))
+        .bind(company_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn get(&self, company_id: Uuid, id: Uuid) -> Result<Option<Sector>, sqlx::Error> {
+        sqlx::query_as::<_, Sector>(&format!(
+            "SELECT {} FROM sectors WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+            SECTOR_COLUMNS
+        ))
+        .bind(id)
+        .bind(company_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn create(&self, company_id: Uuid, input: &SectorInput) -> Result<Sector, sqlx::Error> {
+        sqlx::query_as::<_, Sector>(&format!(
+            r#"
+            INSERT INTO sectors (company_id, code, name, postal_codes, communes, boundary, is_active)
+            VALUES ($1, $2, $3, $4, $5, ST_Multi(ST_GeomFromGeoJSON($6::text))::geography, COALESCE($7, true))
+            RETURNING {}
+            "#,
+            SECTOR_COLUMNS
+        ))
+        .bind(company_id)
+        .bind(input.code.trim())
+        .bind(input.name.trim())
+        .bind(&input.postal_codes)
+        .bind(&input.communes)
+        .bind(input.boundary.as_ref().map(|b| b.to_string()))
+        .bind(input.is_active)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Reemplaza el sector; `None` si no existe
+    pub async fn update(&self, company_id: Uuid, id: Uuid, input: &SectorInput) -> Result<Option<Sector>, sqlx::Error> {
+        sqlx::query_as::<_, Sector>(&format!(
+            r#"
+            UPDATE sectors SET
+                code = $3,
+                name = $4,
+                postal_codes = $5,
+                communes = $6,
+                boundary = ST_Multi(ST_GeomFromGeoJSON($7::text))::geography,
+                is_active = COALESCE($8, is_active),
+                updated_at = NOW()
+            WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+            RETURNING {}
+            "#,
+            SECTOR_COLUMNS
+        ))
+        .bind(id)
+        .bind(company_id)
+        .bind(input.code.trim())
+        .bind(input.name.trim())
+        .bind(&input.postal_codes)
+        .bind(&input.communes)
+        .bind(input.boundary.as_ref().map(|b| b.to_string()))
+        .bind(input.is_active)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Baja lógica; drivers y tournées asignados quedan sin sector
+    pub async fn delete(&self, company_id: Uuid, id: Uuid) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "UPDATE sectors SET deleted_at = NOW(), is_active = false WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .bind(company_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query("UPDATE users SET sector_id = NULL WHERE sector_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE tournees SET sector_id = NULL WHERE sector_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Asignar (o quitar, con `None`) el sector de un driver.
+    /// `false` si el driver o el sector no pertenecen a la empresa.
+    pub async fn assign_driver(&self, company_id: Uuid, driver_id: Uuid, sector_id: Option<Uuid>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users SET sector_id = $3, updated_at = NOW()
+            WHERE id = $1 AND company_id = $2 AND user_type = 'driver' AND deleted_at IS NULL
+              AND ($3::uuid IS NULL OR EXISTS (
+                  SELECT 1 FROM sectors WHERE id = $3 AND company_id = $2 AND deleted_at IS NULL
+              ))
+            "#,
+        )
+        .bind(driver_id)
+        .bind(company_id)
+        .bind(sector_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Asignar (o quitar) el sector de una tournée
+    pub async fn assign_tournee(&self, company_id: Uuid, tournee_id: Uuid, sector_id: Option<Uuid>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE tournees SET sector_id = $3, updated_at = NOW()
+            WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+              AND ($3::uuid IS NULL OR EXISTS (
+                  SELECT 1 FROM sectors WHERE id = $3 AND company_id = $2 AND deleted_at IS NULL
+              ))
+            "#,
+        )
+        .bind(tournee_id)
+        .bind(company_id)
+        .bind(sector_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Sector de un driver (por username o número de tournée) para una fecha:
+    /// el de su tournée de ese día si lo tiene, si no el del driver
+    pub async fn for_driver(&self, company_id: Uuid, matricule: &str, date: &str) -> Result<Option<Sector>, sqlx::Error> {
+        sqlx::query_as::<_, Sector>(&format!(
+            r#"
+            SELECT {} FROM sectors
+            WHERE id = (
+                SELECT COALESCE(t.sector_id, u.sector_id)
+                FROM users u
+                LEFT JOIN tournees t
+                    ON t.driver_id = u.id AND t.tournee_date = $3::date AND t.deleted_at IS NULL
+                WHERE u.company_id = $1
+                  AND u.user_type = 'driver'
+                  AND (u.username = $2 OR u.tournee_number = $2)
+                  AND u.deleted_at IS NULL
+                LIMIT 1
+            )
+              AND deleted_at IS NULL
+              AND is_active
+            "#,
+            SECTOR_COLUMNS
+        ))
+        .bind(company_id)
+        .bind(matricule)
+        .bind(date)
+        .fetch_optional(&self.pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_support::{register, TestDb};
+
+    fn square() -> serde_json::Value {
+        serde_json::json!({
+            "type": "Polygon",
+            "coordinates": [[[2.34, 48.88], [2.36, 48.88], [2.36, 48.90], [2.34, 48.90], [2.34, 48.88]]]
+        })
+    }
+
+    fn sector(postal_codes: &[&str], communes: &[&str]) -> Sector {
+        Sector {
+            id: Uuid::new_v4(),
+            company_id: Uuid::new_v4(),
+            code: "CE18".to_string(),
+            name: "Paris 18".to_string(),
+            postal_codes: postal_codes.iter().map(|c| c.to_string()).collect(),
+            communes: communes.iter().map(|c| c.to_string()).collect(),
+            boundary: None,
+            is_active: true,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_districts_pair_postal_codes_and_communes() {
+        assert_eq!(sector(&["75018"], &["Paris"]).districts(), vec!["75018 Paris"]);
+        assert_eq!(
+            sector(&["93400", "93200"], &["Saint-Ouen", "Saint-Denis"]).districts(),
+            vec!["93400 Saint-Ouen", "93200 Saint-Denis"]
+        );
+        assert_eq!(sector(&["75017", "75018"], &["Paris"]).districts(), vec!["75017 Paris", "75018 Paris"]);
+        assert_eq!(sector(&["75018"], &[]).districts(), vec!["75018"]);
+    }
+
+    #[test]
+    fn test_covers_matches_whole_postal_code() {
+        let s = sector(&["75018"], &["Paris"]);
+        assert!(s.covers("16 RUE JEAN COTTIN, 75018 PARIS"));
+        assert!(!s.covers("16 RUE JEAN COTTIN, 75019 PARIS"));
+        assert!(!s.covers("750180 RUE X"));
+    }
+
+    #[test]
+    fn test_sector_input_validation() {
+        let mut input = SectorInput {
+            code: "CE18".to_string(),
+            name: "Paris 18".to_string(),
+            postal_codes: vec!["75018".to_string()],
+            communes: vec!["Paris".to_string()],
+            boundary: None,
+            is_active: None,
+        };
+        assert!(input.validate().is_ok());
+
+        input.postal_codes = vec!["7518".to_string()];
+        assert!(input.validate().is_err());
+
+        input.postal_codes.clear();
+        assert!(input.validate().is_err());
+
+        input.boundary = Some(square());
+        assert!(input.validate().is_ok());
+
+        input.boundary = Some(serde_json::json!({"type": "MultiPolygon", "coordinates": [square()["coordinates"]]}));
+        assert!(input.validate().is_ok());
+
+        input.boundary = Some(serde_json::json!({"type": "Point", "coordinates": [2.35, 48.89]}));
+        assert!(input.validate().is_err());
+    }
+
+    #[test]
+    fn test_malformed_boundary_is_rejected() {
+        let invalid = [
+            serde_json::json!({"type": "Polygon", "coordinates": []}),
+            serde_json::json!({"type": "Polygon"}),
+            serde_json::json!({"type": "Polygon", "coordinates": "x"}),
+            // Anillo sin cerrar y anillo con menos de 4 posiciones
+            serde_json::json!({"type": "Polygon", "coordinates": [[[2.34, 48.88], [2.36, 48.88], [2.36, 48.90], [2.34, 48.90]]]}),
+            serde_json::json!({"type": "Polygon", "coordinates": [[[2.34, 48.88], [2.36, 48.88], [2.34, 48.88]]]}),
+            // Latitud y longitud invertidas fuera de rango
+            serde_json::json!({"type": "Polygon", "coordinates": [[[48.88, 200.0], [48.90, 200.0], [48.90, 201.0], [48.88, 200.0]]]}),
+            serde_json::json!({"type": "Polygon", "coordinates": [[["2.34", 48.88], [2.36, 48.88], [2.36, 48.90], ["2.34", 48.88]]]}),
+            serde_json::json!({"type": "MultiPolygon", "coordinates": []}),
+            serde_json::json!({"type": "MultiPolygon", "coordinates": [[]]}),
+        ];
+
+        for boundary in invalid {
+            assert!(validate_boundary(&boundary).is_err(), "{}", boundary);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sector_code_reusable_after_delete_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let (_, company_id) = register(&db.app(), "Mu").await;
+        let company_id = Uuid::parse_str(&company_id).unwrap();
+        let store = SectorStore::new(db.pool.clone());
+
+        // Sin polígono: el INSERT no depende de PostGIS
+        let insert = || {
+            sqlx::query_scalar::<_, Uuid>(
+                "INSERT INTO sectors (company_id, code, name, postal_codes) VALUES ($1, 'CE18', 'Paris 18', '{75018}') RETURNING id",
+            )
+            .bind(company_id)
+            .fetch_one(&db.pool)
+        };
+        let sector_id = insert().await.unwrap();
+
+        // Código repetido con el sector vivo: violación de unicidad (409 en la API)
+        let duplicate = insert().await.unwrap_err();
+        assert_eq!(duplicate.as_database_error().and_then(|e| e.code()).as_deref(), Some("23505"));
+
+        // Tras la baja lógica el código vuelve a estar libre
+        assert!(store.delete(company_id, sector_id).await.unwrap());
+        let recreated = insert().await.unwrap();
+        assert_ne!(recreated, sector_id);
+
+        db.drop().await;
+    }
+}
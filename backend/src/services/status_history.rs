@@ -0,0 +1,127 @@
+//! Histórico de estados de tournées y paquetes
+//!
+//! Las tablas `*_status_history` son de solo inserción (un trigger rechaza
+//! UPDATE y DELETE). Cada transición se registra en la misma transacción que
+//! el cambio de estado.
+
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::models::package::DeliveryStatus;
+use crate::models::tournee::{StatusHistoryEntry, TourneeStatus};
+
+/// Registrar una transición de tournée (`from` es `None` al crearla)
+pub async fn record_tournee_status(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    tournee_id: Uuid,
+    from: Option<&TourneeStatus>,
+    to: &TourneeStatus,
+    changed_by: Uuid,
+    notes: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO tournee_status_history (company_id, tournee_id, from_status, to_status, changed_by, notes)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(company_id)
+    .bind(tournee_id)
+    .bind(from.cloned())
+    .bind(to.clone())
+    .bind(changed_by)
+    .bind(notes)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Registrar una transición de paquete (`from` es `None` al crearlo,
+/// `changed_by` es `None` en las importaciones de Colis Privé)
+pub async fn record_package_status(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    package_id: Uuid,
+    from: Option<&DeliveryStatus>,
+    to: &DeliveryStatus,
+    changed_by: Option<Uuid>,
+    notes: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO package_status_history (company_id, package_id, from_status, to_status, changed_by, notes)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(company_id)
+    .bind(package_id)
+    .bind(from.cloned())
+    .bind(to.clone())
+    .bind(changed_by)
+    .bind(notes)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Minutos que la tournée ha pasado en pausa (una pausa abierta cuenta hasta ahora)
+pub async fn paused_minutes(conn: &mut PgConnection, tournee_id: Uuid) -> Result<f64, sqlx::Error> {
+    sqlx::query_scalar::<_, f64>(
+        r#"
+        SELECT COALESCE(SUM(EXTRACT(EPOCH FROM (next_at - created_at))), 0)::float8 / 60
+        FROM (
+            SELECT to_status, created_at,
+                LEAD(created_at, 1, NOW()) OVER (ORDER BY created_at, id) AS next_at
+            FROM tournee_status_history
+            WHERE tournee_id = $1
+        ) h
+        WHERE to_status = 'paused'
+        "#,
+    )
+    .bind(tournee_id)
+    .fetch_one(conn)
+    .await
+}
+
+/// Histórico de una tournée, del más antiguo al más reciente
+pub async fn tournee_history(
+    pool: &PgPool,
+    company_id: Uuid,
+    tournee_id: Uuid,
+) -> Result<Vec<StatusHistoryEntry>, sqlx::Error> {
+    sqlx::query_as::<_, StatusHistoryEntry>(
+        r#"
+        SELECT from_status::text AS from_status, to_status::text AS to_status, changed_by, notes, created_at
+        FROM tournee_status_history
+        WHERE company_id = $1 AND tournee_id = $2
+        ORDER BY created_at, id
+        "#,
+    )
+    .bind(company_id)
+    .bind(tournee_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Histórico de un paquete, del más antiguo al más reciente
+pub async fn package_history(
+    pool: &PgPool,
+    company_id: Uuid,
+    package_id: Uuid,
+) -> Result<Vec<StatusHistoryEntry>, sqlx::Error> {
+    sqlx::query_as::<_, StatusHistoryEntry>(
+        r#"
+        SELECT from_status::text AS from_status, to_status::text AS to_status, changed_by, notes, created_at
+        FROM package_status_history
+        WHERE company_id = $1 AND package_id = $2
+        ORDER BY created_at, id
+        "#,
+    )
+    .bind(company_id)
+    .bind(package_id)
+    .fetch_all(pool)
+    .await
+}
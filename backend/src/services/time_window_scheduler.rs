@@ -0,0 +1,535 @@
+//! Secuenciación con ventanas horarias
+//!
+//! Ordena las paradas de una tournée respetando ventanas horarias duras
+//! (COLIS RENDEZ-VOUS) y blandas, y paquetes prioritarios. Simula el recorrido
+//! para calcular tiempo de servicio, ETA y retraso de cada parada.
+
+use std::sync::Arc;
+
+use chrono::{NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::models::colis_prive_v3_models::DeliveryWindow;
+use crate::services::route_optimizer::{DistanceMetric, GeoPoint, RouteProblem};
+
+/// Mejora mínima para aceptar un movimiento
+const EPSILON: f64 = 1e-6;
+
+/// Longitud máxima de los segmentos que se recolocan
+const MAX_SEGMENT: usize = 3;
+
+/// Tipo de ventana horaria
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowKind {
+    /// Rendez-vous: no se puede entregar antes del inicio; llegar tarde es un fallo
+    Hard,
+    /// Preferencia del destinatario: desviarse se penaliza pero se entrega igual
+    #[default]
+    Soft,
+}
+
+/// Ventana horaria de entrega
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub kind: WindowKind,
+}
+
+impl TimeWindow {
+    /// Convertir la ventana del modelo v3 ("11:00", "11h00", "2025-09-01T11:00:00"...)
+    pub fn from_delivery_window(window: &DeliveryWindow, kind: WindowKind) -> Option<Self> {
+        let start = parse_time(&window.start_time)?;
+        let end = parse_time(&window.end_time)?;
+        (start < end).then_some(Self { start, end, kind })
+    }
+}
+
+/// Interpretar una hora en los formatos que usa Colis Privé
+pub fn parse_time(value: &str) -> Option<NaiveTime> {
+    let value = value.trim();
+
+    ["%H:%M", "%H:%M:%S", "%Hh%M", "%Hh"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(value, format).ok())
+        .or_else(|| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").ok().map(|dt| dt.time()))
+        .or_else(|| chrono::DateTime::parse_from_rfc3339(value).ok().map(|dt| dt.time()))
+}
+
+/// Parada a secuenciar
+#[derive(Debug, Clone)]
+pub struct ScheduleStop {
+    pub id: String,
+    pub location: GeoPoint,
+    pub window: Option<TimeWindow>,
+    /// 0 = normal; cuanto mayor, antes conviene entregarlo
+    pub priority: u8,
+    pub signature_required: bool,
+}
+
+/// Parámetros de la simulación
+#[derive(Debug, Clone)]
+pub struct ScheduleConfig {
+    /// Hora de salida del depósito
+    pub start_time: NaiveTime,
+    pub average_speed_kmh: f64,
+    pub base_service_minutes: f64,
+    pub signature_service_minutes: f64,
+    pub rendez_vous_service_minutes: f64,
+    /// Penalización por minuto fuera de una ventana blanda
+    pub soft_penalty_per_minute: f64,
+    /// Penalización por minuto de retraso en una ventana dura
+    pub hard_penalty_per_minute: f64,
+    /// Peso por nivel de prioridad y minuto transcurrido hasta la entrega
+    pub priority_weight: f64,
+    pub max_passes: usize,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            start_time: NaiveTime::from_hms_opt(11, 0, 0).expect("hora válida"), // horario de la lettre de voiture
+            average_speed_kmh: 20.0,          // reparto urbano
+            base_service_minutes: 2.0,
+            signature_service_minutes: 1.5,
+            rendez_vous_service_minutes: 3.0,
+            soft_penalty_per_minute: 2.0,
+            hard_penalty_per_minute: 1000.0,
+            priority_weight: 0.05,
+            max_passes: 20,
+        }
+    }
+}
+
+/// ETA de una parada
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StopEta {
+    pub id: String,
+    pub sequence: usize,
+    pub arrival: NaiveTime,
+    pub service_start: NaiveTime,
+    pub departure: NaiveTime,
+    pub wait_minutes: f64,
+    pub service_minutes: f64,
+    pub window: Option<TimeWindow>,
+    pub priority: u8,
+    pub late_minutes: f64,
+}
+
+/// Parada que se entregará fuera de su ventana
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LateStop {
+    pub id: String,
+    pub kind: WindowKind,
+    pub window_end: NaiveTime,
+    pub late_minutes: f64,
+}
+
+/// Secuencia con ETAs e informe de retrasos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeWindowSchedule {
+    pub stops: Vec<StopEta>,
+    pub late: Vec<LateStop>,
+    pub total_distance_km: f64,
+    pub total_duration_minutes: f64,
+    pub finish_time: NaiveTime,
+}
+
+/// Secuenciador con ventanas horarias y distancia intercambiable
+#[derive(Clone)]
+pub struct TimeWindowScheduler {
+    metric: Arc<dyn DistanceMetric>,
+    config: ScheduleConfig,
+}
+
+impl TimeWindowScheduler {
+    pub fn new(metric: Arc<dyn DistanceMetric>, config: ScheduleConfig) -> Self {
+        Self { metric, config }
+    }
+
+    /// Tiempo de servicio de una parada, en minutos
+    pub fn service_minutes(&self, stop: &ScheduleStop) -> f64 {
+        let mut minutes = self.config.base_service_minutes;
+        if stop.signature_required {
+            minutes += self.config.signature_service_minutes;
+        }
+        if stop.window.is_some_and(|w| w.kind == WindowKind::Hard) {
+            minutes += self.config.rendez_vous_service_minutes;
+        }
+        minutes
+    }
+
+    /// Secuenciar saliendo de `depot` a `config.start_time` y terminando en `end` (opcional)
+    pub fn schedule(&self, depot: GeoPoint, end: Option<GeoPoint>, stops: &[ScheduleStop]) -> TimeWindowSchedule {
+        let mut points = Vec::with_capacity(stops.len() + 2);
+        points.push(depot);
+        points.extend(stops.iter().map(|s| s.location));
+        points.extend(end);
+
+        let distances: Vec<Vec<f64>> = points
+            .iter()
+            .map(|&from| points.iter().map(|&to| self.metric.distance_km(from, to)).collect())
+            .collect();
+
+        let simulation = Simulation {
+            scheduler: self,
+            stops,
+            distances: &distances,
+            end: end.map(|_| stops.len() + 1),
+        };
+
+        // Semillas: la ruta más corta y el orden por fin de ventana; se mejora la mejor
+        let shortest = RouteProblem::new(&distances, stops.len(), end.is_some()).solve(self.config.max_passes);
+        let by_deadline = simulation.earliest_deadline_order();
+        let mut order = [shortest, by_deadline]
+            .into_iter()
+            .min_by(|a, b| simulation.cost(a).total_cmp(&simulation.cost(b)))
+            .unwrap_or_default();
+
+        simulation.improve(&mut order, self.config.max_passes);
+        simulation.report(&order)
+    }
+}
+
+/// Resultado de simular una parada
+struct Visit {
+    arrival: f64,
+    wait: f64,
+    service_start: f64,
+    departure: f64,
+    late: f64,
+    penalty: f64,
+}
+
+/// Evaluación de órdenes: nodo 0 = depósito, 1..=n = paradas, n+1 = destino opcional
+struct Simulation<'a> {
+    scheduler: &'a TimeWindowScheduler,
+    stops: &'a [ScheduleStop],
+    distances: &'a [Vec<f64>],
+    end: Option<usize>,
+}
+
+impl Simulation<'_> {
+    fn travel_minutes(&self, from: usize, to: usize) -> f64 {
+        self.distances[from][to] / self.scheduler.config.average_speed_kmh * 60.0
+    }
+
+    fn start_minutes(&self) -> f64 {
+        minutes_of(self.scheduler.config.start_time)
+    }
+
+    /// Llegar a `node` saliendo de `from` en el minuto `time`
+    fn visit(&self, from: usize, node: usize, time: f64) -> Visit {
+        let config = &self.scheduler.config;
+        let stop = &self.stops[node - 1];
+        let arrival = time + self.travel_minutes(from, node);
+
+        let (wait, late, penalty) = match stop.window {
+            Some(window) => {
+                let (start, end) = (minutes_of(window.start), minutes_of(window.end));
+                match window.kind {
+                    WindowKind::Hard => {
+                        let wait = (start - arrival).max(0.0);
+                        let late = (arrival - end).max(0.0);
+                        (wait, late, late * config.hard_penalty_per_minute)
+                    }
+                    WindowKind::Soft => {
+                        let late = (arrival - end).max(0.0);
+                        let early = (start - arrival).max(0.0);
+                        (0.0, late, (late + early) * config.soft_penalty_per_minute)
+                    }
+                }
+            }
+            None => (0.0, 0.0, 0.0),
+        };
+
+        let service_start = arrival + wait;
+        let priority_cost = f64::from(stop.priority) * config.priority_weight * (service_start - self.start_minutes());
+
+        Visit {
+            arrival,
+            wait,
+            service_start,
+            departure: service_start + self.scheduler.service_minutes(stop),
+            late,
+            penalty: penalty + priority_cost,
+        }
+    }
+
+    /// Duración total más penalizaciones
+    fn cost(&self, order: &[usize]) -> f64 {
+        let mut time = self.start_minutes();
+        let mut penalty = 0.0;
+        let mut current = 0;
+
+        for &node in order {
+            let visit = self.visit(current, node, time);
+            penalty += visit.penalty;
+            time = visit.departure;
+            current = node;
+        }
+
+        if let Some(end) = self.end {
+            time += self.travel_minutes(current, end);
+        }
+
+        (time - self.start_minutes()) + penalty
+    }
+
+    /// Paradas con ventana por hora de cierre, luego el resto por cercanía
+    fn earliest_deadline_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (1..=self.stops.len()).collect();
+        order.sort_by(|&a, &b| {
+            let deadline = |node: usize| self.stops[node - 1].window.map_or(f64::MAX, |w| minutes_of(w.end));
+            deadline(a)
+                .total_cmp(&deadline(b))
+                .then(self.stops[b - 1].priority.cmp(&self.stops[a - 1].priority))
+                .then(self.distances[0][a].total_cmp(&self.distances[0][b]))
+        });
+        order
+    }
+
+    /// Búsqueda local: recolocar segmentos e invertir tramos mientras mejore
+    fn improve(&self, order: &mut Vec<usize>, max_passes: usize) {
+        let mut best = self.cost(order);
+
+        for _ in 0..max_passes {
+            let before = best;
+            self.relocate(order, &mut best);
+            self.reverse(order, &mut best);
+            if before - best < EPSILON {
+                break;
+            }
+        }
+    }
+
+    fn relocate(&self, order: &mut Vec<usize>, best: &mut f64) {
+        let n = order.len();
+        for len in 1..=MAX_SEGMENT.min(n.saturating_sub(1)) {
+            let mut i = 0;
+            while i + len <= order.len() {
+                let segment: Vec<usize> = order[i..i + len].to_vec();
+                let rest: Vec<usize> = order[..i].iter().chain(&order[i + len..]).copied().collect();
+
+                let improved = (0..=rest.len()).filter(|&k| k != i).find_map(|k| {
+                    let mut candidate = rest.clone();
+                    candidate.splice(k..k, segment.iter().copied());
+                    let cost = self.cost(&candidate);
+                    (cost < *best - EPSILON).then_some((candidate, cost))
+                });
+
+                if let Some((candidate, cost)) = improved {
+                    *order = candidate;
+                    *best = cost;
+                }
+                i += 1;
+            }
+        }
+    }
+
+    fn reverse(&self, order: &mut [usize], best: &mut f64) {
+        let n = order.len();
+        for i in 0..n {
+            for j in (i + 1)..n {
+                order[i..=j].reverse();
+                let cost = self.cost(order);
+                if cost < *best - EPSILON {
+                    *best = cost;
+                } else {
+                    order[i..=j].reverse();
+                }
+            }
+        }
+    }
+
+    fn report(&self, order: &[usize]) -> TimeWindowSchedule {
+        let mut time = self.start_minutes();
+        let mut current = 0;
+        let mut distance = 0.0;
+        let mut stops = Vec::with_capacity(order.len());
+        let mut late = Vec::new();
+
+        for (index, &node) in order.iter().enumerate() {
+            let stop = &self.stops[node - 1];
+            let visit = self.visit(current, node, time);
+            distance += self.distances[current][node];
+
+            if visit.late > EPSILON {
+                if let Some(window) = stop.window {
+                    late.push(LateStop {
+                        id: stop.id.clone(),
+                        kind: window.kind,
+                        window_end: window.end,
+                        late_minutes: round1(visit.late),
+                    });
+                }
+            }
+
+            stops.push(StopEta {
+                id: stop.id.clone(),
+                sequence: index + 1,
+                arrival: time_of(visit.arrival),
+                service_start: time_of(visit.service_start),
+                departure: time_of(visit.departure),
+                wait_minutes: round1(visit.wait),
+                service_minutes: round1(self.scheduler.service_minutes(stop)),
+                window: stop.window,
+                priority: stop.priority,
+                late_minutes: round1(visit.late),
+            });
+
+            time = visit.departure;
+            current = node;
+        }
+
+        if let Some(end) = self.end {
+            time += self.travel_minutes(current, end);
+            distance += self.distances[current][end];
+        }
+
+        TimeWindowSchedule {
+            stops,
+            late,
+            total_distance_km: (distance * 1000.0).round() / 1000.0,
+            total_duration_minutes: round1(time - self.start_minutes()),
+            finish_time: time_of(time),
+        }
+    }
+}
+
+fn minutes_of(time: NaiveTime) -> f64 {
+    f64::from(time.num_seconds_from_midnight()) / 60.0
+}
+
+fn time_of(minutes: f64) -> NaiveTime {
+    let seconds = (minutes * 60.0).round().rem_euclid(86_400.0) as u32;
+    NaiveTime::from_num_seconds_from_midnight_opt(seconds, 0).unwrap_or(NaiveTime::MIN)
+}
+
+fn round1(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 unidad de coordenada = 1 km, para tiempos exactos
+    struct Euclidean;
+
+    impl DistanceMetric for Euclidean {
+        fn distance_km(&self, from: GeoPoint, to: GeoPoint) -> f64 {
+            ((from.latitude - to.latitude).powi(2) + (from.longitude - to.longitude).powi(2)).sqrt()
+        }
+    }
+
+    fn hm(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn stop(id: &str, x: f64) -> ScheduleStop {
+        ScheduleStop {
+            id: id.to_string(),
+            location: GeoPoint { latitude: 0.0, longitude: x },
+            window: None,
+            priority: 0,
+            signature_required: false,
+        }
+    }
+
+    fn window(start: NaiveTime, end: NaiveTime, kind: WindowKind) -> Option<TimeWindow> {
+        Some(TimeWindow { start, end, kind })
+    }
+
+    /// 60 km/h y 2 min de servicio: 1 km = 1 min de trayecto
+    fn scheduler() -> TimeWindowScheduler {
+        let config = ScheduleConfig {
+            start_time: hm(11, 0),
+            average_speed_kmh: 60.0,
+            ..Default::default()
+        };
+        TimeWindowScheduler::new(Arc::new(Euclidean), config)
+    }
+
+    const DEPOT: GeoPoint = GeoPoint { latitude: 0.0, longitude: 0.0 };
+
+    #[test]
+    fn test_parse_time_formats() {
+        assert_eq!(parse_time("11:00"), Some(hm(11, 0)));
+        assert_eq!(parse_time(" 14h30 "), Some(hm(14, 30)));
+        assert_eq!(parse_time("2025-09-01T18:15:00"), Some(hm(18, 15)));
+        assert_eq!(parse_time("2025-09-01T18:15:00+02:00"), Some(hm(18, 15)));
+        assert_eq!(parse_time("mañana"), None);
+
+        let dw = DeliveryWindow { start_time: "12:00".to_string(), end_time: "11:00".to_string(), preferred_time: None };
+        assert!(TimeWindow::from_delivery_window(&dw, WindowKind::Hard).is_none());
+    }
+
+    #[test]
+    fn test_etas_without_windows() {
+        let mut far = stop("far", 20.0);
+        far.signature_required = true;
+        let schedule = scheduler().schedule(DEPOT, None, &[far, stop("near", 10.0)]);
+
+        let ids: Vec<_> = schedule.stops.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["near", "far"]);
+        assert_eq!(schedule.stops[0].arrival, hm(11, 10));
+        assert_eq!(schedule.stops[0].departure, hm(11, 12));
+        assert_eq!(schedule.stops[1].arrival, hm(11, 22));
+        assert_eq!(schedule.stops[1].service_minutes, 3.5);
+        assert_eq!(schedule.finish_time, hm(11, 25).with_second(30).unwrap());
+        assert!(schedule.late.is_empty());
+    }
+
+    #[test]
+    fn test_hard_window_reorders_and_waits() {
+        // El rendez-vous lejano cierra pronto: hay que ir antes que a la parada cercana
+        let mut rdv = stop("rdv", 20.0);
+        rdv.window = window(hm(11, 15), hm(11, 20), WindowKind::Hard);
+        let schedule = scheduler().schedule(DEPOT, None, &[stop("near", 10.0), rdv.clone()]);
+
+        assert_eq!(schedule.stops[0].id, "rdv");
+        assert_eq!(schedule.stops[0].arrival, hm(11, 20));
+        assert!(schedule.late.is_empty());
+
+        // Llegar antes de la apertura obliga a esperar
+        rdv.window = window(hm(11, 25), hm(11, 30), WindowKind::Hard);
+        let schedule = scheduler().schedule(DEPOT, None, &[rdv]);
+
+        assert_eq!(schedule.stops[0].wait_minutes, 5.0);
+        assert_eq!(schedule.stops[0].service_start, hm(11, 25));
+        assert_eq!(schedule.stops[0].service_minutes, 5.0);
+    }
+
+    #[test]
+    fn test_unreachable_window_is_reported_late() {
+        let mut rdv = stop("rdv", 30.0);
+        rdv.window = window(hm(11, 0), hm(11, 20), WindowKind::Hard);
+        let mut soft = stop("soft", 30.5);
+        soft.window = window(hm(11, 0), hm(11, 25), WindowKind::Soft);
+
+        let schedule = scheduler().schedule(DEPOT, None, &[soft, rdv]);
+
+        assert_eq!(schedule.stops[0].id, "rdv");
+        let late: Vec<_> = schedule.late.iter().map(|l| (l.id.as_str(), l.kind, l.late_minutes)).collect();
+        assert_eq!(late, vec![("rdv", WindowKind::Hard, 10.0), ("soft", WindowKind::Soft, 10.5)]);
+    }
+
+    #[test]
+    fn test_priority_stop_goes_first() {
+        // Misma distancia a ambos lados del depósito: la prioridad decide
+        let mut urgent = stop("urgent", -10.0);
+        urgent.priority = 3;
+        let schedule = scheduler().schedule(DEPOT, None, &[stop("normal", 10.0), urgent]);
+
+        assert_eq!(schedule.stops[0].id, "urgent");
+    }
+
+    #[test]
+    fn test_return_to_depot_counts_in_duration() {
+        let schedule = scheduler().schedule(DEPOT, Some(DEPOT), &[stop("a", 10.0)]);
+        assert_eq!(schedule.total_distance_km, 20.0);
+        assert_eq!(schedule.total_duration_minutes, 22.0);
+    }
+}
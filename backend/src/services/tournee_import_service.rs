@@ -0,0 +1,835 @@
+//! Importación de tournées de Colis Privé
+//!
+//! Persiste una tournée obtenida de Colis Privé y sus paquetes en las tablas
+//! `tournees` y `packages` mediante upserts por IDs externos. Cada ejecución
+//! queda registrada en `sync_log`.
+
+use chrono::{NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{PgPool, Row};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::lifecycle::check_package_transition;
+use crate::models::package::DeliveryStatus;
+use crate::models::tournee::TourneeStatus;
+use crate::services::{failure_policies, status_history, PackageData};
+
+/// Nombre del proveedor en `api_integrations.provider_name`
+pub const COLIS_PRIVE_PROVIDER: &str = "colis_prive";
+
+/// Origen registrado en `tournee_origin` / `package_origin`
+const COLIS_PRIVE_ORIGIN: &str = "colis_prive";
+
+/// Nota del histórico para los estados que llegan de Colis Privé
+const IMPORT_NOTE: &str = "Importado de Colis Privé";
+
+/// Errores de la importación
+#[derive(Error, Debug)]
+pub enum TourneeImportError {
+    #[error("No hay integración Colis Privé para la société {0}")]
+    IntegrationNotFound(String),
+
+    #[error("No hay driver con matricule {0} en la empresa")]
+    DriverNotFound(String),
+
+    #[error("No hay vehículo asignable al driver {0} (configura default_vehicle_id en la integración)")]
+    VehicleNotFound(String),
+
+    #[error("La société {0} está configurada en varias integraciones Colis Privé")]
+    AmbiguousIntegration(String),
+
+    #[error("El matricule {0} corresponde a varios drivers de la empresa")]
+    AmbiguousDriver(String),
+
+    #[error("El driver {0} ya tiene otra tournée el {1} que no viene de esta integración")]
+    TourneeConflict(String, NaiveDate),
+
+    #[error("Error de base de datos: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Entidades internas a las que se asocia la tournée importada
+#[derive(Debug, Clone)]
+pub struct ImportContext {
+    pub company_id: Uuid,
+    pub integration_id: Uuid,
+    pub driver_id: Uuid,
+    pub vehicle_id: Uuid,
+}
+
+/// Tournée de Colis Privé lista para importar
+#[derive(Debug, Clone)]
+pub struct ImportedTournee<'a> {
+    pub external_tournee_id: String,
+    pub tournee_number: Option<String>,
+    pub date: NaiveDate,
+    pub packages: &'a [PackageData],
+}
+
+/// Resultado de una importación
+#[derive(Debug, Clone, Serialize)]
+pub struct TourneeImportReport {
+    pub tournee_id: Uuid,
+    pub tournee_created: bool,
+    pub created: i32,
+    pub updated: i32,
+    pub failed: i32,
+}
+
+/// Resolver la integración de Colis Privé de una société: `(integration_id, company_id)`.
+/// Si la société aparece en varias integraciones no se elige una al azar.
+pub async fn resolve_integration(pool: &PgPool, societe: &str) -> Result<(Uuid, Uuid), TourneeImportError> {
+    let integrations = sqlx::query_as::<_, (Uuid, Uuid)>(
+        r#"
+        SELECT id, company_id
+        FROM api_integrations
+        WHERE provider_name = $1
+          AND api_credentials->>'societe' = $2
+          AND deleted_at IS NULL
+        LIMIT 2
+        "#,
+    )
+    .bind(COLIS_PRIVE_PROVIDER)
+    .bind(societe)
+    .fetch_all(pool)
+    .await?;
+
+    match integrations.as_slice() {
+        [] => Err(TourneeImportError::IntegrationNotFound(societe.to_string())),
+        [integration] => Ok(*integration),
+        _ => Err(TourneeImportError::AmbiguousIntegration(societe.to_string())),
+    }
+}
+
+/// Resolver empresa, integración, driver y vehículo para una société/matricule
+pub async fn resolve_import_context(
+    pool: &PgPool,
+    societe: &str,
+    matricule: &str,
+) -> Result<ImportContext, TourneeImportError> {
+    let (integration_id, company_id) = resolve_integration(pool, societe).await?;
+
+    let drivers: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+        SELECT id
+        FROM users
+        WHERE company_id = $1
+          AND user_type = 'driver'
+          AND (username = $2 OR tournee_number = $2)
+          AND deleted_at IS NULL
+        LIMIT 2
+        "#,
+    )
+    .bind(company_id)
+    .bind(matricule)
+    .fetch_all(pool)
+    .await?;
+
+    let driver_id = match drivers.as_slice() {
+        [] => return Err(TourneeImportError::DriverNotFound(matricule.to_string())),
+        [driver_id] => *driver_id,
+        _ => return Err(TourneeImportError::AmbiguousDriver(matricule.to_string())),
+    };
+
+    let vehicle_id = resolve_vehicle(pool, company_id, integration_id, driver_id)
+        .await?
+        .ok_or_else(|| TourneeImportError::VehicleNotFound(matricule.to_string()))?;
+
+    Ok(ImportContext {
+        company_id,
+        integration_id,
+        driver_id,
+        vehicle_id,
+    })
+}
+
+/// Vehículo de la tournée importada, por orden de preferencia:
+/// el de la última tournée del driver, el `default_vehicle_id` configurado en
+/// `provider_config` de la integración o el único vehículo activo de la empresa
+async fn resolve_vehicle(
+    pool: &PgPool,
+    company_id: Uuid,
+    integration_id: Uuid,
+    driver_id: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(
+            (
+                SELECT t.vehicle_id
+                FROM tournees t
+                WHERE t.driver_id = $3 AND t.deleted_at IS NULL
+                ORDER BY t.tournee_date DESC, t.created_at DESC
+                LIMIT 1
+            ),
+            (
+                SELECT v.id
+                FROM api_integrations i
+                JOIN vehicles v ON v.id::text = i.provider_config->>'default_vehicle_id'
+                WHERE i.id = $2 AND v.company_id = $1 AND v.deleted_at IS NULL
+            ),
+            (
+                SELECT MIN(v.id::text)::uuid
+                FROM vehicles v
+                WHERE v.company_id = $1 AND v.vehicle_status = 'active' AND v.deleted_at IS NULL
+                HAVING COUNT(*) = 1
+            )
+        )
+        "#,
+    )
+    .bind(company_id)
+    .bind(integration_id)
+    .bind(driver_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// Upsert de la tournée y sus paquetes, registrando la ejecución en `sync_log`
+pub async fn import_tournee(
+    pool: &PgPool,
+    ctx: &ImportContext,
+    tournee: &ImportedTournee<'_>,
+) -> Result<TourneeImportReport, TourneeImportError> {
+    let start = Utc::now();
+
+    let (tournee_id, tournee_created) = match upsert_tournee(pool, ctx, tournee).await {
+        Ok(result) => result,
+        Err(e) => {
+            log::error!("❌ Error persistiendo tournée {}: {}", tournee.external_tournee_id, e);
+            let counts = SyncCounts { failed: tournee.packages.len() as i32, ..Default::default() };
+            let errors = json!({ "tournee": e.to_string() });
+            record_sync(pool, ctx, start, tournee.packages.len(), &counts, errors).await?;
+            return Err(e);
+        }
+    };
+
+    let mut counts = SyncCounts::default();
+    let mut errors = serde_json::Map::new();
+
+    for (position, package) in tournee.packages.iter().enumerate() {
+        match upsert_package(pool, ctx, tournee_id, package, position as i32 + 1).await {
+            Ok(true) => counts.created += 1,
+            Ok(false) => counts.updated += 1,
+            Err(e) => {
+                log::warn!("⚠️ Error persistiendo paquete {}: {}", package.id, e);
+                counts.failed += 1;
+                errors.insert(package.id.clone(), json!(e.to_string()));
+            }
+        }
+    }
+
+    record_sync(pool, ctx, start, tournee.packages.len(), &counts, serde_json::Value::Object(errors)).await?;
+
+    log::info!("💾 Tournée {} persistida: {} creados, {} actualizados, {} fallidos",
+        tournee.external_tournee_id, counts.created, counts.updated, counts.failed);
+
+    Ok(TourneeImportReport {
+        tournee_id,
+        tournee_created,
+        created: counts.created,
+        updated: counts.updated,
+        failed: counts.failed,
+    })
+}
+
+/// Contadores de una sincronización
+#[derive(Debug, Default)]
+struct SyncCounts {
+    created: i32,
+    updated: i32,
+    failed: i32,
+}
+
+impl SyncCounts {
+    /// Estado para `sync_log.sync_status`
+    fn sync_status(&self) -> &'static str {
+        match (self.failed, self.created + self.updated) {
+            (0, _) => "completed",
+            (_, 0) => "failed",
+            _ => "partial",
+        }
+    }
+}
+
+/// Devuelve `(id, creada)` de la tournée. Al crearla recibe los paquetes
+/// fallidos que el driver tenga pendientes de reprogramar.
+///
+/// Un driver solo tiene una tournée por día (`unique_tournee_per_driver_date`):
+/// si ya existe una de esta integración con otro ID externo (p. ej. Colis Privé
+/// ha renumerado la tournée) se reutiliza; si es de otro origen se rechaza.
+async fn upsert_tournee(
+    pool: &PgPool,
+    ctx: &ImportContext,
+    tournee: &ImportedTournee<'_>,
+) -> Result<(Uuid, bool), TourneeImportError> {
+    let mut tx = pool.begin().await?;
+
+    let existing = sqlx::query_as::<_, (Uuid, Option<Uuid>)>(
+        "SELECT id, integration_id FROM tournees WHERE driver_id = $1 AND tournee_date = $2 FOR UPDATE",
+    )
+    .bind(ctx.driver_id)
+    .bind(tournee.date)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let conflict = || TourneeImportError::TourneeConflict(ctx.driver_id.to_string(), tournee.date);
+
+    if let Some((id, integration_id)) = existing {
+        if integration_id != Some(ctx.integration_id) {
+            return Err(conflict());
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE tournees SET
+                external_tournee_id = $2,
+                tournee_number = COALESCE($3, tournee_number),
+                deleted_at = NULL,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(&tournee.external_tournee_id)
+        .bind(&tournee.tournee_number)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        return Ok((id, false));
+    }
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO tournees (
+            company_id, driver_id, vehicle_id, tournee_date, tournee_number,
+            tournee_origin, external_tournee_id, integration_id, sector_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, (SELECT sector_id FROM users WHERE id = $2))
+        ON CONFLICT (integration_id, external_tournee_id, tournee_date) DO UPDATE SET
+            tournee_number = COALESCE(EXCLUDED.tournee_number, tournees.tournee_number),
+            deleted_at = NULL
+        RETURNING id, (xmax = 0) AS inserted
+        "#,
+    )
+    .bind(ctx.company_id)
+    .bind(ctx.driver_id)
+    .bind(ctx.vehicle_id)
+    .bind(tournee.date)
+    .bind(&tournee.tournee_number)
+    .bind(COLIS_PRIVE_ORIGIN)
+    .bind(&tournee.external_tournee_id)
+    .bind(ctx.integration_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| match e.as_database_error().and_then(|d| d.constraint()) {
+        // Otra importación concurrente creó la tournée del driver
+        Some("unique_tournee_per_driver_date") => conflict(),
+        _ => TourneeImportError::Database(e),
+    })?;
+
+    let (id, inserted) = (row.try_get("id")?, row.try_get("inserted")?);
+    if inserted {
+        failure_policies::carry_over_failed(&mut tx, ctx.company_id, ctx.driver_id, None).await?;
+    }
+    tx.commit().await?;
+
+    Ok((id, inserted))
+}
+
+/// Devuelve `true` si el paquete se creó, `false` si se actualizó.
+/// `sequence` (orden de Colis Privé) solo se fija al crear: no pisa una ruta optimizada.
+/// Sobre un paquete existente el estado importado pasa por la máquina de estados:
+/// si la transición no es válida (p. ej. el driver ya lo entregó) se conserva el actual.
+async fn upsert_package(
+    pool: &PgPool,
+    ctx: &ImportContext,
+    tournee_id: Uuid,
+    package: &PackageData,
+    sequence: i32,
+) -> Result<bool, sqlx::Error> {
+    let phone: String = package.phone.chars().take(20).collect();
+    let imported = map_delivery_status(&package.status);
+
+    let mut tx = pool.begin().await?;
+
+    // El DO UPDATE bloquea la fila existente; RETURNING devuelve su estado previo
+    let row = sqlx::query(
+        r#"
+        INSERT INTO packages (
+            company_id, tournee_id, tracking_number, external_tracking_number,
+            package_origin, external_package_id, integration_id, delivery_status,
+            recipient_name, recipient_phone, delivery_address, delivery_instructions,
+            delivery_coordinates, delivery_sequence
+        )
+        VALUES ($1, $2, $3, $3, $4, $5, $6, $7, $8, $9, $10, $11,
+                point($12::float8, $13::float8), $14)
+        ON CONFLICT (tournee_id, external_package_id) DO UPDATE SET
+            recipient_name = EXCLUDED.recipient_name,
+            recipient_phone = EXCLUDED.recipient_phone,
+            delivery_address = EXCLUDED.delivery_address,
+            delivery_instructions = EXCLUDED.delivery_instructions,
+            delivery_coordinates = COALESCE(EXCLUDED.delivery_coordinates, packages.delivery_coordinates),
+            deleted_at = NULL
+        RETURNING id, delivery_status, (xmax = 0) AS inserted,
+            (SELECT tournee_status FROM tournees WHERE id = $2) AS tournee_status
+        "#,
+    )
+    .bind(ctx.company_id)
+    .bind(tournee_id)
+    .bind(&package.tracking_number)
+    .bind(COLIS_PRIVE_ORIGIN)
+    .bind(&package.id)
+    .bind(ctx.integration_id)
+    .bind(&imported)
+    .bind(&package.recipient_name)
+    .bind(phone)
+    .bind(&package.address)
+    .bind(&package.instructions)
+    .bind(package.longitude)
+    .bind(package.latitude)
+    .bind(sequence)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let package_id: Uuid = row.try_get("id")?;
+    let current: DeliveryStatus = row.try_get("delivery_status")?;
+    let inserted: bool = row.try_get("inserted")?;
+    let tournee_status: TourneeStatus = row.try_get("tournee_status")?;
+
+    if inserted {
+        status_history::record_package_status(
+            &mut tx, ctx.company_id, package_id, None, &imported, None, Some(IMPORT_NOTE),
+        )
+        .await?;
+    } else if current != imported {
+        match check_package_transition(&current, &imported, &tournee_status) {
+            Ok(()) => {
+                sqlx::query("UPDATE packages SET delivery_status = $2, updated_at = NOW() WHERE id = $1")
+                    .bind(package_id)
+                    .bind(&imported)
+                    .execute(&mut *tx)
+                    .await?;
+                status_history::record_package_status(
+                    &mut tx, ctx.company_id, package_id, Some(&current), &imported, None, Some(IMPORT_NOTE),
+                )
+                .await?;
+            }
+            Err(e) => log::info!("ℹ️ Estado importado ignorado para el paquete {}: {}", package.id, e),
+        }
+    }
+
+    tx.commit().await?;
+    Ok(inserted)
+}
+
+/// Registrar la ejecución en `sync_log` y actualizar el estado de la integración
+async fn record_sync(
+    pool: &PgPool,
+    ctx: &ImportContext,
+    start: chrono::DateTime<Utc>,
+    processed: usize,
+    counts: &SyncCounts,
+    error_details: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let end = Utc::now();
+    let status = counts.sync_status();
+
+    sqlx::query(
+        r#"
+        INSERT INTO sync_log (
+            company_id, integration_id, sync_type, sync_direction,
+            records_processed, records_created, records_updated, records_failed,
+            errors_count, sync_duration_seconds, sync_start_time, sync_end_time,
+            error_details, sync_status
+        )
+        VALUES ($1, $2, 'tournee_import', 'inbound', $3, $4, $5, $6, $6, $7, $8, $9, $10, $11)
+        "#,
+    )
+    .bind(ctx.company_id)
+    .bind(ctx.integration_id)
+    .bind(processed as i32)
+    .bind(counts.created)
+    .bind(counts.updated)
+    .bind(counts.failed)
+    .bind((end - start).num_seconds() as i32)
+    .bind(start)
+    .bind(end)
+    .bind(error_details)
+    .bind(status)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE api_integrations SET
+            last_sync_date = $2,
+            last_successful_sync = CASE WHEN $3 THEN $2 ELSE last_successful_sync END,
+            consecutive_errors = CASE WHEN $3 THEN 0 ELSE consecutive_errors + 1 END
+        WHERE id = $1
+        "#,
+    )
+    .bind(ctx.integration_id)
+    .bind(end)
+    .bind(status != "failed")
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Traducir `CodeStatutArticle` de Colis Privé a `delivery_status`
+pub fn map_delivery_status(code: &str) -> DeliveryStatus {
+    let code = code.trim().to_uppercase();
+
+    if code.contains("A_LIVRER") {
+        DeliveryStatus::Pending
+    } else if code.contains("NON_LIVRE") || code.contains("ECHEC") || code.contains("ABSENT") {
+        DeliveryStatus::Failed
+    } else if code.contains("LIVRE") {
+        DeliveryStatus::Delivered
+    } else if code.contains("RETOUR") {
+        DeliveryStatus::Returned
+    } else if code.contains("ANNUL") {
+        DeliveryStatus::Cancelled
+    } else if code.contains("EN_COURS") || code.contains("EN_LIVRAISON") {
+        DeliveryStatus::OutForDelivery
+    } else {
+        DeliveryStatus::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api::test_support::{post_ok, register, TestDb};
+
+    #[test]
+    fn test_map_delivery_status() {
+        assert_eq!(map_delivery_status("LIVRE").as_str(), "delivered");
+        assert_eq!(map_delivery_status("NON_LIVRE").as_str(), "failed");
+        assert_eq!(map_delivery_status("en_cours").as_str(), "out_for_delivery");
+        assert_eq!(map_delivery_status("RETOUR_EXPEDITEUR").as_str(), "returned");
+        assert_eq!(map_delivery_status("A_LIVRER").as_str(), "pending");
+        assert_eq!(map_delivery_status("").as_str(), "pending");
+        assert_eq!(map_delivery_status("DESCONOCIDO").as_str(), "pending");
+    }
+
+    #[test]
+    fn test_sync_status() {
+        let ok = SyncCounts { created: 3, updated: 1, failed: 0 };
+        let partial = SyncCounts { created: 3, updated: 0, failed: 1 };
+        let failed = SyncCounts { created: 0, updated: 0, failed: 2 };
+
+        assert_eq!(ok.sync_status(), "completed");
+        assert_eq!(partial.sync_status(), "partial");
+        assert_eq!(failed.sync_status(), "failed");
+        assert_eq!(SyncCounts::default().sync_status(), "completed");
+    }
+
+    /// Paquete tal como llega de Colis Privé
+    fn colis_prive_package(id: &str, status: &str) -> PackageData {
+        PackageData {
+            id: id.to_string(),
+            tracking_number: format!("CP-{}", id),
+            recipient_name: "Claire Martin".to_string(),
+            address: "8 rue Oberkampf, 75011 Paris".to_string(),
+            status: status.to_string(),
+            instructions: String::new(),
+            phone: "0601020304".to_string(),
+            priority: "normal".to_string(),
+            latitude: None,
+            longitude: None,
+            formatted_address: None,
+            validation_method: None,
+            validation_confidence: None,
+            validation_warnings: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_colis_prive_import_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (admin, company_id) = register(&app, "Zeta").await;
+
+        post_ok(&app, "/users", &admin, json!({
+            "username": "A187518",
+            "password": "driverpass",
+            "full_name": "Hugo Petit",
+            "email": "hugo@zeta.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        sqlx::query(
+            "INSERT INTO api_integrations (company_id, provider_name, api_credentials) VALUES ($1, 'colis_prive', '{\"societe\": \"PCP0010699\"}')",
+        )
+        .bind(Uuid::parse_str(&company_id).unwrap())
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        // Sin tournées previas ni vehículos: no hay a qué asignar la tournée
+        let err = resolve_import_context(&db.pool, "PCP0010699", "A187518").await.unwrap_err();
+        assert!(matches!(err, TourneeImportError::VehicleNotFound(_)));
+
+        // Primer import de un driver nuevo: se usa el único vehículo de la empresa
+        let vehicle = post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "TU-678-VW", "brand": "Renault", "model": "Kangoo", "fuel_type": "electric",
+        }))
+        .await;
+        let ctx = resolve_import_context(&db.pool, "PCP0010699", "A187518").await.unwrap();
+        assert_eq!(ctx.vehicle_id.to_string(), vehicle["id"].as_str().unwrap());
+
+        let packages = vec![colis_prive_package("1001", "A_LIVRER"), colis_prive_package("1002", "A_LIVRER")];
+        let tournee = ImportedTournee {
+            external_tournee_id: "T-A187518".to_string(),
+            tournee_number: Some("A187518".to_string()),
+            date: chrono::Utc::now().date_naive(),
+            packages: &packages,
+        };
+        let report = import_tournee(&db.pool, &ctx, &tournee).await.unwrap();
+        assert!(report.tournee_created);
+        assert_eq!(report.created, 2);
+
+        // Colis Privé renumera la tournée: se reutiliza la del driver para ese día
+        let today = chrono::Utc::now().date_naive();
+        let renamed = ImportedTournee {
+            external_tournee_id: "T-A187518-2".to_string(),
+            tournee_number: None,
+            date: today,
+            packages: &packages,
+        };
+        let renamed = import_tournee(&db.pool, &ctx, &renamed).await.unwrap();
+        assert!(!renamed.tournee_created);
+        assert_eq!(renamed.tournee_id, report.tournee_id);
+
+        // Una tournée manual del driver ese día no se pisa
+        let tomorrow = today + chrono::Duration::days(1);
+        sqlx::query("INSERT INTO tournees (company_id, driver_id, vehicle_id, tournee_date) VALUES ($1, $2, $3, $4)")
+            .bind(ctx.company_id)
+            .bind(ctx.driver_id)
+            .bind(ctx.vehicle_id)
+            .bind(tomorrow)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let manual = ImportedTournee {
+            external_tournee_id: "T-A187518-3".to_string(),
+            tournee_number: None,
+            date: tomorrow,
+            packages: &packages,
+        };
+        let err = import_tournee(&db.pool, &ctx, &manual).await.unwrap_err();
+        assert!(matches!(err, TourneeImportError::TourneeConflict(_, date) if date == tomorrow));
+
+        // Con varios vehículos manda el configurado en la integración
+        let other = post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "XY-901-ZA", "brand": "Citroën", "model": "Berlingo", "fuel_type": "diesel",
+        }))
+        .await;
+        sqlx::query("UPDATE tournees SET deleted_at = NOW() WHERE driver_id = $1")
+            .bind(ctx.driver_id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let err = resolve_import_context(&db.pool, "PCP0010699", "A187518").await.unwrap_err();
+        assert!(matches!(err, TourneeImportError::VehicleNotFound(_)));
+        sqlx::query("UPDATE api_integrations SET provider_config = jsonb_build_object('default_vehicle_id', $1::text)")
+            .bind(other["id"].as_str().unwrap())
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let ctx = resolve_import_context(&db.pool, "PCP0010699", "A187518").await.unwrap();
+        assert_eq!(ctx.vehicle_id.to_string(), other["id"].as_str().unwrap());
+
+        // Varios drivers con el mismo matricule o varias integraciones con la misma société
+        let homonym = post_ok(&app, "/users", &admin, json!({
+            "username": "A187518-bis",
+            "password": "driverpass",
+            "full_name": "Hugo Petit",
+            "email": "hugo.bis@zeta.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        sqlx::query("UPDATE users SET tournee_number = 'A187518' WHERE id = $1")
+            .bind(Uuid::parse_str(homonym["id"].as_str().unwrap()).unwrap())
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let err = resolve_import_context(&db.pool, "PCP0010699", "A187518").await.unwrap_err();
+        assert!(matches!(err, TourneeImportError::AmbiguousDriver(_)));
+
+        let (_, other_company) = register(&app, "Omega").await;
+        sqlx::query(
+            "INSERT INTO api_integrations (company_id, provider_name, api_credentials) VALUES ($1, 'colis_prive', '{\"societe\": \"PCP0010699\"}')",
+        )
+        .bind(Uuid::parse_str(&other_company).unwrap())
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        let err = resolve_import_context(&db.pool, "PCP0010699", "A187518").await.unwrap_err();
+        assert!(matches!(err, TourneeImportError::AmbiguousIntegration(_)));
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn test_reimport_keeps_driver_statuses_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (admin, company_id) = register(&app, "Nu").await;
+
+        post_ok(&app, "/users", &admin, json!({
+            "username": "A187518",
+            "password": "driverpass",
+            "full_name": "Hugo Petit",
+            "email": "hugo@nu.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "TU-678-VW", "brand": "Renault", "model": "Kangoo", "fuel_type": "electric",
+        }))
+        .await;
+        sqlx::query(
+            "INSERT INTO api_integrations (company_id, provider_name, api_credentials) VALUES ($1, 'colis_prive', '{\"societe\": \"PCP0010699\"}')",
+        )
+        .bind(Uuid::parse_str(&company_id).unwrap())
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        let ctx = resolve_import_context(&db.pool, "PCP0010699", "A187518").await.unwrap();
+        let packages = vec![colis_prive_package("1001", "A_LIVRER"), colis_prive_package("1002", "A_LIVRER")];
+        let tournee = ImportedTournee {
+            external_tournee_id: "T-A187518".to_string(),
+            tournee_number: None,
+            date: chrono::Utc::now().date_naive(),
+            packages: &packages,
+        };
+        let report = import_tournee(&db.pool, &ctx, &tournee).await.unwrap();
+        assert_eq!(report.created, 2);
+
+        // Reimportar no pisa lo que hizo el driver: el estado pasa por la máquina de estados
+        sqlx::query("UPDATE tournees SET tournee_status = 'in_progress' WHERE id = $1")
+            .bind(report.tournee_id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE packages SET delivery_status = 'delivered' WHERE external_package_id = '1001'")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let packages = vec![colis_prive_package("1001", "A_LIVRER"), colis_prive_package("1002", "EN_COURS")];
+        let report = import_tournee(&db.pool, &ctx, &ImportedTournee { packages: &packages, ..tournee }).await.unwrap();
+        assert!(!report.tournee_created);
+        assert_eq!(report.updated, 2);
+
+        let statuses: Vec<(String, String, i64)> = sqlx::query_as(
+            r#"
+            SELECT p.external_package_id, p.delivery_status::text, COUNT(h.id)
+            FROM packages p
+            JOIN package_status_history h ON h.package_id = p.id
+            WHERE p.tournee_id = $1
+            GROUP BY p.id
+            ORDER BY p.external_package_id
+            "#,
+        )
+        .bind(report.tournee_id)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(statuses, vec![
+            ("1001".to_string(), "delivered".to_string(), 1),
+            ("1002".to_string(), "out_for_delivery".to_string(), 2),
+        ]);
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn test_colis_prive_import_carries_over_failed_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (admin, company_id) = register(&app, "Lambda").await;
+        let company_id = Uuid::parse_str(&company_id).unwrap();
+
+        let driver = post_ok(&app, "/users", &admin, json!({
+            "username": "A187519",
+            "password": "driverpass",
+            "full_name": "Inès Garnier",
+            "email": "ines@lambda.fr",
+            "user_type": "driver",
+        }))
+        .await;
+        let vehicle = post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "BC-234-DE", "brand": "Renault", "model": "Master", "fuel_type": "diesel",
+        }))
+        .await;
+        sqlx::query(
+            "INSERT INTO api_integrations (company_id, provider_name, api_credentials) VALUES ($1, 'colis_prive', '{\"societe\": \"PCP0010700\"}')",
+        )
+        .bind(company_id)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        // Tournée de ayer cancelada con un paquete fallido a reprogramar
+        let cancelled = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO tournees (company_id, driver_id, vehicle_id, tournee_date, tournee_status) VALUES ($1, $2, $3, CURRENT_DATE - 1, 'cancelled') RETURNING id",
+        )
+        .bind(company_id)
+        .bind(Uuid::parse_str(driver["id"].as_str().unwrap()).unwrap())
+        .bind(Uuid::parse_str(vehicle["id"].as_str().unwrap()).unwrap())
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        let failed = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO packages (company_id, tournee_id, tracking_number, delivery_status, delivery_address, failure_action) VALUES ($1, $2, 'PKG-5001', 'failed', '3 rue Keller, 75011 Paris', 'reschedule') RETURNING id",
+        )
+        .bind(company_id)
+        .bind(cancelled)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+        // La tournée importada hoy recibe la copia
+        let ctx = resolve_import_context(&db.pool, "PCP0010700", "A187519").await.unwrap();
+        let packages = vec![colis_prive_package("2001", "A_LIVRER")];
+        let tournee = ImportedTournee {
+            external_tournee_id: "T-A187519".to_string(),
+            tournee_number: None,
+            date: chrono::Utc::now().date_naive(),
+            packages: &packages,
+        };
+        let report = import_tournee(&db.pool, &ctx, &tournee).await.unwrap();
+        assert!(report.tournee_created);
+
+        let (tracking, status, changed_by) = sqlx::query_as::<_, (String, String, Option<Uuid>)>(
+            r#"
+            SELECT p.tracking_number, p.delivery_status::text, h.changed_by
+            FROM packages p
+            JOIN package_status_history h ON h.package_id = p.id
+            WHERE p.tournee_id = $1 AND p.carried_over_from = $2
+            "#,
+        )
+        .bind(report.tournee_id)
+        .bind(failed)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!((tracking.as_str(), status.as_str(), changed_by), ("PKG-5001", "pending", None));
+
+        db.drop().await;
+    }
+}
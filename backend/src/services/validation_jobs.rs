@@ -0,0 +1,924 @@
+//! Validación de direcciones en segundo plano
+//!
+//! `get_packages` devuelve los paquetes en cuanto llegan de Colis Privé y deja la
+//! validación en una tarea (`address_validation_jobs`). Cada paquete validado se
+//! guarda y se emite como evento (SSE). Un reintento de la misma tournée reutiliza
+//! la tarea y solo valida los paquetes sin resultado o cuya dirección cambió.
+//! El número de direcciones validándose a la vez está acotado para todas las tareas.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::NaiveDate;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use tokio::sync::{broadcast, Semaphore};
+use uuid::Uuid;
+
+use crate::services::address_validation::{AddressValidator, ValidatedAddress, ValidationMethod};
+use crate::services::colis_prive_service::{AddressValidationSummary, PackageData};
+
+/// Eventos pendientes por suscriptor antes de perder los más antiguos
+const EVENT_BUFFER: usize = 256;
+
+/// Configuración de las tareas de validación
+#[derive(Debug, Clone)]
+pub struct ValidationJobConfig {
+    /// Direcciones validándose a la vez, entre todas las tareas
+    pub concurrency: usize,
+}
+
+impl Default for ValidationJobConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: std::env::var("VALIDATION_JOB_CONCURRENCY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|c| *c > 0)
+                .unwrap_or(4),
+        }
+    }
+}
+
+/// Estado de una tarea
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl JobStatus {
+    fn parse(status: &str) -> Self {
+        match status {
+            "running" => JobStatus::Running,
+            "completed" => JobStatus::Completed,
+            "failed" => JobStatus::Failed,
+            _ => JobStatus::Pending,
+        }
+    }
+}
+
+/// Referencia a la tarea en la respuesta de `get_packages`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationJobInfo {
+    pub id: Uuid,
+    pub status: JobStatus,
+    pub total: i32,
+    pub processed: i32,
+}
+
+/// Paquete de una tarea con su resultado, si ya lo tiene
+#[derive(Debug, Clone, Serialize)]
+pub struct JobItem {
+    pub package_id: String,
+    pub position: i32,
+    pub address: String,
+    pub result: Option<ValidatedAddress>,
+}
+
+/// Estado completo de una tarea
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationJobSnapshot {
+    pub id: Uuid,
+    pub status: JobStatus,
+    pub total: i32,
+    pub processed: i32,
+    pub summary: Option<AddressValidationSummary>,
+    pub error: Option<String>,
+    pub items: Vec<JobItem>,
+}
+
+impl ValidationJobSnapshot {
+    pub fn info(&self) -> ValidationJobInfo {
+        ValidationJobInfo {
+            id: self.id,
+            status: self.status,
+            total: self.total,
+            processed: self.processed,
+        }
+    }
+
+    /// Eventos equivalentes al estado actual (para quien se suscribe tarde)
+    pub fn events(&self) -> Vec<ValidationEvent> {
+        let mut events: Vec<ValidationEvent> = self
+            .items
+            .iter()
+            .filter_map(|item| {
+                item.result.as_ref().map(|result| ValidationEvent::Package {
+                    package_id: item.package_id.clone(),
+                    position: item.position,
+                    processed: self.processed,
+                    total: self.total,
+                    result: result.clone(),
+                })
+            })
+            .collect();
+
+        match (self.status, &self.summary, &self.error) {
+            (JobStatus::Completed, Some(summary), _) => events.push(ValidationEvent::Completed { summary: summary.clone() }),
+            (JobStatus::Failed, _, error) => events.push(ValidationEvent::Failed {
+                error: error.clone().unwrap_or_default(),
+            }),
+            _ => {}
+        }
+        events
+    }
+}
+
+/// Progreso emitido mientras se valida una tarea
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ValidationEvent {
+    Package {
+        package_id: String,
+        position: i32,
+        processed: i32,
+        total: i32,
+        result: ValidatedAddress,
+    },
+    Completed {
+        summary: AddressValidationSummary,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+impl ValidationEvent {
+    /// Nombre del evento SSE
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValidationEvent::Package { .. } => "package",
+            ValidationEvent::Completed { .. } => "completed",
+            ValidationEvent::Failed { .. } => "failed",
+        }
+    }
+
+    /// Último evento de la tarea
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, ValidationEvent::Package { .. })
+    }
+}
+
+/// Tournée a validar
+pub struct ValidationRequest<'a> {
+    pub company_id: Uuid,
+    /// Tournée importada; sin ella no se escriben coordenadas en `packages`
+    pub tournee_id: Option<Uuid>,
+    pub societe: &'a str,
+    pub matricule: &'a str,
+    pub date: NaiveDate,
+    pub packages: &'a [PackageData],
+}
+
+#[derive(Debug, FromRow)]
+struct JobRow {
+    id: Uuid,
+    matricule: String,
+    tournee_id: Option<Uuid>,
+    status: String,
+    total: i32,
+    processed: i32,
+    summary: Option<serde_json::Value>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct ItemRow {
+    package_id: String,
+    position: i32,
+    address: String,
+    /// Resultado del validador
+    result: Option<serde_json::Value>,
+    /// Resultado con la plausibilidad de la tournée aplicada
+    final_result: Option<serde_json::Value>,
+}
+
+impl ItemRow {
+    fn into_item(self) -> JobItem {
+        let result = self
+            .final_result
+            .or(self.result)
+            .and_then(|value| serde_json::from_value(value).ok());
+        JobItem {
+            package_id: self.package_id,
+            position: self.position,
+            address: self.address,
+            result,
+        }
+    }
+}
+
+/// Paquetes de la tarea tras un (re)intento: se conserva el resultado de los que
+/// siguen con la misma dirección; los nuevos o modificados quedan pendientes
+fn plan_items(existing: Vec<ItemRow>, packages: &[PackageData]) -> Vec<ItemRow> {
+    let mut previous: HashMap<String, ItemRow> = existing
+        .into_iter()
+        .map(|item| (item.package_id.clone(), item))
+        .collect();
+
+    packages
+        .iter()
+        .enumerate()
+        .map(|(i, package)| {
+            let result = previous
+                .remove(&package.id)
+                .filter(|item| item.address == package.address)
+                .and_then(|item| item.result);
+            ItemRow {
+                package_id: package.id.clone(),
+                position: i as i32 + 1,
+                address: package.address.clone(),
+                result,
+                final_result: None,
+            }
+        })
+        .collect()
+}
+
+/// Clave de la tarea: una por empresa, société, driver y fecha
+/// (`-` si la société no tiene empresa asociada)
+pub fn job_key(company_id: Option<Uuid>, societe: &str, matricule: &str, date: NaiveDate) -> String {
+    let company = company_id.map_or_else(|| "-".to_string(), |id| id.to_string());
+    format!("{}:{}:{}:{}", company, societe, matricule, date)
+}
+
+/// Resumen de la validación de una tournée
+pub fn summarize<'a>(
+    results: impl IntoIterator<Item = &'a ValidatedAddress>,
+    low_plausibility: usize,
+) -> AddressValidationSummary {
+    let mut summary = AddressValidationSummary {
+        total_packages: 0,
+        driver_confirmed: 0,
+        auto_validated: 0,
+        cleaned_auto: 0,
+        completed_auto: 0,
+        partial_found: 0,
+        requires_manual: 0,
+        low_plausibility,
+        warnings: Vec::new(),
+    };
+    if low_plausibility > 0 {
+        summary.warnings.push(format!("{} direcciones con resultado poco plausible", low_plausibility));
+    }
+
+    for result in results {
+        summary.total_packages += 1;
+        match result.validation_method {
+            ValidationMethod::DriverConfirmed => summary.driver_confirmed += 1,
+            ValidationMethod::Original => summary.auto_validated += 1,
+            ValidationMethod::Cleaned => summary.cleaned_auto += 1,
+            ValidationMethod::CompletedWithSector => summary.completed_auto += 1,
+            ValidationMethod::PartialSearch => summary.partial_found += 1,
+            ValidationMethod::ManualRequired => summary.requires_manual += 1,
+        }
+        summary.warnings.extend(result.warnings.iter().cloned());
+    }
+
+    summary
+}
+
+/// Copiar el resultado de la validación al paquete que se devuelve a Android
+pub fn apply_to_package(package: &mut PackageData, validated: &ValidatedAddress) {
+    package.latitude = validated.latitude;
+    package.longitude = validated.longitude;
+    package.formatted_address = validated.formatted_address.clone();
+    package.validation_method = Some(format!("{:?}", validated.validation_method));
+    package.validation_confidence = Some(format!("{:?}", validated.confidence));
+
+    let mut warnings = validated.warnings.clone();
+    if let Some(error) = &validated.error {
+        warnings.push(format!("Error de validación: {}", error));
+    }
+    package.validation_warnings = Some(warnings);
+}
+
+/// Tareas de validación (tablas `address_validation_jobs` y `address_validation_job_items`)
+#[derive(Clone)]
+pub struct ValidationJobs {
+    pool: PgPool,
+    concurrency: usize,
+    permits: Arc<Semaphore>,
+    /// Tareas en curso en este proceso y su canal de eventos
+    running: Arc<Mutex<HashMap<Uuid, broadcast::Sender<ValidationEvent>>>>,
+}
+
+impl ValidationJobs {
+    pub fn new(pool: PgPool, config: ValidationJobConfig) -> Self {
+        Self {
+            pool,
+            concurrency: config.concurrency,
+            permits: Arc::new(Semaphore::new(config.concurrency)),
+            running: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn running(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, broadcast::Sender<ValidationEvent>>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Eventos de una tarea en curso; `None` si no se está validando
+    pub fn subscribe(&self, id: Uuid) -> Option<broadcast::Receiver<ValidationEvent>> {
+        self.running().get(&id).map(|sender| sender.subscribe())
+    }
+
+    /// Crear o reutilizar la tarea de la tournée y validar en segundo plano
+    /// los paquetes que aún no tienen resultado
+    pub async fn start(
+        &self,
+        request: ValidationRequest<'_>,
+        validator: AddressValidator,
+    ) -> Result<ValidationJobSnapshot, sqlx::Error> {
+        let id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO address_validation_jobs (company_id, job_key, matricule, tournee_date, tournee_id)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (job_key) DO UPDATE SET
+                tournee_id = COALESCE(EXCLUDED.tournee_id, address_validation_jobs.tournee_id),
+                updated_at = NOW()
+            RETURNING id
+            "#,
+        )
+        .bind(request.company_id)
+        .bind(job_key(Some(request.company_id), request.societe, request.matricule, request.date))
+        .bind(request.matricule)
+        .bind(request.date)
+        .bind(request.tournee_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        // Reservar la tarea; si ya se está validando se devuelve su progreso
+        let sender = match self.running().entry(id) {
+            Entry::Occupied(_) => None,
+            Entry::Vacant(entry) => Some(entry.insert(broadcast::channel(EVENT_BUFFER).0).clone()),
+        };
+
+        if let Some(sender) = sender {
+            match self.prepare(id, request.packages).await {
+                Ok(Some(pending)) => {
+                    log::info!("🧵 Tarea de validación {}: {} de {} direcciones pendientes",
+                        id, pending.len(), request.packages.len());
+                    let jobs = self.clone();
+                    tokio::spawn(async move { jobs.run(id, pending, validator, sender).await });
+                }
+                Ok(None) => {
+                    log::info!("♻️ Tarea de validación {} ya completada, sin direcciones nuevas", id);
+                    self.running().remove(&id);
+                }
+                Err(e) => {
+                    self.running().remove(&id);
+                    return Err(e);
+                }
+            }
+        }
+
+        self.snapshot(id).await?.ok_or(sqlx::Error::RowNotFound)
+    }
+
+    /// Rehacer la lista de paquetes de la tarea. `None` si ya estaba completada
+    /// con los mismos paquetes y direcciones.
+    async fn prepare(&self, id: Uuid, packages: &[PackageData]) -> Result<Option<Vec<ItemRow>>, sqlx::Error> {
+        let status: String = sqlx::query_scalar("SELECT status FROM address_validation_jobs WHERE id = $1")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        let existing = self.items(id).await?;
+        let existing_count = existing.len();
+
+        let planned = plan_items(existing, packages);
+        let pending: Vec<ItemRow> = planned.iter().filter(|item| item.result.is_none()).cloned().collect();
+        if pending.is_empty() && existing_count == planned.len() && JobStatus::parse(&status) == JobStatus::Completed {
+            return Ok(None);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM address_validation_job_items WHERE job_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        for item in &planned {
+            sqlx::query(
+                r#"
+                INSERT INTO address_validation_job_items (job_id, package_id, position, address, result, validated_at)
+                VALUES ($1, $2, $3, $4, $5, CASE WHEN $5::jsonb IS NULL THEN NULL ELSE NOW() END)
+                "#,
+            )
+            .bind(id)
+            .bind(&item.package_id)
+            .bind(item.position)
+            .bind(&item.address)
+            .bind(&item.result)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query(
+            r#"
+            UPDATE address_validation_jobs SET
+                status = 'pending', total = $2, processed = $3,
+                summary = NULL, error = NULL, completed_at = NULL, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(planned.len() as i32)
+        .bind((planned.len() - pending.len()) as i32)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Some(pending))
+    }
+
+    async fn items(&self, id: Uuid) -> Result<Vec<ItemRow>, sqlx::Error> {
+        sqlx::query_as::<_, ItemRow>(
+            r#"
+            SELECT package_id, position, address, result, final_result
+            FROM address_validation_job_items
+            WHERE job_id = $1
+            ORDER BY position
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn job(&self, id: Uuid) -> Result<Option<JobRow>, sqlx::Error> {
+        sqlx::query_as::<_, JobRow>(
+            r#"
+            SELECT id, matricule, tournee_id, status, total, processed, summary, error
+            FROM address_validation_jobs
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// `true` si la tarea es de la empresa (las tareas sin empresa no son de nadie)
+    pub async fn belongs_to(&self, id: Uuid, company_id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM address_validation_jobs WHERE id = $1 AND company_id = $2)")
+            .bind(id)
+            .bind(company_id)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Estado de una tarea con los resultados disponibles
+    pub async fn snapshot(&self, id: Uuid) -> Result<Option<ValidationJobSnapshot>, sqlx::Error> {
+        let Some(job) = self.job(id).await? else {
+            return Ok(None);
+        };
+        let items = self.items(id).await?.into_iter().map(ItemRow::into_item).collect();
+
+        Ok(Some(ValidationJobSnapshot {
+            id: job.id,
+            status: JobStatus::parse(&job.status),
+            total: job.total,
+            processed: job.processed,
+            summary: job.summary.and_then(|s| serde_json::from_value(s).ok()),
+            error: job.error,
+            items,
+        }))
+    }
+
+    async fn run(
+        self,
+        id: Uuid,
+        pending: Vec<ItemRow>,
+        validator: AddressValidator,
+        sender: broadcast::Sender<ValidationEvent>,
+    ) {
+        let event = match self.validate(id, pending, &validator, &sender).await {
+            Ok(summary) => {
+                log::info!("✅ Tarea de validación {} completada: {} paquetes, {} manuales",
+                    id, summary.total_packages, summary.requires_manual);
+                ValidationEvent::Completed { summary }
+            }
+            Err(e) => {
+                log::error!("❌ Tarea de validación {} fallida: {}", id, e);
+                let error = e.to_string();
+                if let Err(e) = sqlx::query(
+                    "UPDATE address_validation_jobs SET status = 'failed', error = $2, updated_at = NOW() WHERE id = $1",
+                )
+                .bind(id)
+                .bind(&error)
+                .execute(&self.pool)
+                .await
+                {
+                    log::error!("❌ Error marcando la tarea {} como fallida: {}", id, e);
+                }
+                ValidationEvent::Failed { error }
+            }
+        };
+
+        // Sin suscriptores `send` falla, no es un error
+        let _ = sender.send(event);
+        self.running().remove(&id);
+    }
+
+    /// Validar los paquetes pendientes con concurrencia acotada, guardando cada resultado
+    async fn validate(
+        &self,
+        id: Uuid,
+        pending: Vec<ItemRow>,
+        validator: &AddressValidator,
+        sender: &broadcast::Sender<ValidationEvent>,
+    ) -> Result<AddressValidationSummary, sqlx::Error> {
+        let job = self.job(id).await?.ok_or(sqlx::Error::RowNotFound)?;
+        sqlx::query("UPDATE address_validation_jobs SET status = 'running', updated_at = NOW() WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        let matricule = job.matricule.as_str();
+        let mut results = futures::stream::iter(pending)
+            .map(|item| async move {
+                // El semáforo no se cierra nunca
+                let _permit = self.permits.acquire().await.ok();
+                let result = validator
+                    .validate_address(&item.address, matricule)
+                    .await
+                    .unwrap_or_else(|e| ValidatedAddress::failed(&item.address, e.to_string()));
+                (item, result)
+            })
+            .buffer_unordered(self.concurrency);
+
+        let mut processed = job.processed;
+        while let Some((item, result)) = results.next().await {
+            processed += 1;
+            sqlx::query(
+                r#"
+                UPDATE address_validation_job_items SET result = $3, validated_at = NOW()
+                WHERE job_id = $1 AND package_id = $2
+                "#,
+            )
+            .bind(id)
+            .bind(&item.package_id)
+            .bind(serde_json::to_value(&result).unwrap_or_default())
+            .execute(&self.pool)
+            .await?;
+            sqlx::query("UPDATE address_validation_jobs SET processed = $2, updated_at = NOW() WHERE id = $1")
+                .bind(id)
+                .bind(processed)
+                .execute(&self.pool)
+                .await?;
+
+            let _ = sender.send(ValidationEvent::Package {
+                package_id: item.package_id,
+                position: item.position,
+                processed,
+                total: job.total,
+                result,
+            });
+        }
+
+        self.finish(&job, validator).await
+    }
+
+    /// Plausibilidad sobre toda la tournée, resumen y coordenadas de los paquetes guardados
+    async fn finish(&self, job: &JobRow, validator: &AddressValidator) -> Result<AddressValidationSummary, sqlx::Error> {
+        let mut results: Vec<(String, ValidatedAddress)> = self
+            .items(job.id)
+            .await?
+            .into_iter()
+            .map(|item| {
+                let result = item
+                    .result
+                    .and_then(|value| serde_json::from_value(value).ok())
+                    .unwrap_or_else(|| ValidatedAddress::failed(&item.address, "Sin resultado de validación"));
+                (item.package_id, result)
+            })
+            .collect();
+
+        let low_plausibility = validator.apply_plausibility(results.iter_mut().map(|(_, result)| result));
+        let summary = summarize(results.iter().map(|(_, result)| result), low_plausibility);
+
+        let mut tx = self.pool.begin().await?;
+        for (package_id, result) in &results {
+            sqlx::query("UPDATE address_validation_job_items SET final_result = $3 WHERE job_id = $1 AND package_id = $2")
+                .bind(job.id)
+                .bind(package_id)
+                .bind(serde_json::to_value(result).unwrap_or_default())
+                .execute(&mut *tx)
+                .await?;
+
+            // Coordenadas del paquete en la tournée persistida por `get_packages`,
+            // salvo las que ya fijó el driver
+            if let (Some(tournee_id), Some(latitude), Some(longitude), true) =
+                (job.tournee_id, result.latitude, result.longitude, result.success)
+            {
+                sqlx::query(
+                    r#"
+                    UPDATE packages
+                    SET delivery_coordinates = point($3::float8, $4::float8), updated_at = NOW()
+                    WHERE tournee_id = $1 AND external_package_id = $2
+                      AND deleted_at IS NULL AND coordinates_confirmed_at IS NULL
+                    "#,
+                )
+                .bind(tournee_id)
+                .bind(package_id)
+                .bind(longitude)
+                .bind(latitude)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE address_validation_jobs SET
+                status = 'completed', summary = $2, completed_at = NOW(), updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(job.id)
+        .bind(serde_json::to_value(&summary).unwrap_or_default())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_support::{post_ok, register, TestDb};
+    use crate::services::address_validation::ValidationConfidence;
+    use crate::services::geocoder::GeocoderChain;
+    use serde_json::json;
+
+    fn package(id: &str, address: &str) -> PackageData {
+        PackageData {
+            id: id.to_string(),
+            tracking_number: id.to_string(),
+            recipient_name: "DUPONT".to_string(),
+            address: address.to_string(),
+            status: "A_LIVRER".to_string(),
+            instructions: String::new(),
+            phone: String::new(),
+            priority: "NORMAL".to_string(),
+            latitude: None,
+            longitude: None,
+            formatted_address: None,
+            validation_method: None,
+            validation_confidence: None,
+            validation_warnings: None,
+        }
+    }
+
+    fn validated(address: &str, method: ValidationMethod) -> ValidatedAddress {
+        ValidatedAddress {
+            success: true,
+            latitude: Some(48.8917),
+            longitude: Some(2.3522),
+            formatted_address: Some(address.to_string()),
+            original_address: address.to_string(),
+            validation_method: method,
+            confidence: ValidationConfidence::High,
+            warnings: vec![],
+            error: None,
+            plausibility: None,
+        }
+    }
+
+    fn stored(id: &str, address: &str) -> ItemRow {
+        ItemRow {
+            package_id: id.to_string(),
+            position: 1,
+            address: address.to_string(),
+            result: Some(serde_json::to_value(validated(address, ValidationMethod::Original)).unwrap()),
+            final_result: None,
+        }
+    }
+
+    #[test]
+    fn test_job_key_is_scoped_by_company() {
+        let date = NaiveDate::from_ymd_opt(2025, 9, 1).unwrap();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        assert_ne!(job_key(Some(a), "PCP0010699", "A187518", date), job_key(Some(b), "PCP0010699", "A187518", date));
+        assert_eq!(job_key(None, "PCP0010699", "A187518", date), "-:PCP0010699:A187518:2025-09-01");
+    }
+
+    #[test]
+    fn test_plan_items_keeps_results_of_unchanged_addresses() {
+        let existing = vec![
+            stored("P1", "16 RUE JEAN COTTIN 75018 PARIS"),
+            stored("P2", "3 RUE ORDENER 75018 PARIS"),
+            stored("P9", "12 RUE MARCADET 75018 PARIS"),
+        ];
+        let packages = vec![
+            package("P2", "3 RUE ORDENER 75018 PARIS"),
+            package("P1", "18 RUE JEAN COTTIN 75018 PARIS"),
+            package("P3", "94BIS RUE RIQUET 75018 PARIS"),
+        ];
+
+        let planned = plan_items(existing, &packages);
+        let plan: Vec<(&str, i32, bool)> = planned
+            .iter()
+            .map(|i| (i.package_id.as_str(), i.position, i.result.is_some()))
+            .collect();
+        // P2 se reutiliza, P1 cambió de dirección, P3 es nuevo y P9 ya no está en la tournée
+        assert_eq!(plan, vec![("P2", 1, true), ("P1", 2, false), ("P3", 3, false)]);
+    }
+
+    #[test]
+    fn test_summarize_counts_methods_and_warnings() {
+        let mut cleaned = validated("6 7 IMP. DU CURE 75018 PARIS", ValidationMethod::Cleaned);
+        cleaned.warnings.push("Dirección limpiada".to_string());
+        let results = [
+            validated("16 RUE JEAN COTTIN 75018 PARIS", ValidationMethod::Original),
+            validated("3 RUE ORDENER 75018 PARIS", ValidationMethod::DriverConfirmed),
+            cleaned,
+            ValidatedAddress::failed("RUE", "Sin resultado"),
+        ];
+
+        let summary = summarize(results.iter(), 1);
+        assert_eq!(summary.total_packages, 4);
+        assert_eq!(
+            (summary.auto_validated, summary.driver_confirmed, summary.cleaned_auto, summary.requires_manual),
+            (1, 1, 1, 1)
+        );
+        assert_eq!(summary.low_plausibility, 1);
+        assert_eq!(summary.warnings, vec!["1 direcciones con resultado poco plausible", "Dirección limpiada"]);
+    }
+
+    #[test]
+    fn test_apply_failed_result_to_package() {
+        let mut p = package("P1", "RUE");
+        apply_to_package(&mut p, &ValidatedAddress::failed("RUE", "timeout"));
+        assert_eq!(p.latitude, None);
+        assert_eq!(p.validation_method.as_deref(), Some("ManualRequired"));
+        assert_eq!(p.validation_confidence.as_deref(), Some("None"));
+        assert_eq!(p.validation_warnings, Some(vec!["Error de validación: timeout".to_string()]));
+    }
+
+    #[test]
+    fn test_snapshot_events_replay_results_and_end() {
+        let result = validated("16 RUE JEAN COTTIN 75018 PARIS", ValidationMethod::Original);
+        let snapshot = ValidationJobSnapshot {
+            id: Uuid::new_v4(),
+            status: JobStatus::Completed,
+            total: 2,
+            processed: 2,
+            summary: Some(summarize([&result], 0)),
+            error: None,
+            items: vec![
+                JobItem { package_id: "P1".to_string(), position: 1, address: result.original_address.clone(), result: Some(result.clone()) },
+                JobItem { package_id: "P2".to_string(), position: 2, address: "RUE".to_string(), result: None },
+            ],
+        };
+
+        let events = snapshot.events();
+        assert_eq!(events.iter().map(|e| e.name()).collect::<Vec<_>>(), vec!["package", "completed"]);
+        assert!(events[1].is_terminal());
+
+        let json = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(json["type"], "package");
+        assert_eq!(json["package_id"], "P1");
+        assert_eq!(json["result"]["latitude"], 48.8917);
+    }
+
+    #[tokio::test]
+    async fn test_validation_job_ownership_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (_, iota) = register(&app, "Iota").await;
+        let (_, kappa) = register(&app, "Kappa").await;
+        let (iota, kappa) = (Uuid::parse_str(&iota).unwrap(), Uuid::parse_str(&kappa).unwrap());
+
+        let mut ids = Vec::new();
+        for (company_id, key) in [(Some(iota), "iota"), (None, "sin-empresa")] {
+            let id = sqlx::query_scalar::<_, Uuid>(
+                "INSERT INTO address_validation_jobs (company_id, job_key, matricule, tournee_date) VALUES ($1, $2, 'A187518', CURRENT_DATE) RETURNING id",
+            )
+            .bind(company_id)
+            .bind(key)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+            ids.push(id);
+        }
+
+        let jobs = ValidationJobs::new(db.pool.clone(), ValidationJobConfig::default());
+        assert!(jobs.belongs_to(ids[0], iota).await.unwrap());
+        assert!(!jobs.belongs_to(ids[0], kappa).await.unwrap());
+        assert!(!jobs.belongs_to(ids[1], iota).await.unwrap());
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn test_finish_writes_coordinates_of_its_tournee_only_against_postgres() {
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let app = db.app();
+        let (admin, company_id) = register(&app, "Lambda").await;
+        let company_id = Uuid::parse_str(&company_id).unwrap();
+
+        for username in ["A187518", "A187519"] {
+            post_ok(&app, "/users", &admin, json!({
+                "username": username,
+                "password": "driverpass",
+                "full_name": username,
+                "email": format!("{}@lambda.fr", username.to_lowercase()),
+                "user_type": "driver",
+            }))
+            .await;
+        }
+        post_ok(&app, "/vehicles", &admin, json!({
+            "license_plate": "TU-678-VW", "brand": "Renault", "model": "Kangoo", "fuel_type": "electric",
+        }))
+        .await;
+
+        // Dos tournées de la empresa el mismo día, con el mismo id de paquete externo
+        let tournees: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            INSERT INTO tournees (company_id, driver_id, vehicle_id, tournee_date)
+            SELECT $1, u.id, (SELECT id FROM vehicles WHERE company_id = $1), CURRENT_DATE
+            FROM users u WHERE u.company_id = $1 AND u.user_type = 'driver'
+            ORDER BY u.username
+            RETURNING id
+            "#,
+        )
+        .bind(company_id)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(tournees.len(), 2);
+
+        for (tournee_id, external_id, confirmed) in [(tournees[0], "P1", false), (tournees[0], "P2", true), (tournees[1], "P1", false)] {
+            sqlx::query(
+                r#"
+                INSERT INTO packages (company_id, tournee_id, tracking_number, external_package_id, delivery_address,
+                                      delivery_coordinates, coordinates_confirmed_at)
+                VALUES ($1, $2, $3, $3, '16 RUE JEAN COTTIN 75018 PARIS', point(2.0, 48.0),
+                        CASE WHEN $4 THEN NOW() END)
+                "#,
+            )
+            .bind(company_id)
+            .bind(tournee_id)
+            .bind(external_id)
+            .bind(confirmed)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        }
+
+        let job_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO address_validation_jobs (company_id, job_key, matricule, tournee_date, tournee_id) VALUES ($1, 'lambda', 'A187518', CURRENT_DATE, $2) RETURNING id",
+        )
+        .bind(company_id)
+        .bind(tournees[0])
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        for (position, id) in ["P1", "P2"].into_iter().enumerate() {
+            let item = stored(id, "16 RUE JEAN COTTIN 75018 PARIS");
+            sqlx::query("INSERT INTO address_validation_job_items (job_id, package_id, position, address, result) VALUES ($1, $2, $3, $4, $5)")
+                .bind(job_id)
+                .bind(&item.package_id)
+                .bind(position as i32 + 1)
+                .bind(&item.address)
+                .bind(&item.result)
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+
+        let jobs = ValidationJobs::new(db.pool.clone(), ValidationJobConfig::default());
+        let job = jobs.job(job_id).await.unwrap().unwrap();
+        let validator = AddressValidator::new(Arc::new(GeocoderChain::new(vec![], 0.5)));
+        jobs.finish(&job, &validator).await.unwrap();
+
+        // Solo el paquete no confirmado de la tournée de la tarea recibe las coordenadas
+        let coordinates: Vec<(Uuid, String, f64)> = sqlx::query_as(
+            "SELECT tournee_id, external_package_id, delivery_coordinates[1] FROM packages WHERE company_id = $1 ORDER BY tournee_id = $2 DESC, external_package_id",
+        )
+        .bind(company_id)
+        .bind(tournees[0])
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(coordinates, vec![
+            (tournees[0], "P1".to_string(), 48.8917),
+            (tournees[0], "P2".to_string(), 48.0),
+            (tournees[1], "P1".to_string(), 48.0),
+        ]);
+
+        db.drop().await;
+    }
+}
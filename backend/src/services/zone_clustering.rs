@@ -0,0 +1,444 @@
+//! Agrupación por zonas (park-and-walk)
+//!
+//! Agrupa los paquetes geocodificados con DBSCAN según un radio a pie: el
+//! chofer conduce hasta cada zona, aparca una vez y reparte caminando. La ruta
+//! en vehículo visita zonas, no paquetes, y el resultado separa los tramos en
+//! coche de los tramos a pie.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::services::route_optimizer::{DistanceMetric, GeoPoint, Haversine, RouteOptimizer, RouteProblem, Stop};
+
+/// Parámetros de agrupación y de duración de los tramos
+#[derive(Debug, Clone)]
+pub struct ClusteringConfig {
+    /// Radio DBSCAN: distancia máxima entre vecinos de una zona
+    pub walking_radius_m: f64,
+    /// Paquetes mínimos para formar una zona (los demás se reparten puerta a puerta)
+    pub min_packages: usize,
+    /// Distancia máxima de un paquete al punto de aparcamiento (evita zonas en cadena)
+    pub max_walk_from_parking_m: f64,
+    pub walking_speed_kmh: f64,
+    pub driving_speed_kmh: f64,
+    pub parking_minutes: f64,
+    pub service_minutes_per_package: f64,
+}
+
+impl Default for ClusteringConfig {
+    fn default() -> Self {
+        Self {
+            walking_radius_m: 150.0,
+            min_packages: 2,
+            max_walk_from_parking_m: 300.0,
+            walking_speed_kmh: 4.5,
+            driving_speed_kmh: 20.0, // reparto urbano
+            parking_minutes: 2.0,
+            service_minutes_per_package: 2.0,
+        }
+    }
+}
+
+/// Zona de reparto: un aparcamiento y los paquetes en orden a pie
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Zone {
+    pub id: usize,
+    pub parking: GeoPoint,
+    pub package_ids: Vec<String>,
+    pub walking_distance_km: f64,
+    pub walking_minutes: f64,
+}
+
+/// Medio del tramo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LegMode {
+    Drive,
+    Walk,
+}
+
+/// Tramo de la ruta: en coche entre aparcamientos o a pie dentro de una zona
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteLeg {
+    pub mode: LegMode,
+    pub zone_id: Option<usize>,
+    pub from: GeoPoint,
+    pub to: GeoPoint,
+    /// Paquete entregado al final del tramo (solo tramos a pie hacia una puerta)
+    pub package_id: Option<String>,
+    pub distance_km: f64,
+    pub duration_minutes: f64,
+}
+
+/// Ruta por zonas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZonedRoute {
+    /// Zonas en el orden en que se visitan
+    pub zones: Vec<Zone>,
+    pub legs: Vec<RouteLeg>,
+    pub driving_distance_km: f64,
+    pub driving_minutes: f64,
+    pub walking_distance_km: f64,
+    pub walking_minutes: f64,
+    /// Conducción, paseo, aparcamiento y servicio
+    pub total_minutes: f64,
+    /// Paquetes sin coordenadas
+    pub unrouted: Vec<String>,
+}
+
+/// Servicio de agrupación por zonas con distancia de conducción intercambiable
+#[derive(Clone)]
+pub struct ZoneClusterer {
+    driving_metric: Arc<dyn DistanceMetric>,
+    config: ClusteringConfig,
+}
+
+impl ZoneClusterer {
+    pub fn new(driving_metric: Arc<dyn DistanceMetric>, config: ClusteringConfig) -> Self {
+        Self { driving_metric, config }
+    }
+
+    /// Agrupar paquetes `(id, latitud, longitud)` y planificar la ruta por zonas
+    pub fn plan<I>(&self, depot: GeoPoint, end: Option<GeoPoint>, packages: I) -> ZonedRoute
+    where
+        I: IntoIterator<Item = (String, Option<f64>, Option<f64>)>,
+    {
+        let mut stops = Vec::new();
+        let mut unrouted = Vec::new();
+        for (id, latitude, longitude) in packages {
+            match (latitude, longitude) {
+                (Some(latitude), Some(longitude)) => stops.push(Stop { id, location: GeoPoint { latitude, longitude } }),
+                _ => unrouted.push(id),
+            }
+        }
+
+        let zones = self.build_zones(&stops);
+
+        // Ruta en coche entre aparcamientos
+        let parkings: Vec<Stop> = zones
+            .iter()
+            .map(|zone| Stop { id: zone.id.to_string(), location: zone.parking })
+            .collect();
+        let driving = RouteOptimizer::new(self.driving_metric.clone()).optimize(depot, end, &parkings);
+        let mut ordered: Vec<Zone> = driving
+            .sequence
+            .iter()
+            .filter_map(|id| id.parse::<usize>().ok())
+            .filter_map(|id| zones.iter().find(|zone| zone.id == id).cloned())
+            .collect();
+        for (index, zone) in ordered.iter_mut().enumerate() {
+            zone.id = index + 1;
+        }
+
+        self.assemble(depot, end, ordered, &stops, unrouted)
+    }
+
+    /// DBSCAN con límite de distancia al aparcamiento; los puntos que lo superan
+    /// se vuelven a agrupar en otra ronda
+    fn build_zones(&self, stops: &[Stop]) -> Vec<Zone> {
+        let mut remaining: Vec<usize> = (0..stops.len()).collect();
+        let mut zones = Vec::new();
+
+        while !remaining.is_empty() {
+            let mut rejected = Vec::new();
+
+            for cluster in self.dbscan(stops, &remaining) {
+                let parking = medoid(stops, &cluster);
+                let (members, outliers): (Vec<usize>, Vec<usize>) = cluster.into_iter().partition(|&i| {
+                    walking_m(stops[parking].location, stops[i].location) <= self.config.max_walk_from_parking_m
+                });
+                rejected.extend(outliers);
+                zones.push(self.zone(zones.len() + 1, stops[parking].location, &members, stops));
+            }
+
+            remaining = rejected;
+        }
+
+        zones
+    }
+
+    /// Agrupar los índices `points`; el ruido sale como zonas de un solo paquete
+    fn dbscan(&self, stops: &[Stop], points: &[usize]) -> Vec<Vec<usize>> {
+        let neighbors = |p: usize| -> Vec<usize> {
+            points
+                .iter()
+                .copied()
+                .filter(|&q| walking_m(stops[p].location, stops[q].location) <= self.config.walking_radius_m)
+                .collect()
+        };
+
+        let mut assigned = vec![false; stops.len()];
+        let mut clusters = Vec::new();
+
+        for &p in points {
+            if assigned[p] {
+                continue;
+            }
+
+            // Ruido provisional: puede acabar como borde de un grupo posterior
+            let seeds = neighbors(p);
+            if seeds.len() < self.config.min_packages {
+                continue;
+            }
+
+            let mut cluster = Vec::new();
+            let mut queue = seeds;
+            while let Some(q) = queue.pop() {
+                if assigned[q] {
+                    continue;
+                }
+                assigned[q] = true;
+                cluster.push(q);
+
+                let expansion = neighbors(q);
+                if expansion.len() >= self.config.min_packages {
+                    queue.extend(expansion.into_iter().filter(|&r| !assigned[r]));
+                }
+            }
+            clusters.push(cluster);
+        }
+
+        clusters.extend(points.iter().filter(|&&p| !assigned[p]).map(|&p| vec![p]));
+        clusters
+    }
+
+    /// Zona con el orden a pie optimizado (ida y vuelta al aparcamiento)
+    fn zone(&self, id: usize, parking: GeoPoint, members: &[usize], stops: &[Stop]) -> Zone {
+        let mut points = vec![parking];
+        points.extend(members.iter().map(|&i| stops[i].location));
+        points.push(parking);
+
+        let matrix: Vec<Vec<f64>> = points
+            .iter()
+            .map(|&from| points.iter().map(|&to| Haversine.distance_km(from, to)).collect())
+            .collect();
+
+        let problem = RouteProblem::new(&matrix, members.len(), true);
+        let order = problem.solve(20);
+        let walking_distance_km = problem.cost(&order);
+
+        Zone {
+            id,
+            parking,
+            package_ids: order.iter().map(|&node| stops[members[node - 1]].id.clone()).collect(),
+            walking_distance_km: round(walking_distance_km, 3),
+            walking_minutes: round(self.walking_minutes(walking_distance_km), 1),
+        }
+    }
+
+    fn walking_minutes(&self, km: f64) -> f64 {
+        km / self.config.walking_speed_kmh * 60.0
+    }
+
+    fn driving_minutes(&self, km: f64) -> f64 {
+        km / self.config.driving_speed_kmh * 60.0
+    }
+
+    /// Generar los tramos en coche y a pie y los totales
+    fn assemble(&self, depot: GeoPoint, end: Option<GeoPoint>, zones: Vec<Zone>, stops: &[Stop], unrouted: Vec<String>) -> ZonedRoute {
+        let location = |id: &str| stops.iter().find(|s| s.id == id).map(|s| s.location);
+        let mut legs = Vec::new();
+        let mut current = depot;
+
+        for zone in &zones {
+            legs.push(self.drive_leg(current, zone.parking, Some(zone.id)));
+
+            let mut here = zone.parking;
+            for id in &zone.package_ids {
+                let door = location(id).unwrap_or(zone.parking);
+                legs.push(self.walk_leg(here, door, zone.id, Some(id.clone())));
+                here = door;
+            }
+            if here != zone.parking {
+                legs.push(self.walk_leg(here, zone.parking, zone.id, None));
+            }
+            current = zone.parking;
+        }
+
+        if let Some(end) = end {
+            legs.push(self.drive_leg(current, end, None));
+        }
+
+        let total = |mode: LegMode, f: fn(&RouteLeg) -> f64| -> f64 {
+            legs.iter().filter(|leg| leg.mode == mode).map(f).sum()
+        };
+        let driving_distance_km = total(LegMode::Drive, |leg| leg.distance_km);
+        let walking_distance_km = total(LegMode::Walk, |leg| leg.distance_km);
+        let driving_minutes = self.driving_minutes(driving_distance_km);
+        let walking_minutes = self.walking_minutes(walking_distance_km);
+        let packages: usize = zones.iter().map(|zone| zone.package_ids.len()).sum();
+        let total_minutes = driving_minutes
+            + walking_minutes
+            + zones.len() as f64 * self.config.parking_minutes
+            + packages as f64 * self.config.service_minutes_per_package;
+
+        ZonedRoute {
+            zones,
+            legs,
+            driving_distance_km: round(driving_distance_km, 3),
+            driving_minutes: round(driving_minutes, 1),
+            walking_distance_km: round(walking_distance_km, 3),
+            walking_minutes: round(walking_minutes, 1),
+            total_minutes: round(total_minutes, 1),
+            unrouted,
+        }
+    }
+
+    fn drive_leg(&self, from: GeoPoint, to: GeoPoint, zone_id: Option<usize>) -> RouteLeg {
+        let distance_km = self.driving_metric.distance_km(from, to);
+        RouteLeg {
+            mode: LegMode::Drive,
+            zone_id,
+            from,
+            to,
+            package_id: None,
+            distance_km: round(distance_km, 3),
+            duration_minutes: round(self.driving_minutes(distance_km), 1),
+        }
+    }
+
+    fn walk_leg(&self, from: GeoPoint, to: GeoPoint, zone_id: usize, package_id: Option<String>) -> RouteLeg {
+        let distance_km = Haversine.distance_km(from, to);
+        RouteLeg {
+            mode: LegMode::Walk,
+            zone_id: Some(zone_id),
+            from,
+            to,
+            package_id,
+            distance_km: round(distance_km, 3),
+            duration_minutes: round(self.walking_minutes(distance_km), 1),
+        }
+    }
+}
+
+/// Distancia a pie en metros (línea recta)
+fn walking_m(from: GeoPoint, to: GeoPoint) -> f64 {
+    Haversine.distance_km(from, to) * 1000.0
+}
+
+/// Paquete del grupo con menor distancia total al resto: se aparca en una puerta real
+fn medoid(stops: &[Stop], cluster: &[usize]) -> usize {
+    cluster
+        .iter()
+        .copied()
+        .min_by(|&a, &b| {
+            let spread = |p: usize| -> f64 { cluster.iter().map(|&q| walking_m(stops[p].location, stops[q].location)).sum() };
+            spread(a).total_cmp(&spread(b))
+        })
+        .expect("un grupo nunca está vacío")
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ~0.0009° de latitud = 100 m
+    const STEP: f64 = 0.0009;
+    const DEPOT: GeoPoint = GeoPoint { latitude: 48.80, longitude: 2.30 };
+
+    fn package(id: &str, latitude: f64, longitude: f64) -> (String, Option<f64>, Option<f64>) {
+        (id.to_string(), Some(latitude), Some(longitude))
+    }
+
+    fn clusterer() -> ZoneClusterer {
+        ZoneClusterer::new(Arc::new(Haversine), ClusteringConfig::default())
+    }
+
+    #[test]
+    fn test_groups_nearby_packages_into_zones() {
+        let packages = vec![
+            package("a1", 48.85, 2.35),
+            package("b1", 48.87, 2.37),
+            package("a2", 48.85 + STEP, 2.35),
+            package("b2", 48.87 + STEP, 2.37),
+            package("a3", 48.85 + 2.0 * STEP, 2.35),
+            package("lonely", 48.90, 2.40),
+            ("sin-gps".to_string(), None, None),
+        ];
+
+        let route = clusterer().plan(DEPOT, None, packages);
+
+        let mut zones: Vec<Vec<String>> = route.zones.iter().map(|z| {
+            let mut ids = z.package_ids.clone();
+            ids.sort();
+            ids
+        }).collect();
+        zones.sort();
+        assert_eq!(zones, vec![vec!["a1", "a2", "a3"], vec!["b1", "b2"], vec!["lonely"]]);
+        assert_eq!(route.unrouted, vec!["sin-gps"]);
+
+        // Se aparca en el medoide del grupo "a"
+        let zone_a = route.zones.iter().find(|z| z.package_ids.len() == 3).unwrap();
+        assert_eq!(zone_a.parking, GeoPoint { latitude: 48.85 + STEP, longitude: 2.35 });
+        assert_eq!(zone_a.id, 1, "la zona más cercana al depósito va primero");
+    }
+
+    #[test]
+    fn test_legs_separate_driving_and_walking() {
+        let packages = vec![package("a1", 48.85, 2.35), package("a2", 48.85 + STEP, 2.35)];
+
+        let route = clusterer().plan(DEPOT, Some(DEPOT), packages);
+
+        let modes: Vec<LegMode> = route.legs.iter().map(|leg| leg.mode).collect();
+        assert_eq!(modes, vec![LegMode::Drive, LegMode::Walk, LegMode::Walk, LegMode::Walk, LegMode::Drive]);
+
+        // Se aparca en una de las dos puertas: una entrega a 0 m y un paseo de ida y vuelta de 100 m
+        let delivered: Vec<_> = route.legs.iter().filter_map(|leg| leg.package_id.as_deref()).collect();
+        assert_eq!(delivered.len(), 2);
+        assert!((route.walking_distance_km - 0.2).abs() < 0.005, "{}", route.walking_distance_km);
+        assert!(route.driving_minutes > 0.0 && route.walking_minutes > 0.0);
+        assert!(route.total_minutes > route.driving_minutes + route.walking_minutes);
+    }
+
+    #[test]
+    fn test_border_package_joins_zone() {
+        // Con min_packages = 3, "borde" solo tiene un vecino pero está al alcance del núcleo
+        let config = ClusteringConfig { min_packages: 3, ..Default::default() };
+        let packages = vec![
+            package("borde", 48.85 - STEP, 2.35),
+            package("n1", 48.85, 2.35),
+            package("n2", 48.85 + STEP, 2.35),
+            package("n3", 48.85 + 0.9 * STEP, 2.35),
+        ];
+
+        let route = ZoneClusterer::new(Arc::new(Haversine), config).plan(DEPOT, None, packages);
+
+        assert_eq!(route.zones.len(), 1);
+        assert_eq!(route.zones[0].package_ids.len(), 4);
+    }
+
+    #[test]
+    fn test_chained_street_is_split() {
+        // 10 paquetes cada 100 m: DBSCAN los encadena, pero no se camina 900 m desde el coche
+        let packages: Vec<_> = (0..10)
+            .map(|i| package(&format!("p{}", i), 48.85 + i as f64 * STEP, 2.35))
+            .collect();
+
+        let route = clusterer().plan(DEPOT, None, packages);
+
+        assert!(route.zones.len() > 1);
+        for zone in &route.zones {
+            for id in &zone.package_ids {
+                let index: f64 = id[1..].parse().unwrap();
+                let door = GeoPoint { latitude: 48.85 + index * STEP, longitude: 2.35 };
+                assert!(walking_m(zone.parking, door) <= 300.0 + 1e-6);
+            }
+        }
+        let total: usize = route.zones.iter().map(|z| z.package_ids.len()).sum();
+        assert_eq!(total, 10);
+    }
+
+    #[test]
+    fn test_empty_tournee() {
+        let route = clusterer().plan(DEPOT, None, Vec::new());
+        assert!(route.zones.is_empty());
+        assert!(route.legs.is_empty());
+        assert_eq!(route.total_minutes, 0.0);
+    }
+}
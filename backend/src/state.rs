@@ -3,13 +3,24 @@
 //! Este módulo define el estado compartido de la aplicación que se pasa
 //! a través del router de Axum.
 
+use std::sync::Arc;
+use axum::extract::FromRef;
 use sqlx::PgPool;
 use reqwest::Client;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use uuid::Uuid;
 use crate::config::EnvironmentConfig;
-use crate::cache::RedisClient;
+use crate::cache::{AuthCache, RedisClient, TokenStore, TourneeCache};
+use crate::client::{ColisPriveClientConfig, ColisPriveWebClient};
+use crate::services::CredentialVault;
+use crate::services::address_corrections::AddressCorrectionStore;
+use crate::services::blob_store::{blob_store_from_config, BlobStore, BlobStoreConfig};
+use crate::services::distance_matrix::{DistanceMatrixConfig, DistanceMatrixService};
+use crate::services::geocoder::{Geocoder, GeocoderChain, GeocoderConfig};
+use crate::services::geocoding_cache::{CachedGeocoder, GeocodingCacheConfig};
+use crate::services::geocoding_usage::{GeocodingUsage, GeocodingUsageConfig};
+use crate::services::proof_of_delivery::UploadConfig;
+use crate::services::sectors::SectorStore;
+use crate::services::validation_jobs::{ValidationJobConfig, ValidationJobs};
 
 /// Estructura para almacenar tokens de autenticación
 #[derive(Clone, Debug)]
@@ -41,61 +52,147 @@ pub struct AppState {
     pub config: EnvironmentConfig,
     pub redis: RedisClient,
     pub http_client: Client,
-    pub auth_tokens: Arc<RwLock<HashMap<String, AuthToken>>>,
+    pub colis_prive: ColisPriveWebClient,
+    pub credential_vault: CredentialVault,
+    pub auth_tokens: TokenStore,
+    pub tournee_cache: TourneeCache,
+    pub distance_matrix: DistanceMatrixService,
+    pub geocoder_chain: GeocoderChain,
+    pub geocoding_cache: CachedGeocoder,
+    pub geocoding_usage: GeocodingUsage,
+    /// Geocoder sin empresa asociada (el consumo se cuenta sin `company_id`)
+    pub geocoder: Arc<dyn Geocoder>,
+    pub address_corrections: AddressCorrectionStore,
+    pub sectors: SectorStore,
+    pub validation_jobs: ValidationJobs,
 }
 
 impl AppState {
     pub fn new(pool: PgPool, config: EnvironmentConfig, redis: RedisClient) -> Self {
+        let http_client = Client::new();
+        let colis_prive = ColisPriveWebClient::new(http_client.clone(), ColisPriveClientConfig::default());
+        let credential_vault = CredentialVault::from_env(pool.clone());
+        let auth_tokens = TokenStore::new(Some(
+            AuthCache::new(redis.clone()).with_max_login_duration(colis_prive.config.max_call_duration()),
+        ));
+        let tournee_cache = TourneeCache::new(redis.clone(), redis.config());
+        let distance_matrix = DistanceMatrixService::from_config(
+            DistanceMatrixConfig::default(),
+            http_client.clone(),
+            Some(redis.clone()),
+        );
+        let geocoder_chain = GeocoderChain::from_config(
+            GeocoderConfig { mapbox_token: config.mapbox_token.clone(), ..GeocoderConfig::default() },
+            http_client.clone(),
+            Some(pool.clone()),
+        );
+        let geocoding_usage = GeocodingUsage::new(Some(pool.clone()), GeocodingUsageConfig::default());
+        let geocoding_cache = CachedGeocoder::new(
+            Arc::new(geocoder_chain.metered(&geocoding_usage, None)),
+            Some(redis.clone()),
+            Some(pool.clone()),
+            GeocodingCacheConfig::default(),
+        );
+        let geocoder: Arc<dyn Geocoder> = Arc::new(geocoding_cache.clone());
+        let address_corrections = AddressCorrectionStore::new(pool.clone());
+        let sectors = SectorStore::new(pool.clone());
+        let validation_jobs = ValidationJobs::new(pool.clone(), ValidationJobConfig::default());
+
         Self {
             pool,
             config,
             redis,
-            http_client: Client::new(),
-            auth_tokens: Arc::new(RwLock::new(HashMap::new())),
+            http_client,
+            colis_prive,
+            credential_vault,
+            auth_tokens,
+            tournee_cache,
+            distance_matrix,
+            geocoder_chain,
+            geocoding_cache,
+            geocoding_usage,
+            geocoder,
+            address_corrections,
+            sectors,
+            validation_jobs,
         }
     }
 
-    /// Obtener token de autenticación para un usuario específico
-    pub async fn get_auth_token(&self, username: &str, societe: &str) -> Option<AuthToken> {
-        let key = format!("{}:{}", societe, username);
-        log::info!("🔍 Buscando token con clave: '{}'", key);
-        
-        let tokens = self.auth_tokens.read().await;
-        let token_count = tokens.len();
-        log::info!("🔍 Total de tokens en estado: {}", token_count);
-        
-        // 🔍 DEBUG: Imprimir todas las claves disponibles
-        for (k, v) in tokens.iter() {
-            log::info!("🔍 Token disponible: '{}' -> username: '{}', societe: '{}'", k, v.username, v.societe);
+    /// Geocoder con caché cuyas llamadas a proveedores se cuentan para la empresa
+    pub fn geocoder_for(&self, company_id: Option<Uuid>) -> Arc<dyn Geocoder> {
+        if company_id.is_none() {
+            return self.geocoder.clone();
         }
-        
-        let result = tokens.get(&key).cloned();
-        match &result {
-            Some(token) => log::info!("✅ Token encontrado para clave '{}'", key),
-            None => log::warn!("❌ Token NO encontrado para clave '{}'", key),
+        let chain = self.geocoder_chain.metered(&self.geocoding_usage, company_id);
+        Arc::new(self.geocoding_cache.with_inner(Arc::new(chain)))
+    }
+
+    /// Obtener token de autenticación vigente (L1 en memoria, luego Redis)
+    pub async fn get_auth_token(&self, username: &str, societe: &str) -> Option<AuthToken> {
+        let token = self.auth_tokens.get(username, societe).await;
+        if token.is_none() {
+            log::warn!("❌ Token NO encontrado para '{}:{}'", societe, username);
         }
-        result
+        token
     }
 
-    /// Almacenar token de autenticación
+    /// Almacenar token de autenticación (memoria + Redis)
     pub async fn store_auth_token(&self, username: String, societe: String, token: String, expires_in_hours: i32) {
-        let key = format!("{}:{}", societe, username);
-        log::info!("💾 Almacenando token con clave: '{}' para username: '{}', societe: '{}'", key, username, societe);
-        
-        let auth_token = AuthToken::new(token, username, societe, expires_in_hours);
-        let mut tokens = self.auth_tokens.write().await;
-        
-        let old_count = tokens.len();
-        tokens.insert(key.clone(), auth_token);
-        let new_count = tokens.len();
-        
-        log::info!("💾 Token almacenado. Tokens antes: {}, después: {}", old_count, new_count);
-        log::info!("💾 Token almacenado exitosamente con clave: '{}'", key);
+        log::info!("💾 Almacenando token para username: '{}', societe: '{}'", username, societe);
+        self.auth_tokens
+            .store(AuthToken::new(token, username, societe, expires_in_hours))
+            .await;
+    }
+
+    /// Buscar la société de un token vigente del usuario (login previo vía /auth)
+    pub async fn find_societe_for_username(&self, username: &str) -> Option<String> {
+        self.auth_tokens.find_societe(username).await
     }
 
     /// Limpiar tokens expirados
     pub async fn cleanup_expired_tokens(&self) {
-        let mut tokens = self.auth_tokens.write().await;
-        tokens.retain(|_, token| !token.is_expired());
+        self.auth_tokens.cleanup_expired().await;
     }
-}
\ No newline at end of file
+}
+
+/// Estado de la API de flota (`/api/v1`): solo lo que usan sus handlers y el middleware JWT
+#[derive(Clone)]
+pub struct FleetState {
+    pub pool: PgPool,
+    pub config: EnvironmentConfig,
+    /// Sin Redis (p. ej. en tests) no hay tournées cacheadas que invalidar
+    pub tournee_cache: Option<TourneeCache>,
+    /// Fotos de entrega, firmas e inspecciones
+    pub blob_store: Arc<dyn BlobStore>,
+    pub uploads: UploadConfig,
+}
+
+impl FleetState {
+    pub fn new(
+        pool: PgPool,
+        config: EnvironmentConfig,
+        tournee_cache: Option<TourneeCache>,
+        blob_store: Arc<dyn BlobStore>,
+    ) -> Self {
+        Self { pool, config, tournee_cache, blob_store, uploads: UploadConfig::default() }
+    }
+}
+
+impl From<&AppState> for FleetState {
+    fn from(state: &AppState) -> Self {
+        let blob_store = blob_store_from_config(BlobStoreConfig::default(), state.http_client.clone());
+        Self::new(state.pool.clone(), state.config.clone(), Some(state.tournee_cache.clone()), blob_store)
+    }
+}
+
+impl FromRef<FleetState> for PgPool {
+    fn from_ref(state: &FleetState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<FleetState> for EnvironmentConfig {
+    fn from_ref(state: &FleetState) -> Self {
+        state.config.clone()
+    }
+}
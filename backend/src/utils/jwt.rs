@@ -6,11 +6,13 @@
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::{
     config::EnvironmentConfig,
-    models::user::UserType,
+    middleware::auth::find_user,
+    models::user::{UserStatus, UserType},
     utils::errors::AppError,
 };
 
@@ -154,6 +156,50 @@ pub fn extract_token_from_header(auth_header: &str) -> Result<&str, AppError> {
     Ok(token)
 }
 
+/// Verificar el token Bearer de la request y devolver sus claims. Como en
+/// `auth_middleware`, el usuario debe seguir existiendo y estar activo; su
+/// tipo se toma de la base de datos, no del token.
+pub async fn require_user(
+    headers: &axum::http::HeaderMap,
+    config: &JwtConfig,
+    pool: &PgPool,
+) -> Result<JwtClaims, AppError> {
+    let auth_header = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| AppError::Unauthorized("Token de autorización requerido".to_string()))?;
+
+    let mut claims = verify_token(extract_token_from_header(auth_header)?, config)?;
+
+    let (user_id, company_id) = Uuid::parse_str(&claims.sub)
+        .ok()
+        .zip(Uuid::parse_str(&claims.company_id).ok())
+        .ok_or_else(|| AppError::Unauthorized("Token inválido".to_string()))?;
+
+    match find_user(pool, user_id, company_id).await? {
+        Some((user_type, UserStatus::Active)) => {
+            claims.user_type = format!("{:?}", user_type).to_lowercase();
+            Ok(claims)
+        }
+        Some(_) => Err(AppError::Unauthorized("Usuario inactivo o suspendido".to_string())),
+        None => Err(AppError::Unauthorized("Usuario no encontrado".to_string())),
+    }
+}
+
+/// Verificar que la request viene de un admin y devolver sus claims
+pub async fn require_admin(
+    headers: &axum::http::HeaderMap,
+    config: &JwtConfig,
+    pool: &PgPool,
+) -> Result<JwtClaims, AppError> {
+    let claims = require_user(headers, config, pool).await?;
+    if claims.user_type != "admin" {
+        return Err(AppError::Forbidden("Se requiere un usuario admin".to_string()));
+    }
+
+    Ok(claims)
+}
+
 /// Crear respuesta de autenticación exitosa
 pub fn create_auth_response(
     access_token: String,
@@ -246,4 +292,40 @@ mod tests {
         assert!(extract_token_from_header("Invalid header").is_err());
         assert!(extract_token_from_header("Bearer ").is_err());
     }
+
+    #[tokio::test]
+    async fn test_require_user_checks_the_database_against_postgres() {
+        use crate::api::test_support::{register, TestDb};
+
+        let Some(db) = TestDb::create().await else {
+            return;
+        };
+        let (token, _) = register(&db.app(), "Xi").await;
+        let config = JwtConfig::from(&EnvironmentConfig::default());
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(axum::http::header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+
+        assert!(require_admin(&headers, &config, &db.pool).await.is_ok());
+
+        // Un admin degradado deja de serlo aunque su token diga lo contrario
+        sqlx::query("UPDATE users SET user_type = 'driver'")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert!(matches!(require_admin(&headers, &config, &db.pool).await, Err(AppError::Forbidden(_))));
+
+        // Un usuario suspendido o eliminado no pasa aunque su token siga vigente
+        sqlx::query("UPDATE users SET user_status = 'suspended'")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert!(matches!(require_user(&headers, &config, &db.pool).await, Err(AppError::Unauthorized(_))));
+        sqlx::query("UPDATE users SET user_status = 'active', deleted_at = NOW()")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert!(matches!(require_user(&headers, &config, &db.pool).await, Err(AppError::Unauthorized(_))));
+
+        db.drop().await;
+    }
 }
@@ -49,6 +49,17 @@ pub fn validate_datetime(value: &str) -> Result<DateTime<Utc>, ValidationError>
         })
 }
 
+/// Convertir un filtro opcional de la query con uno de los validadores; 400 si no es válido
+pub fn parse_filter<T>(
+    value: Option<&String>,
+    field: &str,
+    parse: fn(&str) -> Result<T, ValidationError>,
+) -> crate::utils::errors::AppResult<Option<T>> {
+    value
+        .map(|v| parse(v).map_err(|_| crate::utils::errors::AppError::BadRequest(format!("{} inválido", field))))
+        .transpose()
+}
+
 /// Validar que un string no esté vacío
 pub fn validate_not_empty(value: &str) -> Result<(), ValidationError> {
     if value.trim().is_empty() {